@@ -0,0 +1,377 @@
+use crate::dmarc::{self, DmarcPolicy};
+use crate::lint::Finding;
+use crate::CheckError;
+use crate::SpnResolver;
+use anyhow::{Context, Result};
+
+const VERSION: &str = "BIMI1";
+
+/// The selector used when a message names none in its `BIMI-Selector` header.
+pub const DEFAULT_SELECTOR: &str = "default";
+
+/// A parsed `v=BIMI1` record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BimiRecord {
+    /// The `l` tag, the HTTPS URL of the SVG logo. Empty if the domain declines to publish one.
+    pub logo_url: String,
+    /// The `a` tag, the HTTPS URL of the PEM file with the Verified Mark Certificate. Empty if
+    /// not given.
+    pub authority_url: String,
+}
+
+impl BimiRecord {
+    /// Returns `true` for a declination record, in which both `l` and `a` are empty.
+    pub fn is_declination(&self) -> bool {
+        self.logo_url.is_empty() && self.authority_url.is_empty()
+    }
+}
+
+/// The outcome of [`SpfChecker::check_bimi`](crate::SpfChecker::check_bimi).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BimiReport {
+    /// The domain queried for the record, e.g. `default._bimi.example.com`.
+    pub record_domain: String,
+    /// The BIMI record, if the domain publishes exactly one.
+    pub record: Option<String>,
+    /// The parsed [`BimiReport::record`], if it is valid.
+    pub parsed: Option<BimiRecord>,
+    pub findings: Vec<Finding>,
+}
+
+impl BimiReport {
+    /// Returns `true` if a valid record is published and no finding is an error.
+    pub fn is_valid(&self) -> bool {
+        self.parsed.is_some()
+            && self
+                .findings
+                .iter()
+                .all(|finding| finding.severity != crate::Severity::Error)
+    }
+}
+
+/// Splits `record` into its `name=value` tags, or returns `None` if it does not start with
+/// `v=BIMI1`.
+fn record_tags(record: &str) -> Option<Vec<(&str, &str)>> {
+    let tags: Vec<_> = record
+        .split(';')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| {
+            let (name, value) = tag.split_once('=').unwrap_or((tag, ""));
+            (name.trim(), value.trim())
+        })
+        .collect();
+
+    match tags.first() {
+        Some(&("v", VERSION)) => Some(tags),
+        _ => None,
+    }
+}
+
+/// Returns `true` if `url` is an absolute `https` URL with a valid host name.
+fn is_https_url(url: &str) -> bool {
+    let Some(rest) = url.strip_prefix("https://") else {
+        return false;
+    };
+
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority
+        .rsplit_once(':')
+        .filter(|(_, port)| port.parse::<u16>().is_ok())
+        .map_or(authority, |(host, _)| host);
+
+    !host.is_empty()
+        && host.contains('.')
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+        && !url.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+/// Whether the path of `url` ends with `extension`, ignoring the query and case.
+fn has_extension(url: &str, extension: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+
+    path.to_ascii_lowercase().ends_with(extension)
+}
+
+/// Parses `record`, reporting invalid tags and URLs as findings for `domain`.
+fn parse_record(record: &str, domain: &str, findings: &mut Vec<Finding>) -> Option<BimiRecord> {
+    let tags = record_tags(record)?;
+
+    let mut logo_url = None;
+    let mut authority_url = None;
+
+    for (name, value) in tags.into_iter().skip(1) {
+        let tag = match name {
+            "l" => &mut logo_url,
+            "a" => &mut authority_url,
+            _ => continue,
+        };
+
+        if tag.is_some() {
+            findings.push(Finding::error(
+                "BIMI_SYNTAX_ERROR",
+                domain,
+                format!("The tag `{name}` appears more than once"),
+            ));
+            return None;
+        }
+
+        *tag = Some(value.to_owned());
+    }
+
+    let Some(logo_url) = logo_url else {
+        findings.push(Finding::error(
+            "BIMI_SYNTAX_ERROR",
+            domain,
+            "The record has no `l` tag".to_owned(),
+        ));
+        return None;
+    };
+
+    let parsed = BimiRecord {
+        logo_url,
+        authority_url: authority_url.unwrap_or_default(),
+    };
+
+    if parsed.is_declination() {
+        findings.push(Finding::warning(
+            "BIMI_DECLINED",
+            domain,
+            "The record declines to publish a logo".to_owned(),
+        ));
+        return Some(parsed);
+    }
+
+    if parsed.logo_url.is_empty() {
+        findings.push(Finding::error(
+            "MISSING_LOGO_URL",
+            domain,
+            "The record has an `a` tag, but no logo in its `l` tag".to_owned(),
+        ));
+    } else if !is_https_url(&parsed.logo_url) {
+        findings.push(Finding::error(
+            "INVALID_LOGO_URL",
+            domain,
+            format!("The logo URL {} is not a valid HTTPS URL", parsed.logo_url),
+        ));
+    } else if !has_extension(&parsed.logo_url, ".svg") {
+        findings.push(Finding::warning(
+            "LOGO_NOT_SVG",
+            domain,
+            format!(
+                "The logo URL {} does not point to an SVG file",
+                parsed.logo_url
+            ),
+        ));
+    }
+
+    if parsed.authority_url.is_empty() {
+        findings.push(Finding::warning(
+            "NO_AUTHORITY_EVIDENCE",
+            domain,
+            "The record has no Verified Mark Certificate in its `a` tag, which major mailbox providers require to show the logo".to_owned(),
+        ));
+    } else if !is_https_url(&parsed.authority_url) {
+        findings.push(Finding::error(
+            "INVALID_AUTHORITY_URL",
+            domain,
+            format!(
+                "The authority URL {} is not a valid HTTPS URL",
+                parsed.authority_url
+            ),
+        ));
+    } else if !has_extension(&parsed.authority_url, ".pem") {
+        findings.push(Finding::warning(
+            "AUTHORITY_NOT_PEM",
+            domain,
+            format!(
+                "The authority URL {} does not point to a PEM file",
+                parsed.authority_url
+            ),
+        ));
+    }
+
+    Some(parsed)
+}
+
+/// Looks up, parses and validates the BIMI record of `domain` for `selector`, and checks that
+/// the DMARC policy of `domain` is strict enough for BIMI.
+pub(crate) async fn check_bimi(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    domain: &str,
+    selector: &str,
+) -> Result<BimiReport> {
+    let record_domain = format!("{selector}._bimi.{domain}");
+    let mut records: Vec<String> = resolver
+        .lookup_txt(&record_domain)
+        .await
+        .context(CheckError::DnsLookupFailed)?
+        .into_iter()
+        .filter(|txt| record_tags(txt).is_some())
+        .collect();
+
+    let mut report = BimiReport {
+        record_domain,
+        record: None,
+        parsed: None,
+        findings: Vec::new(),
+    };
+
+    match records.len() {
+        0 => {
+            report.findings.push(Finding::error(
+                "NO_BIMI_RECORD",
+                &report.record_domain,
+                "The domain publishes no BIMI record for this selector".to_owned(),
+            ));
+            return Ok(report);
+        }
+        1 => {}
+        count => {
+            report.findings.push(Finding::error(
+                "MULTIPLE_BIMI_RECORDS",
+                &report.record_domain,
+                format!("The domain publishes {count} BIMI records for this selector"),
+            ));
+            return Ok(report);
+        }
+    }
+
+    let record = records.remove(0);
+    report.parsed = parse_record(&record, &report.record_domain, &mut report.findings);
+    report.record = Some(record);
+
+    // A logo is only shown for mail protected by a DMARC policy of `quarantine` or `reject`
+    // applying to all messages.
+    if report
+        .parsed
+        .as_ref()
+        .is_some_and(|parsed| !parsed.is_declination())
+    {
+        let dmarc = dmarc::check_dmarc(resolver, domain).await?;
+        let is_enforced = dmarc.parsed.is_some_and(|parsed| {
+            matches!(
+                parsed.policy,
+                Some(DmarcPolicy::Quarantine | DmarcPolicy::Reject)
+            ) && parsed.percentage == 100
+        });
+
+        if !is_enforced {
+            report.findings.push(Finding::error(
+                "DMARC_NOT_ENFORCED",
+                &dmarc.record_domain,
+                "BIMI requires a DMARC policy of `quarantine` or `reject` with `pct=100`"
+                    .to_owned(),
+            ));
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockResolver;
+    use crate::Severity;
+    use crate::SpfChecker;
+
+    #[test]
+    fn test_is_https_url() {
+        for url in [
+            "https://example.com/logo.svg",
+            "https://cdn.example.com:8443/brand/logo.svg?v=2",
+        ] {
+            assert!(is_https_url(url), "{url}");
+        }
+
+        for url in [
+            "http://example.com/logo.svg",
+            "https:///logo.svg",
+            "https://localhost/logo.svg",
+            "https://exa mple.com/logo.svg",
+            "https://-example.com/logo.svg",
+            "example.com/logo.svg",
+        ] {
+            assert!(!is_https_url(url), "{url}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_bimi() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_txt(
+            "default._bimi.example.com",
+            "v=BIMI1; l=https://example.com/logo.svg; a=https://example.com/vmc.pem",
+        );
+        mock_resolver.add_txt("_dmarc.example.com", "v=DMARC1; p=reject");
+        mock_resolver.add_txt(
+            "brand._bimi.example.com",
+            "v=BIMI1; l=http://example.com/logo.png",
+        );
+        mock_resolver.add_txt(
+            "default._bimi.example.org",
+            "v=BIMI1; l=https://example.org/logo.svg",
+        );
+        mock_resolver.add_txt("_dmarc.example.org", "v=DMARC1; p=quarantine; pct=50");
+        mock_resolver.add_txt("default._bimi.example.net", "v=BIMI1; l=; a=;");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+
+        let report = checker
+            .check_bimi("example.com", DEFAULT_SELECTOR)
+            .await
+            .unwrap();
+        assert!(report.is_valid());
+        assert!(report.findings.is_empty());
+        assert_eq!(report.record_domain, "default._bimi.example.com");
+        assert_eq!(
+            report.parsed.map(|parsed| parsed.authority_url),
+            Some("https://example.com/vmc.pem".to_owned())
+        );
+
+        for (domain, selector, expected) in [
+            (
+                "example.com",
+                "brand",
+                vec![
+                    (Severity::Error, "INVALID_LOGO_URL"),
+                    (Severity::Warning, "NO_AUTHORITY_EVIDENCE"),
+                ],
+            ),
+            (
+                "example.org",
+                DEFAULT_SELECTOR,
+                vec![
+                    (Severity::Warning, "NO_AUTHORITY_EVIDENCE"),
+                    (Severity::Error, "DMARC_NOT_ENFORCED"),
+                ],
+            ),
+            (
+                "example.net",
+                DEFAULT_SELECTOR,
+                vec![(Severity::Warning, "BIMI_DECLINED")],
+            ),
+            (
+                "example.de",
+                DEFAULT_SELECTOR,
+                vec![(Severity::Error, "NO_BIMI_RECORD")],
+            ),
+        ] {
+            let report = checker.check_bimi(domain, selector).await.unwrap();
+
+            let findings: Vec<_> = report
+                .findings
+                .iter()
+                .map(|finding| (finding.severity, finding.code))
+                .collect();
+
+            assert_eq!(findings, expected, "{selector}._bimi.{domain}");
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use crate::record::{Mechanism, Modifier, SpfRecord, Term};
+use crate::{
+    expand_target, find_spf_record, has_all_mechanism, CheckError, CheckOptions, SpnResolver,
+    DNS_LOOKUP_LIMIT, MAX_TREE_DOMAINS,
+};
+use anyhow::{anyhow, Context, Result};
+
+/// A term counting against the DNS lookup limit, as listed by
+/// [`SpfChecker::lookup_budget`](crate::SpfChecker::lookup_budget).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BudgetTerm {
+    /// The term as written in the record, e.g. `include:_spf.example.com` or `redirect=...`.
+    pub term: String,
+    /// The domain whose record contains the term.
+    pub domain: String,
+    /// The nesting level of the record: 0 for the checked domain, 1 for the records it
+    /// includes or redirects to, and so on.
+    pub depth: usize,
+}
+
+/// How many of the DNS-querying terms allowed by RFC 7208 §4.6.4 the evaluation of a domain's
+/// SPF record consumes when no mechanism matches.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LookupBudget {
+    /// Every counted term, the terms of each record followed by those of the records it
+    /// references. A domain included several times contributes its terms each time, just like
+    /// during the evaluation.
+    pub terms: Vec<BudgetTerm>,
+    /// Include or redirect cycles, each listing the domains from the first to the repeated
+    /// one. The evaluation of a cycle always exceeds the limit.
+    pub loops: Vec<Vec<String>>,
+}
+
+impl LookupBudget {
+    /// The number of DNS-querying terms allowed during an evaluation.
+    pub const LIMIT: usize = DNS_LOOKUP_LIMIT;
+
+    pub fn total(&self) -> usize {
+        self.terms.len()
+    }
+
+    /// Returns `true` if the evaluation exceeds the limit, resulting in `permerror`.
+    pub fn over_limit(&self) -> bool {
+        self.total() > Self::LIMIT || !self.loops.is_empty()
+    }
+
+    /// The number of counted terms per nesting level, starting with the record of the checked
+    /// domain.
+    pub fn per_level(&self) -> Vec<usize> {
+        let mut levels = Vec::new();
+
+        for term in &self.terms {
+            if levels.len() <= term.depth {
+                levels.resize(term.depth + 1, 0);
+            }
+            levels[term.depth] += 1;
+        }
+
+        levels
+    }
+}
+
+/// Counts the DNS-querying terms of the record of `root_domain` and of every record it
+/// references through `include` and `redirect`.
+pub(crate) async fn lookup_budget(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    root_domain: &str,
+    options: &CheckOptions,
+) -> Result<LookupBudget> {
+    let context = &options.macro_context();
+
+    let mut budget = LookupBudget {
+        terms: Vec::new(),
+        loops: Vec::new(),
+    };
+    // Each domain is stacked together with the chain of domains that referenced it.
+    let mut to_visit_stack = vec![(root_domain.to_owned(), Vec::<String>::new())];
+    let mut lookups = 0;
+
+    while let Some((domain, referrers)) = to_visit_stack.pop() {
+        if referrers.contains(&domain) {
+            budget.loops.push([referrers, vec![domain]].concat());
+            continue;
+        }
+
+        lookups += 1;
+
+        if lookups > MAX_TREE_DOMAINS {
+            return Err(anyhow!(
+                "More than {MAX_TREE_DOMAINS} domains in the SPF tree of {root_domain}"
+            ))
+            .context(CheckError::TreeSizeLimitExceeded);
+        }
+
+        let Some(spf_txt) = find_spf_record(resolver, &domain).await? else {
+            continue;
+        };
+
+        let spf = options
+            .parse_record(&spf_txt)
+            .context(CheckError::SpfParseFailed)?;
+        let depth = referrers.len();
+
+        budget.terms.extend(dns_terms(&spf).map(|term| BudgetTerm {
+            term: term.to_string(),
+            domain: domain.clone(),
+            depth,
+        }));
+
+        let chain = [referrers, vec![domain.clone()]].concat();
+
+        // The redirect is evaluated after all includes, so it is pushed first.
+        if !has_all_mechanism(&spf) {
+            let redirect = spf
+                .redirect()
+                .and_then(|redirect| expand_target(redirect, &domain, context));
+
+            to_visit_stack.extend(redirect.map(|redirect| (redirect, chain.clone())));
+        }
+
+        let includes: Vec<_> = spf
+            .directives()
+            .filter_map(|directive| match &directive.mechanism {
+                Mechanism::Include(include) => expand_target(include, &domain, context),
+                _ => None,
+            })
+            .collect();
+
+        to_visit_stack.extend(
+            includes
+                .into_iter()
+                .rev()
+                .map(|include| (include, chain.clone())),
+        );
+    }
+
+    Ok(budget)
+}
+
+/// The terms of `spf` counting against the DNS lookup limit, in record order: `include`, `a`,
+/// `mx`, `ptr` and `exists` mechanisms plus a `redirect` modifier that is not ignored.
+fn dns_terms(spf: &SpfRecord) -> impl Iterator<Item = &Term> {
+    let has_all = has_all_mechanism(spf);
+
+    spf.terms().iter().filter(move |term| match term {
+        Term::Directive(directive) => directive.mechanism.is_dns_lookup(),
+        Term::Modifier(Modifier::Redirect(_)) => !has_all,
+        Term::Modifier(_) => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockResolver;
+    use crate::SpfChecker;
+
+    #[tokio::test]
+    async fn test_lookup_budget() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 include:_spf.a.net ip4:192.0.2.0/24 include:_spf.a.net mx redirect=_spf.r.net",
+        );
+        mock_resolver.add_record("_spf.a.net", "v=spf1 a exists:%{i}.x.net -all");
+        mock_resolver.add_record("_spf.r.net", "v=spf1 include:example.com ~all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+
+        let budget = checker
+            .lookup_budget("example.com", &CheckOptions::default())
+            .await
+            .unwrap();
+
+        let terms: Vec<_> = budget
+            .terms
+            .iter()
+            .map(|term| (term.term.as_str(), term.domain.as_str(), term.depth))
+            .collect();
+
+        assert_eq!(
+            terms,
+            vec![
+                ("include:_spf.a.net", "example.com", 0),
+                ("include:_spf.a.net", "example.com", 0),
+                ("mx", "example.com", 0),
+                ("redirect=_spf.r.net", "example.com", 0),
+                ("a", "_spf.a.net", 1),
+                ("exists:%{i}.x.net", "_spf.a.net", 1),
+                ("a", "_spf.a.net", 1),
+                ("exists:%{i}.x.net", "_spf.a.net", 1),
+                ("include:example.com", "_spf.r.net", 1),
+            ]
+        );
+        assert_eq!(budget.total(), 9);
+        assert_eq!(budget.per_level(), vec![4, 5]);
+        assert_eq!(
+            budget.loops,
+            vec![vec!["example.com", "_spf.r.net", "example.com"]]
+        );
+        assert!(budget.over_limit());
+    }
+}
@@ -0,0 +1,266 @@
+use crate::record::{Directive, Mechanism, ParseError, Qualifier, SpfRecord, Term};
+use crate::{IpNetwork, LookupBudget, RecordSize, Warning};
+
+/// A structured description of an SPF record, turned into one by [`RecordSpec::build`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordSpec {
+    /// Domains whose SPF records are included, e.g. `_spf.google.com`, in the order receivers
+    /// should evaluate them.
+    pub includes: Vec<String>,
+    /// IPv4 addresses or networks, e.g. `192.0.2.0/24`.
+    pub ip4: Vec<String>,
+    /// IPv6 addresses or networks, e.g. `2001:db8::/32`.
+    pub ip6: Vec<String>,
+    /// The qualifier of the final `all` mechanism.
+    pub all: Qualifier,
+}
+
+impl RecordSpec {
+    /// Builds the record: first the `ip4` and then the `ip6` networks, which receivers match
+    /// without any DNS query, then the includes in the given order and finally `all`.
+    ///
+    /// Duplicate includes and networks covered by another network are dropped. Every term is
+    /// validated like [`SpfRecord::parse_strict`] does.
+    pub fn build(&self) -> Result<SpfRecord, ParseError> {
+        let terms = self
+            .ip4
+            .iter()
+            .map(|network| format!("ip4:{}", network.trim()))
+            .chain(
+                self.ip6
+                    .iter()
+                    .map(|network| format!("ip6:{}", network.trim())),
+            )
+            .chain(
+                self.includes
+                    .iter()
+                    .map(|include| format!("include:{}", include.trim())),
+            )
+            .chain([format!("{}all", self.all.symbol())])
+            .collect::<Vec<_>>();
+
+        let parsed = SpfRecord::parse_strict(&format!("v=spf1 {}", terms.join(" ")))?;
+
+        let mut networks: Vec<IpNetwork> = Vec::new();
+        let mut includes: Vec<String> = Vec::new();
+
+        for directive in parsed.directives() {
+            match &directive.mechanism {
+                Mechanism::Ip4(network) | Mechanism::Ip6(network) => networks.push(*network),
+                Mechanism::Include(include)
+                    if !includes
+                        .iter()
+                        .any(|known| known.eq_ignore_ascii_case(include)) =>
+                {
+                    includes.push(include.clone());
+                }
+                _ => {}
+            }
+        }
+
+        networks.sort();
+        networks.dedup();
+        let networks = networks
+            .iter()
+            .filter(|network| {
+                !networks
+                    .iter()
+                    .any(|other| other != *network && other.covers(network))
+            })
+            .map(|network| Mechanism::from_network(*network))
+            .collect::<Vec<_>>();
+
+        let terms = networks
+            .into_iter()
+            .chain(includes.into_iter().map(Mechanism::Include))
+            .map(|mechanism| Directive {
+                qualifier: Qualifier::Pass,
+                mechanism,
+            })
+            .chain([Directive {
+                qualifier: self.all,
+                mechanism: Mechanism::All,
+            }])
+            .map(Term::Directive)
+            .collect();
+
+        Ok(SpfRecord::from_terms(terms))
+    }
+}
+
+/// A record built by [`SpfChecker::build_record`](crate::SpfChecker::build_record).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BuiltRecord {
+    pub record: String,
+    /// The DNS-querying terms of the record and of the records it includes.
+    pub lookup_budget: LookupBudget,
+    /// The size of the record and of the TXT answer once published next to the domain's
+    /// other TXT records.
+    pub record_size: RecordSize,
+    /// `DNS_LOOKUP_LIMIT_EXCEEDED`, `INCLUDE_LOOP`, `RECORD_EXCEEDS_255_BYTES` or
+    /// `ANSWER_EXCEEDS_512_BYTES`.
+    pub warnings: Vec<Warning>,
+}
+
+impl BuiltRecord {
+    pub(crate) fn new(
+        domain: &str,
+        record: String,
+        lookup_budget: LookupBudget,
+        txts: &[String],
+    ) -> Self {
+        let record_size = RecordSize::new(domain, &record, txts);
+        let mut warnings = Vec::new();
+
+        if lookup_budget.total() > LookupBudget::LIMIT {
+            warnings.push(Warning {
+                code: "DNS_LOOKUP_LIMIT_EXCEEDED",
+                domain: domain.to_owned(),
+                message: format!(
+                    "The record and its includes contain {} DNS-querying terms, more than the limit of {}, which makes the SPF result permerror",
+                    lookup_budget.total(),
+                    LookupBudget::LIMIT
+                ),
+            });
+        }
+
+        for cycle in &lookup_budget.loops {
+            warnings.push(Warning {
+                code: "INCLUDE_LOOP",
+                domain: domain.to_owned(),
+                message: format!(
+                    "The includes form a loop, which makes the SPF result permerror: {}",
+                    cycle.join(" -> ")
+                ),
+            });
+        }
+
+        warnings.extend(record_size.warnings());
+
+        Self {
+            record,
+            lookup_budget,
+            record_size,
+            warnings,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record;
+    use crate::test_support::MockResolver;
+    use crate::SpfChecker;
+
+    fn spec(includes: &[&str], ip4: &[&str], ip6: &[&str]) -> RecordSpec {
+        RecordSpec {
+            includes: includes.iter().map(ToString::to_string).collect(),
+            ip4: ip4.iter().map(ToString::to_string).collect(),
+            ip6: ip6.iter().map(ToString::to_string).collect(),
+            all: Qualifier::SoftFail,
+        }
+    }
+
+    #[test]
+    fn test_build() {
+        let record = spec(
+            &[
+                "_spf.google.com",
+                "spf.protection.outlook.com",
+                "_SPF.google.com",
+            ],
+            &[
+                "198.51.100.7",
+                "192.0.2.0/24",
+                "192.0.2.10",
+                "198.51.100.7/32",
+            ],
+            &["2001:db8::/32", "2001:db8:1::1"],
+        )
+        .build()
+        .unwrap();
+
+        assert_eq!(
+            record.to_string(),
+            "v=spf1 ip4:192.0.2.0/24 ip4:198.51.100.7 ip6:2001:db8::/32 include:_spf.google.com include:spf.protection.outlook.com ~all"
+        );
+        assert_eq!(
+            spec(&[], &[], &[]).build().unwrap().to_string(),
+            "v=spf1 ~all"
+        );
+
+        for spec in [
+            spec(&[], &["2001:db8::1"], &[]),
+            spec(&[], &[], &["192.0.2.1"]),
+            spec(&[], &["192.0.2.0/33"], &[]),
+            spec(&["localhost"], &[], &[]),
+            spec(&["a b.example.com"], &[], &[]),
+        ] {
+            assert!(spec.build().is_err(), "{spec:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_record() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_records(
+            "example.com",
+            &["google-site-verification=abc", "v=spf1 -all"],
+        );
+        mock_resolver.add_record(
+            "_spf.provider.net",
+            "v=spf1 include:_spf2.provider.net -all",
+        );
+        mock_resolver.add_record("_spf2.provider.net", "v=spf1 a mx -all");
+
+        let checker = SpfChecker::new(mock_resolver);
+        let spec = RecordSpec {
+            includes: vec!["_spf.provider.net".to_string()],
+            ip4: vec!["192.0.2.0/24".to_string()],
+            ip6: vec![],
+            all: record::Qualifier::Fail,
+        };
+
+        let built = checker.build_record("example.com", &spec).await.unwrap();
+        assert_eq!(
+            built.record,
+            "v=spf1 ip4:192.0.2.0/24 include:_spf.provider.net -all"
+        );
+        assert_eq!(built.lookup_budget.total(), 4);
+        assert_eq!(built.record_size.record_bytes, built.record.len());
+        assert!(built.warnings.is_empty());
+
+        let spec = RecordSpec {
+            includes: (0..11).map(|i| format!("_spf{i}.provider.net")).collect(),
+            ip4: vec!["192.0.2.0/24".to_string()],
+            ip6: (0..10).map(|i| format!("2001:db8:{i}::/48")).collect(),
+            all: record::Qualifier::SoftFail,
+        };
+        let built = checker.build_record("example.com", &spec).await.unwrap();
+        assert_eq!(
+            built
+                .warnings
+                .iter()
+                .map(|warning| warning.code)
+                .collect::<Vec<_>>(),
+            [
+                "DNS_LOOKUP_LIMIT_EXCEEDED",
+                "RECORD_EXCEEDS_255_BYTES",
+                "ANSWER_EXCEEDS_512_BYTES"
+            ]
+        );
+
+        let spec = RecordSpec {
+            includes: vec![],
+            ip4: vec!["2001:db8::1".to_string()],
+            ip6: vec![],
+            all: record::Qualifier::Fail,
+        };
+        let err = checker
+            .build_record("example.com", &spec)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "SPF_PARSE_FAILED");
+    }
+}
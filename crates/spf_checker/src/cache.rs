@@ -0,0 +1,863 @@
+use crate::lru::Lru;
+use crate::{is_spf_record, log_message, MxRecord, SpnResolver, TxtRecord};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How many domains a [`MemoryCache`] holds by default.
+pub const DEFAULT_CACHE_CAPACITY: usize = 10_000;
+/// How long a [`DnsCache`] remembers by default that a domain has no TXT records.
+pub const DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(60);
+
+/// The TXT records of a domain as returned by a [`SpfCache`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachedRecords {
+    /// The records with the TTLs they were looked up with.
+    pub records: Vec<TxtRecord>,
+    /// How long the records are fresh after they were cached.
+    pub ttl: Duration,
+    /// How long ago the records were cached.
+    pub age: Duration,
+}
+
+impl CachedRecords {
+    /// The time until the records are stale, `None` if they already are.
+    fn remaining(&self) -> Option<Duration> {
+        self.ttl
+            .checked_sub(self.age)
+            .filter(|remaining| !remaining.is_zero())
+    }
+}
+
+/// Stores the TXT records of domains for a [`DnsCache`], e.g. in memory or in a store shared
+/// by several instances of a service.
+#[async_trait]
+pub trait SpfCache: Debug {
+    /// Returns the records of `domain`, `None` if they are not cached or no longer kept.
+    async fn get(&self, domain: &str) -> Result<Option<CachedRecords>>;
+
+    /// Caches `records` of `domain`, fresh for `ttl` and kept `stale` longer to be answered
+    /// while they are looked up again.
+    async fn insert(
+        &self,
+        domain: &str,
+        records: &[TxtRecord],
+        ttl: Duration,
+        stale: Duration,
+    ) -> Result<()>;
+
+    /// Removes the records of `domain`, returning whether they were cached.
+    async fn remove(&self, domain: &str) -> Result<bool>;
+
+    /// The number of cached domains, `None` if the store does not count them.
+    fn entries(&self) -> Option<usize> {
+        None
+    }
+
+    /// The approximate number of bytes the cached records take, `None` if the store does not
+    /// tell.
+    fn memory(&self) -> Option<usize> {
+        None
+    }
+
+    /// The number of lookups that missed because the cached records were no longer kept, `None`
+    /// if the store expires them without telling.
+    fn expired(&self) -> Option<u64> {
+        None
+    }
+
+    /// The number of domains removed before their records expired to make room for others,
+    /// `None` if the store evicts them without telling.
+    fn evictions(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// The TXT records of a domain, remembered until their TTL and the stale window run out.
+#[derive(Debug)]
+struct CacheEntry {
+    records: Vec<TxtRecord>,
+    ttl: Duration,
+    cached_at: Instant,
+    expires_at: Instant,
+}
+
+impl CacheEntry {
+    fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at <= now
+    }
+}
+
+/// Keeps the records in memory, shared by the clones of the [`DnsCache`] only. While it is
+/// full, the least recently used domain makes room for a new one.
+#[derive(Debug)]
+pub struct MemoryCache {
+    capacity: usize,
+    entries: Mutex<Lru<String, CacheEntry>>,
+    expired: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl Default for MemoryCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHE_CAPACITY)
+    }
+}
+
+impl MemoryCache {
+    /// Creates a cache holding the records of at most `capacity` domains, none if zero.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::default(),
+            expired: AtomicU64::default(),
+            evictions: AtomicU64::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl SpfCache for MemoryCache {
+    async fn get(&self, domain: &str) -> Result<Option<CachedRecords>> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().expect("mutex poisoned");
+
+        match entries.peek(domain) {
+            Some(entry) if !entry.is_expired(now) => {
+                let cached = CachedRecords {
+                    records: entry.records.clone(),
+                    ttl: entry.ttl,
+                    age: now.saturating_duration_since(entry.cached_at),
+                };
+                entries.get(domain);
+
+                Ok(Some(cached))
+            }
+            Some(_) => {
+                entries.remove(domain);
+                self.expired.fetch_add(1, Ordering::Relaxed);
+
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn insert(
+        &self,
+        domain: &str,
+        records: &[TxtRecord],
+        ttl: Duration,
+        stale: Duration,
+    ) -> Result<()> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut entries = self.entries.lock().expect("mutex poisoned");
+        if entries.remove(domain).is_none() && entries.len() >= self.capacity && entries.evict() {
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let entry = CacheEntry {
+            records: records.to_vec(),
+            ttl,
+            cached_at: now,
+            expires_at: now + ttl + stale,
+        };
+        entries.insert(domain.to_owned(), entry);
+
+        Ok(())
+    }
+
+    async fn remove(&self, domain: &str) -> Result<bool> {
+        let mut entries = self.entries.lock().expect("mutex poisoned");
+
+        Ok(entries.remove(domain).is_some())
+    }
+
+    fn entries(&self) -> Option<usize> {
+        Some(self.entries.lock().expect("mutex poisoned").len())
+    }
+
+    fn memory(&self) -> Option<usize> {
+        let entries = self.entries.lock().expect("mutex poisoned");
+        let memory = entries
+            .iter()
+            .map(|(domain, entry)| {
+                let records: usize = entry
+                    .records
+                    .iter()
+                    .map(|record| size_of::<TxtRecord>() + record.text.capacity())
+                    .sum();
+
+                size_of::<(String, CacheEntry)>()
+                    + size_of::<(u64, String)>()
+                    + 2 * domain.capacity()
+                    + records
+            })
+            .sum();
+
+        Some(memory)
+    }
+
+    fn expired(&self) -> Option<u64> {
+        Some(self.expired.load(Ordering::Relaxed))
+    }
+
+    fn evictions(&self) -> Option<u64> {
+        Some(self.evictions.load(Ordering::Relaxed))
+    }
+}
+
+/// Returns `records` with their TTL lowered to `remaining`, like a caching name server answers.
+fn remaining_ttls(records: &[TxtRecord], remaining: Duration) -> Vec<TxtRecord> {
+    let remaining = u32::try_from(remaining.as_secs()).unwrap_or(u32::MAX);
+
+    records
+        .iter()
+        .map(|record| TxtRecord {
+            text: record.text.clone(),
+            ttl: record.ttl.min(remaining),
+        })
+        .collect()
+}
+
+/// The counters of a [`DnsCache`] since it was created.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DnsCacheStats {
+    /// The domains currently cached, including expired ones not yet removed. `None` if the
+    /// store does not count them, see [`SpfCache::entries`].
+    pub entries: Option<usize>,
+    /// The approximate bytes taken by the cached records, `None` if the store does not tell,
+    /// see [`SpfCache::memory`].
+    pub memory: Option<usize>,
+    /// The lookups answered from the cache, including `negative_hits`.
+    pub hits: u64,
+    /// The lookups answered from the cache with a domain having no TXT records or not existing.
+    pub negative_hits: u64,
+    /// The lookups answered from the cache with stale records while they were looked up again.
+    pub stale_hits: u64,
+    /// The lookups that queried DNS, including those of expired domains.
+    pub misses: u64,
+    /// The lookups that queried DNS because the cached records of the domain had expired.
+    /// `None` if the store does not count them, see [`SpfCache::expired`].
+    pub expired: Option<u64>,
+    /// The domains removed before their records expired to make room for others. `None` if
+    /// the store does not count them, see [`SpfCache::evictions`].
+    pub evictions: Option<u64>,
+    /// The lookups and insertions the store failed, the lookups of which queried DNS instead.
+    pub errors: u64,
+}
+
+impl DnsCacheStats {
+    /// The share of lookups answered from the cache, `None` before the first lookup.
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let lookups = self.hits + self.misses;
+
+        (lookups > 0).then(|| self.hits as f64 / lookups as f64)
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    store: Box<dyn SpfCache + Send + Sync + 'static>,
+    hits: AtomicU64,
+    negative_hits: AtomicU64,
+    stale_hits: AtomicU64,
+    misses: AtomicU64,
+    errors: AtomicU64,
+    /// The domains whose stale records are being looked up again.
+    refreshing: Mutex<HashSet<String>>,
+}
+
+/// Records answered by [`DnsCache::get`].
+pub(crate) enum Cached {
+    /// Records within their TTL, with the TTLs remaining.
+    Fresh(Vec<TxtRecord>),
+    /// Records past their TTL but within the stale window, with TTLs of 0.
+    Stale(Vec<TxtRecord>),
+}
+
+/// Remembers the TXT records of domains, and with them their SPF records, until the TTL of
+/// the records runs out, so that checks across requests share the lookups of common trees such
+/// as `_spf.google.com`. Clones share the same cache.
+///
+/// Domains without TXT records, including those that do not exist, are remembered for
+/// [`DEFAULT_NEGATIVE_TTL`] unless changed by [`DnsCache::with_negative_ttl`], so that broken
+/// includes do not query their name servers on every check. Failed lookups are not cached. The
+/// records are kept in memory unless another store is given to [`DnsCache::with_store`].
+///
+/// With a stale window set by [`DnsCache::with_stale_window`], records past their TTL are still
+/// answered during the window, while they are looked up again in the background.
+#[derive(Clone, Debug)]
+pub struct DnsCache {
+    inner: Arc<Inner>,
+    negative_ttl: Duration,
+    stale_window: Duration,
+}
+
+impl Default for DnsCache {
+    fn default() -> Self {
+        Self::with_store(MemoryCache::default())
+    }
+}
+
+impl DnsCache {
+    /// Creates a cache keeping the records of at most `capacity` domains in memory, see
+    /// [`MemoryCache::new`].
+    pub fn new(capacity: usize) -> Self {
+        Self::with_store(MemoryCache::new(capacity))
+    }
+
+    /// Creates a cache keeping the records in `store`.
+    pub fn with_store<S>(store: S) -> Self
+    where
+        S: SpfCache + Send + Sync + 'static,
+    {
+        Self {
+            inner: Arc::new(Inner {
+                store: Box::new(store),
+                hits: AtomicU64::default(),
+                negative_hits: AtomicU64::default(),
+                stale_hits: AtomicU64::default(),
+                misses: AtomicU64::default(),
+                errors: AtomicU64::default(),
+                refreshing: Mutex::default(),
+            }),
+            negative_ttl: DEFAULT_NEGATIVE_TTL,
+            stale_window: Duration::ZERO,
+        }
+    }
+
+    /// Returns the cache remembering domains without TXT records for `ttl`, not at all if
+    /// zero.
+    pub fn with_negative_ttl(self, ttl: Duration) -> Self {
+        Self {
+            negative_ttl: ttl,
+            ..self
+        }
+    }
+
+    /// Returns the cache answering records for up to `window` past their TTL, while they are
+    /// looked up again in the background, so that popular records never make a check wait for
+    /// their lookup. Not at all if zero, the default.
+    pub fn with_stale_window(self, window: Duration) -> Self {
+        Self {
+            stale_window: window,
+            ..self
+        }
+    }
+
+    /// Returns the counters of the cache.
+    pub fn stats(&self) -> DnsCacheStats {
+        DnsCacheStats {
+            entries: self.inner.store.entries(),
+            memory: self.inner.store.memory(),
+            hits: self.inner.hits.load(Ordering::Relaxed),
+            negative_hits: self.inner.negative_hits.load(Ordering::Relaxed),
+            stale_hits: self.inner.stale_hits.load(Ordering::Relaxed),
+            misses: self.inner.misses.load(Ordering::Relaxed),
+            expired: self.inner.store.expired(),
+            evictions: self.inner.store.evictions(),
+            errors: self.inner.errors.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Removes the records of `domain`, e.g. after it fixed its SPF record, so that the next
+    /// check looks them up again. Returns whether they were cached.
+    pub async fn purge(&self, domain: &str) -> Result<bool> {
+        self.inner.store.remove(&domain.to_ascii_lowercase()).await
+    }
+
+    /// Returns the lowest remaining TTL of the cached records of `domains`, without counting
+    /// the lookups. Domains without cached records are skipped.
+    pub(crate) async fn remaining_ttl(&self, domains: &[String]) -> Option<Duration> {
+        let mut lowest = None;
+
+        for domain in domains {
+            let Ok(Some(cached)) = self.inner.store.get(&domain.to_ascii_lowercase()).await else {
+                continue;
+            };
+
+            let ttl = cached.ttl.saturating_sub(cached.age);
+            lowest = Some(lowest.map_or(ttl, |lowest: Duration| lowest.min(ttl)));
+        }
+
+        lowest
+    }
+
+    /// Returns the cached records of `domain`, treating a failing store like a miss.
+    pub(crate) async fn get(&self, domain: &str) -> Option<Cached> {
+        let cached = match self.inner.store.get(&domain.to_ascii_lowercase()).await {
+            Ok(cached) => cached,
+            Err(err) => {
+                log_message(format!(
+                    "Warning: DNS cache lookup of {domain} failed: {err:#}"
+                ));
+                self.inner.errors.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        };
+
+        let cached = cached.and_then(|cached| match cached.remaining() {
+            Some(remaining) => Some(Cached::Fresh(remaining_ttls(&cached.records, remaining))),
+            None if cached.age < cached.ttl + self.stale_window => Some(Cached::Stale(
+                remaining_ttls(&cached.records, Duration::ZERO),
+            )),
+            None => None,
+        });
+
+        let Some(Cached::Fresh(records) | Cached::Stale(records)) = &cached else {
+            self.inner.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+
+        self.inner.hits.fetch_add(1, Ordering::Relaxed);
+        if records.is_empty() {
+            self.inner.negative_hits.fetch_add(1, Ordering::Relaxed);
+        }
+        if matches!(cached, Some(Cached::Stale(_))) {
+            self.inner.stale_hits.fetch_add(1, Ordering::Relaxed);
+        }
+
+        cached
+    }
+
+    /// Looks up the records of `domain` through `resolver` again and caches them, in the
+    /// background if running on a Tokio runtime. Does nothing while they are already being
+    /// looked up.
+    async fn refresh(&self, resolver: Arc<dyn SpnResolver + Send + Sync + 'static>, domain: &str) {
+        let domain = domain.to_ascii_lowercase();
+        if !self
+            .inner
+            .refreshing
+            .lock()
+            .expect("mutex poisoned")
+            .insert(domain.clone())
+        {
+            return;
+        }
+
+        let cache = self.clone();
+        let refresh = async move {
+            match resolver.lookup_txt_records(&domain).await {
+                Ok(records) => cache.insert(&domain, &records).await,
+                Err(err) => log_message(format!(
+                    "Warning: Failed to refresh the stale records of {domain}: {err:#}"
+                )),
+            }
+
+            cache
+                .inner
+                .refreshing
+                .lock()
+                .expect("mutex poisoned")
+                .remove(&domain);
+        };
+
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn(refresh);
+            }
+            Err(_) => refresh.await,
+        }
+    }
+
+    /// Caches `records` of `domain` for their lowest TTL, or for the negative TTL if there are
+    /// none.
+    async fn insert(&self, domain: &str, records: &[TxtRecord]) {
+        let ttl = match records.iter().map(|record| record.ttl).min() {
+            Some(ttl) => Duration::from_secs(ttl.into()),
+            None => self.negative_ttl,
+        };
+        if ttl.is_zero() {
+            return;
+        }
+
+        if let Err(err) = self
+            .inner
+            .store
+            .insert(
+                &domain.to_ascii_lowercase(),
+                records,
+                ttl,
+                self.stale_window,
+            )
+            .await
+        {
+            log_message(format!(
+                "Warning: DNS cache insertion of {domain} failed: {err:#}"
+            ));
+            self.inner.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Answers the TXT queries of a resolver from a [`DnsCache`], and all other queries from the
+/// resolver itself.
+#[derive(Debug)]
+pub(crate) struct CachingResolver {
+    resolver: Arc<dyn SpnResolver + Send + Sync + 'static>,
+    cache: DnsCache,
+    /// Whether TXT queries skip the cached records and replace them with the answers.
+    refresh: bool,
+}
+
+impl CachingResolver {
+    pub fn new(resolver: Arc<dyn SpnResolver + Send + Sync + 'static>, cache: DnsCache) -> Self {
+        Self {
+            resolver,
+            cache,
+            refresh: false,
+        }
+    }
+
+    /// Returns a resolver that queries TXT records anew and stores them in `cache`.
+    pub fn refreshing(
+        resolver: Arc<dyn SpnResolver + Send + Sync + 'static>,
+        cache: DnsCache,
+    ) -> Self {
+        Self {
+            resolver,
+            cache,
+            refresh: true,
+        }
+    }
+}
+
+#[async_trait]
+impl SpnResolver for CachingResolver {
+    async fn find_spf_records(&self, domain: &str) -> Result<Vec<String>> {
+        let records = self.lookup_txt(domain).await?;
+
+        Ok(records
+            .into_iter()
+            .filter(|txt| is_spf_record(txt))
+            .collect())
+    }
+
+    async fn lookup_txt(&self, domain: &str) -> Result<Vec<String>> {
+        let records = self.lookup_txt_records(domain).await?;
+
+        Ok(records.into_iter().map(|record| record.text).collect())
+    }
+
+    async fn lookup_txt_records(&self, domain: &str) -> Result<Vec<TxtRecord>> {
+        if !self.refresh {
+            match self.cache.get(domain).await {
+                Some(Cached::Fresh(records)) => return Ok(records),
+                Some(Cached::Stale(records)) => {
+                    self.cache.refresh(self.resolver.clone(), domain).await;
+                    return Ok(records);
+                }
+                None => {}
+            }
+        }
+
+        let records = self.resolver.lookup_txt_records(domain).await?;
+        self.cache.insert(domain, &records).await;
+
+        Ok(records)
+    }
+
+    async fn lookup_a(&self, domain: &str) -> Result<Vec<Ipv4Addr>> {
+        self.resolver.lookup_a(domain).await
+    }
+
+    async fn lookup_aaaa(&self, domain: &str) -> Result<Vec<Ipv6Addr>> {
+        self.resolver.lookup_aaaa(domain).await
+    }
+
+    async fn lookup_mx(&self, domain: &str) -> Result<Vec<String>> {
+        self.resolver.lookup_mx(domain).await
+    }
+
+    async fn lookup_mx_records(&self, domain: &str) -> Result<Vec<MxRecord>> {
+        self.resolver.lookup_mx_records(domain).await
+    }
+
+    async fn lookup_ptr(&self, ip: IpAddr) -> Result<Vec<String>> {
+        self.resolver.lookup_ptr(ip).await
+    }
+
+    async fn lookup_spf_rr(&self, domain: &str) -> Result<Vec<String>> {
+        self.resolver.lookup_spf_rr(domain).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockResolver;
+    use crate::SpfChecker;
+    use anyhow::anyhow;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn test_dns_cache_shares_records_between_checkers() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 include:_spf.a.net include:_spf.b.net -all",
+        );
+        mock_resolver.add_record("_spf.a.net", "v=spf1 ip4:192.0.2.0/24 -all");
+        mock_resolver.add_record("_spf.b.net", "v=spf1 ip4:198.51.100.0/24 -all");
+        mock_resolver.set_ttl("example.com", 3600);
+        mock_resolver.set_ttl("_spf.b.net", 0);
+
+        let cache = DnsCache::default().with_negative_ttl(std::time::Duration::ZERO);
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker
+            .with_dns_cache(cache.clone())
+            .check("example.com", "_spf.c.net")
+            .await
+            .unwrap();
+
+        assert!(!result.found);
+        let lookups = mock_resolver.txt_lookups.load(Ordering::Relaxed);
+
+        let result = checker
+            .with_dns_cache(cache.clone())
+            .check("example.com", "_spf.c.net")
+            .await
+            .unwrap();
+
+        assert!(!result.found);
+        assert!(result.ttls["example.com"] <= 3600);
+        // Only _spf.b.net, whose TTL of 0 forbids caching, and _spf.c.net without records,
+        // which are not remembered, are queried again
+        assert_eq!(
+            mock_resolver.txt_lookups.load(Ordering::Relaxed),
+            lookups + 2
+        );
+
+        let stats = cache.stats();
+        assert_eq!(stats.entries, Some(2));
+        assert_eq!(stats.misses, 6);
+        // The records of example.com are also looked up for its default policy
+        assert_eq!(stats.hits, 4);
+        assert_eq!(stats.expired, Some(0));
+        assert_eq!(stats.errors, 0);
+        assert_eq!(stats.hit_ratio(), Some(0.4));
+        assert!(stats.memory.unwrap() > 0);
+
+        assert!(cache.purge("Example.com").await.unwrap());
+        assert!(!cache.purge("example.com").await.unwrap());
+        assert_eq!(cache.stats().entries, Some(1));
+    }
+
+    #[derive(Debug)]
+    struct UnreachableCache;
+
+    #[async_trait]
+    impl SpfCache for UnreachableCache {
+        async fn get(&self, _domain: &str) -> Result<Option<CachedRecords>> {
+            Err(anyhow!("connection refused"))
+        }
+
+        async fn insert(
+            &self,
+            _domain: &str,
+            _records: &[TxtRecord],
+            _ttl: Duration,
+            _stale: Duration,
+        ) -> Result<()> {
+            Err(anyhow!("connection refused"))
+        }
+
+        async fn remove(&self, _domain: &str) -> Result<bool> {
+            Err(anyhow!("connection refused"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dns_cache_falls_back_to_dns_if_the_store_fails() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("example.com", "v=spf1 include:_spf.a.net -all");
+        mock_resolver.add_record("_spf.a.net", "v=spf1 ip4:192.0.2.0/24 -all");
+
+        let cache = DnsCache::with_store(UnreachableCache);
+        let checker = SpfChecker::new(mock_resolver.clone()).with_dns_cache(cache.clone());
+        let result = checker.check("example.com", "_spf.b.net").await.unwrap();
+
+        assert!(!result.found);
+        let stats = cache.stats();
+        assert_eq!(stats.entries, None);
+        assert_eq!(stats.hits, 0);
+        assert_eq!(
+            stats.misses,
+            mock_resolver.txt_lookups.load(Ordering::Relaxed) as u64
+        );
+        assert!(stats.errors > stats.misses);
+    }
+
+    #[tokio::test]
+    async fn test_dns_cache_remembers_domains_without_records() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("example.com", "v=spf1 include:_spf.broken.net -all");
+
+        let cache = DnsCache::default();
+        let checker = SpfChecker::new(mock_resolver.clone()).with_dns_cache(cache.clone());
+        for _ in 0..2 {
+            let result = checker.check("example.com", "_spf.a.net").await.unwrap();
+            assert!(!result.found);
+        }
+
+        // example.com, the include without records and the target of the fallback, once each
+        assert_eq!(mock_resolver.txt_lookups.load(Ordering::Relaxed), 3);
+        let stats = cache.stats();
+        assert_eq!(stats.entries, Some(3));
+        assert_eq!(stats.negative_hits, 2);
+
+        let uncached = DnsCache::default().with_negative_ttl(std::time::Duration::ZERO);
+        let checker = SpfChecker::new(mock_resolver.clone()).with_dns_cache(uncached.clone());
+        checker.check("example.com", "_spf.a.net").await.unwrap();
+
+        assert_eq!(mock_resolver.txt_lookups.load(Ordering::Relaxed), 6);
+        assert_eq!(uncached.stats().entries, Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_dns_cache_replaces_the_cached_records() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("example.com", "v=spf1 include:_spf.a.net -all");
+        mock_resolver.add_record("_spf.a.net", "v=spf1 ip4:192.0.2.0/24 -all");
+        mock_resolver.set_ttl("example.com", 3600);
+        mock_resolver.set_ttl("_spf.a.net", 120);
+
+        let cache = DnsCache::default();
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let ttl = checker
+            .refresh_dns_cache(&cache, "example.com")
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(ttl <= Duration::from_secs(120) && ttl >= Duration::from_secs(119));
+        assert_eq!(cache.stats().entries, Some(2));
+        assert_eq!(cache.stats().hits + cache.stats().misses, 0);
+
+        mock_resolver.add_record("_spf.a.net", "v=spf1 ip4:198.51.100.0/24 -all");
+        let cached = checker.with_dns_cache(cache.clone());
+        let result = cached.check("example.com", "198.51.100.1").await.unwrap();
+        assert!(!result.found);
+
+        checker
+            .refresh_dns_cache(&cache, "example.com")
+            .await
+            .unwrap();
+        let result = cached.check("example.com", "198.51.100.1").await.unwrap();
+        assert!(result.found);
+    }
+
+    /// Answers every record as if its TTL had just run out.
+    #[derive(Debug, Default)]
+    struct StaleCache(Mutex<HashMap<String, CachedRecords>>);
+
+    #[async_trait]
+    impl SpfCache for StaleCache {
+        async fn get(&self, domain: &str) -> Result<Option<CachedRecords>> {
+            Ok(self.0.lock().unwrap().get(domain).cloned())
+        }
+
+        async fn insert(
+            &self,
+            domain: &str,
+            records: &[TxtRecord],
+            ttl: Duration,
+            _stale: Duration,
+        ) -> Result<()> {
+            let cached = CachedRecords {
+                records: records.to_vec(),
+                ttl,
+                age: ttl,
+            };
+            self.0.lock().unwrap().insert(domain.to_string(), cached);
+            Ok(())
+        }
+
+        async fn remove(&self, domain: &str) -> Result<bool> {
+            Ok(self.0.lock().unwrap().remove(domain).is_some())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dns_cache_answers_stale_records_while_refreshing_them() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("example.com", "v=spf1 include:_spf.a.net -all");
+        mock_resolver.add_record("_spf.a.net", "v=spf1 ip4:192.0.2.0/24 -all");
+
+        let cache =
+            DnsCache::with_store(StaleCache::default()).with_stale_window(Duration::from_secs(60));
+        let checker = SpfChecker::new(mock_resolver.clone()).with_dns_cache(cache.clone());
+        checker.check("example.com", "198.51.100.1").await.unwrap();
+        let lookups = mock_resolver.txt_lookups.load(Ordering::Relaxed);
+        let stats = cache.stats();
+
+        mock_resolver.add_record("_spf.a.net", "v=spf1 ip4:198.51.100.0/24 -all");
+        let result = checker.check("example.com", "198.51.100.1").await.unwrap();
+
+        assert!(!result.found);
+        assert!(cache.stats().stale_hits > stats.stale_hits);
+        assert_eq!(cache.stats().misses, stats.misses);
+
+        while mock_resolver.txt_lookups.load(Ordering::Relaxed) < lookups * 2 {
+            tokio::task::yield_now().await;
+        }
+        tokio::task::yield_now().await;
+
+        let result = checker.check("example.com", "198.51.100.1").await.unwrap();
+        assert!(result.found);
+        assert_eq!(cache.stats().misses, stats.misses);
+    }
+
+    #[tokio::test]
+    async fn test_memory_cache_evicts_the_least_recently_used_domain() {
+        let cache = MemoryCache::new(2);
+        let records = [TxtRecord {
+            text: "v=spf1 -all".to_string(),
+            ttl: 300,
+        }];
+        let ttl = Duration::from_secs(300);
+
+        cache
+            .insert("a.com", &records, ttl, Duration::ZERO)
+            .await
+            .unwrap();
+        cache
+            .insert("b.com", &records, ttl, Duration::ZERO)
+            .await
+            .unwrap();
+        assert!(cache.get("a.com").await.unwrap().is_some());
+        cache
+            .insert("c.com", &records, ttl, Duration::ZERO)
+            .await
+            .unwrap();
+
+        assert!(cache.get("a.com").await.unwrap().is_some());
+        assert!(cache.get("b.com").await.unwrap().is_none());
+        assert!(cache.get("c.com").await.unwrap().is_some());
+        assert_eq!(cache.entries(), Some(2));
+        assert_eq!(cache.evictions(), Some(1));
+
+        cache
+            .insert("c.com", &records, ttl, Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(cache.evictions(), Some(1));
+
+        let disabled = MemoryCache::new(0);
+        disabled
+            .insert("a.com", &records, ttl, Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(disabled.entries(), Some(0));
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{CheckError, CheckOptions, CheckResult, MultipleSpfRecords};
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+/// The normalized domain and target of a check with its options.
+type Key = (String, String, CheckOptions);
+/// The result of a check, set once it completes.
+type Outcome = watch::Receiver<Option<Arc<Result<CheckResult>>>>;
+
+/// The checks currently running, so that identical checks started meanwhile wait for their
+/// result instead of traversing the same tree again.
+#[derive(Default)]
+pub(crate) struct InFlight {
+    checks: Mutex<HashMap<Key, Outcome>>,
+    coalesced: AtomicU64,
+}
+
+impl fmt::Debug for InFlight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InFlight")
+            .field("coalesced", &self.coalesced)
+            .finish_non_exhaustive()
+    }
+}
+
+impl InFlight {
+    /// The number of checks answered with the result of an identical one.
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+
+    /// Runs `check` for `key`, or waits for the result of the identical check already running.
+    /// If that check is cancelled, e.g. because its request was dropped, `check` runs instead.
+    pub async fn run(
+        &self,
+        key: Key,
+        check: impl Future<Output = Result<CheckResult>>,
+    ) -> Result<CheckResult> {
+        let running = {
+            let mut checks = self.checks.lock().expect("mutex poisoned");
+            match checks.get(&key) {
+                Some(outcome) => Err(outcome.clone()),
+                None => {
+                    let (sender, outcome) = watch::channel(None);
+                    checks.insert(key.clone(), outcome);
+                    Ok(sender)
+                }
+            }
+        };
+
+        let mut outcome = match running {
+            Ok(sender) => {
+                let _running = Running {
+                    in_flight: self,
+                    key: &key,
+                };
+                let result = check.await;
+                sender.send_replace(Some(Arc::new(copy(&result))));
+
+                return result;
+            }
+            Err(outcome) => outcome,
+        };
+
+        let Ok(result) = outcome.wait_for(Option::is_some).await else {
+            return check.await;
+        };
+        self.coalesced.fetch_add(1, Ordering::Relaxed);
+
+        copy(result.as_ref().expect("waited for the result"))
+    }
+}
+
+/// Removes a check from the running ones when it completes or is cancelled, so that later
+/// checks run anew.
+struct Running<'a> {
+    in_flight: &'a InFlight,
+    key: &'a Key,
+}
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.in_flight
+            .checks
+            .lock()
+            .expect("mutex poisoned")
+            .remove(self.key);
+    }
+}
+
+/// A copy of `result` for a waiting check. Errors, which cannot be cloned, keep their
+/// [`CheckError`], the conflicting records of [`MultipleSpfRecords`] and their cause.
+fn copy(result: &Result<CheckResult>) -> Result<CheckResult> {
+    let err = match result {
+        Ok(result) => return Ok(result.clone()),
+        Err(err) => err,
+    };
+
+    if let Some(multiple) = err.downcast_ref::<MultipleSpfRecords>() {
+        return Err(multiple.clone().into());
+    }
+
+    match (CheckError::of(err), err.chain().nth(1)) {
+        (Some(error), Some(cause)) => Err(anyhow!("{cause}")).context(error),
+        (Some(error), None) => Err(error.into()),
+        (None, _) => Err(anyhow!("{err:#}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockResolver;
+    use crate::SpfChecker;
+
+    #[tokio::test]
+    async fn test_coalescing_runs_concurrent_identical_checks_once() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("example.com", "v=spf1 include:_spf.a.net -all");
+        mock_resolver.add_record("_spf.a.net", "v=spf1 ip4:192.0.2.0/24 -all");
+        mock_resolver.yielding.store(true, Ordering::Relaxed);
+
+        let checker = SpfChecker::new(mock_resolver.clone()).with_coalescing();
+        let (first, second, other) = tokio::join!(
+            checker.check("example.com", "_spf.a.net"),
+            checker.check("Example.COM.", "_spf.a.net"),
+            checker.check("example.com", "_spf.b.net"),
+        );
+
+        assert!(first.unwrap().found);
+        assert!(second.unwrap().found);
+        assert!(!other.unwrap().found);
+        assert_eq!(checker.coalesced_checks(), 1);
+
+        let lookups = mock_resolver.txt_lookups.load(Ordering::Relaxed);
+        checker.check("example.com", "_spf.a.net").await.unwrap();
+        assert!(mock_resolver.txt_lookups.load(Ordering::Relaxed) > lookups);
+
+        mock_resolver.add_records("example.com", &["v=spf1 -all", "v=spf1 ~all"]);
+        let (first, second) = tokio::join!(
+            checker.check("example.com", "_spf.a.net"),
+            checker.check("example.com", "_spf.a.net"),
+        );
+        let (first, second) = (first.err().unwrap(), second.err().unwrap());
+        assert_eq!(CheckError::of(&second), CheckError::of(&first));
+        assert_eq!(
+            second.downcast_ref::<MultipleSpfRecords>(),
+            first.downcast_ref::<MultipleSpfRecords>()
+        );
+        assert_eq!(checker.coalesced_checks(), 2);
+    }
+}
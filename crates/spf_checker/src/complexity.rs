@@ -0,0 +1,116 @@
+use crate::record::Mechanism;
+use crate::{dns_term_count, parsed, SpfTreeNode};
+use std::collections::{BTreeMap, HashSet};
+
+/// Size metrics of an SPF tree, as returned by [`SpfTreeNode::complexity`], to follow records
+/// drifting towards the limits over time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Complexity {
+    /// Number of mechanisms of the records, counted each time a domain is referenced.
+    pub mechanisms: usize,
+    /// The deepest nesting level of a record: 0 if the root domain references none.
+    pub depth: usize,
+    /// Number of DNS-querying terms of the records, counted like during the evaluation, which
+    /// RFC 7208 §4.6.4 limits to 10.
+    pub dns_lookups: usize,
+    /// Number of distinct `ip4` and `ip6` mechanisms, i.e. the size of the flattened record.
+    pub ip_networks: usize,
+    /// Total size in bytes of the records of the distinct domains.
+    pub record_bytes: usize,
+}
+
+impl SpfTreeNode {
+    /// Measures the records of the tree. Loops are not followed again.
+    pub fn complexity(&self) -> Complexity {
+        let mut complexity = Complexity {
+            mechanisms: 0,
+            depth: 0,
+            dns_lookups: 0,
+            ip_networks: 0,
+            record_bytes: 0,
+        };
+        let mut networks = HashSet::new();
+        let mut records = BTreeMap::new();
+        let mut nodes = vec![(self, 0)];
+
+        while let Some((node, depth)) = nodes.pop() {
+            if node.is_loop {
+                continue;
+            }
+
+            // The tree only contains records that parse, leniently at least.
+            let Some((record, spf)) = node
+                .record
+                .as_deref()
+                .and_then(|record| parsed::parse(record, false).ok().map(|spf| (record, spf)))
+            else {
+                continue;
+            };
+
+            records.insert(node.domain.as_str(), record.len());
+
+            complexity.depth = complexity.depth.max(depth);
+            complexity.dns_lookups += dns_term_count(&spf);
+
+            for directive in spf.directives() {
+                complexity.mechanisms += 1;
+
+                if let Mechanism::Ip4(network) | Mechanism::Ip6(network) = &directive.mechanism {
+                    networks.insert(*network);
+                }
+            }
+
+            nodes.extend(node.children.iter().map(|child| (child, depth + 1)));
+        }
+
+        complexity.ip_networks = networks.len();
+        complexity.record_bytes = records.values().sum();
+
+        complexity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(domain: &str, record: &str, children: Vec<SpfTreeNode>) -> SpfTreeNode {
+        SpfTreeNode {
+            domain: domain.to_string(),
+            term: None,
+            record: Some(record.to_string()),
+            is_loop: false,
+            error: None,
+            children,
+        }
+    }
+
+    #[test]
+    fn test_complexity() {
+        let shared = || node("_spf.shared.net", "v=spf1 ip4:192.0.2.0/24 -all", vec![]);
+
+        let tree = node(
+            "example.com",
+            "v=spf1 include:_spf.a.net include:_spf.shared.net mx -all",
+            vec![
+                node(
+                    "_spf.a.net",
+                    "v=spf1 include:_spf.shared.net ip6:2001:db8::/32 ~all",
+                    vec![shared()],
+                ),
+                shared(),
+            ],
+        );
+
+        assert_eq!(
+            tree.complexity(),
+            Complexity {
+                mechanisms: 11,
+                depth: 2,
+                dns_lookups: 4,
+                ip_networks: 2,
+                record_bytes: 57 + 53 + 28,
+            }
+        );
+    }
+}
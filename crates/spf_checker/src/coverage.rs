@@ -0,0 +1,195 @@
+use crate::diff::mechanism_networks;
+use crate::record::{Mechanism, Qualifier};
+use crate::{CheckError, CheckOptions, IpNetwork, SpfTreeNode, SpnResolver};
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// The outcome of [`SpfChecker::coverage`](crate::SpfChecker::coverage).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Coverage {
+    /// Whether the first domain authorizes every address the second domain authorizes.
+    pub covers: bool,
+    /// The addresses authorized by the second domain but not by the first, sorted. Networks
+    /// the first domain authorizes only in part are split into the parts it does not, e.g.
+    /// `192.0.2.128/25` of `192.0.2.0/24` if the first domain authorizes `192.0.2.0/25`.
+    pub uncovered_networks: Vec<IpNetwork>,
+    /// The `pass` mechanisms of either tree whose addresses cannot be enumerated, e.g. `ptr`,
+    /// `exists:%{i}._spf.example.com` or `a` with unexpandable macros, sorted. The comparison
+    /// ignores them.
+    pub skipped_mechanisms: Vec<String>,
+}
+
+/// The networks an SPF tree authorizes and the mechanisms whose networks are unknown.
+#[derive(Default)]
+struct AuthorizedNetworks {
+    networks: BTreeSet<IpNetwork>,
+    skipped: BTreeSet<String>,
+}
+
+/// Computes whether `a` authorizes every address `b` authorizes.
+pub(crate) async fn compare_coverage(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    a: &SpfTreeNode,
+    b: &SpfTreeNode,
+    options: &CheckOptions,
+) -> Result<Coverage> {
+    let a = authorized_networks(resolver, a, options).await?;
+    let b = authorized_networks(resolver, b, options).await?;
+
+    let uncovered_networks = uncovered(&b.networks, &a.networks);
+
+    Ok(Coverage {
+        covers: uncovered_networks.is_empty(),
+        uncovered_networks,
+        skipped_mechanisms: a.skipped.union(&b.skipped).cloned().collect(),
+    })
+}
+
+/// Collects the networks of the `pass` mechanisms of `tree`, following only includes that
+/// pass as well, since a matching include results in its own qualifier.
+///
+/// Mechanisms are not evaluated in order, so a `pass` network shadowed by an earlier `fail`
+/// mechanism still counts as authorized.
+async fn authorized_networks(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    tree: &SpfTreeNode,
+    options: &CheckOptions,
+) -> Result<AuthorizedNetworks> {
+    let context = &options.macro_context();
+    let mut authorized = AuthorizedNetworks::default();
+    let mut nodes = vec![tree];
+
+    while let Some(node) = nodes.pop() {
+        let Some(record) = &node.record else {
+            continue;
+        };
+        let spf = options
+            .parse_record(record)
+            .context(CheckError::SpfParseFailed)?;
+
+        nodes.extend(node.children.iter().filter(|child| {
+            // Redirects and `+include` terms, as written in the parent's record.
+            !child
+                .term
+                .as_deref()
+                .is_some_and(|term| term.starts_with(['-', '~', '?']))
+        }));
+
+        for directive in spf.directives() {
+            if directive.qualifier != Qualifier::Pass {
+                continue;
+            }
+
+            match &directive.mechanism {
+                Mechanism::All => {
+                    authorized.networks.extend([
+                        IpNetwork::new(Ipv4Addr::UNSPECIFIED.into(), 0).unwrap(),
+                        IpNetwork::new(Ipv6Addr::UNSPECIFIED.into(), 0).unwrap(),
+                    ]);
+                }
+                Mechanism::Include(_) => {}
+                mechanism => {
+                    match mechanism_networks(resolver, mechanism, &node.domain, context).await? {
+                        Some(networks) => authorized.networks.extend(networks),
+                        None => {
+                            authorized.skipped.insert(directive.to_string());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(authorized)
+}
+
+/// The parts of `networks` not covered by `covering`, without parts covered by other parts.
+fn uncovered(networks: &BTreeSet<IpNetwork>, covering: &BTreeSet<IpNetwork>) -> Vec<IpNetwork> {
+    let mut parts = BTreeSet::new();
+    let mut pending: Vec<_> = networks.iter().copied().collect();
+
+    while let Some(network) = pending.pop() {
+        if covering.iter().any(|other| other.covers(&network)) {
+            continue;
+        }
+
+        // Only the halves not overlapping any covering network are left uncovered as a whole.
+        match network.split() {
+            Some((low, high)) if covering.iter().any(|other| network.covers(other)) => {
+                pending.extend([low, high]);
+            }
+            _ => {
+                parts.insert(network);
+            }
+        }
+    }
+
+    parts
+        .iter()
+        .filter(|part| {
+            !parts
+                .iter()
+                .any(|other| other != *part && other.covers(part))
+        })
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockResolver;
+    use crate::SpfChecker;
+
+    #[tokio::test]
+    async fn test_coverage() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "a.example.com",
+            "v=spf1 include:_spf.p.net ~include:_spf.soft.net ip4:192.0.2.0/25 exists:%{i}._spf.a.example.com -all",
+        );
+        mock_resolver.add_record(
+            "_spf.p.net",
+            "v=spf1 ip4:198.51.100.0/24 -ip4:203.0.113.0/24 -all",
+        );
+        mock_resolver.add_record("_spf.soft.net", "v=spf1 ip4:203.0.113.0/24 -all");
+        mock_resolver.add_record(
+            "b.example.com",
+            "v=spf1 ip4:192.0.2.0/24 a:mail.b.example.com ip4:203.0.113.5 -all",
+        );
+        mock_resolver.add_addresses("mail.b.example.com", &["198.51.100.7"]);
+        mock_resolver.add_record(
+            "c.example.com",
+            "v=spf1 ip4:192.0.2.0/24 ip4:198.51.100.0/24 ip4:203.0.113.0/24 -all",
+        );
+
+        let checker = SpfChecker::new(mock_resolver);
+
+        // The networks of the `~include` and of `-ip4` do not authorize `203.0.113.5`.
+        let coverage = checker
+            .coverage("a.example.com", "b.example.com", &CheckOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            coverage,
+            Coverage {
+                covers: false,
+                uncovered_networks: vec![
+                    "192.0.2.128/25".parse().unwrap(),
+                    "203.0.113.5".parse().unwrap(),
+                ],
+                skipped_mechanisms: vec!["exists:%{i}._spf.a.example.com".to_string()],
+            }
+        );
+
+        let coverage = checker
+            .coverage("c.example.com", "b.example.com", &CheckOptions::default())
+            .await
+            .unwrap();
+
+        assert!(coverage.covers);
+        assert!(coverage.uncovered_networks.is_empty());
+    }
+}
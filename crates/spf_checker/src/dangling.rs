@@ -0,0 +1,114 @@
+use crate::{CheckError, SpfTreeNode, SpnResolver};
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+
+/// An include or redirect of an SPF tree whose domain publishes no SPF record, which receivers
+/// evaluate as `permerror` (RFC 7208 §5.2 and §6.1).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DanglingTerm {
+    /// The term referencing the domain, e.g. `include:_spf.old-provider.net`.
+    pub term: String,
+    /// The domain referenced by `term`.
+    pub domain: String,
+    /// The domain whose record contains `term`.
+    pub parent_domain: String,
+    /// The record containing `term`.
+    pub parent_record: String,
+    /// `NO_RECORDS` if the domain has no TXT records at all, usually because it does not
+    /// exist, or `NO_SPF_RECORD` if none of its TXT records is an SPF record.
+    pub reason: &'static str,
+}
+
+/// Returns the includes and redirects of `tree` whose domains publish no SPF record, level by
+/// level and in record order.
+pub(crate) async fn find_dangling_terms(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    tree: &SpfTreeNode,
+) -> Result<Vec<DanglingTerm>> {
+    let mut dangling = Vec::new();
+    let mut parents = VecDeque::from([tree]);
+
+    while let Some(parent) = parents.pop_front() {
+        let Some(parent_record) = &parent.record else {
+            continue;
+        };
+
+        for child in &parent.children {
+            if child.is_loop {
+                continue;
+            }
+
+            if child.record.is_some() {
+                parents.push_back(child);
+                continue;
+            }
+
+            let txts = resolver
+                .lookup_txt(&child.domain)
+                .await
+                .context(CheckError::DnsLookupFailed)?;
+
+            dangling.push(DanglingTerm {
+                term: child.term.clone().unwrap_or_default(),
+                domain: child.domain.clone(),
+                parent_domain: parent.domain.clone(),
+                parent_record: parent_record.clone(),
+                reason: if txts.is_empty() {
+                    "NO_RECORDS"
+                } else {
+                    "NO_SPF_RECORD"
+                },
+            });
+        }
+    }
+
+    Ok(dangling)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockResolver;
+    use crate::CheckOptions;
+    use crate::SpfChecker;
+
+    #[tokio::test]
+    async fn test_dangling_terms() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 include:_spf.gone.net include:_spf.a.net redirect=_spf.example.net",
+        );
+        mock_resolver.add_record("_spf.a.net", "v=spf1 include:_spf.moved.net -all");
+        mock_resolver.add_txt("_spf.moved.net", "google-site-verification=abc");
+        mock_resolver.add_record("_spf.example.net", "v=spf1 -all");
+
+        let checker = SpfChecker::new(mock_resolver);
+        let dangling = checker
+            .dangling_terms("example.com", &CheckOptions::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            dangling,
+            vec![
+                DanglingTerm {
+                    term: "include:_spf.gone.net".to_string(),
+                    domain: "_spf.gone.net".to_string(),
+                    parent_domain: "example.com".to_string(),
+                    parent_record:
+                        "v=spf1 include:_spf.gone.net include:_spf.a.net redirect=_spf.example.net"
+                            .to_string(),
+                    reason: "NO_RECORDS",
+                },
+                DanglingTerm {
+                    term: "include:_spf.moved.net".to_string(),
+                    domain: "_spf.moved.net".to_string(),
+                    parent_domain: "_spf.a.net".to_string(),
+                    parent_record: "v=spf1 include:_spf.moved.net -all".to_string(),
+                    reason: "NO_SPF_RECORD",
+                },
+            ]
+        );
+    }
+}
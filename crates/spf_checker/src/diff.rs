@@ -0,0 +1,225 @@
+use crate::macros::MacroContext;
+use crate::mx::resolve_host;
+use crate::record::Mechanism;
+use crate::{expand_target, CheckError, CheckOptions, IpNetwork, SpfTreeNode, SpnResolver};
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+
+/// The outcome of [`SpfChecker::diff`](crate::SpfChecker::diff).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpfDiff {
+    /// The mechanisms of the records in the tree of the first domain only, e.g.
+    /// `~include:_spf.old-provider.net`, sorted.
+    pub mechanisms_only_in_a: Vec<String>,
+    /// The mechanisms of the records in the tree of the second domain only, sorted.
+    pub mechanisms_only_in_b: Vec<String>,
+    /// The mechanisms found in both trees, sorted.
+    pub common_mechanisms: Vec<String>,
+    /// The networks authorized by the first domain that are not covered by any network of the
+    /// second, sorted.
+    pub networks_only_in_a: Vec<IpNetwork>,
+    /// The networks authorized by the second domain that are not covered by any network of the
+    /// first, sorted.
+    pub networks_only_in_b: Vec<IpNetwork>,
+    /// The networks authorized by the first domain that are covered by a network of the
+    /// second, sorted.
+    pub common_networks: Vec<IpNetwork>,
+}
+
+/// The mechanisms and networks of an SPF tree.
+#[derive(Default)]
+struct TreeTerms {
+    mechanisms: BTreeSet<String>,
+    networks: BTreeSet<IpNetwork>,
+}
+
+/// Compares the trees `a` and `b`, resolving the networks of their `a` and `mx` mechanisms.
+pub(crate) async fn diff_trees(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    a: &SpfTreeNode,
+    b: &SpfTreeNode,
+    options: &CheckOptions,
+) -> Result<SpfDiff> {
+    let a = tree_terms(resolver, a, options).await?;
+    let b = tree_terms(resolver, b, options).await?;
+
+    let covered_by = |network: &IpNetwork, networks: &BTreeSet<IpNetwork>| {
+        networks.iter().any(|other| other.covers(network))
+    };
+
+    let (common_networks, networks_only_in_a) = a
+        .networks
+        .iter()
+        .copied()
+        .partition(|network| covered_by(network, &b.networks));
+
+    Ok(SpfDiff {
+        mechanisms_only_in_a: a.mechanisms.difference(&b.mechanisms).cloned().collect(),
+        mechanisms_only_in_b: b.mechanisms.difference(&a.mechanisms).cloned().collect(),
+        common_mechanisms: a.mechanisms.intersection(&b.mechanisms).cloned().collect(),
+        networks_only_in_a,
+        networks_only_in_b: b
+            .networks
+            .iter()
+            .filter(|network| !covered_by(network, &a.networks))
+            .copied()
+            .collect(),
+        common_networks,
+    })
+}
+
+/// Collects the mechanisms of all records of `tree` and the networks they authorize.
+///
+/// `a` and `mx` mechanisms are resolved as by [`mechanism_networks`]; those that cannot be,
+/// and `ptr` and `exists` mechanisms, contribute no networks.
+async fn tree_terms(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    tree: &SpfTreeNode,
+    options: &CheckOptions,
+) -> Result<TreeTerms> {
+    let context = &options.macro_context();
+    let mut terms = TreeTerms::default();
+    let mut nodes = vec![tree];
+
+    while let Some(node) = nodes.pop() {
+        nodes.extend(&node.children);
+
+        let Some(record) = &node.record else {
+            continue;
+        };
+        let spf = options
+            .parse_record(record)
+            .context(CheckError::SpfParseFailed)?;
+
+        for directive in spf.directives() {
+            terms.mechanisms.insert(directive.to_string());
+
+            if let Some(networks) =
+                mechanism_networks(resolver, &directive.mechanism, &node.domain, context).await?
+            {
+                terms.networks.extend(networks);
+            }
+        }
+    }
+
+    Ok(terms)
+}
+
+/// The networks matched by an `ip4`, `ip6`, `a` or `mx` mechanism of the record of `domain`.
+///
+/// `a` and `mx` mechanisms are resolved to the networks around the addresses of their hosts.
+/// Returns `None` for hosts whose macros cannot be expanded with the values of `context`, and
+/// for other mechanisms, which match no fixed networks.
+pub(crate) async fn mechanism_networks(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    mechanism: &Mechanism,
+    domain: &str,
+    context: &MacroContext<'_>,
+) -> Result<Option<Vec<IpNetwork>>> {
+    let (host, cidr) = match mechanism {
+        Mechanism::Ip4(network) | Mechanism::Ip6(network) => return Ok(Some(vec![*network])),
+        Mechanism::A { domain, cidr } | Mechanism::Mx { domain, cidr } => (domain, cidr),
+        _ => return Ok(None),
+    };
+
+    let host = match host {
+        Some(host) => match expand_target(host, domain, context) {
+            Some(host) => host,
+            None => return Ok(None),
+        },
+        None => domain.to_string(),
+    };
+
+    let hosts = match mechanism {
+        Mechanism::Mx { .. } => resolver
+            .lookup_mx(&host)
+            .await
+            .context(CheckError::DnsLookupFailed)?,
+        _ => vec![host],
+    };
+
+    let mut networks = Vec::new();
+
+    for host in hosts {
+        let addresses = resolve_host(resolver, &host)
+            .await
+            .context(CheckError::DnsLookupFailed)?;
+
+        networks.extend(addresses.into_iter().map(|addr| cidr.network(addr)));
+    }
+
+    Ok(Some(networks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockResolver;
+    use crate::SpfChecker;
+
+    #[tokio::test]
+    async fn test_diff() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "old.example.com",
+            "v=spf1 include:_spf.old.net a:mail.example.com -all",
+        );
+        mock_resolver.add_record(
+            "_spf.old.net",
+            "v=spf1 ip4:192.0.2.0/24 ip4:198.51.100.0/24 -all",
+        );
+        mock_resolver.add_addresses("mail.example.com", &["203.0.113.10"]);
+        mock_resolver.add_record(
+            "new.example.com",
+            "v=spf1 include:_spf.new.net a:mail.example.com -all",
+        );
+        mock_resolver.add_record(
+            "_spf.new.net",
+            "v=spf1 ip4:192.0.2.0/23 ip6:2001:db8::/32 -all",
+        );
+
+        let checker = SpfChecker::new(mock_resolver);
+        let diff = checker
+            .diff(
+                "old.example.com",
+                "new.example.com",
+                &CheckOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        let networks = |networks: &[&str]| -> Vec<IpNetwork> {
+            networks
+                .iter()
+                .map(|network| network.parse().unwrap())
+                .collect()
+        };
+
+        assert_eq!(
+            diff.mechanisms_only_in_a,
+            vec![
+                "include:_spf.old.net",
+                "ip4:192.0.2.0/24",
+                "ip4:198.51.100.0/24"
+            ]
+        );
+        assert_eq!(
+            diff.mechanisms_only_in_b,
+            vec![
+                "include:_spf.new.net",
+                "ip4:192.0.2.0/23",
+                "ip6:2001:db8::/32"
+            ]
+        );
+        assert_eq!(diff.common_mechanisms, vec!["-all", "a:mail.example.com"]);
+        assert_eq!(diff.networks_only_in_a, networks(&["198.51.100.0/24"]));
+        assert_eq!(
+            diff.networks_only_in_b,
+            networks(&["192.0.2.0/23", "2001:db8::/32"])
+        );
+        assert_eq!(
+            diff.common_networks,
+            networks(&["192.0.2.0/24", "203.0.113.10/32"])
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use crate::lint::Finding;
+use crate::CheckError;
+use crate::SpnResolver;
+use anyhow::{Context, Result};
+
+/// Selectors commonly used by mail providers and servers, tried when the selectors of a
+/// domain are unknown. DKIM selectors cannot be listed through DNS.
+pub const COMMON_DKIM_SELECTORS: &[&str] = &[
+    "default",
+    "dkim",
+    "mail",
+    "google",
+    "selector1",
+    "selector2",
+    "k1",
+    "k2",
+    "s1",
+    "s2",
+    "mxvault",
+];
+
+/// A DKIM key record found at `<selector>._domainkey.<domain>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DkimKey {
+    pub selector: String,
+    pub record: String,
+    /// The `k` tag, `rsa` if not given.
+    pub key_type: String,
+    /// Whether the `p` tag is empty, which revokes the key (RFC 6376 §3.6.1).
+    pub revoked: bool,
+    /// Whether the `t` tag contains `y`, marking the domain as testing DKIM.
+    pub testing: bool,
+}
+
+/// The outcome of [`SpfChecker::check_dkim`](crate::SpfChecker::check_dkim).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DkimReport {
+    /// The keys found, in the order of the checked selectors.
+    pub keys: Vec<DkimKey>,
+    pub findings: Vec<Finding>,
+}
+
+impl DkimReport {
+    /// Returns `true` if a key is found and no finding is an error.
+    pub fn is_valid(&self) -> bool {
+        !self.keys.is_empty()
+            && self
+                .findings
+                .iter()
+                .all(|finding| finding.severity != crate::Severity::Error)
+    }
+}
+
+/// Parses a key record according to RFC 6376 §3.6.1, returning an error message for records
+/// that verifiers ignore.
+fn parse_key(selector: &str, record: &str) -> Result<DkimKey, String> {
+    let mut key = DkimKey {
+        selector: selector.to_owned(),
+        record: record.to_owned(),
+        key_type: "rsa".to_owned(),
+        revoked: false,
+        testing: false,
+    };
+    let mut public_key = None;
+
+    let tags = record
+        .split(';')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty());
+
+    for (index, tag) in tags.enumerate() {
+        let Some((name, value)) = tag.split_once('=') else {
+            return Err(format!("invalid tag `{tag}`, expected `name=value`"));
+        };
+        let value = value.trim();
+
+        match name.trim() {
+            // > If specified, this tag MUST be set to "DKIM1" [...] this tag MUST be the first
+            // > tag in the record.
+            "v" if index != 0 || value != "DKIM1" => {
+                return Err("`v=DKIM1` must be the first tag if given".to_owned())
+            }
+            "k" => key.key_type = value.to_owned(),
+            "p" => {
+                let is_base64 = value
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+/= \t".contains(c));
+
+                if !is_base64 {
+                    return Err("the public key in `p` is not base64".to_owned());
+                }
+                public_key = Some(value);
+            }
+            "t" => key.testing = value.split(':').any(|flag| flag.trim() == "y"),
+            _ => {}
+        }
+    }
+
+    let public_key = public_key.ok_or("the record has no `p` tag")?;
+    key.revoked = public_key.is_empty();
+
+    Ok(key)
+}
+
+/// Looks up the DKIM key records of `domain` for each of `selectors`.
+pub(crate) async fn check_dkim(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    domain: &str,
+    selectors: &[&str],
+) -> Result<DkimReport> {
+    let mut report = DkimReport {
+        keys: Vec::new(),
+        findings: Vec::new(),
+    };
+
+    for selector in selectors {
+        let key_domain = format!("{selector}._domainkey.{domain}");
+        let records = resolver
+            .lookup_txt(&key_domain)
+            .await
+            .context(CheckError::DnsLookupFailed)?;
+
+        // Some providers publish an unrelated TXT record next to the key, so only records that
+        // look like keys are considered.
+        let Some(record) = records
+            .into_iter()
+            .find(|txt| txt.starts_with("v=DKIM1") || txt.contains("p="))
+        else {
+            continue;
+        };
+
+        let key = match parse_key(selector, &record) {
+            Ok(key) => key,
+            Err(reason) => {
+                report.findings.push(Finding::error(
+                    "DKIM_SYNTAX_ERROR",
+                    &key_domain,
+                    format!("The key record cannot be parsed, so verifiers ignore it: {reason}"),
+                ));
+                continue;
+            }
+        };
+
+        if key.revoked {
+            report.findings.push(Finding::warning(
+                "DKIM_KEY_REVOKED",
+                &key_domain,
+                "The key is revoked, so signatures using it fail".to_owned(),
+            ));
+        } else if !matches!(key.key_type.as_str(), "rsa" | "ed25519") {
+            report.findings.push(Finding::error(
+                "DKIM_UNKNOWN_KEY_TYPE",
+                &key_domain,
+                format!(
+                    "The key type `{}` is neither `rsa` nor `ed25519`",
+                    key.key_type
+                ),
+            ));
+        }
+
+        if key.testing {
+            report.findings.push(Finding::warning(
+                "DKIM_TESTING",
+                &key_domain,
+                "The key is in testing mode (`t=y`), so verifiers may treat failing signatures like unsigned mail".to_owned(),
+            ));
+        }
+
+        report.keys.push(key);
+    }
+
+    if report.keys.is_empty() && report.findings.is_empty() {
+        report.findings.push(Finding::warning(
+            "NO_DKIM_KEY_FOUND",
+            domain,
+            format!(
+                "No DKIM key was found for the selectors {}; keys for other selectors cannot be discovered",
+                selectors.join(", ")
+            ),
+        ));
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockResolver;
+    use crate::Severity;
+    use crate::SpfChecker;
+
+    #[tokio::test]
+    async fn test_check_dkim() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_txt(
+            "selector1._domainkey.example.com",
+            "v=DKIM1; k=rsa; p=MIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQDwIRP/UC3SBsEmGqZ9ZJW3/DkMoGeLnQg1fWn7/zYt",
+        );
+        mock_resolver.add_txt("google._domainkey.example.com", "v=DKIM1; p=");
+        mock_resolver.add_txt(
+            "k1._domainkey.example.com",
+            "k=ed25519; t=y; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=",
+        );
+        mock_resolver.add_txt("s1._domainkey.example.com", "p=MIGf; v=DKIM1");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+
+        let report = checker
+            .check_dkim("example.com", COMMON_DKIM_SELECTORS)
+            .await
+            .unwrap();
+
+        let keys: Vec<_> = report
+            .keys
+            .iter()
+            .map(|key| (key.selector.as_str(), key.key_type.as_str(), key.revoked))
+            .collect();
+        let findings: Vec<_> = report
+            .findings
+            .iter()
+            .map(|finding| (finding.severity, finding.code, finding.domain.as_str()))
+            .collect();
+
+        assert_eq!(
+            keys,
+            vec![
+                ("google", "rsa", true),
+                ("selector1", "rsa", false),
+                ("k1", "ed25519", false),
+            ]
+        );
+        assert_eq!(
+            findings,
+            vec![
+                (
+                    Severity::Warning,
+                    "DKIM_KEY_REVOKED",
+                    "google._domainkey.example.com"
+                ),
+                (
+                    Severity::Warning,
+                    "DKIM_TESTING",
+                    "k1._domainkey.example.com"
+                ),
+                (
+                    Severity::Error,
+                    "DKIM_SYNTAX_ERROR",
+                    "s1._domainkey.example.com"
+                ),
+            ]
+        );
+        assert!(!report.is_valid());
+
+        let report = checker
+            .check_dkim("example.org", COMMON_DKIM_SELECTORS)
+            .await
+            .unwrap();
+        assert!(report.keys.is_empty());
+        assert_eq!(report.findings[0].code, "NO_DKIM_KEY_FOUND");
+    }
+}
@@ -0,0 +1,535 @@
+use crate::lint::Finding;
+use crate::CheckError;
+use crate::SpnResolver;
+use anyhow::{Context, Result};
+use std::fmt;
+use std::str::FromStr;
+
+const VERSION: &str = "DMARC1";
+
+/// The requested handling of messages failing DMARC (RFC 7489 §6.3).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DmarcPolicy {
+    None,
+    Quarantine,
+    Reject,
+}
+
+impl DmarcPolicy {
+    /// The policy as written in a record, e.g. `quarantine`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Quarantine => "quarantine",
+            Self::Reject => "reject",
+        }
+    }
+}
+
+impl fmt::Display for DmarcPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The DKIM or SPF identifier alignment mode of a DMARC record (RFC 7489 §3.1).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Alignment {
+    #[default]
+    Relaxed,
+    Strict,
+}
+
+impl Alignment {
+    /// The mode as written in a record: `r` or `s`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Relaxed => "r",
+            Self::Strict => "s",
+        }
+    }
+}
+
+impl fmt::Display for Alignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A parsed `v=DMARC1` record. Tags that are not given have their default value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DmarcRecord {
+    /// The `p` tag, required by RFC 7489 but missing from some records.
+    pub policy: Option<DmarcPolicy>,
+    /// The `sp` tag for subdomains, defaulting to `policy`.
+    pub subdomain_policy: Option<DmarcPolicy>,
+    /// The URIs of the `rua` tag receiving aggregate reports.
+    pub aggregate_report_uris: Vec<String>,
+    /// The URIs of the `ruf` tag receiving failure reports.
+    pub failure_report_uris: Vec<String>,
+    /// The `pct` tag, the percentage of failing messages the policy applies to.
+    pub percentage: u8,
+    /// The `adkim` tag.
+    pub dkim_alignment: Alignment,
+    /// The `aspf` tag.
+    pub spf_alignment: Alignment,
+    /// The `fo` tag, e.g. `0` or `1:d:s`.
+    pub failure_options: String,
+    /// The `ri` tag, the requested interval between aggregate reports in seconds.
+    pub report_interval: u32,
+    /// Names of tags not defined by RFC 7489, which are ignored.
+    pub unknown_tags: Vec<String>,
+}
+
+impl Default for DmarcRecord {
+    fn default() -> Self {
+        Self {
+            policy: None,
+            subdomain_policy: None,
+            aggregate_report_uris: Vec::new(),
+            failure_report_uris: Vec::new(),
+            percentage: 100,
+            dkim_alignment: Alignment::default(),
+            spf_alignment: Alignment::default(),
+            failure_options: "0".to_owned(),
+            report_interval: 86400,
+            unknown_tags: Vec::new(),
+        }
+    }
+}
+
+impl DmarcRecord {
+    /// Findings about a record that is valid, but is missing its policy or provides weak
+    /// protection, reported for `domain`.
+    fn findings(&self, domain: &str) -> Vec<Finding> {
+        let mut findings = Vec::new();
+
+        match self.policy {
+            // https://datatracker.ietf.org/doc/html/rfc7489#section-6.6.3
+            //
+            // > If the "p" tag is missing but a valid "rua" tag is present, the Mail Receiver
+            // > [...] MUST act as if a record containing "p=none" was retrieved.
+            None => findings.push(Finding::error(
+                "MISSING_POLICY",
+                domain,
+                "The record has no `p` tag, so it is either ignored or treated as `p=none`"
+                    .to_owned(),
+            )),
+            Some(DmarcPolicy::None) => findings.push(Finding::warning(
+                "POLICY_NONE",
+                domain,
+                "The policy `p=none` only monitors and does not protect against spoofing"
+                    .to_owned(),
+            )),
+            Some(_) if self.percentage < 100 => findings.push(Finding::warning(
+                "PARTIAL_PERCENTAGE",
+                domain,
+                format!(
+                    "The policy applies to only {}% of the failing messages",
+                    self.percentage
+                ),
+            )),
+            Some(_) => {}
+        }
+
+        if self.subdomain_policy == Some(DmarcPolicy::None)
+            && self
+                .policy
+                .is_some_and(|policy| policy != DmarcPolicy::None)
+        {
+            findings.push(Finding::warning(
+                "SUBDOMAIN_POLICY_NONE",
+                domain,
+                "The subdomain policy `sp=none` leaves subdomains unprotected".to_owned(),
+            ));
+        }
+
+        if self.aggregate_report_uris.is_empty() {
+            findings.push(Finding::warning(
+                "NO_AGGREGATE_REPORTS",
+                domain,
+                "The record has no `rua` tag, so no aggregate reports are sent".to_owned(),
+            ));
+        }
+
+        for tag in &self.unknown_tags {
+            findings.push(Finding::warning(
+                "UNKNOWN_TAG",
+                domain,
+                format!("The tag `{tag}` is not defined by RFC 7489 and is ignored"),
+            ));
+        }
+
+        findings
+    }
+}
+
+impl FromStr for DmarcRecord {
+    type Err = DmarcParseError;
+
+    /// Parses a record according to the ABNF of RFC 7489 §6.4.
+    ///
+    /// The `v` tag must come first. Invalid values of known tags, repeated tags and tags
+    /// without `=` are rejected, unknown tags are kept in
+    /// [`DmarcRecord::unknown_tags`].
+    fn from_str(record: &str) -> Result<Self, Self::Err> {
+        let mut tags = record
+            .split(';')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| match tag.split_once('=') {
+                Some((name, value)) => Ok((tag, name.trim(), value.trim())),
+                None => Err(DmarcParseError::new(tag, "expected `name=value`")),
+            });
+
+        match tags.next().transpose()? {
+            Some((_, "v", VERSION)) => {}
+            Some((tag, _, _)) => {
+                return Err(DmarcParseError::new(tag, "expected `v=DMARC1` first"))
+            }
+            None => return Err(DmarcParseError::new("", "expected `v=DMARC1`")),
+        }
+
+        let mut result = Self::default();
+        let mut seen = Vec::new();
+
+        for tag in tags {
+            let (tag, name, value) = tag?;
+            let name = name.to_ascii_lowercase();
+
+            if seen.contains(&name) {
+                return Err(DmarcParseError::new(
+                    tag,
+                    "tag must not appear more than once",
+                ));
+            }
+
+            match name.as_str() {
+                "p" => result.policy = Some(policy(tag, value)?),
+                "sp" => result.subdomain_policy = Some(policy(tag, value)?),
+                "rua" => result.aggregate_report_uris = report_uris(tag, value)?,
+                "ruf" => result.failure_report_uris = report_uris(tag, value)?,
+                "pct" => {
+                    result.percentage =
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|percentage| *percentage <= 100)
+                            .ok_or_else(|| DmarcParseError::new(tag, "expected 0 to 100"))?
+                }
+                "adkim" => result.dkim_alignment = alignment(tag, value)?,
+                "aspf" => result.spf_alignment = alignment(tag, value)?,
+                "fo" => {
+                    let is_valid = value
+                        .split(':')
+                        .all(|option| matches!(option.trim(), "0" | "1" | "d" | "s"));
+
+                    if !is_valid {
+                        return Err(DmarcParseError::new(
+                            tag,
+                            "expected `0`, `1`, `d` or `s` separated by `:`",
+                        ));
+                    }
+                    result.failure_options = value.to_owned();
+                }
+                "ri" => {
+                    result.report_interval = value
+                        .parse()
+                        .map_err(|_| DmarcParseError::new(tag, "expected a number of seconds"))?
+                }
+                // Only the `afrf` report format is defined.
+                "rf" => {
+                    if !value.eq_ignore_ascii_case("afrf") {
+                        return Err(DmarcParseError::new(tag, "expected `afrf`"));
+                    }
+                }
+                "v" => return Err(DmarcParseError::new(tag, "`v` must be the first tag")),
+                _ => result.unknown_tags.push(name.clone()),
+            }
+
+            seen.push(name);
+        }
+
+        Ok(result)
+    }
+}
+
+fn policy(tag: &str, value: &str) -> Result<DmarcPolicy, DmarcParseError> {
+    match value.to_ascii_lowercase().as_str() {
+        "none" => Ok(DmarcPolicy::None),
+        "quarantine" => Ok(DmarcPolicy::Quarantine),
+        "reject" => Ok(DmarcPolicy::Reject),
+        _ => Err(DmarcParseError::new(
+            tag,
+            "expected `none`, `quarantine` or `reject`",
+        )),
+    }
+}
+
+fn alignment(tag: &str, value: &str) -> Result<Alignment, DmarcParseError> {
+    match value.to_ascii_lowercase().as_str() {
+        "r" => Ok(Alignment::Relaxed),
+        "s" => Ok(Alignment::Strict),
+        _ => Err(DmarcParseError::new(tag, "expected `r` or `s`")),
+    }
+}
+
+/// Splits a comma-separated list of report URIs, each optionally followed by a size limit such
+/// as `!10m` (RFC 7489 §6.2).
+fn report_uris(tag: &str, value: &str) -> Result<Vec<String>, DmarcParseError> {
+    value
+        .split(',')
+        .map(str::trim)
+        .map(|uri| {
+            let address = uri.split_once('!').map_or(uri, |(address, _)| address);
+
+            match address.split_once(':') {
+                Some((scheme, rest))
+                    if !rest.is_empty()
+                        && scheme.starts_with(|c: char| c.is_ascii_alphabetic()) =>
+                {
+                    Ok(uri.to_owned())
+                }
+                _ => Err(DmarcParseError::new(
+                    tag,
+                    format!("invalid report URI `{uri}`, expected e.g. `mailto:dmarc@example.com`"),
+                )),
+            }
+        })
+        .collect()
+}
+
+/// A syntax error in a DMARC record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DmarcParseError {
+    tag: String,
+    reason: String,
+}
+
+impl DmarcParseError {
+    fn new(tag: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            tag: tag.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// The offending tag as written in the record.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+}
+
+impl fmt::Display for DmarcParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid tag `{}`: {}", self.tag, self.reason)
+    }
+}
+
+impl std::error::Error for DmarcParseError {}
+
+/// The outcome of [`SpfChecker::check_dmarc`](crate::SpfChecker::check_dmarc).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DmarcReport {
+    /// The domain queried for the record, e.g. `_dmarc.example.com`.
+    pub record_domain: String,
+    /// The DMARC record, if the domain publishes exactly one.
+    pub record: Option<String>,
+    /// The parsed [`DmarcReport::record`], if it is valid.
+    pub parsed: Option<DmarcRecord>,
+    pub findings: Vec<Finding>,
+}
+
+impl DmarcReport {
+    /// Returns `true` if a valid record is published and no finding is an error.
+    pub fn is_valid(&self) -> bool {
+        self.parsed.is_some()
+            && self
+                .findings
+                .iter()
+                .all(|finding| finding.severity != crate::Severity::Error)
+    }
+}
+
+fn is_dmarc_record(txt: &str) -> bool {
+    let version = txt.split(';').next().unwrap_or_default();
+
+    matches!(version.split_once('='), Some((name, value)) if name.trim() == "v" && value.trim() == VERSION)
+}
+
+/// Looks up, parses and validates the DMARC record of `domain`.
+pub(crate) async fn check_dmarc(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    domain: &str,
+) -> Result<DmarcReport> {
+    let record_domain = format!("_dmarc.{domain}");
+    let mut records: Vec<String> = resolver
+        .lookup_txt(&record_domain)
+        .await
+        .context(CheckError::DnsLookupFailed)?
+        .into_iter()
+        .filter(|txt| is_dmarc_record(txt))
+        .collect();
+
+    let mut report = DmarcReport {
+        record_domain,
+        record: None,
+        parsed: None,
+        findings: Vec::new(),
+    };
+
+    // https://datatracker.ietf.org/doc/html/rfc7489#section-6.6.3
+    //
+    // > If the remaining set contains multiple records or no records, policy discovery
+    // > terminates and DMARC processing is not applied to this message.
+    let record = match records.len() {
+        0 => {
+            report.findings.push(Finding::error(
+                "NO_DMARC_RECORD",
+                &report.record_domain,
+                "The domain publishes no DMARC record".to_owned(),
+            ));
+            return Ok(report);
+        }
+        1 => records.remove(0),
+        count => {
+            report.findings.push(Finding::error(
+                "MULTIPLE_DMARC_RECORDS",
+                &report.record_domain,
+                format!("The domain publishes {count} DMARC records, so none of them is applied"),
+            ));
+            return Ok(report);
+        }
+    };
+
+    match DmarcRecord::from_str(&record) {
+        Ok(parsed) => {
+            report.findings = parsed.findings(&report.record_domain);
+            report.parsed = Some(parsed);
+        }
+        Err(err) => report.findings.push(Finding::error(
+            "DMARC_SYNTAX_ERROR",
+            &report.record_domain,
+            format!("The record cannot be parsed, so it is not applied: {err}"),
+        )),
+    }
+
+    report.record = Some(record);
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockResolver;
+    use crate::Severity;
+    use crate::SpfChecker;
+
+    #[test]
+    fn test_parse_record() {
+        let record = DmarcRecord::from_str(
+            "v=DMARC1; p=quarantine; sp=reject; rua=mailto:a@example.com,mailto:b@example.com!10m; pct=50; adkim=s; fo=1:d; x=y",
+        )
+        .unwrap();
+
+        assert_eq!(
+            record,
+            DmarcRecord {
+                policy: Some(DmarcPolicy::Quarantine),
+                subdomain_policy: Some(DmarcPolicy::Reject),
+                aggregate_report_uris: vec![
+                    "mailto:a@example.com".to_owned(),
+                    "mailto:b@example.com!10m".to_owned()
+                ],
+                percentage: 50,
+                dkim_alignment: Alignment::Strict,
+                failure_options: "1:d".to_owned(),
+                unknown_tags: vec!["x".to_owned()],
+                ..DmarcRecord::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_record_rejects_invalid_tags() {
+        for (record, tag) in [
+            ("p=reject; v=DMARC1", "p=reject"),
+            ("v=DMARC1; p=block", "p=block"),
+            ("v=DMARC1; p=none; pct=101", "pct=101"),
+            ("v=DMARC1; p=none; adkim=x", "adkim=x"),
+            (
+                "v=DMARC1; p=none; rua=dmarc@example.com",
+                "rua=dmarc@example.com",
+            ),
+            ("v=DMARC1; p=none; p=reject", "p=reject"),
+            ("v=DMARC1; p", "p"),
+        ] {
+            assert_eq!(
+                DmarcRecord::from_str(record).map_err(|err| err.tag().to_owned()),
+                Err(tag.to_owned()),
+                "{record}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_dmarc() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_txt(
+            "_dmarc.example.com",
+            "v=DMARC1; p=reject; sp=none; rua=mailto:dmarc@example.com",
+        );
+        mock_resolver.add_txt("_dmarc.example.org", "v=DMARC1; p=none; pct=20; foo=bar");
+        mock_resolver.add_records(
+            "_dmarc.example.net",
+            &["v=DMARC1; p=reject", "v=DMARC1; p=none"],
+        );
+        mock_resolver.add_txt("_dmarc.example.de", "v=DMARC1; p=block");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+
+        for (domain, expected) in [
+            (
+                "example.com",
+                vec![(Severity::Warning, "SUBDOMAIN_POLICY_NONE")],
+            ),
+            (
+                "example.org",
+                vec![
+                    (Severity::Warning, "POLICY_NONE"),
+                    (Severity::Warning, "NO_AGGREGATE_REPORTS"),
+                    (Severity::Warning, "UNKNOWN_TAG"),
+                ],
+            ),
+            (
+                "example.net",
+                vec![(Severity::Error, "MULTIPLE_DMARC_RECORDS")],
+            ),
+            ("example.de", vec![(Severity::Error, "DMARC_SYNTAX_ERROR")]),
+            ("example.at", vec![(Severity::Error, "NO_DMARC_RECORD")]),
+        ] {
+            let report = checker.check_dmarc(domain).await.unwrap();
+
+            let findings: Vec<_> = report
+                .findings
+                .iter()
+                .map(|finding| (finding.severity, finding.code))
+                .collect();
+
+            assert_eq!(findings, expected, "{domain}");
+            assert_eq!(report.record_domain, format!("_dmarc.{domain}"));
+        }
+
+        let report = checker.check_dmarc("example.com").await.unwrap();
+        assert!(report.is_valid());
+        assert_eq!(
+            report.parsed.and_then(|parsed| parsed.policy),
+            Some(DmarcPolicy::Reject)
+        );
+
+        let report = checker.check_dmarc("example.de").await.unwrap();
+        assert!(!report.is_valid());
+        assert_eq!(report.record.as_deref(), Some("v=DMARC1; p=block"));
+    }
+}
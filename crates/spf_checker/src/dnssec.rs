@@ -0,0 +1,111 @@
+use crate::{SpfTreeNode, SpnResolver};
+use std::collections::{HashSet, VecDeque};
+
+/// Whether the SPF answers of the domains of an SPF tree are DNSSEC-signed, as returned by
+/// [`SpfChecker::dnssec_report`](crate::SpfChecker::dnssec_report).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DnssecReport {
+    /// The domains of the tree, level by level and in record order, without duplicates.
+    pub domains: Vec<DomainAuthentication>,
+    /// Whether the TXT records of every domain passed validation, so that none of the answers
+    /// the check relies on could have been spoofed.
+    pub fully_signed: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DomainAuthentication {
+    pub domain: String,
+    /// Whether the TXT records of the domain were signed and passed DNSSEC validation.
+    pub authenticated_data: bool,
+}
+
+/// Validates the TXT records of each domain of `tree` through `validator`, which is expected
+/// to fail lookups whose answers are unsigned or do not validate.
+pub(crate) async fn validate_tree(
+    validator: &(dyn SpnResolver + Send + Sync),
+    tree: &SpfTreeNode,
+) -> DnssecReport {
+    let mut domains = Vec::new();
+    let mut seen = HashSet::new();
+    let mut nodes = VecDeque::from([tree]);
+
+    while let Some(node) = nodes.pop_front() {
+        if node.is_loop || !seen.insert(node.domain.as_str()) {
+            continue;
+        }
+
+        // Signed zones answer with their TXT records, while empty answers cannot be told
+        // apart from unsigned ones.
+        let authenticated_data = matches!(
+            validator.lookup_txt(&node.domain).await,
+            Ok(records) if !records.is_empty()
+        );
+
+        domains.push(DomainAuthentication {
+            domain: node.domain.clone(),
+            authenticated_data,
+        });
+
+        nodes.extend(&node.children);
+    }
+
+    DnssecReport {
+        fully_signed: domains.iter().all(|domain| domain.authenticated_data),
+        domains,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockResolver;
+    use crate::CheckOptions;
+    use crate::SpfChecker;
+
+    #[tokio::test]
+    async fn test_dnssec_report() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 include:_spf.a.net include:_spf.b.net include:example.com -all",
+        );
+        mock_resolver.add_record("_spf.a.net", "v=spf1 include:_spf.b.net -all");
+        mock_resolver.add_record("_spf.b.net", "v=spf1 ip4:192.0.2.0/24 -all");
+
+        // Only answers for the signed zones pass validation.
+        let validator = MockResolver::new();
+        validator.add_record("example.com", "v=spf1 include:_spf.a.net -all");
+        validator.add_record("_spf.b.net", "v=spf1 ip4:192.0.2.0/24 -all");
+
+        let checker = SpfChecker::new(mock_resolver);
+
+        let err = checker
+            .dnssec_report("example.com", &CheckOptions::default())
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "DNSSEC_UNAVAILABLE");
+
+        let report = checker
+            .with_dnssec_validator(validator)
+            .dnssec_report("example.com", &CheckOptions::default())
+            .await
+            .unwrap();
+
+        let domain = |domain: &str, authenticated_data| DomainAuthentication {
+            domain: domain.to_string(),
+            authenticated_data,
+        };
+
+        assert_eq!(
+            report,
+            DnssecReport {
+                domains: vec![
+                    domain("example.com", true),
+                    domain("_spf.a.net", false),
+                    domain("_spf.b.net", true),
+                ],
+                fully_signed: false,
+            }
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{CheckError, MxRecord, SpnResolver, TxtRecord};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+
+/// Answers the SPF lookups of one domain with a record that is not necessarily published, and
+/// all other lookups through the wrapped resolver. Without a resolver, all other lookups fail
+/// with `DNS_LOOKUPS_DISABLED`.
+#[derive(Debug)]
+pub(crate) struct DraftResolver {
+    resolver: Option<Arc<dyn SpnResolver + Send + Sync + 'static>>,
+    domain: String,
+    record: String,
+}
+
+impl DraftResolver {
+    pub fn new(
+        resolver: Option<Arc<dyn SpnResolver + Send + Sync + 'static>>,
+        domain: String,
+        record: String,
+    ) -> Self {
+        Self {
+            resolver,
+            domain,
+            record,
+        }
+    }
+
+    fn is_draft_domain(&self, domain: &str) -> bool {
+        domain
+            .trim_end_matches('.')
+            .eq_ignore_ascii_case(&self.domain)
+    }
+
+    fn resolver(&self) -> Result<&(dyn SpnResolver + Send + Sync)> {
+        self.resolver
+            .as_deref()
+            .ok_or_else(|| anyhow!(CheckError::DnsLookupsDisabled))
+    }
+
+    /// The TXT records of the draft domain: the published ones other than SPF records if DNS
+    /// lookups are enabled, followed by the draft record.
+    async fn draft_txt_records(&self) -> Result<Vec<TxtRecord>> {
+        let mut records = match &self.resolver {
+            Some(resolver) => resolver.lookup_txt_records(&self.domain).await?,
+            None => Vec::new(),
+        };
+
+        records.retain(|txt| !txt.is_spf());
+        records.push(TxtRecord {
+            text: self.record.clone(),
+            ttl: 0,
+        });
+
+        Ok(records)
+    }
+}
+
+#[async_trait]
+impl SpnResolver for DraftResolver {
+    async fn find_spf_records(&self, domain: &str) -> Result<Vec<String>> {
+        if self.is_draft_domain(domain) {
+            return Ok(vec![self.record.clone()]);
+        }
+
+        self.resolver()?.find_spf_records(domain).await
+    }
+
+    async fn lookup_txt(&self, domain: &str) -> Result<Vec<String>> {
+        if self.is_draft_domain(domain) {
+            let records = self.draft_txt_records().await?;
+            return Ok(records.into_iter().map(|txt| txt.text).collect());
+        }
+
+        self.resolver()?.lookup_txt(domain).await
+    }
+
+    async fn lookup_txt_records(&self, domain: &str) -> Result<Vec<TxtRecord>> {
+        if self.is_draft_domain(domain) {
+            return self.draft_txt_records().await;
+        }
+
+        self.resolver()?.lookup_txt_records(domain).await
+    }
+
+    async fn lookup_a(&self, domain: &str) -> Result<Vec<Ipv4Addr>> {
+        self.resolver()?.lookup_a(domain).await
+    }
+
+    async fn lookup_aaaa(&self, domain: &str) -> Result<Vec<Ipv6Addr>> {
+        self.resolver()?.lookup_aaaa(domain).await
+    }
+
+    async fn lookup_mx(&self, domain: &str) -> Result<Vec<String>> {
+        self.resolver()?.lookup_mx(domain).await
+    }
+
+    async fn lookup_mx_records(&self, domain: &str) -> Result<Vec<MxRecord>> {
+        self.resolver()?.lookup_mx_records(domain).await
+    }
+
+    async fn lookup_ptr(&self, ip: IpAddr) -> Result<Vec<String>> {
+        self.resolver()?.lookup_ptr(ip).await
+    }
+
+    /// The draft record stands for a TXT record, so the draft domain has no records of the
+    /// deprecated `SPF` type.
+    async fn lookup_spf_rr(&self, domain: &str) -> Result<Vec<String>> {
+        if self.is_draft_domain(domain) {
+            return Ok(Vec::new());
+        }
+
+        self.resolver()?.lookup_spf_rr(domain).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::MockResolver;
+    use crate::SpfChecker;
+    use crate::SpfResult;
+
+    #[tokio::test]
+    async fn test_with_record() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("example.com", "v=spf1 -all");
+        mock_resolver.add_record("_spf.provider.net", "v=spf1 ip4:198.51.100.0/24 -all");
+
+        let checker = SpfChecker::new(mock_resolver);
+        let record = "v=spf1 ip4:192.0.2.0/24 include:_spf.provider.net -all";
+
+        let draft = checker.with_record("Example.com", record, true).unwrap();
+        let result = draft
+            .check("example.com", "_spf.provider.net")
+            .await
+            .unwrap();
+        assert!(result.found);
+        assert_eq!(result.spf_record, Some(record.to_string()));
+
+        let evaluation = draft
+            .check_host("198.51.100.1".parse().unwrap(), "", "example.com")
+            .await;
+        assert_eq!(evaluation.result, SpfResult::Pass);
+
+        // Without DNS, only the terms before the include can be evaluated
+        let offline = checker.with_record("example.com", record, false).unwrap();
+        let evaluation = offline
+            .check_host("192.0.2.10".parse().unwrap(), "", "example.com")
+            .await;
+        assert_eq!(evaluation.result, SpfResult::Pass);
+        let evaluation = offline
+            .check_host("198.51.100.1".parse().unwrap(), "", "example.com")
+            .await;
+        assert_eq!(evaluation.result, SpfResult::TempError);
+
+        for record in ["ip4:192.0.2.0/24 -all", "v=spf1 ip4:192.0.2.0/33 -all"] {
+            let err = checker
+                .with_record("example.com", record, true)
+                .unwrap_err();
+            assert_eq!(err.to_string(), "SPF_PARSE_FAILED");
+        }
+    }
+}
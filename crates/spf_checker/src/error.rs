@@ -0,0 +1,194 @@
+use crate::MultipleSpfRecords;
+
+/// The errors a check fails with, carried by the [`anyhow::Error`]s of the checker as context
+/// of their cause. Their `Display` is the error code, e.g. `DNS_LOOKUP_FAILED`, so that
+/// `err.to_string()` returns the code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, thiserror::Error)]
+#[error("{}", self.code())]
+pub enum CheckError {
+    /// A domain or target is not a valid domain name.
+    InvalidDomain,
+    /// A DNS lookup failed, e.g. with `SERVFAIL` or without connection to the name server.
+    DnsLookupFailed,
+    /// A DNS lookup got no answer in time.
+    DnsTimeout,
+    /// A looked up domain does not exist.
+    DnsNxdomain,
+    /// A record had to be looked up by a checker with DNS lookups disabled.
+    DnsLookupsDisabled,
+    /// DNSSEC validation was requested from a checker without validating resolver.
+    DnssecUnavailable,
+    /// The checked domain publishes no SPF record.
+    NoSpfRecord,
+    /// A domain publishes more than one SPF record, see [`MultipleSpfRecords`].
+    MultipleSpfRecords,
+    /// An SPF record does not parse.
+    SpfParseFailed,
+    /// The SPF record of a target does not parse.
+    TargetSpfParseFailed,
+    /// More than [`crate::DNS_LOOKUP_LIMIT`] DNS-querying terms were reached before a domain,
+    /// which was not looked up anymore.
+    LookupLimitExceeded,
+    /// More than 2 `a` or `mx` lookups returned no records, see RFC 7208 §4.6.4.
+    VoidLookupLimitExceeded,
+    /// The SPF tree has more domains than are traversed.
+    TreeSizeLimitExceeded,
+}
+
+/// The descriptions of a [`CheckError`].
+struct Details {
+    code: &'static str,
+    title: &'static str,
+    message: &'static str,
+}
+
+impl CheckError {
+    pub const ALL: &'static [Self] = &[
+        Self::InvalidDomain,
+        Self::DnsLookupFailed,
+        Self::DnsTimeout,
+        Self::DnsNxdomain,
+        Self::DnsLookupsDisabled,
+        Self::DnssecUnavailable,
+        Self::NoSpfRecord,
+        Self::MultipleSpfRecords,
+        Self::SpfParseFailed,
+        Self::TargetSpfParseFailed,
+        Self::LookupLimitExceeded,
+        Self::VoidLookupLimitExceeded,
+        Self::TreeSizeLimitExceeded,
+    ];
+
+    fn details(self) -> Details {
+        let (code, title, message) = match self {
+            Self::InvalidDomain => (
+                "INVALID_DOMAIN",
+                "Invalid domain",
+                "The domain or target is not a valid domain name",
+            ),
+            Self::DnsLookupFailed => (
+                "DNS_LOOKUP_FAILED",
+                "DNS lookup failed",
+                "A DNS lookup failed",
+            ),
+            Self::DnsTimeout => (
+                "DNS_TIMEOUT",
+                "DNS lookup timed out",
+                "A DNS lookup timed out",
+            ),
+            Self::DnsNxdomain => (
+                "DNS_NXDOMAIN",
+                "Domain does not exist",
+                "A looked up domain does not exist",
+            ),
+            Self::DnsLookupsDisabled => (
+                "DNS_LOOKUPS_DISABLED",
+                "DNS lookups disabled",
+                "A record had to be looked up, but DNS lookups are disabled",
+            ),
+            Self::DnssecUnavailable => (
+                "DNSSEC_UNAVAILABLE",
+                "DNSSEC unavailable",
+                "No DNSSEC-validating resolver is configured",
+            ),
+            Self::NoSpfRecord => (
+                "NO_SPF_RECORD",
+                "No SPF record",
+                "The domain publishes no SPF record",
+            ),
+            Self::MultipleSpfRecords => (
+                "MULTIPLE_SPF_RECORDS",
+                "Multiple SPF records",
+                "The domain publishes more than one SPF record",
+            ),
+            Self::SpfParseFailed => (
+                "SPF_PARSE_FAILED",
+                "SPF record does not parse",
+                "An SPF record does not parse",
+            ),
+            Self::TargetSpfParseFailed => (
+                "TARGET_SPF_PARSE_FAILED",
+                "SPF record of the target does not parse",
+                "The SPF record of the target does not parse",
+            ),
+            Self::LookupLimitExceeded => (
+                "LOOKUP_LIMIT_EXCEEDED",
+                "DNS lookup limit exceeded",
+                "The SPF tree takes more than 10 DNS lookups",
+            ),
+            Self::VoidLookupLimitExceeded => (
+                "VOID_LOOKUP_LIMIT_EXCEEDED",
+                "Void lookup limit exceeded",
+                "Too many DNS lookups returned no records",
+            ),
+            Self::TreeSizeLimitExceeded => (
+                "TREE_SIZE_LIMIT_EXCEEDED",
+                "SPF tree too large",
+                "The SPF tree has too many domains",
+            ),
+        };
+
+        Details {
+            code,
+            title,
+            message,
+        }
+    }
+
+    /// The error code, e.g. `DNS_LOOKUP_FAILED`.
+    pub fn code(self) -> &'static str {
+        self.details().code
+    }
+
+    /// A short summary of the kind of error, e.g. for the title of problem details.
+    pub fn title(self) -> &'static str {
+        self.details().title
+    }
+
+    /// A description of the error for humans, which unlike the code may change.
+    pub fn message(self) -> &'static str {
+        self.details().message
+    }
+
+    /// Whether the error may go away when retried, which receivers treat as temperror rather
+    /// than permerror.
+    pub fn is_temporary(self) -> bool {
+        matches!(self, Self::DnsLookupFailed | Self::DnsTimeout)
+    }
+
+    /// The error with the code `code`, e.g. `DNS_LOOKUP_FAILED`.
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|error| error.code() == code)
+    }
+
+    /// The error `err` of the checker failed with, `None` for errors without code.
+    pub fn of(err: &anyhow::Error) -> Option<Self> {
+        if err.is::<MultipleSpfRecords>() {
+            return Some(Self::MultipleSpfRecords);
+        }
+
+        err.downcast_ref::<Self>().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+
+    #[test]
+    fn test_check_error() {
+        let err = Err::<(), _>(anyhow!("unexpected end of input"))
+            .context(CheckError::SpfParseFailed)
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), "SPF_PARSE_FAILED");
+        assert_eq!(CheckError::of(&err), Some(CheckError::SpfParseFailed));
+        assert_eq!(CheckError::of(&anyhow!("other")), None);
+
+        for &error in CheckError::ALL {
+            assert_eq!(error.to_string(), error.code());
+            assert_eq!(CheckError::from_code(error.code()), Some(error));
+        }
+    }
+}
@@ -0,0 +1,757 @@
+use crate::macros::{self, MacroContext};
+use crate::record::{Directive, DualCidr, Mechanism, Qualifier};
+use crate::{find_explanation, parsed, SpnResolver, DNS_LOOKUP_LIMIT, VOID_LOOKUP_LIMIT};
+use std::fmt;
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+
+/// https://datatracker.ietf.org/doc/html/rfc7208#section-4.6.4
+///
+/// > When evaluating the "mx" mechanism, the number of "MX" resource records queried is
+/// > included in the overall limit of 10 mechanisms/modifiers that cause DNS lookups [...]
+/// > If this limit is exceeded, the "mx" mechanism MUST produce a "permerror" result.
+const MX_LOOKUP_LIMIT: usize = 10;
+
+/// https://datatracker.ietf.org/doc/html/rfc7208#section-4.6.4
+///
+/// > [...] if more than 10 "PTR" records are returned, only the first 10 are processed.
+const PTR_LOOKUP_LIMIT: usize = 10;
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// The result of an SPF evaluation (RFC 7208 §2.6).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpfResult {
+    None,
+    Neutral,
+    Pass,
+    Fail,
+    SoftFail,
+    TempError,
+    PermError,
+}
+
+impl SpfResult {
+    /// The result name as used by RFC 7208, e.g. `softfail`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Neutral => "neutral",
+            Self::Pass => "pass",
+            Self::Fail => "fail",
+            Self::SoftFail => "softfail",
+            Self::TempError => "temperror",
+            Self::PermError => "permerror",
+        }
+    }
+}
+
+impl From<Qualifier> for SpfResult {
+    fn from(qualifier: Qualifier) -> Self {
+        match qualifier {
+            Qualifier::Pass => Self::Pass,
+            Qualifier::Fail => Self::Fail,
+            Qualifier::SoftFail => Self::SoftFail,
+            Qualifier::Neutral => Self::Neutral,
+        }
+    }
+}
+
+impl fmt::Display for SpfResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The outcome of [`SpfChecker::check_host`](crate::SpfChecker::check_host).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Evaluation {
+    pub result: SpfResult,
+    /// The directive that matched the client IP, e.g. `ip4:192.0.2.0/24`.
+    pub mechanism: Option<String>,
+    /// The domain whose record declares [`Evaluation::mechanism`].
+    pub mechanism_domain: Option<String>,
+    /// Number of terms that counted against the DNS lookup limit.
+    pub dns_lookups: usize,
+    /// Number of `a`, `mx` and `exists` lookups that returned no records.
+    pub void_lookups: usize,
+    /// Why the evaluation ended with `temperror` or `permerror`.
+    pub error: Option<String>,
+    /// The explanation published through the `exp` modifier for a `fail` result, with macros
+    /// expanded.
+    pub explanation: Option<String>,
+}
+
+/// Normalizes the MAIL FROM identity: an empty reverse-path is replaced by
+/// `postmaster@<helo>` (RFC 7208 §2.4) and a missing local-part by `postmaster` (§4.3).
+pub(crate) fn sender_identity(sender: &str, helo: &str) -> String {
+    match sender.rsplit_once('@') {
+        Some(("", domain)) => format!("postmaster@{domain}"),
+        Some(_) => sender.to_owned(),
+        None if sender.is_empty() => format!("postmaster@{helo}"),
+        None => format!("postmaster@{sender}"),
+    }
+}
+
+struct Match {
+    mechanism: String,
+    domain: String,
+}
+
+struct Verdict {
+    result: SpfResult,
+    matched: Option<Match>,
+    explanation: Option<String>,
+}
+
+impl Verdict {
+    fn unmatched(result: SpfResult) -> Self {
+        Self {
+            result,
+            matched: None,
+            explanation: None,
+        }
+    }
+}
+
+/// Aborts the evaluation with `temperror` or `permerror`.
+struct Failure {
+    result: SpfResult,
+    reason: String,
+}
+
+impl Failure {
+    fn perm(reason: impl Into<String>) -> Self {
+        Self {
+            result: SpfResult::PermError,
+            reason: reason.into(),
+        }
+    }
+
+    fn dns(err: anyhow::Error) -> Self {
+        Self {
+            result: SpfResult::TempError,
+            reason: format!("{err:#}"),
+        }
+    }
+}
+
+/// State of a single `check_host()` evaluation, shared across nested `include` and
+/// `redirect` evaluations.
+pub(crate) struct Evaluator<'a> {
+    resolver: &'a (dyn SpnResolver + Send + Sync),
+    ip: IpAddr,
+    sender: &'a str,
+    helo: &'a str,
+    dns_lookups: usize,
+    void_lookups: usize,
+}
+
+impl<'a> Evaluator<'a> {
+    /// `sender` is expected to be normalized by [`sender_identity`].
+    pub(crate) fn new(
+        resolver: &'a (dyn SpnResolver + Send + Sync),
+        ip: IpAddr,
+        sender: &'a str,
+        helo: &'a str,
+    ) -> Self {
+        // https://datatracker.ietf.org/doc/html/rfc7208#section-5
+        //
+        // > [...] IPv4-mapped IPv6 addresses [...] MUST be evaluated using the IPv4 rules.
+        let ip = match ip {
+            IpAddr::V6(ip) => ip.to_ipv4_mapped().map_or(IpAddr::V6(ip), IpAddr::V4),
+            ip => ip,
+        };
+
+        Self {
+            resolver,
+            ip,
+            sender,
+            helo,
+            dns_lookups: 0,
+            void_lookups: 0,
+        }
+    }
+
+    /// Evaluates the domain of the MAIL FROM identity.
+    pub(crate) async fn evaluate(mut self) -> Evaluation {
+        let domain = self
+            .sender
+            .rsplit_once('@')
+            .map_or(self.sender, |(_, domain)| domain);
+
+        let outcome = if is_valid_domain(domain) {
+            self.check_host(domain.to_owned()).await
+        } else {
+            Ok(Verdict::unmatched(SpfResult::None))
+        };
+
+        let (verdict, error) = match outcome {
+            Ok(verdict) => (verdict, None),
+            Err(failure) => (Verdict::unmatched(failure.result), Some(failure.reason)),
+        };
+
+        let (mechanism, mechanism_domain) = verdict
+            .matched
+            .map(|matched| (matched.mechanism, matched.domain))
+            .unzip();
+
+        Evaluation {
+            result: verdict.result,
+            mechanism,
+            mechanism_domain,
+            dns_lookups: self.dns_lookups,
+            void_lookups: self.void_lookups,
+            error,
+            explanation: verdict.explanation,
+        }
+    }
+
+    fn check_host(&mut self, domain: String) -> BoxFuture<'_, Result<Verdict, Failure>> {
+        Box::pin(async move {
+            let mut records = self
+                .resolver
+                .find_spf_records(&domain)
+                .await
+                .map_err(Failure::dns)?;
+
+            // https://datatracker.ietf.org/doc/html/rfc7208#section-4.5
+            if records.len() > 1 {
+                return Err(Failure::perm(format!(
+                    "{domain} publishes multiple SPF records: `{}`",
+                    records.join("`, `")
+                )));
+            }
+
+            let Some(spf_txt) = records.pop() else {
+                return Ok(Verdict::unmatched(SpfResult::None));
+            };
+
+            let record = parsed::parse(&spf_txt, false)
+                .map_err(|err| Failure::perm(format!("{domain}: {err}")))?;
+
+            for directive in record.directives() {
+                if let Some(matched) = self.matches(&domain, directive).await? {
+                    let result = SpfResult::from(directive.qualifier);
+
+                    // https://datatracker.ietf.org/doc/html/rfc7208#section-6.2
+                    //
+                    // Only evaluated for the record producing the result: explanations of
+                    // included records are dropped together with their verdict.
+                    let explanation = match (result, record.explanation()) {
+                        (SpfResult::Fail, Some(spec)) => {
+                            find_explanation(self.resolver, spec, &domain, &self.macro_context())
+                                .await
+                        }
+                        _ => None,
+                    };
+
+                    return Ok(Verdict {
+                        result,
+                        matched: Some(matched),
+                        explanation,
+                    });
+                }
+            }
+
+            // An `all` mechanism always matches, so reaching this point also honors
+            // RFC 7208 §6.1: "redirect" is ignored if the record contains "all".
+            if let Some(redirect) = record.redirect() {
+                self.count_dns_lookup()?;
+
+                let redirect = self.expand(redirect, &domain)?;
+                let verdict = self.check_host(redirect.clone()).await?;
+
+                if verdict.result == SpfResult::None {
+                    return Err(Failure::perm(format!(
+                        "redirect target {redirect} has no SPF record"
+                    )));
+                }
+
+                return Ok(verdict);
+            }
+
+            Ok(Verdict::unmatched(SpfResult::Neutral))
+        })
+    }
+
+    async fn matches(
+        &mut self,
+        domain: &str,
+        directive: &Directive,
+    ) -> Result<Option<Match>, Failure> {
+        if directive.mechanism.is_dns_lookup() {
+            self.count_dns_lookup()?;
+        }
+
+        let is_match = match &directive.mechanism {
+            Mechanism::All => true,
+            Mechanism::Include(target) => {
+                let target = self.expand(target, domain)?;
+                let verdict = self.check_host(target.clone()).await?;
+
+                // https://datatracker.ietf.org/doc/html/rfc7208#section-5.2
+                return match verdict.result {
+                    SpfResult::Pass => Ok(verdict.matched),
+                    SpfResult::None => Err(Failure::perm(format!(
+                        "included domain {target} has no SPF record"
+                    ))),
+                    _ => Ok(None),
+                };
+            }
+            Mechanism::A { domain: host, cidr } => {
+                let host = self.target_domain(host.as_deref(), domain)?;
+                let addresses = self.lookup_addresses(&host).await.map_err(Failure::dns)?;
+
+                if addresses.is_empty() {
+                    self.count_void_lookup()?;
+                }
+
+                self.network_matches(&addresses, *cidr)
+            }
+            Mechanism::Mx { domain: host, cidr } => {
+                let host = self.target_domain(host.as_deref(), domain)?;
+                self.mx_matches(&host, *cidr).await?
+            }
+            Mechanism::Ptr(target) => {
+                let target = self.target_domain(target.as_deref(), domain)?;
+                self.ptr_matches(&target).await
+            }
+            Mechanism::Ip4(network) | Mechanism::Ip6(network) => network.contains(self.ip),
+            // https://datatracker.ietf.org/doc/html/rfc7208#section-5.7
+            //
+            // > The resulting domain name is used for a DNS A RR lookup (even when the
+            // > connection type is IPv6). If any A record is returned, this mechanism matches.
+            Mechanism::Exists(spec) => {
+                let host = self.expand(spec, domain)?;
+                let addresses = self.resolver.lookup_a(&host).await.map_err(Failure::dns)?;
+
+                if addresses.is_empty() {
+                    self.count_void_lookup()?;
+                }
+
+                !addresses.is_empty()
+            }
+        };
+
+        Ok(is_match.then(|| Match {
+            mechanism: directive.to_string(),
+            domain: domain.to_owned(),
+        }))
+    }
+
+    fn macro_context(&self) -> MacroContext<'_> {
+        MacroContext {
+            sender: Some(self.sender),
+            ip: Some(self.ip),
+            helo: Some(self.helo).filter(|helo| !helo.is_empty()),
+        }
+    }
+
+    /// Expands the macros of a domain spec found in the record of `domain`.
+    fn expand(&self, spec: &str, domain: &str) -> Result<String, Failure> {
+        macros::expand_domain(spec, domain, &self.macro_context())
+            .map_err(|err| Failure::perm(format!("{domain}: {err}")))
+    }
+
+    /// The domain named by an optional domain spec, defaulting to the current `domain`.
+    fn target_domain(&self, spec: Option<&str>, domain: &str) -> Result<String, Failure> {
+        match spec {
+            Some(spec) => self.expand(spec, domain),
+            None => Ok(domain.to_owned()),
+        }
+    }
+
+    fn count_void_lookup(&mut self) -> Result<(), Failure> {
+        self.void_lookups += 1;
+
+        if self.void_lookups > VOID_LOOKUP_LIMIT {
+            return Err(Failure::perm(format!(
+                "more than {VOID_LOOKUP_LIMIT} void DNS lookups"
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn count_dns_lookup(&mut self) -> Result<(), Failure> {
+        self.dns_lookups += 1;
+
+        if self.dns_lookups > DNS_LOOKUP_LIMIT {
+            return Err(Failure::perm(format!(
+                "more than {DNS_LOOKUP_LIMIT} DNS lookups required"
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn lookup_addresses(&self, host: &str) -> anyhow::Result<Vec<IpAddr>> {
+        Ok(match self.ip {
+            IpAddr::V4(_) => self
+                .resolver
+                .lookup_a(host)
+                .await?
+                .into_iter()
+                .map(IpAddr::V4)
+                .collect(),
+            IpAddr::V6(_) => self
+                .resolver
+                .lookup_aaaa(host)
+                .await?
+                .into_iter()
+                .map(IpAddr::V6)
+                .collect(),
+        })
+    }
+
+    fn network_matches(&self, addresses: &[IpAddr], cidr: DualCidr) -> bool {
+        addresses
+            .iter()
+            .any(|addr| cidr.network(*addr).contains(self.ip))
+    }
+
+    async fn mx_matches(&mut self, host: &str, cidr: DualCidr) -> Result<bool, Failure> {
+        let exchanges = self.resolver.lookup_mx(host).await.map_err(Failure::dns)?;
+
+        if exchanges.is_empty() {
+            self.count_void_lookup()?;
+        }
+
+        if exchanges.len() > MX_LOOKUP_LIMIT {
+            return Err(Failure::perm(format!(
+                "{host} has more than {MX_LOOKUP_LIMIT} MX records"
+            )));
+        }
+
+        for exchange in &exchanges {
+            let addresses = self
+                .lookup_addresses(exchange)
+                .await
+                .map_err(Failure::dns)?;
+
+            if self.network_matches(&addresses, cidr) {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// https://datatracker.ietf.org/doc/html/rfc7208#section-5.5
+    ///
+    /// DNS errors while looking up PTR or address records make the mechanism not match
+    /// instead of aborting the evaluation.
+    async fn ptr_matches(&self, target: &str) -> bool {
+        let Ok(names) = self.resolver.lookup_ptr(self.ip).await else {
+            return false;
+        };
+
+        for name in names.iter().take(PTR_LOOKUP_LIMIT) {
+            if !is_same_or_subdomain(name, target) {
+                continue;
+            }
+
+            if let Ok(addresses) = self.lookup_addresses(name).await {
+                if addresses.contains(&self.ip) {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+}
+
+/// https://datatracker.ietf.org/doc/html/rfc7208#section-4.3
+///
+/// > If the <domain> is malformed (e.g., label longer than 63 characters, zero-length label
+/// > not at the end, etc.) or is not a multi-label domain name, [...] check_host()
+/// > immediately returns the result "none".
+fn is_valid_domain(domain: &str) -> bool {
+    let domain = domain.strip_suffix('.').unwrap_or(domain);
+
+    domain.contains('.')
+        && domain
+            .split('.')
+            .all(|label| (1..=63).contains(&label.len()))
+}
+
+fn is_same_or_subdomain(name: &str, domain: &str) -> bool {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+
+    name == domain || name.ends_with(&format!(".{domain}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockResolver;
+    use crate::SpfChecker;
+
+    #[tokio::test]
+    async fn test_check_host_pass_via_included_ip4_network() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("example.com", "v=spf1 include:_spf.provider.net -all");
+        mock_resolver.add_record("_spf.provider.net", "v=spf1 ip4:192.0.2.0/24 ~all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker
+            .check_host(
+                "192.0.2.10".parse().unwrap(),
+                "billing@example.com",
+                "mail.example.com",
+            )
+            .await;
+
+        assert_eq!(result.result, SpfResult::Pass);
+        assert_eq!(result.mechanism, Some("ip4:192.0.2.0/24".to_string()));
+        assert_eq!(
+            result.mechanism_domain,
+            Some("_spf.provider.net".to_string())
+        );
+        assert_eq!(result.dns_lookups, 1);
+        assert_eq!(result.error, None);
+    }
+
+    #[tokio::test]
+    async fn test_check_host_fail_when_include_does_not_match() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("example.com", "v=spf1 include:_spf.provider.net -all");
+        mock_resolver.add_record("_spf.provider.net", "v=spf1 ip4:192.0.2.0/24 ~all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker
+            .check_host(
+                "198.51.100.1".parse().unwrap(),
+                "billing@example.com",
+                "mail.example.com",
+            )
+            .await;
+
+        assert_eq!(result.result, SpfResult::Fail);
+        assert_eq!(result.mechanism, Some("-all".to_string()));
+        assert_eq!(result.mechanism_domain, Some("example.com".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_check_host_a_mx_and_ptr_mechanisms() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 a:web.example.com/24 mx ?ptr:relay.example.net ~all",
+        );
+        mock_resolver.add_addresses("web.example.com", &["192.0.2.1"]);
+        mock_resolver.add_exchanges("example.com", &["mail.example.com"]);
+        mock_resolver.add_addresses("mail.example.com", &["198.51.100.25", "2001:db8::25"]);
+        mock_resolver.add_pointers("203.0.113.7", &["out.relay.example.net"]);
+        mock_resolver.add_addresses("out.relay.example.net", &["203.0.113.7"]);
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+
+        for (ip, result, mechanism) in [
+            ("192.0.2.200", SpfResult::Pass, "a:web.example.com/24"),
+            ("2001:db8::25", SpfResult::Pass, "mx"),
+            ("203.0.113.7", SpfResult::Neutral, "?ptr:relay.example.net"),
+            ("203.0.113.8", SpfResult::SoftFail, "~all"),
+        ] {
+            let evaluation = checker
+                .check_host(ip.parse().unwrap(), "", "example.com")
+                .await;
+
+            assert_eq!(evaluation.result, result, "{ip}");
+            assert_eq!(evaluation.mechanism, Some(mechanism.to_string()), "{ip}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_host_none_and_permerror_results() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("example.com", "v=spf1 redirect=_spf.example.com");
+        mock_resolver.add_record("broken.example.com", "v=spf1 include:example.net/24 -all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let ip = "192.0.2.1".parse().unwrap();
+
+        let none = checker.check_host(ip, "user@other.example", "").await;
+        assert_eq!(none.result, SpfResult::None);
+
+        let missing_redirect = checker.check_host(ip, "user@example.com", "").await;
+        assert_eq!(missing_redirect.result, SpfResult::PermError);
+        assert!(missing_redirect.error.is_some());
+
+        let syntax_error = checker.check_host(ip, "user@broken.example.com", "").await;
+        assert_eq!(syntax_error.result, SpfResult::PermError);
+    }
+
+    #[tokio::test]
+    async fn test_check_host_dns_lookup_limit() {
+        let mock_resolver = MockResolver::new();
+
+        for index in 0..=DNS_LOOKUP_LIMIT {
+            mock_resolver.add_record(
+                &format!("spf{index}.example.com"),
+                &format!("v=spf1 include:spf{}.example.com -all", index + 1),
+            );
+        }
+        mock_resolver.add_record(
+            &format!("spf{}.example.com", DNS_LOOKUP_LIMIT + 1),
+            "v=spf1 ip4:192.0.2.1 -all",
+        );
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker
+            .check_host("192.0.2.1".parse().unwrap(), "user@spf0.example.com", "")
+            .await;
+
+        assert_eq!(result.result, SpfResult::PermError);
+        assert_eq!(result.dns_lookups, DNS_LOOKUP_LIMIT + 1);
+    }
+
+    #[tokio::test]
+    async fn test_check_host_expands_macros() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("example.com", "v=spf1 include:%{l}._spf.%{d} -all");
+        mock_resolver.add_record(
+            "billing._spf.example.com",
+            "v=spf1 a:%{ir}.hosts.%{d2} -all",
+        );
+        mock_resolver.add_addresses("10.2.0.192.hosts.example.com", &["192.0.2.10"]);
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker
+            .check_host("192.0.2.10".parse().unwrap(), "billing@example.com", "")
+            .await;
+
+        assert_eq!(result.result, SpfResult::Pass);
+        assert_eq!(
+            result.mechanism_domain,
+            Some("billing._spf.example.com".to_string())
+        );
+
+        mock_resolver.add_record("broken.example.com", "v=spf1 a:%{x}.example.com -all");
+        let invalid = checker
+            .check_host(
+                "192.0.2.10".parse().unwrap(),
+                "billing@broken.example.com",
+                "",
+            )
+            .await;
+        assert_eq!(invalid.result, SpfResult::PermError);
+    }
+
+    #[tokio::test]
+    async fn test_check_host_void_lookup_limit() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 a:gone1.example.com mx:gone2.example.com ip4:192.0.2.1 -all",
+        );
+        mock_resolver.add_record(
+            "void.example.com",
+            "v=spf1 a:gone1.example.com mx:gone2.example.com a:gone3.example.com ip4:192.0.2.1 -all",
+        );
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let ip = "192.0.2.1".parse().unwrap();
+
+        let within_limit = checker.check_host(ip, "user@example.com", "").await;
+        assert_eq!(within_limit.result, SpfResult::Pass);
+        assert_eq!(within_limit.void_lookups, 2);
+
+        let exceeded = checker.check_host(ip, "user@void.example.com", "").await;
+        assert_eq!(exceeded.result, SpfResult::PermError);
+        assert_eq!(exceeded.void_lookups, 3);
+    }
+
+    #[tokio::test]
+    async fn test_check_host_exists_mechanism() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("example.com", "v=spf1 exists:%{ir}.%{v}._spf.%{d} -all");
+        mock_resolver.add_addresses("10.2.0.192.in-addr._spf.example.com", &["127.0.0.2"]);
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+
+        let allowed = checker
+            .check_host("192.0.2.10".parse().unwrap(), "user@example.com", "")
+            .await;
+        assert_eq!(allowed.result, SpfResult::Pass);
+        assert_eq!(
+            allowed.mechanism,
+            Some("exists:%{ir}.%{v}._spf.%{d}".to_string())
+        );
+        assert_eq!(allowed.void_lookups, 0);
+
+        let denied = checker
+            .check_host("192.0.2.11".parse().unwrap(), "user@example.com", "")
+            .await;
+        assert_eq!(denied.result, SpfResult::Fail);
+        assert_eq!(denied.void_lookups, 1);
+    }
+
+    #[tokio::test]
+    async fn test_check_host_explanation() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 include:_spf.example.com ip4:192.0.2.1 exp=explain.%{d} -all",
+        );
+        mock_resolver.add_record(
+            "_spf.example.com",
+            "v=spf1 ip4:198.51.100.0/24 exp=explain.%{d} -all",
+        );
+        mock_resolver.add_txt(
+            "explain.example.com",
+            "%{c} is not allowed to send for %{o}",
+        );
+        mock_resolver.add_txt("explain._spf.example.com", "not used for includes");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+
+        let failed = checker
+            .check_host("203.0.113.5".parse().unwrap(), "user@example.com", "")
+            .await;
+        assert_eq!(failed.result, SpfResult::Fail);
+        assert_eq!(
+            failed.explanation,
+            Some("203.0.113.5 is not allowed to send for example.com".to_string())
+        );
+
+        let passed = checker
+            .check_host("192.0.2.1".parse().unwrap(), "user@example.com", "")
+            .await;
+        assert_eq!(passed.result, SpfResult::Pass);
+        assert_eq!(passed.explanation, None);
+    }
+
+    #[tokio::test]
+    async fn test_check_ip() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("example.com", "v=spf1 include:_spf.example.com -all");
+        mock_resolver.add_record("_spf.example.com", "v=spf1 mx ~all");
+        mock_resolver.add_exchanges("_spf.example.com", &["mx.example.com"]);
+        mock_resolver.add_addresses("mx.example.com", &["192.0.2.25"]);
+
+        let checker = SpfChecker::new(mock_resolver);
+
+        let result = checker
+            .check_ip("Example.COM.", "192.0.2.25".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(result.result, SpfResult::Pass);
+        assert_eq!(result.mechanism, Some("mx".to_string()));
+        assert_eq!(
+            result.mechanism_domain,
+            Some("_spf.example.com".to_string())
+        );
+
+        let result = checker
+            .check_ip("example.com", "198.51.100.1".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(result.result, SpfResult::Fail);
+        assert_eq!(result.mechanism, Some("-all".to_string()));
+    }
+}
@@ -0,0 +1,71 @@
+use crate::{parsed, SpfTreeNode};
+use std::collections::BTreeMap;
+
+/// A mechanism of a record of an SPF tree, as listed by [`SpfTreeNode::mechanisms`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourcedMechanism {
+    /// The mechanism with its qualifier as in the record, e.g. `~ip4:192.0.2.0/24`, omitting
+    /// the default `+`.
+    pub mechanism: String,
+    /// The domain whose record declares the mechanism.
+    pub domain: String,
+    /// The nesting level of the record: 0 for the root domain, 1 for the records it includes
+    /// or redirects to, and so on.
+    pub depth: usize,
+    /// The domains from the root domain to `domain`, through which the mechanism is reached.
+    pub path: Vec<String>,
+}
+
+impl SpfTreeNode {
+    /// Returns every mechanism of the records of the tree with the domain declaring it, the
+    /// mechanisms of each record followed by those of the records it references. A domain
+    /// referenced several times contributes its mechanisms each time.
+    pub fn mechanisms(&self) -> Vec<SourcedMechanism> {
+        let mut mechanisms = Vec::new();
+        let mut nodes = vec![(self, vec![self.domain.clone()])];
+
+        while let Some((node, path)) = nodes.pop() {
+            // The tree only contains records that parse, leniently at least.
+            let Some(spf) = node
+                .record
+                .as_deref()
+                .and_then(|record| parsed::parse(record, false).ok())
+            else {
+                continue;
+            };
+
+            mechanisms.extend(spf.directives().map(|directive| SourcedMechanism {
+                mechanism: directive.to_string(),
+                domain: node.domain.clone(),
+                depth: path.len() - 1,
+                path: path.clone(),
+            }));
+
+            nodes.extend(node.children.iter().rev().map(|child| {
+                let mut path = path.clone();
+                path.push(child.domain.clone());
+                (child, path)
+            }));
+        }
+
+        mechanisms
+    }
+
+    /// Returns the SPF record of each domain of the tree, `None` for those without one.
+    /// Domains whose record could not be looked up are left out.
+    pub fn records(&self) -> BTreeMap<String, Option<String>> {
+        let mut records = BTreeMap::new();
+        let mut nodes = vec![self];
+
+        while let Some(node) = nodes.pop() {
+            if node.is_loop || (node.record.is_none() && node.error.is_some()) {
+                continue;
+            }
+
+            records.insert(node.domain.clone(), node.record.clone());
+            nodes.extend(&node.children);
+        }
+
+        records
+    }
+}
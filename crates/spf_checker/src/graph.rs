@@ -0,0 +1,316 @@
+use crate::{normalize_domain, SpfTreeNode};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Fill and stroke color of the target and of the references leading to it.
+const HIGHLIGHT_FILL: &str = "#c8e6c9";
+const HIGHLIGHT_STROKE: &str = "#2e7d32";
+
+/// The include and redirect relationships of an SPF tree, with one node per domain, so that a
+/// domain referenced several times and loops back to an ancestor show up as such.
+struct Graph {
+    nodes: Vec<GraphNode>,
+    edges: Vec<GraphEdge>,
+}
+
+struct GraphNode {
+    domain: String,
+    has_record: bool,
+    error: Option<String>,
+    is_target: bool,
+}
+
+struct GraphEdge {
+    from: usize,
+    to: usize,
+    /// The kind of the referencing term with its qualifier, e.g. `include` or `~include`.
+    label: String,
+    is_loop: bool,
+    /// Whether the edge lies on a path from the root to the target.
+    on_path: bool,
+}
+
+impl Graph {
+    fn new(tree: &SpfTreeNode, target: Option<&str>) -> Self {
+        let mut graph = Self {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        };
+        let mut indexes = HashMap::new();
+        let target = target.map(normalize_domain);
+
+        graph.add(tree, None, &mut indexes, &mut Vec::new(), target.as_deref());
+
+        graph
+    }
+
+    /// Adds `node` and its descendants below `parent`, marking the edges in `ancestors` as
+    /// leading to the target once it is reached.
+    fn add(
+        &mut self,
+        node: &SpfTreeNode,
+        parent: Option<usize>,
+        indexes: &mut HashMap<String, usize>,
+        ancestors: &mut Vec<usize>,
+        target: Option<&str>,
+    ) {
+        let is_target = target == Some(normalize_domain(&node.domain).as_str());
+
+        let index = *indexes.entry(node.domain.clone()).or_insert_with(|| {
+            self.nodes.push(GraphNode {
+                domain: node.domain.clone(),
+                has_record: node.record.is_some(),
+                error: node.error.clone(),
+                is_target,
+            });
+            self.nodes.len() - 1
+        });
+
+        if let Some(parent) = parent {
+            let label = node
+                .term
+                .as_deref()
+                .and_then(|term| term.split([':', '=']).next())
+                .unwrap_or_default()
+                .to_string();
+
+            let edge = match self
+                .edges
+                .iter()
+                .position(|edge| edge.from == parent && edge.to == index && edge.label == label)
+            {
+                Some(edge) => edge,
+                None => {
+                    self.edges.push(GraphEdge {
+                        from: parent,
+                        to: index,
+                        label,
+                        is_loop: node.is_loop,
+                        on_path: false,
+                    });
+                    self.edges.len() - 1
+                }
+            };
+
+            ancestors.push(edge);
+        }
+
+        if is_target {
+            for edge in ancestors.iter() {
+                self.edges[*edge].on_path = true;
+            }
+        }
+
+        if !node.is_loop {
+            for child in &node.children {
+                self.add(child, Some(index), indexes, ancestors, target);
+            }
+        }
+
+        if parent.is_some() {
+            ancestors.pop();
+        }
+    }
+
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph spf {\n    rankdir=LR;\n    node [shape=box];\n");
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            let mut attributes = vec![format!("label=\"{}\"", dot_escape(&node.label()))];
+
+            if node.is_target {
+                attributes.push(format!(
+                    "style=\"filled,bold\", fillcolor=\"{HIGHLIGHT_FILL}\", color=\"{HIGHLIGHT_STROKE}\""
+                ));
+            } else if !node.has_record {
+                attributes.push("style=dashed".to_string());
+            }
+
+            let _ = writeln!(dot, "    n{index} [{}];", attributes.join(", "));
+        }
+
+        for edge in &self.edges {
+            let mut attributes = vec![format!("label=\"{}\"", dot_escape(&edge.label))];
+
+            if edge.on_path {
+                attributes.push(format!("color=\"{HIGHLIGHT_STROKE}\", penwidth=2"));
+            }
+
+            if edge.is_loop {
+                attributes.push("style=dashed".to_string());
+            }
+
+            let _ = writeln!(
+                dot,
+                "    n{} -> n{} [{}];",
+                edge.from,
+                edge.to,
+                attributes.join(", ")
+            );
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn to_mermaid(&self) -> String {
+        let mut mermaid = String::from("graph LR\n");
+
+        for (index, node) in self.nodes.iter().enumerate() {
+            let label = mermaid_escape(&node.label());
+
+            // Stadium shapes mark domains without record, which receivers fail to evaluate.
+            let _ = if node.has_record {
+                writeln!(mermaid, "    n{index}[\"{label}\"]")
+            } else {
+                writeln!(mermaid, "    n{index}([\"{label}\"])")
+            };
+        }
+
+        for edge in &self.edges {
+            let arrow = if edge.is_loop { "-.->" } else { "-->" };
+
+            let _ = writeln!(
+                mermaid,
+                "    n{} {arrow}|\"{}\"| n{}",
+                edge.from,
+                mermaid_escape(&edge.label),
+                edge.to
+            );
+        }
+
+        let targets: Vec<_> = (0..self.nodes.len())
+            .filter(|index| self.nodes[*index].is_target)
+            .map(|index| format!("n{index}"))
+            .collect();
+
+        if !targets.is_empty() {
+            let _ = writeln!(
+                mermaid,
+                "    classDef target fill:{HIGHLIGHT_FILL},stroke:{HIGHLIGHT_STROKE},stroke-width:2px"
+            );
+            let _ = writeln!(mermaid, "    class {} target", targets.join(","));
+        }
+
+        let paths: Vec<_> = (0..self.edges.len())
+            .filter(|index| self.edges[*index].on_path)
+            .map(|index| index.to_string())
+            .collect();
+
+        if !paths.is_empty() {
+            let _ = writeln!(
+                mermaid,
+                "    linkStyle {} stroke:{HIGHLIGHT_STROKE},stroke-width:2px",
+                paths.join(",")
+            );
+        }
+
+        mermaid
+    }
+}
+
+impl GraphNode {
+    fn label(&self) -> String {
+        match (&self.error, self.has_record) {
+            (Some(error), _) => format!("{}\n{}", self.domain, error),
+            (None, false) => format!("{}\nno SPF record", self.domain),
+            (None, true) => self.domain.clone(),
+        }
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn mermaid_escape(text: &str) -> String {
+    text.replace('"', "#quot;").replace('\n', "<br>")
+}
+
+impl SpfTreeNode {
+    /// Renders the include and redirect relationships of the tree as a Graphviz DOT graph, with
+    /// one node per domain. Nodes of `target`, if given, and the references leading to them from
+    /// the root are highlighted; domains without record are drawn dashed.
+    pub fn to_dot(&self, target: Option<&str>) -> String {
+        Graph::new(self, target).to_dot()
+    }
+
+    /// Renders the tree like [`SpfTreeNode::to_dot`] as a Mermaid flowchart, drawing domains
+    /// without record with rounded edges.
+    pub fn to_mermaid(&self, target: Option<&str>) -> String {
+        Graph::new(self, target).to_mermaid()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(domain: &str, term: Option<&str>, children: Vec<SpfTreeNode>) -> SpfTreeNode {
+        SpfTreeNode {
+            domain: domain.to_string(),
+            term: term.map(str::to_string),
+            record: Some("v=spf1 -all".to_string()),
+            is_loop: false,
+            error: None,
+            children,
+        }
+    }
+
+    #[test]
+    fn test_graph_highlights_path_to_target() {
+        let mut missing = node(
+            "_spf.gone.example",
+            Some("include:_spf.gone.example"),
+            vec![],
+        );
+        missing.record = None;
+
+        let tree = node(
+            "example.com",
+            None,
+            vec![
+                node(
+                    "_spf.provider.net",
+                    Some("~include:_spf.provider.net"),
+                    vec![node(
+                        "spf.target.net",
+                        Some("include:spf.target.net"),
+                        vec![],
+                    )],
+                ),
+                missing,
+            ],
+        );
+
+        assert_eq!(
+            tree.to_dot(Some("SPF.target.net.")),
+            "digraph spf {\n    rankdir=LR;\n    node [shape=box];\n\
+             \x20   n0 [label=\"example.com\"];\n\
+             \x20   n1 [label=\"_spf.provider.net\"];\n\
+             \x20   n2 [label=\"spf.target.net\", style=\"filled,bold\", fillcolor=\"#c8e6c9\", color=\"#2e7d32\"];\n\
+             \x20   n3 [label=\"_spf.gone.example\\nno SPF record\", style=dashed];\n\
+             \x20   n0 -> n1 [label=\"~include\", color=\"#2e7d32\", penwidth=2];\n\
+             \x20   n1 -> n2 [label=\"include\", color=\"#2e7d32\", penwidth=2];\n\
+             \x20   n0 -> n3 [label=\"include\"];\n\
+             }\n"
+        );
+
+        assert_eq!(
+            tree.to_mermaid(Some("spf.target.net")),
+            "graph LR\n\
+             \x20   n0[\"example.com\"]\n\
+             \x20   n1[\"_spf.provider.net\"]\n\
+             \x20   n2[\"spf.target.net\"]\n\
+             \x20   n3([\"_spf.gone.example<br>no SPF record\"])\n\
+             \x20   n0 -->|\"~include\"| n1\n\
+             \x20   n1 -->|\"include\"| n2\n\
+             \x20   n0 -->|\"include\"| n3\n\
+             \x20   classDef target fill:#c8e6c9,stroke:#2e7d32,stroke-width:2px\n\
+             \x20   class n2 target\n\
+             \x20   linkStyle 0,1 stroke:#2e7d32,stroke-width:2px\n"
+        );
+    }
+}
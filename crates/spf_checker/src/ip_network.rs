@@ -0,0 +1,156 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+/// An IPv4 or IPv6 network in CIDR notation, as used by the `ip4` and `ip6` mechanisms.
+///
+/// The address is kept as written in the record; only the leading `prefix` bits are
+/// significant when comparing networks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    /// Creates a network from an address and a prefix length.
+    ///
+    /// Returns `None` if `prefix` exceeds the bit length of the address family.
+    pub fn new(addr: IpAddr, prefix: u8) -> Option<Self> {
+        (prefix <= max_prefix(addr)).then_some(Self { addr, prefix })
+    }
+
+    /// Creates a network covering exactly one address.
+    pub fn host(addr: IpAddr) -> Self {
+        Self {
+            addr,
+            prefix: max_prefix(addr),
+        }
+    }
+
+    /// The address as written in the record.
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// The number of significant leading bits.
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Returns `true` if `ip` lies within this network.
+    ///
+    /// Addresses of a different family are never contained.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = mask_u32(self.prefix);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = mask_u128(self.prefix);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if every address of `other` lies within this network.
+    pub fn covers(&self, other: &IpNetwork) -> bool {
+        self.prefix <= other.prefix && self.contains(other.addr)
+    }
+
+    /// Returns `true` if this network and `other` share at least one address.
+    pub fn overlaps(&self, other: &IpNetwork) -> bool {
+        self.covers(other) || other.covers(self)
+    }
+
+    /// Returns the network made up of exactly this network and `other`, if they are its two
+    /// halves, e.g. `192.0.2.0/24` for `192.0.2.0/25` and `192.0.2.128/25`.
+    pub fn merge(&self, other: &IpNetwork) -> Option<IpNetwork> {
+        if self.prefix != other.prefix || self.prefix == 0 || self.covers(other) {
+            return None;
+        }
+
+        let prefix = self.prefix - 1;
+        let addr = match self.addr {
+            IpAddr::V4(addr) => IpAddr::V4(Ipv4Addr::from(u32::from(addr) & mask_u32(prefix))),
+            IpAddr::V6(addr) => IpAddr::V6(Ipv6Addr::from(u128::from(addr) & mask_u128(prefix))),
+        };
+        let merged = Self { addr, prefix };
+
+        merged.contains(other.addr).then_some(merged)
+    }
+
+    /// Returns the two halves of the network, e.g. `192.0.2.0/25` and `192.0.2.128/25` for
+    /// `192.0.2.0/24`, or `None` for a single address.
+    pub fn split(&self) -> Option<(IpNetwork, IpNetwork)> {
+        if self.prefix == max_prefix(self.addr) {
+            return None;
+        }
+
+        let prefix = self.prefix + 1;
+        let (low, high) = match self.addr {
+            IpAddr::V4(addr) => {
+                let low = u32::from(addr) & mask_u32(self.prefix);
+                let high = low | 1 << (32 - u32::from(prefix));
+                (IpAddr::V4(low.into()), IpAddr::V4(high.into()))
+            }
+            IpAddr::V6(addr) => {
+                let low = u128::from(addr) & mask_u128(self.prefix);
+                let high = low | 1 << (128 - u32::from(prefix));
+                (IpAddr::V6(low.into()), IpAddr::V6(high.into()))
+            }
+        };
+
+        Some((Self { addr: low, prefix }, Self { addr: high, prefix }))
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = String;
+
+    /// Parses `addr` or `addr/prefix`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value, None),
+        };
+
+        let addr = IpAddr::from_str(addr).map_err(|_| format!("invalid IP address `{addr}`"))?;
+
+        match prefix {
+            None => Ok(Self::host(addr)),
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .and_then(|prefix| Self::new(addr, prefix))
+                .ok_or_else(|| format!("invalid prefix length `{prefix}`")),
+        }
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.prefix == max_prefix(self.addr) {
+            write!(f, "{}", self.addr)
+        } else {
+            write!(f, "{}/{}", self.addr, self.prefix)
+        }
+    }
+}
+
+fn max_prefix(addr: IpAddr) -> u8 {
+    match addr {
+        IpAddr::V4(_) => 32,
+        IpAddr::V6(_) => 128,
+    }
+}
+
+fn mask_u32(prefix: u8) -> u32 {
+    u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0)
+}
+
+fn mask_u128(prefix: u8) -> u128 {
+    u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0)
+}
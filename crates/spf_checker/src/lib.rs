@@ -1,419 +1,4375 @@
-use anyhow::{Context, Result};
+mod bimi;
+mod budget;
+mod builder;
+mod cache;
+mod coalesce;
+mod complexity;
+mod coverage;
+mod dangling;
+mod diff;
+mod dkim;
+mod dmarc;
+mod dnssec;
+mod draft;
+mod error;
+mod evaluation;
+mod flatten;
+mod graph;
+mod ip_network;
+mod lint;
+mod lru;
+mod macros;
+mod memo;
+mod mta_sts;
+mod mx;
+mod optimize;
+mod parsed;
+mod providers;
+pub mod record;
+#[cfg(test)]
+pub(crate) mod test_support;
+mod timing;
+mod trace;
+
+pub use bimi::{BimiRecord, BimiReport, DEFAULT_SELECTOR};
+pub use budget::{BudgetTerm, LookupBudget};
+pub use builder::{BuiltRecord, RecordSpec};
+pub use cache::{
+    CachedRecords, DnsCache, DnsCacheStats, MemoryCache, SpfCache, DEFAULT_CACHE_CAPACITY,
+    DEFAULT_NEGATIVE_TTL,
+};
+pub use complexity::Complexity;
+pub use coverage::Coverage;
+pub use dangling::DanglingTerm;
+pub use diff::SpfDiff;
+pub use dkim::{DkimKey, DkimReport, COMMON_DKIM_SELECTORS};
+pub use dmarc::{Alignment, DmarcParseError, DmarcPolicy, DmarcRecord, DmarcReport};
+pub use dnssec::{DnssecReport, DomainAuthentication};
+pub use error::CheckError;
+pub use evaluation::{Evaluation, SpfResult};
+pub use flatten::SourcedMechanism;
+pub use ip_network::IpNetwork;
+pub use lint::{Finding, Severity, Validation};
+pub use mta_sts::{MtaStsMode, MtaStsPolicy, MtaStsReport, PolicyFetcher};
+pub use mx::{MxHost, MxRecord, MxReport};
+pub use optimize::{Optimization, OptimizedRecord};
+pub use providers::{identify_provider, EmailProvider, ProviderMatch, EMAIL_PROVIDERS};
+pub use timing::{QueryTiming, QueryTimings};
+pub use trace::{Explanation, ProgressListener, TraceStep};
+
+use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
-use decon_spf::Spf;
-use std::collections::HashSet;
+use evaluation::Evaluator;
+use lint::Linter;
+use macros::MacroContext;
+use record::{Directive, Mechanism, Qualifier, SpfRecord};
+use std::collections::{BTreeMap, HashSet};
 use std::fmt::Debug;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::pin::Pin;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+use trust_dns_resolver::proto::rr::rdata::{MX, TXT};
+use trust_dns_resolver::proto::rr::{RData, RecordType};
+use trust_dns_resolver::proto::serialize::binary::BinEncodable;
 use trust_dns_resolver::TokioAsyncResolver;
 
+/// DNS queries needed for SPF resolution and evaluation.
+///
+/// Domains that do not exist or have no records of the requested type yield an empty
+/// result rather than an error.
 #[async_trait]
 pub trait SpnResolver: Debug {
-    async fn find_spf_record(&self, domain: &str) -> Result<Option<String>>;
+    /// Resolves all `v=spf1` TXT records of `domain`.
+    ///
+    /// More than one record is a permanent error (RFC 7208 §4.5) that callers must detect.
+    async fn find_spf_records(&self, domain: &str) -> Result<Vec<String>>;
+
+    /// Resolves all TXT records of `domain`, e.g. the explanation of an `exp` modifier.
+    async fn lookup_txt(&self, domain: &str) -> Result<Vec<String>>;
+
+    /// Resolves all TXT records of `domain` with their TTLs.
+    async fn lookup_txt_records(&self, domain: &str) -> Result<Vec<TxtRecord>>;
+
+    /// Resolves the `A` records of `domain`.
+    async fn lookup_a(&self, domain: &str) -> Result<Vec<Ipv4Addr>>;
+
+    /// Resolves the `AAAA` records of `domain`.
+    async fn lookup_aaaa(&self, domain: &str) -> Result<Vec<Ipv6Addr>>;
+
+    /// Resolves the exchange host names of the `MX` records of `domain`.
+    async fn lookup_mx(&self, domain: &str) -> Result<Vec<String>>;
+
+    /// Resolves the `MX` records of `domain` with their preferences.
+    async fn lookup_mx_records(&self, domain: &str) -> Result<Vec<MxRecord>>;
+
+    /// Resolves the `PTR` host names of `ip`.
+    async fn lookup_ptr(&self, ip: IpAddr) -> Result<Vec<String>>;
+
+    /// Resolves the records of the deprecated `SPF` type (99) of `domain`, their
+    /// character-strings concatenated like those of TXT records.
+    async fn lookup_spf_rr(&self, domain: &str) -> Result<Vec<String>>;
 }
 
 #[async_trait]
 impl SpnResolver for TokioAsyncResolver {
-    async fn find_spf_record(&self, domain: &str) -> Result<Option<String>> {
-        let response = self.txt_lookup(domain).await.context("DNS_LOOKUP_FAILED")?;
+    async fn find_spf_records(&self, domain: &str) -> Result<Vec<String>> {
+        let records = self.lookup_txt(domain).await?;
 
-        Ok(response.iter().find_map(|record| {
-            let txt = record.to_string();
-            txt.starts_with("v=spf1").then_some(txt)
-        }))
+        Ok(records
+            .into_iter()
+            .filter(|txt| is_spf_record(txt))
+            .collect())
     }
-}
 
-pub struct CheckResult {
-    pub found: bool,
-    pub visited: usize,
-    pub spf_record: Option<String>,
-    pub included_domains: Option<Vec<String>>,
-    pub fallback_check: bool,
-}
+    async fn lookup_txt(&self, domain: &str) -> Result<Vec<String>> {
+        records_or_empty(self.txt_lookup(domain).await, |response| {
+            response.iter().map(txt_string).collect()
+        })
+    }
 
-/// https://datatracker.ietf.org/doc/html/rfc7208#section-4.6.4
-///
-/// > SPF implementations MUST limit the total number of those terms to 10
-/// > during SPF evaluation, to avoid unreasonable load on the DNS.
-const DNS_LOOKUP_LIMIT: usize = 10;
+    async fn lookup_txt_records(&self, domain: &str) -> Result<Vec<TxtRecord>> {
+        records_or_empty(self.txt_lookup(domain).await, |response| {
+            response
+                .as_lookup()
+                .record_iter()
+                .filter_map(|record| match record.data()? {
+                    RData::TXT(txt) => Some(TxtRecord {
+                        text: txt_string(txt),
+                        ttl: record.ttl(),
+                    }),
+                    _ => None,
+                })
+                .collect()
+        })
+    }
 
-fn log_message(msg: impl AsRef<str>) {
-    println!(
-        "[{}] {}",
-        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f"),
-        msg.as_ref()
-    );
-}
+    async fn lookup_a(&self, domain: &str) -> Result<Vec<Ipv4Addr>> {
+        records_or_empty(self.ipv4_lookup(domain).await, |response| {
+            response.iter().map(|record| record.0).collect()
+        })
+    }
 
-#[derive(Clone, Debug)]
-pub struct SpfChecker {
-    resolver: Arc<dyn SpnResolver + Send + Sync + 'static>,
-}
+    async fn lookup_aaaa(&self, domain: &str) -> Result<Vec<Ipv6Addr>> {
+        records_or_empty(self.ipv6_lookup(domain).await, |response| {
+            response.iter().map(|record| record.0).collect()
+        })
+    }
 
-impl SpfChecker {
-    pub fn new<R>(resolver: R) -> Self
-    where
-        R: SpnResolver + Send + Sync + 'static,
-    {
-        Self {
-            resolver: Arc::new(resolver),
-        }
+    async fn lookup_mx(&self, domain: &str) -> Result<Vec<String>> {
+        records_or_empty(self.mx_lookup(domain).await, |response| {
+            response
+                .iter()
+                .map(|record| host_name(record.exchange()))
+                .collect()
+        })
     }
 
-    pub async fn check(&self, root_domain: &String, target: &String) -> Result<CheckResult> {
-        // First, try the original logic
-        let initial_result = self.check_direct_include(root_domain, target).await?;
+    async fn lookup_mx_records(&self, domain: &str) -> Result<Vec<MxRecord>> {
+        records_or_empty(self.mx_lookup(domain).await, |response| {
+            response.iter().map(mx_record).collect()
+        })
+    }
 
-        if initial_result.found {
-            return Ok(initial_result);
+    async fn lookup_ptr(&self, ip: IpAddr) -> Result<Vec<String>> {
+        records_or_empty(self.reverse_lookup(ip).await, |response| {
+            response.iter().map(host_name).collect()
+        })
+    }
+
+    async fn lookup_spf_rr(&self, domain: &str) -> Result<Vec<String>> {
+        let record_type = RecordType::from(SPF_RR_TYPE);
+
+        records_or_empty(self.lookup(domain, record_type).await, |response| {
+            response
+                .record_iter()
+                .filter(|record| record.record_type() == record_type)
+                .filter_map(|record| record.data()?.to_bytes().ok())
+                .map(|rdata| character_strings(&rdata))
+                .collect()
+        })
+    }
+}
+
+/// Maps NXDOMAIN and empty answers to an empty result, keeping all other failures as errors.
+fn records_or_empty<L, T>(
+    response: std::result::Result<L, ResolveError>,
+    records: impl FnOnce(L) -> Vec<T>,
+) -> Result<Vec<T>> {
+    match response {
+        Ok(response) => Ok(records(response)),
+        Err(err) if matches!(err.kind(), ResolveErrorKind::NoRecordsFound { .. }) => Ok(Vec::new()),
+        Err(err) if matches!(err.kind(), ResolveErrorKind::Timeout) => {
+            Err(err).context(CheckError::DnsTimeout)
         }
+        Err(err) => Err(err).context(CheckError::DnsLookupFailed),
+    }
+}
 
-        // If target include not found, try fallback mechanism check
-        log_message(format!(
-            "Target include '{target}' not found directly. Attempting fallback mechanism check."
-        ));
+/// https://datatracker.ietf.org/doc/html/rfc7208#section-3.3
+///
+/// > If a published record contains multiple character-strings, then the record MUST be
+/// > treated as if those strings are concatenated together without adding spaces.
+fn txt_string(txt: &TXT) -> String {
+    txt.txt_data()
+        .iter()
+        .map(|segment| String::from_utf8_lossy(segment))
+        .collect()
+}
 
-        self.check_target_mechanisms(root_domain, target, initial_result)
-            .await
+/// Concatenates the character-strings of RDATA in the format of TXT records, each prefixed by
+/// its length, as used by records of the `SPF` type whose type the resolver does not know.
+fn character_strings(rdata: &[u8]) -> String {
+    let mut text = String::new();
+    let mut rest = rdata;
+
+    while let Some((&length, tail)) = rest.split_first() {
+        let (segment, tail) = tail.split_at(usize::from(length).min(tail.len()));
+        text.push_str(&String::from_utf8_lossy(segment));
+        rest = tail;
     }
 
-    async fn check_direct_include(
-        &self,
-        root_domain: &String,
-        target: &String,
-    ) -> Result<CheckResult> {
-        let mut to_visit_stack = vec![root_domain.to_owned()];
-        let mut visited = HashSet::new();
+    text
+}
 
-        let mut root_spf_record = None;
-        let mut included_domains: Vec<String> = Vec::new();
+/// https://datatracker.ietf.org/doc/html/rfc7208#section-3.1
+///
+/// > SPF records MUST be published as a DNS TXT (type 16) Resource Record (RR) [RFC1035] only.
+/// > [...] Use of alternative DNS RR types was supported in SPF's experimental phase but has
+/// > been discontinued.
+const SPF_RR_TYPE: u16 = 99;
 
-        while let Some(current_domain) = to_visit_stack.pop() {
-            if visited.len() >= DNS_LOOKUP_LIMIT {
-                log_message(format!(
-                    "Maximum DNS lookup limit reached of {} reached. Visited domains: {:?}",
-                    DNS_LOOKUP_LIMIT,
-                    visited.iter().collect::<Vec<_>>()
-                ));
-                break;
-            }
+/// https://datatracker.ietf.org/doc/html/rfc7208#section-4.5
+///
+/// > Records that do not begin with a version section of exactly "v=spf1" are discarded.
+fn is_spf_record(txt: &str) -> bool {
+    let version = txt.split(' ').next().unwrap_or_default();
 
-            if !visited.insert(current_domain.clone()) {
-                // Already visited
-                continue;
-            }
+    version.eq_ignore_ascii_case("v=spf1")
+}
 
-            let Some(spf_txt) = self.resolver.find_spf_record(&current_domain).await? else {
-                continue;
-            };
+/// https://datatracker.ietf.org/doc/html/rfc4406
+///
+/// Sender ID records start with `spf2.0/` followed by the identities they cover, e.g.
+/// `spf2.0/pra` or `spf2.0/mfrom,pra`. RFC 6686 moved Sender ID to historic status, so
+/// receivers no longer evaluate them.
+fn is_sender_id_record(txt: &str) -> bool {
+    let version = txt.split(' ').next().unwrap_or_default();
 
-            let spf = Spf::from_str(&spf_txt).context("SPF_PARSE_FAILED")?;
+    version.len() > 7 && version[..7].eq_ignore_ascii_case("spf2.0/")
+}
 
-            if root_domain == &current_domain {
-                root_spf_record = Some(spf_txt);
-            }
+/// A TXT record as returned by [`SpfChecker::txt_records`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TxtRecord {
+    /// The character strings of the record, concatenated without separator.
+    pub text: String,
+    /// The remaining time to live in seconds, as reported by the resolver.
+    pub ttl: u32,
+}
 
-            let includes: Vec<String> = spf
-                .iter()
-                .filter(|mechanism| mechanism.kind().is_include())
-                .map(|mechanism| mechanism.raw())
-                .collect();
+impl TxtRecord {
+    /// Returns `true` if the record is an SPF record (RFC 7208 §4.5).
+    pub fn is_spf(&self) -> bool {
+        is_spf_record(&self.text)
+    }
 
-            included_domains.extend(includes.iter().cloned());
+    /// Returns `true` if the record is a legacy Sender ID record, e.g. `spf2.0/pra ...`.
+    pub fn is_sender_id(&self) -> bool {
+        is_sender_id_record(&self.text)
+    }
 
-            if includes.contains(target) {
-                // Target found
-                return Ok(CheckResult {
-                    found: true,
-                    visited: visited.len(),
-                    spf_record: root_spf_record,
-                    included_domains: Some(included_domains),
-                    fallback_check: false,
-                });
-            }
+    /// Returns `true` if the record is not an SPF record, but was likely meant to be one, e.g.
+    /// `v=spf 1 ...`, `v = spf1 ...` or `v=spf1include:...`.
+    pub fn looks_like_spf(&self) -> bool {
+        let compact: String = self
+            .text
+            .chars()
+            .take(10)
+            .filter(|c| !c.is_whitespace())
+            .collect();
 
-            // https://datatracker.ietf.org/doc/html/rfc7208#section-6.1
-            //
-            // > Any "redirect" modifier MUST be ignored if there is an "all" mechanism anywhere in
-            // > the record."
-            if !spf.iter().any(|mechanism| mechanism.kind().is_all()) {
-                let redirect = spf
-                    .iter()
-                    .filter(|mechanism| mechanism.kind().is_redirect())
-                    .map(|mechanism| mechanism.raw());
+        !self.is_spf() && compact.to_ascii_lowercase().starts_with("v=spf")
+    }
+}
 
-                to_visit_stack.extend(redirect);
-            }
+/// Error of a domain publishing more than one SPF record, which RFC 7208 §4.5 defines as a
+/// permanent error.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("{}", CheckError::MultipleSpfRecords)]
+pub struct MultipleSpfRecords {
+    pub domain: String,
+    pub records: Vec<String>,
+}
 
-            // Prefer `include` before `redirect` by pushing them onto the top of the stack.
-            to_visit_stack.extend(includes);
-        }
+/// Returns the only SPF record of `domain`, if any.
+pub(crate) async fn find_spf_record(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    domain: &str,
+) -> Result<Option<String>> {
+    only_spf_record(domain, resolver.find_spf_records(domain).await?)
+}
 
-        // Target not found in any domain
-        Ok(CheckResult {
-            found: false,
-            visited: visited.len(),
-            spf_record: root_spf_record,
-            included_domains: Some(included_domains),
-            fallback_check: false,
-        })
+/// Returns the only one of the SPF `records` of `domain`, if any.
+fn only_spf_record(domain: &str, mut records: Vec<String>) -> Result<Option<String>> {
+    if records.len() > 1 {
+        return Err(MultipleSpfRecords {
+            domain: domain.to_owned(),
+            records,
+        }
+        .into());
     }
 
-    async fn check_target_mechanisms(
-        &self,
-        root_domain: &String,
-        target: &String,
-        initial_result: CheckResult,
-    ) -> Result<CheckResult> {
-        // Resolve the target includes SPF record
-        let Some(target_spf_txt) = self.resolver.find_spf_record(target).await? else {
-            log_message(format!("No SPF record found for target domain: {target}"));
-            return Ok(CheckResult {
-                found: false,
-                visited: initial_result.visited,
-                spf_record: initial_result.spf_record,
-                included_domains: initial_result.included_domains,
-                fallback_check: true,
-            });
-        };
+    Ok(records.pop())
+}
+
+/// https://datatracker.ietf.org/doc/html/rfc7208#section-3.3
+///
+/// > TXT records have a maximum length of 255 octets per character-string.
+const MAX_TXT_STRING_LENGTH: usize = 255;
 
-        let target_spf = Spf::from_str(&target_spf_txt).context("TARGET_SPF_PARSE_FAILED")?;
+/// https://datatracker.ietf.org/doc/html/rfc7208#section-3.4
+///
+/// > The published SPF record for a given domain name SHOULD remain small enough that the
+/// > results of a query for it will fit within 512 octets. Otherwise, there is a possibility
+/// > of exceeding a DNS protocol limit.
+const MAX_UDP_ANSWER_SIZE: usize = 512;
 
-        // Extract mechanisms from target SPF (excluding 'all' mechanisms)
-        let target_mechanisms: Vec<String> = target_spf
-            .iter()
-            .filter(|mechanism| !mechanism.kind().is_all()) // Exclude 'all' mechanisms
-            .map(|mechanism| mechanism.raw())
-            .collect();
+/// Size of a TXT answer from which it is reported as close to [`MAX_UDP_ANSWER_SIZE`], leaving
+/// little room to add to the record.
+const NEAR_UDP_ANSWER_SIZE: usize = 450;
 
-        if target_mechanisms.is_empty() {
-            log_message(format!(
-                "No mechanisms found in target SPF record: {target_spf_txt}"
-            ));
-            return Ok(CheckResult {
-                found: false,
-                visited: initial_result.visited,
-                spf_record: initial_result.spf_record,
-                included_domains: initial_result.included_domains,
-                fallback_check: true,
-            });
+/// Size of the SPF record of a domain and of the DNS answer carrying it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordSize {
+    pub domain: String,
+    /// Length of the SPF record in bytes, its character-strings concatenated.
+    pub record_bytes: usize,
+    /// Estimated size in bytes of the DNS message answering the TXT query of the domain,
+    /// including all of its TXT records, not only the SPF one.
+    pub answer_bytes: usize,
+}
+
+impl RecordSize {
+    fn new(domain: &str, spf_txt: &str, txts: &[String]) -> Self {
+        Self {
+            domain: domain.to_owned(),
+            record_bytes: spf_txt.len(),
+            answer_bytes: txt_answer_size(domain, txts),
         }
+    }
 
-        log_message(format!("Target mechanisms to check: {target_mechanisms:?}"));
+    /// Warnings about the record or answer risking truncation.
+    fn warnings(&self) -> Vec<Warning> {
+        let mut warnings = Vec::new();
 
-        // Now check if all target mechanisms are present in root domain's SPF chain
-        let all_mechanisms_found = self
-            .check_all_mechanisms_present(root_domain, &target_mechanisms)
-            .await?;
+        if self.record_bytes > MAX_TXT_STRING_LENGTH {
+            warnings.push(Warning {
+                code: "RECORD_EXCEEDS_255_BYTES",
+                domain: self.domain.clone(),
+                message: format!(
+                    "The SPF record has {} bytes and must be split into multiple character-strings of at most {MAX_TXT_STRING_LENGTH} bytes, which some DNS providers truncate instead",
+                    self.record_bytes
+                ),
+            });
+        }
 
-        Ok(CheckResult {
-            found: all_mechanisms_found,
-            visited: initial_result.visited + 1, // +1 for the target domain lookup
-            spf_record: initial_result.spf_record,
-            included_domains: initial_result.included_domains,
-            fallback_check: true,
-        })
+        if self.answer_bytes > MAX_UDP_ANSWER_SIZE {
+            warnings.push(Warning {
+                code: "ANSWER_EXCEEDS_512_BYTES",
+                domain: self.domain.clone(),
+                message: format!(
+                    "The TXT answer has about {} bytes and exceeds the {MAX_UDP_ANSWER_SIZE}-byte UDP limit, so resolvers without EDNS or TCP fallback may fail to retrieve the SPF record",
+                    self.answer_bytes
+                ),
+            });
+        } else if self.answer_bytes > NEAR_UDP_ANSWER_SIZE {
+            warnings.push(Warning {
+                code: "ANSWER_NEAR_512_BYTES",
+                domain: self.domain.clone(),
+                message: format!(
+                    "The TXT answer has about {} bytes, close to the {MAX_UDP_ANSWER_SIZE}-byte UDP limit, so adding to the record risks truncation",
+                    self.answer_bytes
+                ),
+            });
+        }
+
+        warnings
     }
+}
 
-    async fn check_all_mechanisms_present(
-        &self,
-        root_domain: &String,
-        target_mechanisms: &[String],
-    ) -> Result<bool> {
-        let mut to_visit_stack = vec![root_domain.to_owned()];
-        let mut visited = HashSet::new();
-        let mut found_mechanisms = HashSet::new();
+/// Estimates the size of the DNS message answering the TXT query of `domain` with `txts`,
+/// assuming name compression and no EDNS.
+fn txt_answer_size(domain: &str, txts: &[String]) -> usize {
+    // Header, plus the question's name in wire format with its type and class
+    let question = 12 + domain.trim_end_matches('.').len() + 2 + 4;
 
-        while let Some(current_domain) = to_visit_stack.pop() {
-            if visited.len() >= DNS_LOOKUP_LIMIT {
-                log_message(format!(
-                    "Maximum DNS lookup limit reached during mechanism check: {DNS_LOOKUP_LIMIT}"
-                ));
-                break;
-            }
+    let answers: usize = txts
+        .iter()
+        .map(|txt| {
+            // Each character-string is prefixed by its length
+            let strings = txt.len().div_ceil(MAX_TXT_STRING_LENGTH).max(1);
 
-            if !visited.insert(current_domain.clone()) {
-                continue;
-            }
+            // Compressed name pointer, type, class, TTL and data length
+            2 + 10 + strings + txt.len()
+        })
+        .sum();
 
-            let Some(spf_txt) = self.resolver.find_spf_record(&current_domain).await? else {
-                continue;
-            };
+    question + answers
+}
 
-            let spf = Spf::from_str(&spf_txt).context("SPF_PARSE_FAILED")?;
+/// Resolves the explanation of an `exp` modifier in the record of `domain`.
+///
+/// Returns `None` if the explanation cannot be determined, in which case RFC 7208 §6.2
+/// requires to proceed as if no `exp` modifier was given.
+pub(crate) async fn find_explanation(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    spec: &str,
+    domain: &str,
+    context: &MacroContext<'_>,
+) -> Option<String> {
+    let target = macros::expand_domain(spec, domain, context).ok()?;
+    let mut texts = resolver.lookup_txt(&target).await.ok()?;
 
-            // Collect all mechanisms from current SPF record
-            let current_mechanisms: Vec<String> =
-                spf.iter().map(|mechanism| mechanism.raw()).collect();
+    if texts.len() != 1 {
+        return None;
+    }
 
-            // Check which target mechanisms are present in current record
-            for target_mechanism in target_mechanisms {
-                if current_mechanisms.contains(target_mechanism) {
-                    found_mechanisms.insert(target_mechanism.clone());
-                }
-            }
+    macros::expand_explanation(&texts.pop()?, domain, context).ok()
+}
 
-            // If all target mechanisms found, return early
-            if found_mechanisms.len() == target_mechanisms.len() {
-                log_message(format!("All target mechanisms found: {found_mechanisms:?}"));
-                return Ok(true);
-            }
+fn host_name(name: impl ToString) -> String {
+    name.to_string().trim_end_matches('.').to_owned()
+}
 
-            // Continue traversing includes and redirects
-            let includes: Vec<String> = spf
-                .iter()
-                .filter(|mechanism| mechanism.kind().is_include())
-                .map(|mechanism| mechanism.raw())
-                .collect();
+fn mx_record(mx: &MX) -> MxRecord {
+    let exchange = host_name(mx.exchange());
 
-            if !spf.iter().any(|mechanism| mechanism.kind().is_all()) {
-                let redirect = spf
-                    .iter()
-                    .filter(|mechanism| mechanism.kind().is_redirect())
-                    .map(|mechanism| mechanism.raw());
+    MxRecord {
+        preference: mx.preference(),
+        // The root domain of a Null MX is empty without its trailing dot.
+        exchange: if exchange.is_empty() {
+            ".".to_owned()
+        } else {
+            exchange
+        },
+    }
+}
 
-                to_visit_stack.extend(redirect);
-            }
+/// A finding about a traversed record that does not prevent the check, e.g. a deprecated
+/// `ptr` mechanism.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Warning {
+    /// Stable identifier of the kind of finding, e.g. `PTR_MECHANISM`.
+    pub code: &'static str,
+    /// The domain whose record the finding is about.
+    pub domain: String,
+    /// Human-readable description of the finding.
+    pub message: String,
+}
 
-            to_visit_stack.extend(includes);
+#[derive(Clone)]
+pub struct CheckResult {
+    pub found: bool,
+    pub visited: usize,
+    /// The domains whose records were looked up, in traversal order, including the target if
+    /// the fallback check compared its record.
+    pub visited_domains: Vec<String>,
+    pub spf_record: Option<String>,
+    pub included_domains: Option<Vec<String>>,
+    /// The `redirect` modifiers followed, in traversal order. Unlike includes, whose results
+    /// add to that of the record, a redirect replaces the evaluation of the record with that of
+    /// its target (RFC 7208 §6.1).
+    pub redirect_chain: Vec<Redirect>,
+    pub fallback_check: bool,
+    /// The mechanism through which the target was found, e.g. `include:_spf.example.com` or
+    /// `mx`. Not set by the fallback mechanism check.
+    pub mechanism: Option<String>,
+    /// The domain whose record declares [`CheckResult::mechanism`].
+    pub mechanism_domain: Option<String>,
+    /// The qualifier of [`CheckResult::mechanism`], e.g. [`Qualifier::SoftFail`] for
+    /// `~include:_spf.example.com`.
+    pub qualifier: Option<Qualifier>,
+    /// The qualifier of the `all` mechanism terminating the domain's record, following
+    /// `redirect` modifiers. [`Qualifier::Neutral`] if there is none (RFC 7208 §4.7); `None`
+    /// if the domain has no SPF record.
+    pub default_policy: Option<Qualifier>,
+    /// The terminal `all` mechanism with its qualifier, e.g. `?all`, `None` if the record
+    /// governing [`CheckResult::default_policy`] has none and the policy is only implied.
+    pub all_mechanism: Option<String>,
+    /// The domain of the record governing [`CheckResult::default_policy`]: the checked domain
+    /// or the last target of its `redirect` chain.
+    pub default_policy_domain: Option<String>,
+    /// The explanation published through the `exp` modifier of the record declaring
+    /// [`CheckResult::default_policy`], with macros expanded.
+    pub explanation: Option<String>,
+    /// Number of DNS-querying terms (`include`, `a`, `mx`, `ptr`, `exists` and `redirect`) in
+    /// the traversed records, which RFC 7208 §4.6.4 limits to [`DNS_LOOKUP_LIMIT`].
+    pub dns_mechanism_count: usize,
+    /// Whether [`CheckResult::dns_mechanism_count`] exceeds [`DNS_LOOKUP_LIMIT`], which makes
+    /// receivers fail the evaluation with permerror. The traversal stops at that point, so the
+    /// target may not have been found only because it is referenced beyond the limit.
+    pub limit_exceeded: bool,
+    /// Number of `a` and `mx` lookups that returned no records.
+    pub void_lookups: usize,
+    /// Include or redirect cycles, each starting and ending with the same domain, e.g.
+    /// `["example.com", "b.com", "example.com"]`.
+    pub loops: Vec<Vec<String>>,
+    /// The domains traversed from the root domain to the one declaring
+    /// [`CheckResult::mechanism`], followed by the target, e.g.
+    /// `["example.com", "_spf.provider.net", "mail.easybill.de"]`.
+    pub path: Option<Vec<String>>,
+    /// Findings about the traversed records, in traversal order.
+    pub warnings: Vec<Warning>,
+    /// Sizes of the SPF records of the traversed domains, in traversal order.
+    pub record_sizes: Vec<RecordSize>,
+    /// The SPF record of each traversed domain, `None` for those without one. Includes the
+    /// target if the fallback check looked up its record.
+    pub records: BTreeMap<String, Option<String>>,
+    /// The remaining TTL in seconds of the SPF record of each traversed domain with one, as
+    /// reported by the resolver: how long receivers may keep evaluating the current record.
+    pub ttls: BTreeMap<String, u32>,
+    /// For a network target not covered by any single `ip4` or `ip6` mechanism, those sharing
+    /// addresses with it.
+    pub overlapping_mechanisms: Vec<MechanismMatch>,
+    /// Terms whose macros cannot be expanded with the given [`CheckOptions`], e.g.
+    /// `include:%{i}._spf.example.net` without client IP, which were skipped.
+    pub skipped_macro_terms: Vec<SkippedTerm>,
+    /// Every mechanism matching the target, in traversal order. Only wildcard targets such as
+    /// `*.sendgrid.net` and [`CheckOptions::all_matches`] traverse the whole chain; others stop
+    /// at the record of the first match.
+    pub matches: Vec<MechanismMatch>,
+    /// Mechanisms declared more than once across the traversed records, e.g. an include of
+    /// `_spf.google.com` both by the root domain and by a vendor's record, in order of their
+    /// first occurrence. Repeated DNS-querying mechanisms waste lookups of the limit.
+    pub duplicates: Vec<DuplicateMechanism>,
+    /// The Sender ID records (`spf2.0/...`) published next to the SPF record of the root
+    /// domain, which receivers no longer evaluate.
+    pub sender_id_records: Vec<String>,
+    /// The included or redirected domains whose record could not be looked up or parsed, in
+    /// traversal order, and the domain before which the DNS lookup limit stopped the traversal
+    /// without a match. Their branches are skipped while the remaining ones are still checked,
+    /// so the result is partial if any are reported; receivers fail such an evaluation with
+    /// temperror or permerror. A failure of the root domain fails the check instead.
+    pub errors: Vec<DomainError>,
+}
+
+impl CheckResult {
+    /// The result receivers give mail from the target, in the terms of RFC 7208 §2.6:
+    /// `none` without SPF record, `temperror` or `permerror` if a traversed domain failed, else
+    /// the result of the qualifier of the matching mechanism, `permerror` if the DNS lookup
+    /// limit was exceeded before a match, and the result of the default policy otherwise.
+    pub fn result(&self) -> SpfResult {
+        if self.spf_record.is_none() {
+            return SpfResult::None;
         }
 
-        let missing_mechanisms: Vec<&String> = target_mechanisms
-            .iter()
-            .filter(|mechanism| !found_mechanisms.contains(*mechanism))
-            .collect();
+        if let Some(error) = self.errors.first() {
+            return match error.error {
+                error if error.is_temporary() => SpfResult::TempError,
+                _ => SpfResult::PermError,
+            };
+        }
 
-        log_message(format!(
-            "Mechanism check completed. Found: {found_mechanisms:?}, Missing: {missing_mechanisms:?}"
-        ));
+        if self.found {
+            return self.qualifier.map_or(SpfResult::Pass, SpfResult::from);
+        }
 
-        Ok(found_mechanisms.len() == target_mechanisms.len())
+        if self.limit_exceeded {
+            return SpfResult::PermError;
+        }
+
+        self.default_policy
+            .map_or(SpfResult::Neutral, SpfResult::from)
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// A domain of a check that could not be looked up or parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DomainError {
+    pub domain: String,
+    /// The error, e.g. [`CheckError::DnsLookupFailed`] or [`CheckError::SpfParseFailed`].
+    pub error: CheckError,
+}
+
+/// The policy ending the evaluation of a domain, as found by
+/// [`SpfChecker::find_default_policy`].
+struct DefaultPolicy {
+    qualifier: Qualifier,
+    /// Whether the record has an `all` mechanism, rather than implying `?all`.
+    explicit: bool,
+    /// The domain whose record ends the evaluation.
+    domain: String,
+    explanation: Option<String>,
+}
 
-    use async_trait::async_trait;
-    use std::collections::HashMap;
-    use std::sync::Mutex;
+/// A `redirect` modifier followed by the check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Redirect {
+    /// The domain whose record declares the modifier.
+    pub domain: String,
+    /// The domain the evaluation is redirected to.
+    pub target: String,
+}
 
-    #[derive(Debug, Clone)]
-    struct MockResolver {
-        records: Arc<Mutex<HashMap<String, String>>>,
-    }
+/// A term of a traversed record that was not followed or compared with the target.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SkippedTerm {
+    /// The term as written, e.g. `include:%{i}._spf.example.net`.
+    pub term: String,
+    /// The domain whose record contains the term.
+    pub domain: String,
+    /// Why the term was skipped, e.g. a macro without value.
+    pub reason: String,
+}
 
-    impl MockResolver {
-        fn new() -> Self {
-            Self {
-                records: Arc::new(Mutex::new(HashMap::new())),
-            }
-        }
+/// A mechanism together with the domain whose record declares it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MechanismMatch {
+    /// The mechanism without qualifier, e.g. `ip4:192.0.2.0/24`.
+    pub mechanism: String,
+    pub domain: String,
+}
+
+/// A mechanism declared more than once across the records of a check.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateMechanism {
+    /// The mechanism without qualifier, e.g. `include:_spf.google.com`.
+    pub mechanism: String,
+    /// The domains whose records declare it, once per occurrence, in traversal order.
+    pub domains: Vec<String>,
+}
+
+/// A domain of the include and redirect tree returned by [`SpfChecker::tree`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpfTreeNode {
+    pub domain: String,
+    /// The term of the parent's record referencing the domain, e.g. `include:_spf.example.com`
+    /// or `redirect=_spf.example.com` (`None` for the root).
+    pub term: Option<String>,
+    /// The SPF record of the domain (`None` if it has none).
+    pub record: Option<String>,
+    /// Whether the domain is one of its own ancestors, in which case it is not traversed again.
+    pub is_loop: bool,
+    /// The error the record of the domain could not be looked up or parsed with, e.g.
+    /// `MULTIPLE_SPF_RECORDS`. Only set by [`SpfChecker::partial_tree`].
+    pub error: Option<String>,
+    /// The domains referenced by `record`, includes in record order followed by the redirect.
+    pub children: Vec<SpfTreeNode>,
+}
+
+/// Optional inputs for [`SpfChecker::check_with_options`].
+///
+/// Include and redirect targets containing macros, e.g. `%{i}._spf.example.com`, can only be
+/// resolved when the values they reference are provided. Terms that cannot be expanded are
+/// skipped and reported in [`CheckResult::skipped_macro_terms`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CheckOptions {
+    /// Client IP used for `%{i}` and `%{v}`.
+    pub ip: Option<IpAddr>,
+    /// MAIL FROM address used for `%{s}`, `%{l}` and `%{o}`.
+    pub sender: Option<String>,
+    /// HELO identity used for `%{h}`.
+    pub helo: Option<String>,
+    /// How include, `a` and `mx` hosts are compared with the target.
+    pub match_mode: MatchMode,
+    /// The kinds of mechanisms compared with a domain target.
+    pub mechanisms: MechanismFilter,
+    /// Fails with `SPF_PARSE_FAILED` on syntax violations that are otherwise tolerated, see
+    /// [`SpfRecord::parse_strict`].
+    pub strict: bool,
+    /// How many levels of includes and redirects below the checked domain are traversed, e.g.
+    /// `Some(1)` for its direct includes only. Unlimited if `None`.
+    pub max_depth: Option<usize>,
+    /// Whether `redirect` modifiers are followed (default `true`).
+    pub follow_redirects: bool,
+    /// Also queries the deprecated `SPF` record type (99) of each traversed domain, warning
+    /// if the domain publishes its record only that way or with other content than its TXT
+    /// record (default `false`).
+    pub lookup_spf_rr: bool,
+    /// Traverses the whole chain after the first match, so that [`CheckResult::matches`] lists
+    /// every mechanism matching the target, as for wildcard targets (default `false`).
+    pub all_matches: bool,
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        Self {
+            ip: None,
+            sender: None,
+            helo: None,
+            match_mode: MatchMode::default(),
+            mechanisms: MechanismFilter::default(),
+            strict: false,
+            max_depth: None,
+            follow_redirects: true,
+            lookup_spf_rr: false,
+            all_matches: false,
+        }
+    }
+}
+
+/// The kinds of mechanisms whose domains are compared with a domain target. `ip4` and `ip6`
+/// mechanisms are always compared with IP and CIDR targets.
+///
+/// Parses from a comma-separated list of mechanism names, e.g. `include,a,mx,exists`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MechanismFilter {
+    pub include: bool,
+    pub a: bool,
+    pub mx: bool,
+    /// Compares the domain spec of `exists` mechanisms, expanded without a DNS lookup.
+    pub exists: bool,
+}
+
+impl Default for MechanismFilter {
+    /// `include`, `a` and `mx`.
+    fn default() -> Self {
+        Self {
+            include: true,
+            a: true,
+            mx: true,
+            exists: false,
+        }
+    }
+}
+
+impl FromStr for MechanismFilter {
+    type Err = String;
+
+    fn from_str(value: &str) -> std::result::Result<Self, Self::Err> {
+        let mut filter = Self {
+            include: false,
+            a: false,
+            mx: false,
+            exists: false,
+        };
+
+        for name in value.split(',').map(str::trim) {
+            match name.to_ascii_lowercase().as_str() {
+                "include" => filter.include = true,
+                "a" => filter.a = true,
+                "mx" => filter.mx = true,
+                "exists" => filter.exists = true,
+                _ => return Err(format!("unsupported mechanism `{name}`")),
+            }
+        }
+
+        Ok(filter)
+    }
+}
+
+/// How host names found in the SPF chain are compared with a domain target.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MatchMode {
+    /// The host must equal the target.
+    #[default]
+    Exact,
+    /// The host must equal the target or be one of its subdomains, e.g. `mail.easybill.de` for
+    /// the target `easybill.de`.
+    Subdomain,
+}
+
+impl MatchMode {
+    /// Returns `true` if `host` matches `target`, ignoring case and trailing dots.
+    ///
+    /// A `target` containing `*` is a glob pattern in either mode, e.g. `*.sendgrid.net`.
+    pub fn matches(self, host: &str, target: &str) -> bool {
+        if is_wildcard(target) {
+            return glob_matches(target, host);
+        }
+
+        match self {
+            Self::Exact => is_same_host(host, target),
+            Self::Subdomain => {
+                let host = host.trim_end_matches('.').to_ascii_lowercase();
+                let target = target.trim_end_matches('.').to_ascii_lowercase();
+
+                host == target || host.ends_with(&format!(".{target}"))
+            }
+        }
+    }
+}
+
+impl CheckOptions {
+    fn macro_context(&self) -> MacroContext<'_> {
+        MacroContext {
+            sender: self.sender.as_deref(),
+            ip: self.ip,
+            helo: self.helo.as_deref(),
+        }
+    }
+
+    /// Returns `true` if the includes and redirects of a domain `depth` levels below the
+    /// checked domain are traversed.
+    fn descends_below(&self, depth: usize) -> bool {
+        self.max_depth.is_none_or(|max_depth| depth < max_depth)
+    }
+
+    /// Parses `record` as [`CheckOptions::strict`] demands, answering records parsed before
+    /// from memory.
+    fn parse_record(&self, record: &str) -> Result<Arc<SpfRecord>, record::ParseError> {
+        parsed::parse(record, self.strict)
+    }
+}
+
+/// https://datatracker.ietf.org/doc/html/rfc7208#section-4.6.4
+///
+/// > SPF implementations MUST limit the total number of those terms to 10
+/// > during SPF evaluation, to avoid unreasonable load on the DNS.
+const DNS_LOOKUP_LIMIT: usize = 10;
+
+/// https://datatracker.ietf.org/doc/html/rfc7208#section-4.6.4
+///
+/// > As for the limits, such "void lookups" SHOULD be limited to two. If this limit is
+/// > exceeded, the evaluation MUST produce a "permerror" result.
+const VOID_LOOKUP_LIMIT: usize = 2;
+
+/// The maximum number of domains looked up for [`SpfChecker::tree`]. Unlike the evaluation, the
+/// tree includes domains referenced more than once, e.g. by several includes.
+const MAX_TREE_DOMAINS: usize = 100;
+
+/// Expands the macros of an include or redirect target found in the record of `domain` and
+/// normalizes the result.
+///
+/// Returns `None` if the macros cannot be expanded, see [`unexpandable_terms`].
+fn expand_target(target: &str, domain: &str, context: &MacroContext) -> Option<String> {
+    if !macros::contains_macro(target) {
+        return Some(normalize_domain(target));
+    }
+
+    let expanded = macros::expand_domain(target, domain, context).ok()?;
+
+    Some(normalize_domain(&expanded))
+}
+
+/// Returns the terms of `spf`, published by `domain`, that are followed or compared with the
+/// target but whose macros cannot be expanded with the values of `options`.
+fn unexpandable_terms(spf: &SpfRecord, domain: &str, options: &CheckOptions) -> Vec<SkippedTerm> {
+    let context = options.macro_context();
+
+    let mechanisms = spf
+        .directives()
+        .filter(|directive| match directive.mechanism {
+            Mechanism::Include(_) => true,
+            Mechanism::A { .. } => options.mechanisms.a,
+            Mechanism::Mx { .. } => options.mechanisms.mx,
+            Mechanism::Exists(_) => options.mechanisms.exists,
+            _ => false,
+        })
+        .filter_map(|directive| Some((directive.to_string(), directive.mechanism.domain_spec()?)));
+
+    let redirect = spf
+        .redirect()
+        .filter(|_| !has_all_mechanism(spf))
+        .map(|redirect| (format!("redirect={redirect}"), redirect));
+
+    mechanisms
+        .chain(redirect)
+        .filter(|(_, spec)| macros::contains_macro(spec))
+        .filter_map(|(term, spec)| {
+            let err = macros::expand_domain(spec, domain, &context).err()?;
+
+            log_message(format!("Skipping '{term}' of {domain}: {err}"));
+
+            Some(SkippedTerm {
+                term,
+                domain: domain.to_owned(),
+                reason: err.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Lowercases `domain` and strips surrounding whitespace and the trailing dot, so that e.g.
+/// `Example.COM.` and `example.com` are looked up and visited only once.
+fn normalize_domain(domain: &str) -> String {
+    domain.trim().trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Converts an internationalized `domain` to its normalized ASCII-compatible form, e.g.
+/// `Bücher.example` to `xn--bcher-kva.example`, as needed for DNS lookups.
+///
+/// Fails with `INVALID_DOMAIN` if `domain` is not a valid internationalized domain name.
+pub fn domain_to_ascii(domain: &str) -> Result<String> {
+    let domain = domain.trim();
+
+    if domain.is_ascii() {
+        return Ok(normalize_domain(domain));
+    }
+
+    // Without STD3 rules, `idna` passes characters such as spaces through unchanged.
+    let ascii = idna::domain_to_ascii(domain)
+        .ok()
+        .filter(|ascii| !ascii.contains(char::is_whitespace))
+        .context(CheckError::InvalidDomain)?;
+
+    Ok(normalize_domain(&ascii))
+}
+
+/// Converts the punycode labels of `domain` to Unicode, e.g. `xn--bcher-kva.example` to
+/// `bücher.example`. Labels that cannot be decoded are kept.
+pub fn domain_to_unicode(domain: &str) -> String {
+    let domain = normalize_domain(domain);
+
+    if !domain.contains("xn--") {
+        return domain;
+    }
+
+    idna::domain_to_unicode(&domain).0
+}
+
+/// Returns the domain of an email address, e.g. `mail.example.com` for
+/// `bounce@mail.example.com`, or `None` if `input` contains no `@`.
+pub fn address_domain(input: &str) -> Option<&str> {
+    input.trim().rsplit_once('@').map(|(_, domain)| domain)
+}
+
+/// Returns the organizational domain of `domain` as defined by RFC 7489 §3.2, the public
+/// suffix plus one label, e.g. `example.co.uk` for `mail.example.co.uk`. `None` for public
+/// suffixes themselves and invalid domains.
+pub fn organizational_domain(domain: &str) -> Option<String> {
+    let domain = domain_to_ascii(domain).ok()?;
+
+    psl::domain_str(&domain).map(str::to_owned)
+}
+
+fn log_message(msg: impl AsRef<str>) {
+    println!(
+        "[{}] {}",
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f"),
+        msg.as_ref()
+    );
+}
+
+#[derive(Clone, Debug)]
+pub struct SpfChecker {
+    resolver: Arc<dyn SpnResolver + Send + Sync + 'static>,
+    fetcher: Arc<dyn PolicyFetcher + Send + Sync + 'static>,
+    /// Validates the answers of [`SpfChecker::dnssec_report`], if configured.
+    validator: Option<Arc<dyn SpnResolver + Send + Sync + 'static>>,
+    /// Collects the steps of the check run by [`SpfChecker::explain`].
+    trace: Option<Arc<trace::Trace>>,
+    /// The running checks shared by identical ones, see [`SpfChecker::with_coalescing`].
+    in_flight: Option<Arc<coalesce::InFlight>>,
+}
+
+impl SpfChecker {
+    pub fn new<R>(resolver: R) -> Self
+    where
+        R: SpnResolver + Send + Sync + 'static,
+    {
+        Self {
+            resolver: Arc::new(resolver),
+            fetcher: Arc::new(mta_sts::default_fetcher()),
+            validator: None,
+            trace: None,
+            in_flight: None,
+        }
+    }
+
+    /// Returns a checker that looks up each name only once across all of its checks, e.g. to
+    /// check several targets against the same SPF tree.
+    ///
+    /// The remembered answers never expire, so the returned checker is meant for a single
+    /// request.
+    pub fn with_lookup_cache(&self) -> Self {
+        Self {
+            resolver: Arc::new(memo::MemoResolver::new(self.resolver.clone())),
+            fetcher: self.fetcher.clone(),
+            validator: self.validator.clone(),
+            trace: self.trace.clone(),
+            in_flight: self.in_flight.clone(),
+        }
+    }
+
+    /// Returns a checker that answers TXT queries, and with them the SPF records of its checks,
+    /// from `cache` until the TTL of the records runs out. Unlike
+    /// [`SpfChecker::with_lookup_cache`], the cache is meant to be shared across requests.
+    ///
+    /// The TTLs of cached records, e.g. in [`CheckResult::ttls`], are those remaining.
+    pub fn with_dns_cache(&self, cache: DnsCache) -> Self {
+        Self {
+            resolver: Arc::new(cache::CachingResolver::new(self.resolver.clone(), cache)),
+            fetcher: self.fetcher.clone(),
+            validator: self.validator.clone(),
+            trace: self.trace.clone(),
+            in_flight: self.in_flight.clone(),
+        }
+    }
+
+    /// Returns a checker whose concurrent checks of the same domain and target with the same
+    /// options run only once, the others waiting for and sharing its result, e.g. while many
+    /// clients check the same domain during an incident. Clones of the returned checker and the
+    /// checkers derived from it by [`SpfChecker::with_lookup_cache`] and
+    /// [`SpfChecker::with_dns_cache`] share the running checks.
+    ///
+    /// Checks that complete are not remembered; later ones run anew.
+    pub fn with_coalescing(&self) -> Self {
+        Self {
+            in_flight: Some(Arc::default()),
+            ..self.clone()
+        }
+    }
+
+    /// The number of checks answered with the result of an identical running one, see
+    /// [`SpfChecker::with_coalescing`].
+    pub fn coalesced_checks(&self) -> u64 {
+        self.in_flight
+            .as_ref()
+            .map_or(0, |in_flight| in_flight.coalesced())
+    }
+
+    /// Looks up the records of the SPF tree of `root_domain` anew and stores them in `cache`,
+    /// e.g. to fill the cache at startup or to refresh popular records before they expire.
+    /// Returns the lowest remaining TTL of the cached records of the tree, `None` if none are
+    /// cached.
+    ///
+    /// The checker must not answer from `cache` itself, as returned by
+    /// [`SpfChecker::with_dns_cache`], or the cached records are stored again unchanged.
+    pub async fn refresh_dns_cache(
+        &self,
+        cache: &DnsCache,
+        root_domain: &str,
+    ) -> Result<Option<Duration>> {
+        let checker = Self {
+            resolver: Arc::new(cache::CachingResolver::refreshing(
+                self.resolver.clone(),
+                cache.clone(),
+            )),
+            fetcher: self.fetcher.clone(),
+            validator: self.validator.clone(),
+            trace: None,
+            in_flight: None,
+        };
+        let tree = checker
+            .partial_tree(root_domain, &CheckOptions::default())
+            .await?;
+
+        let mut domains = Vec::new();
+        let mut nodes = vec![&tree];
+        while let Some(node) = nodes.pop() {
+            nodes.extend(&node.children);
+            if !node.is_loop {
+                domains.push(node.domain.clone());
+            }
+        }
+
+        Ok(cache.remaining_ttl(&domains).await)
+    }
+
+    /// Returns a checker that records how long each of its DNS queries takes, e.g. to find a
+    /// slow name server, together with the handle to read the timings.
+    ///
+    /// Answers remembered by [`SpfChecker::with_lookup_cache`] are only timed if the cache is
+    /// added to the returned checker rather than the other way around.
+    pub fn with_query_timings(&self) -> (Self, QueryTimings) {
+        let timings = QueryTimings::default();
+        let checker = Self {
+            resolver: Arc::new(timing::TimingResolver::new(
+                self.resolver.clone(),
+                timings.clone(),
+            )),
+            fetcher: self.fetcher.clone(),
+            validator: self.validator.clone(),
+            trace: self.trace.clone(),
+            in_flight: None,
+        };
+
+        (checker, timings)
+    }
+
+    /// Returns a checker that finds `record` as the SPF record of `domain` instead of the
+    /// published one, to check a record before publishing it. Fails with `SPF_PARSE_FAILED` if
+    /// `record` is not a valid SPF record.
+    ///
+    /// If `resolve_dns` is `false`, all other lookups, e.g. of includes, fail with
+    /// `DNS_LOOKUPS_DISABLED` instead of querying DNS.
+    pub fn with_record(&self, domain: &str, record: &str, resolve_dns: bool) -> Result<Self> {
+        let domain = domain_to_ascii(domain)?;
+        let record = record.trim();
+
+        if !is_spf_record(record) {
+            return Err(anyhow!("The record does not start with `v=spf1`"))
+                .context(CheckError::SpfParseFailed);
+        }
+        SpfRecord::from_str(record).context(CheckError::SpfParseFailed)?;
+
+        let resolver = resolve_dns.then(|| self.resolver.clone());
+
+        Ok(Self {
+            resolver: Arc::new(draft::DraftResolver::new(
+                resolver,
+                domain,
+                record.to_owned(),
+            )),
+            fetcher: self.fetcher.clone(),
+            validator: self.validator.clone(),
+            trace: self.trace.clone(),
+            in_flight: None,
+        })
+    }
+
+    /// Builds the SPF record described by `spec` for `domain`, see [`RecordSpec::build`], and
+    /// counts the DNS lookups its evaluation would take, resolving the included records.
+    ///
+    /// Exceeding the lookup limit or the size limits is reported as warning, not as error.
+    pub async fn build_record(&self, domain: &str, spec: &RecordSpec) -> Result<BuiltRecord> {
+        let domain = domain_to_ascii(domain)?;
+        let record = spec
+            .build()
+            .context(CheckError::SpfParseFailed)?
+            .to_string();
+
+        let draft = self.with_record(&domain, &record, true)?;
+        let lookup_budget = draft
+            .lookup_budget(&domain, &CheckOptions::default())
+            .await?;
+        let txts = draft
+            .resolver
+            .lookup_txt(&domain)
+            .await
+            .context(CheckError::DnsLookupFailed)?;
+
+        Ok(BuiltRecord::new(&domain, record, lookup_budget, &txts))
+    }
+
+    /// Runs [`SpfChecker::check_with_options`] and returns, besides its result, every step it
+    /// took: each DNS query, each record and mechanism compared with the target and why it
+    /// matched or not, and each include and redirect followed.
+    pub async fn explain(
+        &self,
+        root_domain: &str,
+        target: &str,
+        options: &CheckOptions,
+    ) -> Explanation {
+        let trace = Arc::new(trace::Trace::default());
+        let checker = Self {
+            resolver: Arc::new(trace::TracingResolver::new(
+                self.resolver.clone(),
+                trace.clone(),
+            )),
+            fetcher: self.fetcher.clone(),
+            validator: self.validator.clone(),
+            trace: Some(trace.clone()),
+            in_flight: None,
+        };
+
+        let result = checker
+            .check_with_options(root_domain, target, options)
+            .await;
+
+        Explanation {
+            steps: trace.take_steps(),
+            result,
+        }
+    }
+
+    /// Returns a checker that passes each step of its checks and trees to `listener` as soon as
+    /// it is taken, e.g. to show the progress of a deep tree. The steps are those returned by
+    /// [`SpfChecker::explain`], including the DNS queries.
+    pub fn with_progress(&self, listener: impl Fn(&TraceStep) + Send + Sync + 'static) -> Self {
+        let trace = Arc::new(trace::Trace::with_listener(Box::new(listener)));
+
+        Self {
+            resolver: Arc::new(trace::TracingResolver::new(
+                self.resolver.clone(),
+                trace.clone(),
+            )),
+            fetcher: self.fetcher.clone(),
+            validator: self.validator.clone(),
+            trace: Some(trace),
+            in_flight: None,
+        }
+    }
+
+    /// Replaces the HTTPS client fetching MTA-STS policies.
+    pub fn with_policy_fetcher<F>(mut self, fetcher: F) -> Self
+    where
+        F: PolicyFetcher + Send + Sync + 'static,
+    {
+        self.fetcher = Arc::new(fetcher);
+        self
+    }
+
+    /// Sets the resolver validating the answers of [`SpfChecker::dnssec_report`], which must
+    /// fail lookups whose answers are unsigned or do not pass DNSSEC validation.
+    pub fn with_dnssec_validator<R>(mut self, validator: R) -> Self
+    where
+        R: SpnResolver + Send + Sync + 'static,
+    {
+        self.validator = Some(Arc::new(validator));
+        self
+    }
+
+    /// Evaluates whether `ip` may send mail for the MAIL FROM identity `sender` following
+    /// RFC 7208 `check_host()`.
+    ///
+    /// An empty `sender` evaluates the HELO identity `helo` instead. DNS failures and
+    /// invalid records are reported as `temperror` and `permerror` results, not as errors.
+    pub async fn check_host(&self, ip: IpAddr, sender: &str, helo: &str) -> Evaluation {
+        let sender = evaluation::sender_identity(sender, helo);
+
+        Evaluator::new(self.resolver.as_ref(), ip, &sender, helo)
+            .evaluate()
+            .await
+    }
+
+    /// Evaluates whether `ip` may send mail for `domain`, as [`SpfChecker::check_host`] with
+    /// `domain` as HELO identity and the sender `postmaster@<domain>`.
+    pub async fn check_ip(&self, domain: &str, ip: IpAddr) -> Result<Evaluation> {
+        let domain = domain_to_ascii(domain)?;
+
+        Ok(self.check_host(ip, "", &domain).await)
+    }
+
+    /// Validates the SPF record of `root_domain` and every record it references through
+    /// `include` and `redirect`, reporting syntax errors, missing and duplicate records,
+    /// DNS lookup limit overruns and size issues as findings instead of failing.
+    ///
+    /// The macro values, `max_depth` and `follow_redirects` of `options` apply as for
+    /// [`SpfChecker::check_with_options`]. Fails on DNS errors.
+    pub async fn validate(&self, root_domain: &str, options: &CheckOptions) -> Result<Validation> {
+        let root_domain = domain_to_ascii(root_domain)?;
+
+        Linter::new(self.resolver.as_ref(), options)
+            .validate(&root_domain)
+            .await
+    }
+
+    /// Counts the terms of `root_domain`'s SPF record and of the records it references that
+    /// count against the DNS lookup limit, expanding macros with the values of `options`.
+    pub async fn lookup_budget(
+        &self,
+        root_domain: &str,
+        options: &CheckOptions,
+    ) -> Result<LookupBudget> {
+        let root_domain = domain_to_ascii(root_domain)?;
+
+        budget::lookup_budget(self.resolver.as_ref(), &root_domain, options).await
+    }
+
+    /// Looks up the DMARC record of `domain` at `_dmarc.<domain>`, parses and validates it.
+    ///
+    /// A missing, duplicate or invalid record is reported as finding, not as an error.
+    pub async fn check_dmarc(&self, domain: &str) -> Result<DmarcReport> {
+        let domain = domain_to_ascii(domain)?;
+
+        dmarc::check_dmarc(self.resolver.as_ref(), &domain).await
+    }
+
+    /// Looks up the `MX` records of `domain`, whether it publishes a Null MX (RFC 7505) and
+    /// whether the exchanges resolve.
+    pub async fn check_mx(&self, domain: &str) -> Result<MxReport> {
+        let domain = domain_to_ascii(domain)?;
+
+        mx::check_mx(self.resolver.as_ref(), &domain).await
+    }
+
+    /// Looks up the MTA-STS record at `_mta-sts.<domain>` and the TLS-RPT record at
+    /// `_smtp._tls.<domain>`, and fetches the MTA-STS policy from
+    /// `https://mta-sts.<domain>/.well-known/mta-sts.txt`.
+    ///
+    /// Missing or invalid records and policies are reported as findings, not as errors.
+    pub async fn check_mta_sts(&self, domain: &str) -> Result<MtaStsReport> {
+        let domain = domain_to_ascii(domain)?;
+
+        mta_sts::check_mta_sts(self.resolver.as_ref(), self.fetcher.as_ref(), &domain).await
+    }
+
+    /// Looks up the BIMI record of `domain` at `<selector>._bimi.<domain>`, parses and validates
+    /// it, and checks that the DMARC policy of `domain` is enforced as BIMI requires.
+    ///
+    /// A missing or invalid record is reported as finding, not as an error.
+    pub async fn check_bimi(&self, domain: &str, selector: &str) -> Result<BimiReport> {
+        let domain = domain_to_ascii(domain)?;
+
+        bimi::check_bimi(self.resolver.as_ref(), &domain, selector).await
+    }
+
+    /// Looks up the DKIM key records of `domain` at `<selector>._domainkey.<domain>` for each
+    /// of `selectors`, e.g. [`COMMON_DKIM_SELECTORS`].
+    ///
+    /// Selectors without a key are skipped. Invalid keys are reported as findings.
+    pub async fn check_dkim(&self, domain: &str, selectors: &[&str]) -> Result<DkimReport> {
+        let domain = domain_to_ascii(domain)?;
+
+        dkim::check_dkim(self.resolver.as_ref(), &domain, selectors).await
+    }
+
+    /// Looks up all TXT records of `domain` with their TTLs, including those that are not SPF
+    /// records.
+    pub async fn txt_records(&self, domain: &str) -> Result<Vec<TxtRecord>> {
+        let domain = domain_to_ascii(domain)?;
+
+        self.resolver.lookup_txt_records(&domain).await
+    }
+
+    pub async fn check(&self, root_domain: &str, target: &str) -> Result<CheckResult> {
+        self.check_with_options(root_domain, target, &CheckOptions::default())
+            .await
+    }
+
+    /// Like [`SpfChecker::check`], expanding macros in include and redirect targets with the
+    /// values of `options`.
+    pub async fn check_with_options(
+        &self,
+        root_domain: &str,
+        target: &str,
+        options: &CheckOptions,
+    ) -> Result<CheckResult> {
+        let root_domain = &domain_to_ascii(root_domain)?;
+        let target = &domain_to_ascii(target)?;
+
+        // A traced check must collect its own steps.
+        match &self.in_flight {
+            Some(in_flight) if self.trace.is_none() => {
+                let key = (root_domain.clone(), target.clone(), options.clone());
+                let check = self.run_check(root_domain, target, options);
+
+                in_flight.run(key, check).await
+            }
+            _ => self.run_check(root_domain, target, options).await,
+        }
+    }
+
+    /// Checks the normalized `root_domain` for `target`, see [`SpfChecker::check_with_options`].
+    async fn run_check(
+        &self,
+        root_domain: &String,
+        target: &String,
+        options: &CheckOptions,
+    ) -> Result<CheckResult> {
+        // First, try the original logic
+        let mut initial_result = self
+            .check_direct_include(root_domain, target, options)
+            .await?;
+
+        let default_policy = self
+            .find_default_policy(root_domain, options, &mut initial_result.errors)
+            .await?;
+
+        if let Some(policy) = &default_policy {
+            self.trace("DEFAULT_POLICY", &policy.domain, None, None, || {
+                format!(
+                    "The evaluation ends with `{}all`",
+                    policy.qualifier.symbol()
+                )
+            });
+        }
+
+        let initial_result = match default_policy {
+            Some(policy) => CheckResult {
+                default_policy: Some(policy.qualifier),
+                all_mechanism: policy
+                    .explicit
+                    .then(|| format!("{}all", policy.qualifier.symbol())),
+                default_policy_domain: Some(policy.domain),
+                explanation: policy.explanation,
+                ..initial_result
+            },
+            None => initial_result,
+        };
+
+        // The fallback compares the target's own SPF record, which neither an IP network nor a
+        // wildcard target has.
+        if initial_result.found || IpNetwork::from_str(target).is_ok() || is_wildcard(target) {
+            return Ok(initial_result);
+        }
+
+        // If target include not found, try fallback mechanism check
+        log_message(format!(
+            "Target include '{target}' not found directly. Attempting fallback mechanism check."
+        ));
+        self.trace("FALLBACK", target, None, None, || {
+            "The target was not found directly, so the mechanisms of its own record are looked for"
+                .to_owned()
+        });
+
+        self.check_target_mechanisms(root_domain, target, initial_result, options)
+            .await
+    }
+
+    /// Returns the tree of `root_domain`'s SPF record and the records it references through
+    /// `include` and `redirect`, as limited by the macro values, `max_depth`,
+    /// `follow_redirects` and `strict` of `options`.
+    ///
+    /// Unlike [`SpfChecker::check`], the tree is traversed completely, including records
+    /// beyond the DNS lookup limit. Fails with `TREE_SIZE_LIMIT_EXCEEDED` once more than
+    /// [`MAX_TREE_DOMAINS`] domains would be looked up.
+    pub async fn tree(&self, root_domain: &str, options: &CheckOptions) -> Result<SpfTreeNode> {
+        let root_domain = domain_to_ascii(root_domain)?;
+        let mut lookups = 0;
+
+        self.tree_node(root_domain, None, Vec::new(), options, false, &mut lookups)
+            .await
+    }
+
+    /// Like [`SpfChecker::tree`], but records the error of a domain whose record cannot be
+    /// looked up or parsed in its node and continues with the remaining domains, instead of
+    /// failing.
+    pub async fn partial_tree(
+        &self,
+        root_domain: &str,
+        options: &CheckOptions,
+    ) -> Result<SpfTreeNode> {
+        let root_domain = domain_to_ascii(root_domain)?;
+        let mut lookups = 0;
+
+        self.tree_node(root_domain, None, Vec::new(), options, true, &mut lookups)
+            .await
+    }
+
+    /// Looks up the TXT records of each domain of the SPF tree of `root_domain` through the
+    /// validator set by [`SpfChecker::with_dnssec_validator`], to tell whether the answers of
+    /// a check could have been spoofed. Fails with `DNSSEC_UNAVAILABLE` without validator.
+    pub async fn dnssec_report(
+        &self,
+        root_domain: &str,
+        options: &CheckOptions,
+    ) -> Result<DnssecReport> {
+        let validator = self
+            .validator
+            .as_ref()
+            .ok_or_else(|| anyhow!("No DNSSEC-validating resolver is configured"))
+            .context(CheckError::DnssecUnavailable)?;
+
+        let tree = self.tree(root_domain, options).await?;
+
+        Ok(dnssec::validate_tree(validator.as_ref(), &tree).await)
+    }
+
+    /// Returns the includes and redirects of the SPF tree of `root_domain`, as returned by
+    /// [`SpfChecker::tree`], that reference domains without SPF record.
+    pub async fn dangling_terms(
+        &self,
+        root_domain: &str,
+        options: &CheckOptions,
+    ) -> Result<Vec<DanglingTerm>> {
+        let tree = self.tree(root_domain, options).await?;
+
+        dangling::find_dangling_terms(self.resolver.as_ref(), &tree).await
+    }
+
+    /// Suggests changes to the SPF record of `root_domain` that keep its results but save DNS
+    /// lookups or space: removing unreachable terms and duplicate includes, inlining includes
+    /// of single networks and merging networks.
+    pub async fn optimize(&self, root_domain: &str) -> Result<OptimizedRecord> {
+        let root_domain = domain_to_ascii(root_domain)?;
+
+        let spf_txt = find_spf_record(self.resolver.as_ref(), &root_domain)
+            .await?
+            .ok_or_else(|| anyhow!("{root_domain} publishes no SPF record"))
+            .context(CheckError::NoSpfRecord)?;
+        let spf = SpfRecord::from_str(&spf_txt).context(CheckError::SpfParseFailed)?;
+
+        optimize::optimize_record(self.resolver.as_ref(), &spf_txt, &spf).await
+    }
+
+    /// Compares the SPF trees of `domain_a` and `domain_b`, as returned by
+    /// [`SpfChecker::tree`], by their mechanisms and the networks these authorize.
+    pub async fn diff(
+        &self,
+        domain_a: &str,
+        domain_b: &str,
+        options: &CheckOptions,
+    ) -> Result<SpfDiff> {
+        let tree_a = self.tree(domain_a, options).await?;
+        let tree_b = self.tree(domain_b, options).await?;
+
+        diff::diff_trees(self.resolver.as_ref(), &tree_a, &tree_b, options).await
+    }
+
+    /// Computes whether `domain_a` authorizes every address `domain_b` authorizes, e.g. before
+    /// replacing the record of `domain_b` with an include of `domain_a`, by flattening the
+    /// `pass` mechanisms of both trees to networks.
+    pub async fn coverage(
+        &self,
+        domain_a: &str,
+        domain_b: &str,
+        options: &CheckOptions,
+    ) -> Result<Coverage> {
+        let tree_a = self.tree(domain_a, options).await?;
+        let tree_b = self.tree(domain_b, options).await?;
+
+        coverage::compare_coverage(self.resolver.as_ref(), &tree_a, &tree_b, options).await
+    }
+
+    fn tree_node<'a>(
+        &'a self,
+        domain: String,
+        term: Option<String>,
+        ancestors: Vec<String>,
+        options: &'a CheckOptions,
+        partial: bool,
+        lookups: &'a mut usize,
+    ) -> Pin<Box<dyn Future<Output = Result<SpfTreeNode>> + Send + 'a>> {
+        Box::pin(async move {
+            let mut node = SpfTreeNode {
+                domain,
+                term,
+                record: None,
+                is_loop: false,
+                error: None,
+                children: Vec::new(),
+            };
+
+            if ancestors.contains(&node.domain) {
+                node.is_loop = true;
+                self.trace("SKIPPED", &node.domain, None, None, || {
+                    format!("Loop: {} -> {}", ancestors.join(" -> "), node.domain)
+                });
+                return Ok(node);
+            }
+
+            *lookups += 1;
+
+            if *lookups > MAX_TREE_DOMAINS {
+                return Err(anyhow!(
+                    "More than {MAX_TREE_DOMAINS} domains in the SPF tree of {}",
+                    ancestors.first().unwrap_or(&node.domain)
+                ))
+                .context(CheckError::TreeSizeLimitExceeded);
+            }
+
+            let spf_txt = match find_spf_record(self.resolver.as_ref(), &node.domain).await {
+                Ok(spf_txt) => spf_txt,
+                Err(err) if partial => {
+                    node.error = Some(err.to_string());
+                    return Ok(node);
+                }
+                Err(err) => return Err(err),
+            };
+
+            let Some(spf_txt) = spf_txt else {
+                self.trace("RECORD", &node.domain, None, None, || {
+                    "No SPF record".to_owned()
+                });
+                return Ok(node);
+            };
+
+            let spf = match options
+                .parse_record(&spf_txt)
+                .context(CheckError::SpfParseFailed)
+            {
+                Ok(spf) => spf,
+                Err(err) if partial => {
+                    node.error = Some(err.to_string());
+                    node.record = Some(spf_txt);
+                    return Ok(node);
+                }
+                Err(err) => return Err(err),
+            };
+            self.trace("RECORD", &node.domain, Some(spf_txt.clone()), None, || {
+                format!("{} DNS-querying terms", dns_term_count(&spf))
+            });
+            node.record = Some(spf_txt);
+
+            if !options.descends_below(ancestors.len()) {
+                return Ok(node);
+            }
+
+            let context = &options.macro_context();
+
+            let mut references: Vec<(String, String)> = spf
+                .directives()
+                .filter_map(|directive| match &directive.mechanism {
+                    Mechanism::Include(domain) => Some((
+                        directive.to_string(),
+                        expand_target(domain, &node.domain, context)?,
+                    )),
+                    _ => None,
+                })
+                .collect();
+
+            // https://datatracker.ietf.org/doc/html/rfc7208#section-6.1
+            //
+            // > Any "redirect" modifier MUST be ignored if there is an "all" mechanism anywhere in
+            // > the record."
+            if options.follow_redirects && !has_all_mechanism(&spf) {
+                references.extend(spf.redirect().and_then(|redirect| {
+                    Some((
+                        format!("redirect={redirect}"),
+                        expand_target(redirect, &node.domain, context)?,
+                    ))
+                }));
+            }
+
+            let ancestors = [ancestors, vec![node.domain.clone()]].concat();
+
+            for (term, domain) in references {
+                self.trace("FOLLOW", &node.domain, Some(term.clone()), None, || {
+                    format!("{domain} is traversed")
+                });
+
+                let child = self
+                    .tree_node(
+                        domain,
+                        Some(term),
+                        ancestors.clone(),
+                        options,
+                        partial,
+                        lookups,
+                    )
+                    .await?;
+
+                node.children.push(child);
+            }
+
+            Ok(node)
+        })
+    }
+
+    async fn check_direct_include(
+        &self,
+        root_domain: &String,
+        target: &String,
+        options: &CheckOptions,
+    ) -> Result<CheckResult> {
+        let context = &options.macro_context();
+
+        // Each domain is stacked together with the chain of domains that referenced it and
+        // whether the last of them did so through `redirect`.
+        let mut to_visit_stack = vec![(root_domain.to_owned(), Vec::new(), false)];
+        let mut visited = HashSet::new();
+        let mut visited_domains = Vec::new();
+        let mut loops = Vec::new();
+        let mut warnings = Vec::new();
+        let mut record_sizes = Vec::new();
+        let mut records = BTreeMap::new();
+        let mut ttls = BTreeMap::new();
+        let mut overlapping_mechanisms = Vec::new();
+        let target_network = IpNetwork::from_str(target).ok();
+        // A wildcard target may match mechanisms anywhere in the tree
+        let all_matches = is_wildcard(target) || options.all_matches;
+        let mut target_addresses = None;
+        let mut matches = Vec::new();
+        let mut skipped_macro_terms = Vec::new();
+        let mut occurrences: Vec<DuplicateMechanism> = Vec::new();
+        // The first match, together with the domain declaring it and the path to it
+        let mut first_match = None;
+        let mut dns_mechanism_count = 0;
+        let mut void_lookups = 0;
+
+        let mut root_spf_record = None;
+        let mut sender_id_records = Vec::new();
+        let mut included_domains: Vec<String> = Vec::new();
+        let mut redirect_chain = Vec::new();
+        let mut errors = Vec::new();
+
+        while let Some((current_domain, referrers, redirected)) = to_visit_stack.pop() {
+            if dns_mechanism_count > DNS_LOOKUP_LIMIT {
+                log_message(format!(
+                    "Maximum DNS lookup limit of {} exceeded with {} DNS-querying terms. Visited domains: {:?}",
+                    DNS_LOOKUP_LIMIT,
+                    dns_mechanism_count,
+                    visited_domains
+                ));
+                self.trace("LIMIT_EXCEEDED", &current_domain, None, None, || {
+                    format!(
+                        "More than {DNS_LOOKUP_LIMIT} DNS-querying terms, the traversal stops before this domain"
+                    )
+                });
+                // Receivers stop at a match before reaching the limit.
+                if first_match.is_none() {
+                    errors.push(DomainError {
+                        domain: current_domain,
+                        error: CheckError::LookupLimitExceeded,
+                    });
+                }
+                break;
+            }
+
+            if !visited.insert(current_domain.clone()) {
+                // Already visited, either through another branch or, if it references itself
+                // through its own chain, in a loop
+                if let Some(start) = referrers
+                    .iter()
+                    .position(|domain| domain == &current_domain)
+                {
+                    let mut cycle = referrers[start..].to_vec();
+                    cycle.push(current_domain.clone());
+
+                    let message = format!("SPF loop detected: {}", cycle.join(" -> "));
+
+                    log_message(format!("Warning: {message}"));
+                    // Reported for the domain whose record closes the loop
+                    warnings.push(Warning {
+                        code: "SPF_LOOP",
+                        domain: referrers.last().cloned().unwrap_or_default(),
+                        message,
+                    });
+                    loops.push(cycle.clone());
+                    self.trace("SKIPPED", &current_domain, None, None, || {
+                        format!("Loop: {}", cycle.join(" -> "))
+                    });
+                } else {
+                    if !redirected {
+                        warnings.push(Warning {
+                            code: "DUPLICATE_INCLUDE",
+                            domain: referrers.last().cloned().unwrap_or_default(),
+                            message: format!(
+                                "{current_domain} is included more than once, which costs additional DNS lookups without authorizing more senders"
+                            ),
+                        });
+                    }
+                    self.trace("SKIPPED", &current_domain, None, None, || {
+                        "Already visited".to_owned()
+                    });
+                }
+                continue;
+            }
+            visited_domains.push(current_domain.clone());
+
+            // All TXT records are needed to estimate the size of the answer.
+            let txt_records = match self.resolver.lookup_txt_records(&current_domain).await {
+                Ok(txt_records) => txt_records,
+                Err(err) => {
+                    self.skip_failed(root_domain, &current_domain, err, &mut errors)?;
+                    continue;
+                }
+            };
+            let txts: Vec<String> = txt_records.iter().map(|txt| txt.text.clone()).collect();
+            let spf_records = txts.iter().filter(|txt| is_spf_record(txt)).cloned();
+
+            let domain_sender_id_records: Vec<String> = txts
+                .iter()
+                .filter(|txt| is_sender_id_record(txt))
+                .cloned()
+                .collect();
+            if !domain_sender_id_records.is_empty() {
+                warnings.push(sender_id_warning(
+                    &current_domain,
+                    &domain_sender_id_records,
+                    txts.iter().any(|txt| is_spf_record(txt)),
+                ));
+            }
+            if root_domain == &current_domain {
+                sender_id_records = domain_sender_id_records;
+            }
+
+            if options.lookup_spf_rr {
+                let spf_rrs = match self.resolver.lookup_spf_rr(&current_domain).await {
+                    Ok(spf_rrs) => spf_rrs,
+                    Err(err) => {
+                        self.skip_failed(root_domain, &current_domain, err, &mut errors)?;
+                        continue;
+                    }
+                };
+                warnings.extend(spf_rr_warning(&current_domain, &txts, &spf_rrs));
+            }
+
+            let spf_txt = match only_spf_record(&current_domain, spf_records.collect()) {
+                Ok(spf_txt) => spf_txt,
+                Err(err) => {
+                    self.skip_failed(root_domain, &current_domain, err, &mut errors)?;
+                    continue;
+                }
+            };
+            records.insert(current_domain.clone(), spf_txt.clone());
+
+            let Some(spf_txt) = spf_txt else {
+                self.trace("RECORD", &current_domain, None, None, || {
+                    "No SPF record".to_owned()
+                });
+
+                // https://datatracker.ietf.org/doc/html/rfc7208#section-6.1
+                //
+                // > If the redirect domain has no SPF record, or if it does not exist, then the
+                // > check_host() result is "permerror" [...].
+                if redirected {
+                    let message = format!(
+                        "The redirect target {current_domain} has no SPF record, which makes the SPF result permerror"
+                    );
+
+                    log_message(format!("Warning: {message}"));
+                    warnings.push(Warning {
+                        code: "REDIRECT_TARGET_MISSING",
+                        domain: referrers.last().cloned().unwrap_or_default(),
+                        message,
+                    });
+                }
+                continue;
+            };
+
+            if let Some(ttl) = txt_records
+                .iter()
+                .filter(|txt| txt.text == spf_txt)
+                .map(|txt| txt.ttl)
+                .min()
+            {
+                ttls.insert(current_domain.clone(), ttl);
+            }
+
+            let record_size = RecordSize::new(&current_domain, &spf_txt, &txts);
+            warnings.extend(record_size.warnings());
+            record_sizes.push(record_size);
+
+            let spf = match options
+                .parse_record(&spf_txt)
+                .context(CheckError::SpfParseFailed)
+            {
+                Ok(spf) => spf,
+                Err(err) => {
+                    self.skip_failed(root_domain, &current_domain, err, &mut errors)?;
+                    continue;
+                }
+            };
+
+            if root_domain == &current_domain {
+                root_spf_record = Some(spf_txt.clone());
+            }
+
+            dns_mechanism_count += dns_term_count(&spf);
+            if let Some(trace) = &self.trace {
+                trace.set_dns_mechanism_count(dns_mechanism_count);
+            }
+            self.trace(
+                "RECORD",
+                &current_domain,
+                Some(spf_txt.clone()),
+                None,
+                || format!("{} DNS-querying terms", dns_term_count(&spf)),
+            );
+
+            warnings.extend(record_warnings(&spf, &current_domain));
+
+            for directive in spf.directives() {
+                if directive.mechanism == Mechanism::All {
+                    continue;
+                }
+
+                let mechanism = directive.mechanism.to_string();
+                match occurrences
+                    .iter_mut()
+                    .find(|occurrence| occurrence.mechanism == mechanism)
+                {
+                    Some(occurrence) => occurrence.domains.push(current_domain.clone()),
+                    None => occurrences.push(DuplicateMechanism {
+                        mechanism,
+                        domains: vec![current_domain.clone()],
+                    }),
+                }
+            }
+
+            // https://datatracker.ietf.org/doc/html/rfc7208#section-4.7
+            //
+            // > If none of the mechanisms match and there is no "redirect" modifier, then the
+            // > check_host() returns a result of "neutral", just as if "?all" were specified as
+            // > the last directive.
+            let ends_evaluation = referrers.is_empty() || redirected;
+            if ends_evaluation && !has_all_mechanism(&spf) && spf.redirect().is_none() {
+                warnings.push(Warning {
+                    code: "MISSING_ALL",
+                    domain: current_domain.clone(),
+                    message: "The record has neither an `all` mechanism nor a `redirect` modifier, so senders it does not list get a neutral result".to_owned(),
+                });
+            }
+
+            let unexpandable = unexpandable_terms(&spf, &current_domain, options);
+            warnings.extend(unexpandable.iter().map(|skipped| Warning {
+                code: "MACRO_TERM_SKIPPED",
+                domain: skipped.domain.clone(),
+                message: format!("`{}` was not checked: {}", skipped.term, skipped.reason),
+            }));
+            skipped_macro_terms.extend(unexpandable);
+
+            let includes: Vec<(Qualifier, String)> = spf
+                .directives()
+                .filter_map(|directive| match &directive.mechanism {
+                    Mechanism::Include(domain) => Some((
+                        directive.qualifier,
+                        expand_target(domain, &current_domain, context)?,
+                    )),
+                    _ => None,
+                })
+                .collect();
+
+            included_domains.extend(includes.iter().map(|(_, domain)| domain.clone()));
+
+            let matched: Vec<Directive> = if let Some(network) = target_network {
+                self.trace_network_mechanisms(&spf, &current_domain, &network);
+
+                let matched = find_network_mechanism(&spf, &network).cloned();
+
+                if matched.is_none() {
+                    overlapping_mechanisms.extend(
+                        overlapping_network_mechanisms(&spf, &network).map(|directive| {
+                            MechanismMatch {
+                                mechanism: directive.mechanism.to_string(),
+                                domain: current_domain.clone(),
+                            }
+                        }),
+                    );
+                }
+
+                matched.into_iter().collect()
+            } else {
+                self.trace_domain_mechanisms(&spf, &current_domain, target, options);
+
+                let included = includes
+                    .iter()
+                    .filter(|(_, domain)| {
+                        options.mechanisms.include && options.match_mode.matches(domain, target)
+                    })
+                    .map(|(qualifier, domain)| Directive {
+                        qualifier: *qualifier,
+                        mechanism: Mechanism::Include(domain.clone()),
+                    });
+
+                let existing =
+                    spf.directives()
+                        .filter_map(|directive| match &directive.mechanism {
+                            Mechanism::Exists(domain) if options.mechanisms.exists => {
+                                let domain = expand_target(domain, &current_domain, context)?;
+
+                                options
+                                    .match_mode
+                                    .matches(&domain, target)
+                                    .then_some(Directive {
+                                        qualifier: directive.qualifier,
+                                        mechanism: Mechanism::Exists(domain),
+                                    })
+                            }
+                            _ => None,
+                        });
+
+                let mut matched: Vec<Directive> = included.chain(existing).collect();
+
+                if matched.is_empty() || all_matches {
+                    let host_mechanisms = self
+                        .find_host_mechanisms(
+                            &spf,
+                            &current_domain,
+                            target,
+                            options,
+                            &mut target_addresses,
+                            &mut void_lookups,
+                        )
+                        .await;
+
+                    match host_mechanisms {
+                        Ok(host_mechanisms) => matched.extend(host_mechanisms),
+                        // https://datatracker.ietf.org/doc/html/rfc7208#section-4.6.4
+                        //
+                        // > [...] the check_host() function SHOULD return a "permerror" result.
+                        Err(err)
+                            if CheckError::of(&err)
+                                == Some(CheckError::VoidLookupLimitExceeded) =>
+                        {
+                            log_message(format!(
+                                "Void lookup limit of {VOID_LOOKUP_LIMIT} exceeded in {current_domain}"
+                            ));
+                            self.trace("LIMIT_EXCEEDED", &current_domain, None, None, || {
+                                format!(
+                                    "More than {VOID_LOOKUP_LIMIT} a or mx lookups returned no records, the traversal stops"
+                                )
+                            });
+                            // Receivers stop at a match before reaching the limit.
+                            if first_match.is_none() {
+                                errors.push(DomainError {
+                                    domain: current_domain,
+                                    error: CheckError::VoidLookupLimitExceeded,
+                                });
+                            }
+                            break;
+                        }
+                        Err(err) => {
+                            self.skip_failed(root_domain, &current_domain, err, &mut errors)?;
+                            continue;
+                        }
+                    }
+                }
+
+                matched
+            };
+
+            let depth = referrers.len();
+            let chain = [referrers, vec![current_domain.clone()]].concat();
+
+            if let Some(directive) = matched.first() {
+                first_match.get_or_insert_with(|| {
+                    (
+                        directive.clone(),
+                        current_domain.clone(),
+                        [chain.clone(), vec![target.to_owned()]].concat(),
+                    )
+                });
+
+                matches.extend(matched.iter().map(|directive| MechanismMatch {
+                    mechanism: directive.mechanism.to_string(),
+                    domain: current_domain.clone(),
+                }));
+
+                if !all_matches {
+                    break;
+                }
+            }
+
+            if !options.descends_below(depth) {
+                log_message(format!(
+                    "Not traversing includes and redirects of {current_domain} beyond the maximum depth"
+                ));
+                self.trace("SKIPPED", &current_domain, None, None, || {
+                    "The includes and redirect are beyond the maximum depth".to_owned()
+                });
+                continue;
+            }
+
+            // https://datatracker.ietf.org/doc/html/rfc7208#section-6.1
+            //
+            // > Any "redirect" modifier MUST be ignored if there is an "all" mechanism anywhere in
+            // > the record."
+            if options.follow_redirects && !has_all_mechanism(&spf) {
+                let redirect = spf.redirect().and_then(|redirect| {
+                    Some((
+                        expand_target(redirect, &current_domain, context)?,
+                        chain.clone(),
+                        true,
+                    ))
+                });
+
+                if let Some((redirect, _, _)) = &redirect {
+                    self.trace(
+                        "FOLLOW",
+                        &current_domain,
+                        Some(format!("redirect={redirect}")),
+                        None,
+                        || "The redirect is traversed after the includes".to_owned(),
+                    );
+                    redirect_chain.push(Redirect {
+                        domain: current_domain.clone(),
+                        target: redirect.clone(),
+                    });
+                }
+                to_visit_stack.extend(redirect);
+            }
+
+            for (_, domain) in &includes {
+                self.trace(
+                    "FOLLOW",
+                    &current_domain,
+                    Some(format!("include:{domain}")),
+                    None,
+                    || "The include is traversed".to_owned(),
+                );
+            }
+
+            // Prefer `include` before `redirect` by pushing them onto the top of the stack.
+            to_visit_stack.extend(
+                includes
+                    .into_iter()
+                    .map(|(_, domain)| (domain, chain.clone(), false)),
+            );
+        }
+
+        let (mechanism, mechanism_domain, qualifier, path) = match first_match {
+            Some((directive, domain, path)) => (
+                Some(directive.mechanism.to_string()),
+                Some(domain),
+                Some(directive.qualifier),
+                Some(path),
+            ),
+            None => (None, None, None, None),
+        };
+
+        Ok(CheckResult {
+            found: mechanism.is_some(),
+            visited: visited.len(),
+            visited_domains,
+            spf_record: root_spf_record,
+            included_domains: Some(included_domains),
+            redirect_chain,
+            fallback_check: false,
+            mechanism,
+            mechanism_domain,
+            qualifier,
+            default_policy: None,
+            all_mechanism: None,
+            default_policy_domain: None,
+            explanation: None,
+            dns_mechanism_count,
+            limit_exceeded: dns_mechanism_count > DNS_LOOKUP_LIMIT,
+            void_lookups,
+            loops,
+            path,
+            warnings,
+            record_sizes,
+            records,
+            ttls,
+            overlapping_mechanisms,
+            skipped_macro_terms,
+            matches,
+            duplicates: occurrences
+                .into_iter()
+                .filter(|occurrence| occurrence.domains.len() > 1)
+                .collect(),
+            sender_id_records,
+            errors,
+        })
+    }
+
+    /// Fails the check for the root domain, and only skips the branch of any other domain,
+    /// recording its error in `errors` unless an earlier pass over the tree already did.
+    fn skip_failed(
+        &self,
+        root_domain: &str,
+        domain: &str,
+        err: anyhow::Error,
+        errors: &mut Vec<DomainError>,
+    ) -> Result<()> {
+        if domain == root_domain {
+            return Err(err);
+        }
+        if errors.iter().any(|error| error.domain == domain) {
+            return Ok(());
+        }
+
+        log_message(format!(
+            "Skipping {domain}, whose record could not be checked: {err:#}"
+        ));
+        self.trace("SKIPPED", domain, None, None, || {
+            format!("The record could not be checked: {err}")
+        });
+        errors.push(DomainError {
+            domain: domain.to_owned(),
+            // Only the lookups of the resolver fail without code.
+            error: CheckError::of(&err).unwrap_or(CheckError::DnsLookupFailed),
+        });
+
+        Ok(())
+    }
+
+    /// Returns the qualifier of the `all` mechanism that ends the evaluation of `root_domain`,
+    /// following its `redirect` chain, together with the explanation of the same record. A
+    /// failing redirect target is recorded in `errors` and leaves the policy unknown.
+    async fn find_default_policy(
+        &self,
+        root_domain: &str,
+        options: &CheckOptions,
+        errors: &mut Vec<DomainError>,
+    ) -> Result<Option<DefaultPolicy>> {
+        let context = &options.macro_context();
+
+        let mut current_domain = root_domain.to_owned();
+        let mut visited = HashSet::new();
+
+        while visited.len() < DNS_LOOKUP_LIMIT && visited.insert(current_domain.clone()) {
+            let spf_txt = match find_spf_record(self.resolver.as_ref(), &current_domain).await {
+                Ok(Some(spf_txt)) => spf_txt,
+                Ok(None) => break,
+                Err(err) => {
+                    self.skip_failed(root_domain, &current_domain, err, errors)?;
+                    break;
+                }
+            };
+
+            let spf = match options
+                .parse_record(&spf_txt)
+                .context(CheckError::SpfParseFailed)
+            {
+                Ok(spf) => spf,
+                Err(err) => {
+                    self.skip_failed(root_domain, &current_domain, err, errors)?;
+                    break;
+                }
+            };
+
+            let (qualifier, explicit) = match spf
+                .directives()
+                .find(|directive| directive.mechanism == Mechanism::All)
+            {
+                Some(all) => (all.qualifier, true),
+                None => match spf.redirect() {
+                    // The redirect would lead beyond what the options allow to traverse.
+                    Some(_)
+                        if !options.follow_redirects
+                            || !options.descends_below(visited.len() - 1) =>
+                    {
+                        break
+                    }
+                    Some(redirect) => match expand_target(redirect, &current_domain, context) {
+                        Some(redirect) => {
+                            current_domain = redirect;
+                            continue;
+                        }
+                        None => break,
+                    },
+                    None => (Qualifier::Neutral, false),
+                },
+            };
+
+            let explanation = match spf.explanation() {
+                Some(spec) => {
+                    find_explanation(self.resolver.as_ref(), spec, &current_domain, context).await
+                }
+                None => None,
+            };
+
+            return Ok(Some(DefaultPolicy {
+                qualifier,
+                explicit,
+                domain: current_domain,
+                explanation,
+            }));
+        }
+
+        // The root domain or a redirect target has no SPF record or failed, the redirects loop
+        // or are not followed.
+        Ok(None)
+    }
+
+    /// Returns the first `a` or `mx` mechanism of `spf` that authorizes the `target` host,
+    /// either by naming a host matching it or by resolving to networks covering all of its
+    /// addresses. For a wildcard target, every mechanism naming a matching host is returned.
+    ///
+    /// The addresses of `target` are resolved on first use and cached in `target_addresses`.
+    /// Fails with `VOID_LOOKUP_LIMIT_EXCEEDED` once more than [`VOID_LOOKUP_LIMIT`] `a` or
+    /// `mx` lookups returned no records, which ends the traversal with the error of the domain.
+    async fn find_host_mechanisms(
+        &self,
+        spf: &SpfRecord,
+        current_domain: &str,
+        target: &str,
+        options: &CheckOptions,
+        target_addresses: &mut Option<Vec<IpAddr>>,
+        void_lookups: &mut usize,
+    ) -> Result<Vec<Directive>> {
+        let context = &options.macro_context();
+        let wildcard = is_wildcard(target);
+        let mut matched = Vec::new();
+
+        for directive in spf.directives() {
+            let (host, cidr) = match &directive.mechanism {
+                Mechanism::A { domain, cidr } if options.mechanisms.a => (domain, cidr),
+                Mechanism::Mx { domain, cidr } if options.mechanisms.mx => (domain, cidr),
+                _ => continue,
+            };
+
+            let host = match host {
+                Some(host) => match expand_target(host, current_domain, context) {
+                    Some(host) => host,
+                    None => continue,
+                },
+                None => current_domain.to_owned(),
+            };
+
+            // The addresses of an `a` host are needed anyway to detect void lookups, those of MX
+            // hosts only when comparing addresses.
+            let (hosts, addresses) = match directive.mechanism {
+                Mechanism::Mx { .. } => (self.resolver.lookup_mx(&host).await?, None),
+                _ => {
+                    let addresses = self.lookup_addresses(&host).await?;
+                    (vec![host], Some(addresses))
+                }
+            };
+
+            let is_void = match &addresses {
+                Some(addresses) => addresses.is_empty(),
+                None => hosts.is_empty(),
+            };
+
+            if is_void {
+                *void_lookups += 1;
+
+                if *void_lookups > VOID_LOOKUP_LIMIT {
+                    return Err(anyhow!(CheckError::VoidLookupLimitExceeded));
+                }
+            }
+
+            if let Some(host) = hosts
+                .iter()
+                .find(|host| options.match_mode.matches(host, target))
+            {
+                self.trace(
+                    "MECHANISM",
+                    current_domain,
+                    Some(directive.to_string()),
+                    Some(true),
+                    || format!("The host {host} matches the target"),
+                );
+                matched.push(directive.clone());
+
+                if wildcard || options.all_matches {
+                    continue;
+                }
+                return Ok(matched);
+            }
+
+            // A wildcard target has no addresses to compare
+            if wildcard {
+                self.trace(
+                    "MECHANISM",
+                    current_domain,
+                    Some(directive.to_string()),
+                    Some(false),
+                    || "No host matches the target".to_owned(),
+                );
+                continue;
+            }
+
+            if target_addresses.is_none() {
+                *target_addresses = Some(self.lookup_addresses(target).await?);
+            }
+
+            let target_addresses = target_addresses.as_deref().unwrap_or_default();
+
+            if target_addresses.is_empty() {
+                self.trace(
+                    "MECHANISM",
+                    current_domain,
+                    Some(directive.to_string()),
+                    Some(false),
+                    || "No host matches the target, which has no addresses to compare".to_owned(),
+                );
+                continue;
+            }
+
+            let addresses = match addresses {
+                Some(addresses) => addresses,
+                None => {
+                    let mut addresses = Vec::new();
+                    for host in &hosts {
+                        addresses.extend(self.lookup_addresses(host).await?);
+                    }
+                    addresses
+                }
+            };
+
+            let networks: Vec<_> = addresses
+                .into_iter()
+                .map(|addr| cidr.network(addr))
+                .collect();
+
+            let covered = target_addresses
+                .iter()
+                .all(|addr| networks.iter().any(|network| network.contains(*addr)));
+
+            self.trace(
+                "MECHANISM",
+                current_domain,
+                Some(directive.to_string()),
+                Some(covered),
+                || {
+                    if covered {
+                        "No host matches the target, but the networks of the hosts cover all of its addresses".to_owned()
+                    } else {
+                        "Neither a host matches the target nor do the networks of the hosts cover all of its addresses".to_owned()
+                    }
+                },
+            );
+
+            if covered {
+                matched.push(directive.clone());
+
+                if !options.all_matches {
+                    return Ok(matched);
+                }
+            }
+        }
+
+        Ok(matched)
+    }
+
+    /// Resolves both the `A` and `AAAA` records of `host`.
+    async fn lookup_addresses(&self, host: &str) -> Result<Vec<IpAddr>> {
+        let mut addresses: Vec<IpAddr> = self
+            .resolver
+            .lookup_a(host)
+            .await?
+            .into_iter()
+            .map(IpAddr::V4)
+            .collect();
+
+        addresses.extend(
+            self.resolver
+                .lookup_aaaa(host)
+                .await?
+                .into_iter()
+                .map(IpAddr::V6),
+        );
+
+        Ok(addresses)
+    }
+
+    /// Records a step for [`SpfChecker::explain`]. `message` is only built while tracing.
+    fn trace(
+        &self,
+        kind: &'static str,
+        domain: &str,
+        term: Option<String>,
+        matched: Option<bool>,
+        message: impl FnOnce() -> String,
+    ) {
+        if let Some(trace) = &self.trace {
+            trace.push(kind, domain, term, matched, message());
+        }
+    }
+
+    /// Records the `include` and `exists` mechanisms of `spf` compared with the domain
+    /// `target` for [`SpfChecker::explain`].
+    fn trace_domain_mechanisms(
+        &self,
+        spf: &SpfRecord,
+        current_domain: &str,
+        target: &str,
+        options: &CheckOptions,
+    ) {
+        if self.trace.is_none() {
+            return;
+        }
+
+        let context = &options.macro_context();
+
+        for directive in spf.directives() {
+            let (compared, domain) = match &directive.mechanism {
+                Mechanism::Include(domain) => (options.mechanisms.include, domain),
+                Mechanism::Exists(domain) => (options.mechanisms.exists, domain),
+                _ => continue,
+            };
+
+            if !compared {
+                continue;
+            }
+
+            let expanded = expand_target(domain, current_domain, context);
+            let matched = expanded
+                .as_ref()
+                .is_some_and(|domain| options.match_mode.matches(domain, target));
+
+            self.trace(
+                "MECHANISM",
+                current_domain,
+                Some(directive.to_string()),
+                Some(matched),
+                || match expanded {
+                    None => "The macros of the domain cannot be expanded".to_owned(),
+                    Some(domain) if matched => format!("{domain} matches the target"),
+                    Some(domain) => format!("{domain} does not match the target"),
+                },
+            );
+        }
+    }
+
+    /// Records the `ip4` and `ip6` mechanisms of `spf` compared with the target `network` for
+    /// [`SpfChecker::explain`].
+    fn trace_network_mechanisms(&self, spf: &SpfRecord, current_domain: &str, network: &IpNetwork) {
+        if self.trace.is_none() {
+            return;
+        }
+
+        for directive in spf.directives() {
+            let (Mechanism::Ip4(declared) | Mechanism::Ip6(declared)) = &directive.mechanism else {
+                continue;
+            };
+
+            let covers = declared.covers(network);
+
+            self.trace(
+                "MECHANISM",
+                current_domain,
+                Some(directive.to_string()),
+                Some(covers),
+                || {
+                    if covers {
+                        format!("{declared} covers {network}")
+                    } else if declared.overlaps(network) {
+                        format!("{declared} overlaps {network}, but does not cover all of it")
+                    } else {
+                        format!("{declared} does not contain {network}")
+                    }
+                },
+            );
+        }
+    }
+
+    async fn check_target_mechanisms(
+        &self,
+        root_domain: &String,
+        target: &String,
+        mut initial_result: CheckResult,
+        options: &CheckOptions,
+    ) -> Result<CheckResult> {
+        // Resolve the target includes SPF record
+        let target_spf_txt = find_spf_record(self.resolver.as_ref(), target).await?;
+        initial_result
+            .records
+            .entry(target.clone())
+            .or_insert_with(|| target_spf_txt.clone());
+
+        let Some(target_spf_txt) = target_spf_txt else {
+            log_message(format!("No SPF record found for target domain: {target}"));
+            return Ok(CheckResult {
+                fallback_check: true,
+                ..initial_result
+            });
+        };
+
+        let target_spf = options
+            .parse_record(&target_spf_txt)
+            .context(CheckError::TargetSpfParseFailed)?;
+
+        // Extract mechanisms from target SPF (excluding 'all' mechanisms)
+        let target_mechanisms: Vec<String> = target_spf
+            .directives()
+            .filter(|directive| directive.mechanism != Mechanism::All) // Exclude 'all' mechanisms
+            .map(|directive| directive.mechanism.to_string())
+            .collect();
+
+        if target_mechanisms.is_empty() {
+            log_message(format!(
+                "No mechanisms found in target SPF record: {target_spf_txt}"
+            ));
+            return Ok(CheckResult {
+                fallback_check: true,
+                ..initial_result
+            });
+        }
+
+        log_message(format!("Target mechanisms to check: {target_mechanisms:?}"));
+
+        // Now check if all target mechanisms are present in root domain's SPF chain
+        let all_mechanisms_found = self
+            .check_all_mechanisms_present(
+                root_domain,
+                &target_mechanisms,
+                options,
+                &mut initial_result.errors,
+            )
+            .await?;
+
+        let mut visited_domains = initial_result.visited_domains;
+        visited_domains.push(target.clone());
+
+        Ok(CheckResult {
+            found: all_mechanisms_found,
+            visited: initial_result.visited + 1, // +1 for the target domain lookup
+            visited_domains,
+            fallback_check: true,
+            ..initial_result
+        })
+    }
+
+    /// Returns whether all `target_mechanisms` are declared in the tree of `root_domain`. Like
+    /// the direct check, the branches of failing domains are skipped and recorded in `errors`.
+    async fn check_all_mechanisms_present(
+        &self,
+        root_domain: &String,
+        target_mechanisms: &[String],
+        options: &CheckOptions,
+        errors: &mut Vec<DomainError>,
+    ) -> Result<bool> {
+        let context = &options.macro_context();
+
+        // Each domain is stacked together with its depth below the root domain.
+        let mut to_visit_stack = vec![(root_domain.to_owned(), 0)];
+        let mut visited = HashSet::new();
+        let mut found_mechanisms = HashSet::new();
+        let mut dns_mechanism_count = 0;
+
+        while let Some((current_domain, depth)) = to_visit_stack.pop() {
+            if dns_mechanism_count > DNS_LOOKUP_LIMIT {
+                log_message(format!(
+                    "Maximum DNS lookup limit reached during mechanism check: {DNS_LOOKUP_LIMIT}"
+                ));
+                break;
+            }
+
+            if !visited.insert(current_domain.clone()) {
+                continue;
+            }
+
+            let spf_txt = match find_spf_record(self.resolver.as_ref(), &current_domain).await {
+                Ok(Some(spf_txt)) => spf_txt,
+                Ok(None) => continue,
+                Err(err) => {
+                    self.skip_failed(root_domain, &current_domain, err, errors)?;
+                    continue;
+                }
+            };
+
+            let spf = match options
+                .parse_record(&spf_txt)
+                .context(CheckError::SpfParseFailed)
+            {
+                Ok(spf) => spf,
+                Err(err) => {
+                    self.skip_failed(root_domain, &current_domain, err, errors)?;
+                    continue;
+                }
+            };
+
+            dns_mechanism_count += dns_term_count(&spf);
+            if let Some(trace) = &self.trace {
+                trace.set_dns_mechanism_count(dns_mechanism_count);
+            }
+
+            // Collect all mechanisms from current SPF record
+            let current_mechanisms: Vec<String> = spf
+                .directives()
+                .map(|directive| directive.mechanism.to_string())
+                .collect();
+
+            // Check which target mechanisms are present in current record
+            for target_mechanism in target_mechanisms {
+                if current_mechanisms.contains(target_mechanism) {
+                    found_mechanisms.insert(target_mechanism.clone());
+                    self.trace(
+                        "MECHANISM",
+                        &current_domain,
+                        Some(target_mechanism.clone()),
+                        Some(true),
+                        || "The mechanism is also in the target's record".to_owned(),
+                    );
+                }
+            }
+
+            // If all target mechanisms found, return early
+            if found_mechanisms.len() == target_mechanisms.len() {
+                log_message(format!("All target mechanisms found: {found_mechanisms:?}"));
+                return Ok(true);
+            }
+
+            if !options.descends_below(depth) {
+                continue;
+            }
+
+            // Continue traversing includes and redirects
+            let includes: Vec<(String, usize)> = spf
+                .directives()
+                .filter_map(|directive| match &directive.mechanism {
+                    Mechanism::Include(domain) => {
+                        Some((expand_target(domain, &current_domain, context)?, depth + 1))
+                    }
+                    _ => None,
+                })
+                .collect();
+
+            if options.follow_redirects && !has_all_mechanism(&spf) {
+                let redirect = spf.redirect().and_then(|redirect| {
+                    Some((
+                        expand_target(redirect, &current_domain, context)?,
+                        depth + 1,
+                    ))
+                });
+
+                to_visit_stack.extend(redirect);
+            }
+
+            to_visit_stack.extend(includes);
+        }
+
+        let missing_mechanisms: Vec<&String> = target_mechanisms
+            .iter()
+            .filter(|mechanism| !found_mechanisms.contains(*mechanism))
+            .collect();
+
+        log_message(format!(
+            "Mechanism check completed. Found: {found_mechanisms:?}, Missing: {missing_mechanisms:?}"
+        ));
+
+        Ok(found_mechanisms.len() == target_mechanisms.len())
+    }
+}
+
+/// Returns the `ip4` or `ip6` directive of `spf` whose network covers all of `network`.
+fn find_network_mechanism<'a>(spf: &'a SpfRecord, network: &IpNetwork) -> Option<&'a Directive> {
+    spf.directives()
+        .find(|directive| match &directive.mechanism {
+            Mechanism::Ip4(declared) | Mechanism::Ip6(declared) => declared.covers(network),
+            _ => false,
+        })
+}
+
+/// Returns the `ip4` and `ip6` directives of `spf` whose networks share addresses with
+/// `network`.
+fn overlapping_network_mechanisms<'a>(
+    spf: &'a SpfRecord,
+    network: &'a IpNetwork,
+) -> impl Iterator<Item = &'a Directive> {
+    spf.directives()
+        .filter(|directive| match &directive.mechanism {
+            Mechanism::Ip4(declared) | Mechanism::Ip6(declared) => declared.overlaps(network),
+            _ => false,
+        })
+}
+
+/// Number of terms of `spf` that count against [`DNS_LOOKUP_LIMIT`]: `include`, `a`, `mx`,
+/// `ptr` and `exists` mechanisms plus a `redirect` modifier that is not ignored.
+fn dns_term_count(spf: &SpfRecord) -> usize {
+    let mechanisms = spf
+        .directives()
+        .filter(|directive| directive.mechanism.is_dns_lookup())
+        .count();
+
+    mechanisms + usize::from(spf.redirect().is_some() && !has_all_mechanism(spf))
+}
+
+/// Findings about the record `spf` published by `domain`.
+///
+/// https://datatracker.ietf.org/doc/html/rfc7208#section-5.5
+///
+/// > Note: This mechanism is slow, it is not as reliable as other mechanisms in cases of DNS
+/// > errors, and it places a large burden on the .arpa name servers. [...] This mechanism
+/// > SHOULD NOT be published.
+fn record_warnings(spf: &SpfRecord, domain: &str) -> Vec<Warning> {
+    spf.directives()
+        .filter_map(|directive| match directive.mechanism {
+            Mechanism::Ptr(_) => Some(Warning {
+                code: "PTR_MECHANISM",
+                domain: domain.to_owned(),
+                message: format!(
+                    "`{directive}` is deprecated by RFC 7208 §5.5: it is slow, unreliable on DNS errors and burdens the .arpa name servers"
+                ),
+            }),
+            Mechanism::All if directive.qualifier == Qualifier::Pass => Some(Warning {
+                code: "PASS_ALL",
+                domain: domain.to_owned(),
+                message: format!(
+                    "`{directive}` authorizes every host on the internet to send mail for the domain, which makes the record useless"
+                ),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Warns about SPF records of `domain` published as records of the deprecated `SPF` type, see
+/// [`SPF_RR_TYPE`], if there is no TXT record with the same content.
+fn spf_rr_warning(domain: &str, txts: &[String], spf_rrs: &[String]) -> Option<Warning> {
+    let mut rr_records: Vec<&String> = spf_rrs.iter().filter(|rr| is_spf_record(rr)).collect();
+    let mut txt_records: Vec<&String> = txts.iter().filter(|txt| is_spf_record(txt)).collect();
+
+    if rr_records.is_empty() {
+        return None;
+    }
+
+    rr_records.sort();
+    txt_records.sort();
+
+    let quoted = |records: &[&String]| {
+        records
+            .iter()
+            .map(|record| format!("`{record}`"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    if txt_records.is_empty() {
+        return Some(Warning {
+            code: "SPF_RR_ONLY",
+            domain: domain.to_owned(),
+            message: format!(
+                "The SPF record {} is only published with the deprecated SPF record type, which receivers no longer query, so the domain has no SPF policy; it must be published as TXT record",
+                quoted(&rr_records)
+            ),
+        });
+    }
+
+    (rr_records != txt_records).then(|| Warning {
+        code: "SPF_RR_MISMATCH",
+        domain: domain.to_owned(),
+        message: format!(
+            "The record {} of the deprecated SPF record type differs from the TXT record {}; receivers only evaluate the TXT record, so the other one should be removed",
+            quoted(&rr_records),
+            quoted(&txt_records)
+        ),
+    })
+}
+
+/// Warns about the Sender ID `records` of `domain`, which are useless since RFC 6686 concluded
+/// the Sender ID experiment.
+fn sender_id_warning(domain: &str, records: &[String], has_spf_record: bool) -> Warning {
+    let records = records
+        .iter()
+        .map(|record| format!("`{record}`"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let message = if has_spf_record {
+        format!(
+            "The domain also publishes the Sender ID record {records}, which is deprecated by RFC 6686 and ignored by receivers, so it can be removed"
+        )
+    } else {
+        format!(
+            "The domain publishes the Sender ID record {records}, which is deprecated by RFC 6686 and ignored by receivers, instead of a `v=spf1` record"
+        )
+    };
+
+    Warning {
+        code: "SENDER_ID_RECORD",
+        domain: domain.to_owned(),
+        message,
+    }
+}
+
+fn has_all_mechanism(spf: &SpfRecord) -> bool {
+    spf.directives()
+        .any(|directive| directive.mechanism == Mechanism::All)
+}
+
+fn is_wildcard(target: &str) -> bool {
+    target.contains('*')
+}
+
+/// Returns `true` if `host` matches `pattern`, where `*` stands for any sequence of
+/// characters, ignoring case and trailing dots.
+fn glob_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.trim_end_matches('.').to_ascii_lowercase();
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let segments: Vec<&str> = pattern.split('*').collect();
+
+    let [first, middle @ .., last] = segments.as_slice() else {
+        return host == pattern;
+    };
+
+    if host.len() < first.len() + last.len() || !host.starts_with(first) || !host.ends_with(last) {
+        return false;
+    }
+
+    let mut rest = &host[first.len()..host.len() - last.len()];
+
+    for segment in middle {
+        match rest.find(segment) {
+            Some(index) => rest = &rest[index + segment.len()..],
+            None => return false,
+        }
+    }
+
+    true
+}
+
+fn is_same_host(host: &str, other: &str) -> bool {
+    host.trim_end_matches('.')
+        .eq_ignore_ascii_case(other.trim_end_matches('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_support::MockResolver;
+
+    #[test]
+    fn test_txt_strings_are_concatenated_without_separator() {
+        let txt = TXT::new(vec![
+            "v=spf1 ip4:192.0.2.0/24 include:_spf.example".to_string(),
+            ".com -all".to_string(),
+        ]);
+
+        assert_eq!(
+            txt_string(&txt),
+            "v=spf1 ip4:192.0.2.0/24 include:_spf.example.com -all"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_target_in_first_record() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(&root_domain, "v=spf1 include:mail.easybill.de ~all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(result.found);
+        assert_eq!(result.visited, 1);
+        assert_eq!(
+            result.spf_record,
+            Some("v=spf1 include:mail.easybill.de ~all".to_string()),
+        );
+        assert_eq!(result.included_domains, Some(vec![target_domain]));
+        assert!(!result.fallback_check);
+        assert_eq!(result.qualifier, Some(Qualifier::Pass));
+        assert_eq!(result.default_policy, Some(Qualifier::SoftFail));
+    }
+
+    #[tokio::test]
+    async fn test_target_in_softfailed_include_with_redirected_policy() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 ~include:mail.easybill.de redirect=_spf.example.com",
+        );
+        mock_resolver.add_record("_spf.example.com", "v=spf1 ip4:192.0.2.1 -all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(result.found);
+        assert_eq!(
+            result.mechanism,
+            Some("include:mail.easybill.de".to_string())
+        );
+        assert_eq!(result.qualifier, Some(Qualifier::SoftFail));
+        assert_eq!(result.default_policy, Some(Qualifier::Fail));
+        assert_eq!(result.all_mechanism.as_deref(), Some("-all"));
+        assert_eq!(
+            result.default_policy_domain.as_deref(),
+            Some("_spf.example.com")
+        );
+
+        mock_resolver.add_record(&root_domain, "v=spf1 include:mail.easybill.de");
+
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+        assert_eq!(result.default_policy, Some(Qualifier::Neutral));
+        assert_eq!(result.all_mechanism, None);
+        assert_eq!(result.default_policy_domain.as_deref(), Some("example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_default_policy() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("_spf.example.net", "v=spf1 ~all");
+        let checker = SpfChecker::new(mock_resolver.clone());
+
+        for (record, policy, all, domain) in [
+            ("v=spf1 -all", Qualifier::Fail, Some("-all"), "example.com"),
+            (
+                "v=spf1 ~all",
+                Qualifier::SoftFail,
+                Some("~all"),
+                "example.com",
+            ),
+            // RFC 7208 §4.7: without `all`, the result is neutral.
+            (
+                "v=spf1 ip4:192.0.2.1",
+                Qualifier::Neutral,
+                None,
+                "example.com",
+            ),
+            (
+                "v=spf1 redirect=_spf.example.net",
+                Qualifier::SoftFail,
+                Some("~all"),
+                "_spf.example.net",
+            ),
+            // The redirect is ignored next to `all` (RFC 7208 §6.1).
+            (
+                "v=spf1 -all redirect=_spf.example.net",
+                Qualifier::Fail,
+                Some("-all"),
+                "example.com",
+            ),
+        ] {
+            mock_resolver.add_record("example.com", record);
+            let result = checker.check("example.com", "other.com").await.unwrap();
+
+            assert_eq!(result.default_policy, Some(policy), "{record}");
+            assert_eq!(result.all_mechanism.as_deref(), all, "{record}");
+            assert_eq!(
+                result.default_policy_domain.as_deref(),
+                Some(domain),
+                "{record}"
+            );
+        }
+
+        // Without SPF record, there is no policy.
+        let result = checker.check("other.com", "example.com").await.unwrap();
+        assert_eq!(result.default_policy, None);
+        assert_eq!(result.all_mechanism, None);
+    }
+
+    #[tokio::test]
+    async fn test_explanation_of_default_policy() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 include:mail.easybill.de exp=ignored.example.com redirect=_spf.example.com",
+        );
+        mock_resolver.add_record(
+            "_spf.example.com",
+            "v=spf1 ip4:192.0.2.1 exp=explain._spf.%{d2} -all",
+        );
+        mock_resolver.add_txt(
+            "explain._spf.example.com",
+            "See https://%{d}/why for mail from %{i}",
+        );
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let options = CheckOptions {
+            ip: Some("198.51.100.1".parse().unwrap()),
+            ..CheckOptions::default()
+        };
+        let result = checker
+            .check_with_options(&root_domain, &target_domain, &options)
+            .await
+            .unwrap();
+
+        assert_eq!(result.default_policy, Some(Qualifier::Fail));
+        assert_eq!(
+            result.explanation,
+            Some("See https://_spf.example.com/why for mail from 198.51.100.1".to_string())
+        );
+
+        // `%{i}` cannot be expanded without a client IP
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+        assert_eq!(result.explanation, None);
+    }
+
+    #[tokio::test]
+    async fn test_target_not_in_first_record() {
+        let root_domain = "_spf.example.com".to_string();
+        let target_domain = "mail.other.com".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(&root_domain, "v=spf1 include:mail.easybill.de ~all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(!result.found);
+        assert_eq!(result.visited, 2);
+        assert_eq!(
+            result.spf_record,
+            Some("v=spf1 include:mail.easybill.de ~all".to_string()),
+        );
+        assert_eq!(
+            result.included_domains,
+            Some(vec!["mail.easybill.de".to_string()])
+        );
+        assert!(result.fallback_check);
+    }
+
+    #[tokio::test]
+    async fn test_fallback_mechanism_check_success() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        // Root domain has individual mechanisms instead of include
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 a:server1.easybill.de mx:server2.easybill.de ~all",
+        );
+        // Target domain has the same mechanisms
+        mock_resolver.add_record(
+            &target_domain,
+            "v=spf1 a:server1.easybill.de mx:server2.easybill.de ~all",
+        );
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(result.found);
+        assert!(result.fallback_check);
+    }
+
+    #[tokio::test]
+    async fn test_fallback_mechanism_check_partial_match() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        // Root domain has only one of the target mechanisms
+        mock_resolver.add_record(&root_domain, "v=spf1 a:server1.easybill.de ~all");
+        // Target domain has multiple mechanisms
+        mock_resolver.add_record(
+            &target_domain,
+            "v=spf1 a:server1.easybill.de mx:server2.easybill.de ~all",
+        );
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(!result.found);
+        assert!(result.fallback_check);
+    }
+
+    #[tokio::test]
+    async fn test_dns_mechanism_count_limits_traversal() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let includes: Vec<String> = (0..=DNS_LOOKUP_LIMIT)
+            .map(|index| format!("include:spf{index}.example.com"))
+            .collect();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            &root_domain,
+            &format!("v=spf1 {} redirect=_spf.example.com", includes.join(" ")),
+        );
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(!result.found);
+        assert!(result.limit_exceeded);
+        assert_eq!(result.result(), SpfResult::PermError);
+        assert_eq!(result.dns_mechanism_count, DNS_LOOKUP_LIMIT + 2);
+        // The traversal stops after the root record exceeded the limit
+        assert_eq!(result.visited, 1);
+        assert_eq!(
+            result.errors,
+            vec![DomainError {
+                domain: "spf10.example.com".to_string(),
+                error: CheckError::LookupLimitExceeded,
+            }]
+        );
+
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 a mx include:_spf.example.com ip4:192.0.2.1 -all",
+        );
+        mock_resolver.add_record("_spf.example.com", "v=spf1 include:mail.easybill.de ~all");
+
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(result.found);
+        assert!(!result.limit_exceeded);
+        assert_eq!(result.result(), SpfResult::Pass);
+        assert_eq!(result.dns_mechanism_count, 4);
+    }
+
+    #[tokio::test]
+    async fn test_dns_mechanism_count_at_the_limit() {
+        let target_domain = "mail.easybill.de";
+        let mock_resolver = MockResolver::new();
+        for index in 0..=DNS_LOOKUP_LIMIT {
+            mock_resolver.add_record(
+                &format!("spf{index}.example.com"),
+                "v=spf1 ip4:192.0.2.0/24 -all",
+            );
+        }
+        let checker = SpfChecker::new(mock_resolver.clone());
+
+        let record = |lookups: usize| {
+            let includes: Vec<String> = (0..lookups)
+                .map(|index| format!("include:spf{index}.example.com"))
+                .collect();
+            format!("v=spf1 {} -all", includes.join(" "))
+        };
+
+        // Exactly the limit is still compliant.
+        mock_resolver.add_record("example.com", &record(DNS_LOOKUP_LIMIT));
+        let result = checker.check("example.com", target_domain).await.unwrap();
+        assert_eq!(result.dns_mechanism_count, DNS_LOOKUP_LIMIT);
+        assert!(!result.limit_exceeded);
+        assert!(result.errors.is_empty());
+        assert_eq!(result.result(), SpfResult::Fail);
+
+        // One more lookup exceeds it, and the domain beyond the limit is not looked up.
+        mock_resolver.add_record("example.com", &record(DNS_LOOKUP_LIMIT + 1));
+        let result = checker.check("example.com", target_domain).await.unwrap();
+        assert_eq!(result.dns_mechanism_count, DNS_LOOKUP_LIMIT + 1);
+        assert!(result.limit_exceeded);
+        assert_eq!(
+            result.errors,
+            vec![DomainError {
+                domain: "spf10.example.com".to_string(),
+                error: CheckError::LookupLimitExceeded,
+            }]
+        );
+        assert_eq!(result.result(), SpfResult::PermError);
+    }
+
+    #[tokio::test]
+    async fn test_visited_domains() {
+        // example.com includes a and b, which both include c.
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 include:a.example.net include:b.example.net -all",
+        );
+        mock_resolver.add_record("a.example.net", "v=spf1 include:c.example.net -all");
+        mock_resolver.add_record("b.example.net", "v=spf1 include:c.example.net -all");
+        mock_resolver.add_record("c.example.net", "v=spf1 ip4:192.0.2.0/24 -all");
+        mock_resolver.add_record("other.com", "v=spf1 ip4:198.51.100.0/24 -all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check("example.com", "other.com").await.unwrap();
+
+        // In traversal order, which takes the includes of a record from the stack last to
+        // first, with the shared include only once, followed by the target compared by the
+        // fallback check.
+        assert_eq!(
+            result.visited_domains,
+            [
+                "example.com",
+                "b.example.net",
+                "c.example.net",
+                "a.example.net",
+                "other.com"
+            ]
+        );
+        assert_eq!(result.visited, 5);
+    }
+
+    #[tokio::test]
+    async fn test_multiple_spf_records() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(&root_domain, "v=spf1 include:_spf.example.com -all");
+        mock_resolver.add_records(
+            "_spf.example.com",
+            &[
+                "v=spf1 ip4:192.0.2.1 -all",
+                "v=spf1 include:mail.easybill.de -all",
+            ],
+        );
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        // Only the branch of the included domain is skipped
+        assert!(!result.found);
+        assert_eq!(
+            result.errors,
+            vec![DomainError {
+                domain: "_spf.example.com".to_string(),
+                error: CheckError::MultipleSpfRecords,
+            }]
+        );
+
+        mock_resolver.add_records(
+            &root_domain,
+            &[
+                "v=spf1 ip4:192.0.2.1 -all",
+                "v=spf1 include:mail.easybill.de -all",
+            ],
+        );
+
+        let err = checker
+            .check(&root_domain, &target_domain)
+            .await
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err.downcast_ref::<MultipleSpfRecords>(),
+            Some(&MultipleSpfRecords {
+                domain: root_domain.clone(),
+                records: vec![
+                    "v=spf1 ip4:192.0.2.1 -all".to_string(),
+                    "v=spf1 include:mail.easybill.de -all".to_string()
+                ],
+            })
+        );
+
+        let result = checker
+            .check_host("192.0.2.1".parse().unwrap(), "user@example.com", "")
+            .await;
+        assert_eq!(result.result, SpfResult::PermError);
+    }
+
+    #[tokio::test]
+    async fn test_failed_domains_are_skipped() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 include:_spf.provider.net include:_spf.broken.net include:_spf.down.net -all",
+        );
+        mock_resolver.fail_lookups("_spf.down.net");
+        mock_resolver.add_record("_spf.broken.net", "v=spf1 ip4:192.0.2.300 -all");
+        mock_resolver.add_record("_spf.provider.net", "v=spf1 include:mail.easybill.de ~all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(result.found);
+        assert_eq!(
+            result.mechanism_domain.as_deref(),
+            Some("_spf.provider.net")
+        );
+        // Receivers fail the evaluation if they reach the domain that failed
+        assert_eq!(result.result(), SpfResult::TempError);
+        assert_eq!(
+            result.errors,
+            vec![
+                DomainError {
+                    domain: "_spf.down.net".to_string(),
+                    error: CheckError::DnsLookupFailed,
+                },
+                DomainError {
+                    domain: "_spf.broken.net".to_string(),
+                    error: CheckError::SpfParseFailed,
+                },
+            ]
+        );
+
+        mock_resolver.fail_lookups(&root_domain);
+
+        let err = checker
+            .check(&root_domain, &target_domain)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "DNS_LOOKUP_FAILED");
+    }
+
+    #[tokio::test]
+    async fn test_failed_domains_are_skipped_by_fallback() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 include:_spf.down.net include:_spf.provider.net -all",
+        );
+        mock_resolver.fail_lookups("_spf.down.net");
+        mock_resolver.add_record("_spf.provider.net", "v=spf1 ip4:192.0.2.0/24 ~all");
+        mock_resolver.add_record(&target_domain, "v=spf1 ip4:192.0.2.0/24 -all");
+
+        let checker = SpfChecker::new(mock_resolver);
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(result.found);
+        assert!(result.fallback_check);
+        // Recorded once, although both the direct and the fallback check reached it
+        assert_eq!(
+            result.errors,
+            vec![DomainError {
+                domain: "_spf.down.net".to_string(),
+                error: CheckError::DnsLookupFailed,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_redirect_is_skipped() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 include:mail.easybill.de redirect=_spf.down.net",
+        );
+        mock_resolver.fail_lookups("_spf.down.net");
+
+        let checker = SpfChecker::new(mock_resolver);
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(result.found);
+        assert_eq!(result.default_policy, None);
+        assert_eq!(result.result(), SpfResult::TempError);
+        assert_eq!(
+            result.errors,
+            vec![DomainError {
+                domain: "_spf.down.net".to_string(),
+                error: CheckError::DnsLookupFailed,
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_mechanisms() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 include:_spf.google.com include:_spf.vendor.net mx -all",
+        );
+        mock_resolver.add_record(
+            "_spf.vendor.net",
+            "v=spf1 include:_spf.google.com ~include:_spf.google.com ip4:192.0.2.1 -all",
+        );
+        mock_resolver.add_record("_spf.google.com", "v=spf1 ip4:192.0.2.1 ~all");
+
+        let checker = SpfChecker::new(mock_resolver);
+        let result = checker
+            .check("example.com", "mail.easybill.de")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result.duplicates,
+            vec![
+                DuplicateMechanism {
+                    mechanism: "include:_spf.google.com".to_string(),
+                    domains: vec![
+                        "example.com".to_string(),
+                        "_spf.vendor.net".to_string(),
+                        "_spf.vendor.net".to_string(),
+                    ],
+                },
+                DuplicateMechanism {
+                    mechanism: "ip4:192.0.2.1".to_string(),
+                    domains: vec!["_spf.vendor.net".to_string(), "_spf.google.com".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_path_to_target() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 include:_spf.other.net include:_spf.provider.net -all",
+        );
+        mock_resolver.add_record("_spf.other.net", "v=spf1 ip4:198.51.100.0/24 ~all");
+        mock_resolver.add_record("_spf.provider.net", "v=spf1 redirect=_spf2.provider.net");
+        mock_resolver.add_record("_spf2.provider.net", "v=spf1 include:mail.easybill.de ~all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(result.found);
+        assert_eq!(
+            result.path,
+            Some(vec![
+                "example.com".to_string(),
+                "_spf.provider.net".to_string(),
+                "_spf2.provider.net".to_string(),
+                "mail.easybill.de".to_string()
+            ])
+        );
+        assert_eq!(
+            result.included_domains,
+            Some(vec![
+                "_spf.other.net".to_string(),
+                "_spf.provider.net".to_string(),
+                "mail.easybill.de".to_string()
+            ])
+        );
+        assert_eq!(
+            result.visited_domains,
+            vec![
+                "example.com".to_string(),
+                "_spf.provider.net".to_string(),
+                "_spf2.provider.net".to_string(),
+            ]
+        );
+        assert_eq!(
+            result.redirect_chain,
+            vec![Redirect {
+                domain: "_spf.provider.net".to_string(),
+                target: "_spf2.provider.net".to_string(),
+            }]
+        );
+
+        let result = checker.check(&root_domain, "other.com").await.unwrap();
+        assert_eq!(result.path, None);
+    }
+
+    #[tokio::test]
+    async fn test_record_warnings() {
+        let root_domain = "example.com".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 include:_spf.a.net include:_spf.b.net redirect=_spf.example.com",
+        );
+        mock_resolver.add_record("_spf.a.net", "v=spf1 ptr:example.com +all");
+        mock_resolver.add_record(
+            "_spf.b.net",
+            &format!(
+                "v=spf1 include:_spf.a.net include:%{{i}}._spf.example.net ip4:{} -all",
+                vec!["192.0.2.1"; 26].join(" ip4:")
+            ),
+        );
+        mock_resolver.add_record("_spf.example.com", "v=spf1 ip4:198.51.100.0/24");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker
+            .check(&root_domain, "mail.easybill.de")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result
+                .warnings
+                .iter()
+                .map(|warning| (warning.code, warning.domain.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("RECORD_EXCEEDS_255_BYTES", "_spf.b.net"),
+                ("ANSWER_NEAR_512_BYTES", "_spf.b.net"),
+                ("MACRO_TERM_SKIPPED", "_spf.b.net"),
+                ("PTR_MECHANISM", "_spf.a.net"),
+                ("PASS_ALL", "_spf.a.net"),
+                // Included through _spf.b.net first
+                ("DUPLICATE_INCLUDE", "example.com"),
+                ("MISSING_ALL", "_spf.example.com"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_include_loop_is_reported() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(&root_domain, "v=spf1 include:c.com include:b.com -all");
+        mock_resolver.add_record("b.com", "v=spf1 include:example.com ~all");
+        // Reached twice without forming a loop
+        mock_resolver.add_record("c.com", "v=spf1 include:b.com ~all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(!result.found);
+        assert_eq!(
+            result.loops,
+            vec![vec![
+                "example.com".to_string(),
+                "b.com".to_string(),
+                "example.com".to_string()
+            ]]
+        );
+        assert_eq!(
+            result
+                .warnings
+                .iter()
+                .map(|warning| (warning.code, warning.domain.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("SPF_LOOP", "b.com"), ("DUPLICATE_INCLUDE", "c.com")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ptr_mechanisms_are_reported() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(&root_domain, "v=spf1 ptr include:_spf.provider.net -all");
+        mock_resolver.add_record("_spf.provider.net", "v=spf1 ?ptr:provider.net ~all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(!result.found);
+        assert_eq!(
+            result
+                .warnings
+                .iter()
+                .map(|warning| (warning.code, warning.domain.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("PTR_MECHANISM", "example.com"),
+                ("PTR_MECHANISM", "_spf.provider.net")
+            ]
+        );
+        assert!(result.warnings[1]
+            .message
+            .starts_with("`?ptr:provider.net`"));
+    }
+
+    #[tokio::test]
+    async fn test_sender_id_records_are_reported() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_records(
+            &root_domain,
+            &[
+                "v=spf1 include:_spf.provider.net -all",
+                "spf2.0/pra include:_spf.provider.net -all",
+            ],
+        );
+        mock_resolver.add_records(
+            "_spf.provider.net",
+            &["SPF2.0/mfrom,pra ip4:192.0.2.0/24 -all"],
+        );
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(!result.found);
+        assert_eq!(
+            result.sender_id_records,
+            vec!["spf2.0/pra include:_spf.provider.net -all".to_string()]
+        );
+        assert_eq!(
+            result
+                .warnings
+                .iter()
+                .map(|warning| (warning.code, warning.domain.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("SENDER_ID_RECORD", "example.com"),
+                ("SENDER_ID_RECORD", "_spf.provider.net")
+            ]
+        );
+        assert!(result.warnings[1]
+            .message
+            .contains("instead of a `v=spf1` record"));
+
+        for (txt, sender_id) in [
+            ("spf2.0/pra -all", true),
+            ("spf2.0/mfrom,pra", true),
+            ("spf2.0/ -all", false),
+            ("v=spf1 -all", false),
+            ("spf2.0 -all", false),
+        ] {
+            assert_eq!(is_sender_id_record(txt), sender_id, "{txt}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spf_rr_records_are_reported() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 include:_spf.provider.net include:_spf.other.net -all",
+        );
+        mock_resolver.add_spf_rr(&root_domain, "v=spf1 include:_spf.provider.net -all");
+        mock_resolver.add_record("_spf.provider.net", "v=spf1 ip4:192.0.2.0/24 -all");
+        mock_resolver.add_spf_rr("_spf.provider.net", "v=spf1 ip4:192.0.2.0/24 -all");
+        mock_resolver.add_spf_rr("_spf.other.net", "v=spf1 ip4:198.51.100.0/24 -all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+        assert!(result.warnings.is_empty());
+
+        let options = CheckOptions {
+            lookup_spf_rr: true,
+            ..CheckOptions::default()
+        };
+        let result = checker
+            .check_with_options(&root_domain, &target_domain, &options)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result
+                .warnings
+                .iter()
+                .map(|warning| (warning.code, warning.domain.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("SPF_RR_MISMATCH", "example.com"),
+                ("SPF_RR_ONLY", "_spf.other.net")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_character_strings() {
+        assert_eq!(
+            character_strings(b"\x0bv=spf1 ip4:\x11192.0.2.0/24 -all"),
+            "v=spf1 ip4:192.0.2.0/24 -all"
+        );
+        assert_eq!(character_strings(b""), "");
+        // A length beyond the end takes the remaining bytes
+        assert_eq!(character_strings(b"\x10v=spf1"), "v=spf1");
+    }
+
+    #[tokio::test]
+    async fn test_records_of_traversed_domains_are_reported() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 include:_spf.provider.net include:_spf.gone.example -all",
+        );
+        mock_resolver.add_record("_spf.provider.net", "v=spf1 ip4:198.51.100.0/24 ~all");
+        mock_resolver.add_record("mail.easybill.de", "v=spf1 ip4:192.0.2.1 -all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker
+            .check("example.com", "mail.easybill.de")
+            .await
+            .unwrap();
+
+        assert!(!result.found);
+        assert_eq!(
+            result.records,
+            BTreeMap::from([
+                ("_spf.gone.example".to_string(), None),
+                (
+                    "_spf.provider.net".to_string(),
+                    Some("v=spf1 ip4:198.51.100.0/24 ~all".to_string())
+                ),
+                (
+                    "example.com".to_string(),
+                    Some(
+                        "v=spf1 include:_spf.provider.net include:_spf.gone.example -all"
+                            .to_string()
+                    )
+                ),
+                // Looked up by the fallback check
+                (
+                    "mail.easybill.de".to_string(),
+                    Some("v=spf1 ip4:192.0.2.1 -all".to_string())
+                ),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ttls_of_traversed_records_are_reported() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("example.com", "v=spf1 include:_spf.provider.net -all");
+        mock_resolver.add_record("_spf.provider.net", "v=spf1 include:mail.easybill.de ~all");
+        mock_resolver.set_ttl("example.com", 3600);
+        mock_resolver.set_ttl("_spf.provider.net", 120);
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker
+            .check("example.com", "mail.easybill.de")
+            .await
+            .unwrap();
+
+        assert!(result.found);
+        assert_eq!(
+            result.ttls,
+            BTreeMap::from([
+                ("_spf.provider.net".to_string(), 120),
+                ("example.com".to_string(), 3600),
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_record_sizes_are_reported() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let networks: Vec<String> = (0..20).map(|i| format!("ip4:192.0.2.{i}")).collect();
+        let long_record = format!(
+            "v=spf1 {} include:_spf.provider.net -all",
+            networks.join(" ")
+        );
+        let verification = format!("site-verification={}", "x".repeat(200));
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_records(&root_domain, &[&long_record, &verification, &verification]);
+        mock_resolver.add_record("_spf.provider.net", "v=spf1 ip4:198.51.100.0/24 ~all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert_eq!(
+            result.record_sizes,
+            vec![
+                RecordSize {
+                    domain: "example.com".to_string(),
+                    record_bytes: long_record.len(),
+                    // Header and question, then 12 bytes plus the length prefix of each
+                    // character-string per record
+                    answer_bytes: 29
+                        + (12 + 2 + long_record.len())
+                        + 2 * (12 + 1 + verification.len()),
+                },
+                RecordSize {
+                    domain: "_spf.provider.net".to_string(),
+                    record_bytes: 31,
+                    answer_bytes: 35 + (12 + 1 + 31),
+                }
+            ]
+        );
+        assert_eq!(
+            result
+                .warnings
+                .iter()
+                .map(|warning| (warning.code, warning.domain.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("RECORD_EXCEEDS_255_BYTES", "example.com"),
+                ("ANSWER_EXCEEDS_512_BYTES", "example.com")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_missing_redirect_target_is_reported() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 include:_spf.other.net redirect=_spf.provider.net",
+        );
+        // An include without SPF record is not a redirect target
+        mock_resolver.add_txt("_spf.other.net", "site-verification=abc");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(!result.found);
+        assert_eq!(result.default_policy, None);
+        assert_eq!(
+            result
+                .warnings
+                .iter()
+                .map(|warning| (warning.code, warning.domain.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("REDIRECT_TARGET_MISSING", "example.com")]
+        );
+        assert!(result.warnings[0].message.contains("_spf.provider.net"));
+    }
+
+    #[tokio::test]
+    async fn test_subdomain_match_mode() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 include:noteasybill.de ~include:spf.easybill.de -all",
+        );
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+        assert!(!result.found);
+
+        let options = CheckOptions {
+            match_mode: MatchMode::Subdomain,
+            ..CheckOptions::default()
+        };
+        let result = checker
+            .check_with_options(&root_domain, &target_domain, &options)
+            .await
+            .unwrap();
+
+        assert!(result.found);
+        assert_eq!(
+            result.mechanism,
+            Some("include:spf.easybill.de".to_string())
+        );
+        assert_eq!(result.qualifier, Some(Qualifier::SoftFail));
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("*.sendgrid.net", "u123.wl.SendGrid.net."));
+        assert!(glob_matches("*spf*.example.com", "_spf1.example.com"));
+        assert!(glob_matches("mail.*", "mail.easybill.de"));
+        assert!(!glob_matches("*.sendgrid.net", "sendgrid.net"));
+        assert!(!glob_matches("*.sendgrid.net", "sendgrid.net.example.com"));
+        assert!(!glob_matches("a*a", "a"));
+    }
+
+    #[tokio::test]
+    async fn test_wildcard_target_reports_every_match() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "*.sendgrid.net".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 include:u1.wl.sendgrid.net include:_spf.provider.net -all",
+        );
+        mock_resolver.add_record(
+            "_spf.provider.net",
+            "v=spf1 a:mta.sendgrid.net include:sendgrid.net ~all",
+        );
+        mock_resolver.add_addresses("mta.sendgrid.net", &["167.89.0.1"]);
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(result.found);
+        assert!(!result.fallback_check);
+        assert_eq!(
+            result.mechanism,
+            Some("include:u1.wl.sendgrid.net".to_string())
+        );
+        assert_eq!(
+            result
+                .matches
+                .iter()
+                .map(|matched| (matched.mechanism.as_str(), matched.domain.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("include:u1.wl.sendgrid.net", "example.com"),
+                ("a:mta.sendgrid.net", "_spf.provider.net")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_all_matches() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 include:mail.easybill.de include:_spf.legacy.net -all",
+        );
+        mock_resolver.add_record(
+            "_spf.legacy.net",
+            "v=spf1 a:mail.easybill.de include:mail.easybill.de ~all",
+        );
+        mock_resolver.add_addresses("mail.easybill.de", &["192.0.2.1"]);
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+        assert_eq!(result.matches.len(), 1);
+
+        let options = CheckOptions {
+            all_matches: true,
+            ..CheckOptions::default()
+        };
+        let result = checker
+            .check_with_options(&root_domain, &target_domain, &options)
+            .await
+            .unwrap();
+
+        assert_eq!(result.mechanism_domain.as_deref(), Some("example.com"));
+        assert_eq!(
+            result
+                .matches
+                .iter()
+                .map(|matched| (matched.mechanism.as_str(), matched.domain.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("include:mail.easybill.de", "example.com"),
+                ("include:mail.easybill.de", "_spf.legacy.net"),
+                ("a:mail.easybill.de", "_spf.legacy.net")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mechanism_filter() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 a:mail.easybill.de exists:mail.easybill.de -all",
+        );
+        mock_resolver.add_addresses("mail.easybill.de", &["192.0.2.1"]);
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+
+        for (mechanisms, expected) in [
+            ("include,a,mx", Some("a:mail.easybill.de")),
+            ("include,mx", None),
+            ("exists", Some("exists:mail.easybill.de")),
+        ] {
+            let options = CheckOptions {
+                mechanisms: mechanisms.parse().unwrap(),
+                ..CheckOptions::default()
+            };
+            let result = checker
+                .check_with_options(&root_domain, &target_domain, &options)
+                .await
+                .unwrap();
+
+            assert_eq!(result.mechanism.as_deref(), expected, "{mechanisms}");
+        }
+
+        assert_eq!(
+            "include,ptr".parse::<MechanismFilter>(),
+            Err("unsupported mechanism `ptr`".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tree() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 include:_spf.a.net ~include:_spf.b.net redirect=_spf.example.net",
+        );
+        mock_resolver.add_record("_spf.a.net", "v=spf1 include:example.com -all");
+        mock_resolver.add_record("_spf.example.net", "v=spf1 ip4:192.0.2.0/24 -all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+
+        let tree = checker
+            .tree("Example.com", &CheckOptions::default())
+            .await
+            .unwrap();
+
+        let node = |domain: &str, term: &str, record: Option<&str>| SpfTreeNode {
+            domain: domain.to_string(),
+            term: Some(term.to_string()),
+            record: record.map(str::to_string),
+            is_loop: false,
+            error: None,
+            children: Vec::new(),
+        };
+
+        assert_eq!(
+            tree,
+            SpfTreeNode {
+                domain: "example.com".to_string(),
+                term: None,
+                record: Some(
+                    "v=spf1 include:_spf.a.net ~include:_spf.b.net redirect=_spf.example.net"
+                        .to_string()
+                ),
+                is_loop: false,
+                error: None,
+                children: vec![
+                    SpfTreeNode {
+                        children: vec![SpfTreeNode {
+                            is_loop: true,
+                            ..node("example.com", "include:example.com", None)
+                        }],
+                        ..node(
+                            "_spf.a.net",
+                            "include:_spf.a.net",
+                            Some("v=spf1 include:example.com -all")
+                        )
+                    },
+                    node("_spf.b.net", "~include:_spf.b.net", None),
+                    node(
+                        "_spf.example.net",
+                        "redirect=_spf.example.net",
+                        Some("v=spf1 ip4:192.0.2.0/24 -all")
+                    ),
+                ],
+            }
+        );
+
+        assert!(tree.providers().is_empty());
+
+        let options = CheckOptions {
+            follow_redirects: false,
+            max_depth: Some(0),
+            ..CheckOptions::default()
+        };
+        let tree = checker.tree("example.com", &options).await.unwrap();
+
+        assert!(tree.record.is_some());
+        assert!(tree.children.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tree_mechanisms() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 include:_spf.provider.net ip4:192.0.2.0/24 redirect=_spf.example.net",
+        );
+        mock_resolver.add_record(
+            "_spf.provider.net",
+            "v=spf1 ~include:_spf2.provider.net -all",
+        );
+        mock_resolver.add_record("_spf2.provider.net", "v=spf1 ip4:198.51.100.0/24 -all");
+        mock_resolver.add_record("_spf.example.net", "v=spf1 mx ~all");
+
+        let checker = SpfChecker::new(mock_resolver);
+        let tree = checker
+            .tree("example.com", &CheckOptions::default())
+            .await
+            .unwrap();
+
+        let mechanism = |mechanism: &str, path: &[&str]| SourcedMechanism {
+            mechanism: mechanism.to_string(),
+            domain: path.last().unwrap().to_string(),
+            depth: path.len() - 1,
+            path: path.iter().map(|domain| domain.to_string()).collect(),
+        };
+
+        assert_eq!(
+            tree.mechanisms(),
+            vec![
+                mechanism("include:_spf.provider.net", &["example.com"]),
+                mechanism("ip4:192.0.2.0/24", &["example.com"]),
+                mechanism(
+                    "~include:_spf2.provider.net",
+                    &["example.com", "_spf.provider.net"]
+                ),
+                mechanism("-all", &["example.com", "_spf.provider.net"]),
+                mechanism(
+                    "ip4:198.51.100.0/24",
+                    &["example.com", "_spf.provider.net", "_spf2.provider.net"]
+                ),
+                mechanism(
+                    "-all",
+                    &["example.com", "_spf.provider.net", "_spf2.provider.net"]
+                ),
+                mechanism("mx", &["example.com", "_spf.example.net"]),
+                mechanism("~all", &["example.com", "_spf.example.net"]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_partial_tree() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 include:_spf.a.net include:_spf.b.net -all",
+        );
+        mock_resolver.add_records(
+            "_spf.a.net",
+            &["v=spf1 ip4:192.0.2.1 -all", "v=spf1 ip4:192.0.2.2 -all"],
+        );
+        mock_resolver.add_record("_spf.b.net", "v=spf1 ip4:198.51.100.0/24 -all");
+
+        let checker = SpfChecker::new(mock_resolver);
+        let options = CheckOptions::default();
+
+        let err = checker.tree("example.com", &options).await.unwrap_err();
+        assert_eq!(err.to_string(), "MULTIPLE_SPF_RECORDS");
+
+        let tree = checker.partial_tree("example.com", &options).await.unwrap();
+
+        assert_eq!(tree.error, None);
+        assert_eq!(tree.children.len(), 2);
+        assert_eq!(tree.children[0].domain, "_spf.a.net");
+        assert_eq!(tree.children[0].record, None);
+        assert_eq!(
+            tree.children[0].error.as_deref(),
+            Some("MULTIPLE_SPF_RECORDS")
+        );
+        assert_eq!(
+            tree.children[1].record.as_deref(),
+            Some("v=spf1 ip4:198.51.100.0/24 -all")
+        );
+        assert_eq!(tree.children[1].error, None);
+    }
+
+    #[tokio::test]
+    async fn test_tree_progress() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("example.com", "v=spf1 include:_spf.a.net -all");
+        mock_resolver.add_record("_spf.a.net", "v=spf1 include:example.com -all");
+
+        let steps = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let checker = SpfChecker::new(mock_resolver).with_progress({
+            let steps = steps.clone();
+            move |step: &TraceStep| {
+                steps
+                    .lock()
+                    .unwrap()
+                    .push((step.kind, step.domain.clone(), step.term.clone()))
+            }
+        });
+
+        checker
+            .tree("example.com", &CheckOptions::default())
+            .await
+            .unwrap();
+
+        let step = |kind, domain: &str, term: Option<&str>| {
+            (kind, domain.to_string(), term.map(str::to_string))
+        };
+
+        assert_eq!(
+            *steps.lock().unwrap(),
+            vec![
+                step("DNS_QUERY", "example.com", Some("TXT")),
+                step(
+                    "RECORD",
+                    "example.com",
+                    Some("v=spf1 include:_spf.a.net -all")
+                ),
+                step("FOLLOW", "example.com", Some("include:_spf.a.net")),
+                step("DNS_QUERY", "_spf.a.net", Some("TXT")),
+                step(
+                    "RECORD",
+                    "_spf.a.net",
+                    Some("v=spf1 include:example.com -all")
+                ),
+                step("FOLLOW", "_spf.a.net", Some("include:example.com")),
+                step("SKIPPED", "example.com", None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_max_depth_and_follow_redirects() {
+        let root_domain = "example.com".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 include:_spf.provider.net redirect=_spf.redirect.net",
+        );
+        mock_resolver.add_record("_spf.provider.net", "v=spf1 include:_spf.target.com ~all");
+        mock_resolver.add_record("_spf.redirect.net", "v=spf1 include:_spf.other.com -all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
 
-        fn add_record(&self, domain: &str, spf_record: &str) {
-            let mut records = self.records.lock().unwrap();
-            records.insert(domain.to_string(), spf_record.to_owned());
-        }
-    }
+        // The default policy is declared by the redirect target
+        for (target, max_depth, follow_redirects, found, default_policy) in [
+            ("_spf.target.com", None, true, true, Some(Qualifier::Fail)),
+            (
+                "_spf.target.com",
+                Some(1),
+                true,
+                true,
+                Some(Qualifier::Fail),
+            ),
+            ("_spf.target.com", Some(0), true, false, None),
+            ("_spf.other.com", None, true, true, Some(Qualifier::Fail)),
+            ("_spf.other.com", None, false, false, None),
+        ] {
+            let options = CheckOptions {
+                max_depth,
+                follow_redirects,
+                ..CheckOptions::default()
+            };
+            let result = checker
+                .check_with_options(&root_domain, target, &options)
+                .await
+                .unwrap();
 
-    #[async_trait]
-    impl SpnResolver for MockResolver {
-        async fn find_spf_record(&self, domain: &str) -> Result<Option<String>> {
-            let records = self.records.lock().expect("mutex poisoned");
-            Ok(records.get(domain).cloned())
+            assert_eq!(
+                result.found, found,
+                "{target} {max_depth:?} {follow_redirects}"
+            );
+            assert_eq!(
+                result.default_policy, default_policy,
+                "{target} {max_depth:?} {follow_redirects}"
+            );
         }
     }
 
     #[tokio::test]
-    async fn test_target_in_first_record() {
+    async fn test_strict_mode_rejects_tolerated_syntax() {
         let root_domain = "example.com".to_string();
-        let target_domain = "mail.easybill.de".to_string();
+        let target_domain = "_spf.target.com".to_string();
 
         let mock_resolver = MockResolver::new();
-        mock_resolver.add_record(&root_domain, "v=spf1 include:mail.easybill.de ~all");
+        mock_resolver.add_record(&root_domain, "v=spf1 include:_spf.provider.net -all");
+        mock_resolver.add_record(
+            "_spf.provider.net",
+            "v=spf1 a:localhost include:_spf.target.com ~all",
+        );
+        mock_resolver.add_record(&target_domain, "v=spf1 ip4:192.0.2.0/24 -all");
 
         let checker = SpfChecker::new(mock_resolver.clone());
-        let result = checker.check(&root_domain, &target_domain).await.unwrap();
 
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
         assert!(result.found);
-        assert_eq!(result.visited, 1);
+
+        let options = CheckOptions {
+            strict: true,
+            ..CheckOptions::default()
+        };
+        let result = checker
+            .check_with_options(&root_domain, &target_domain, &options)
+            .await
+            .unwrap();
+        assert!(!result.found);
         assert_eq!(
-            result.spf_record,
-            Some("v=spf1 include:mail.easybill.de ~all".to_string()),
+            result.errors,
+            vec![DomainError {
+                domain: "_spf.provider.net".to_string(),
+                error: CheckError::SpfParseFailed,
+            }]
+        );
+
+        mock_resolver.add_record(&root_domain, "v=spf1 a:localhost -all");
+        let Err(err) = checker
+            .check_with_options(&root_domain, &target_domain, &options)
+            .await
+        else {
+            panic!("strict mode must reject `a:localhost`");
+        };
+
+        assert_eq!(err.to_string(), "SPF_PARSE_FAILED");
+        assert_eq!(
+            err.root_cause().to_string(),
+            "invalid term `a:localhost`: domain must end in a top-level label or a macro"
         );
-        assert_eq!(result.included_domains, Some(vec![target_domain]));
-        assert!(!result.fallback_check);
     }
 
     #[tokio::test]
-    async fn test_target_not_in_first_record() {
-        let root_domain = "_spf.example.com".to_string();
-        let target_domain = "mail.other.com".to_string();
+    async fn test_domains_are_normalized() {
+        let root_domain = " Example.COM. ".to_string();
+        let target_domain = "Mail.EasyBill.de.".to_string();
 
         let mock_resolver = MockResolver::new();
-        mock_resolver.add_record(&root_domain, "v=spf1 include:mail.easybill.de ~all");
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 include:_SPF.Provider.net. include:_spf.provider.net -all",
+        );
+        mock_resolver.add_record("_spf.provider.net", "v=spf1 include:MAIL.easybill.de ~all");
 
         let checker = SpfChecker::new(mock_resolver.clone());
         let result = checker.check(&root_domain, &target_domain).await.unwrap();
 
-        assert!(!result.found);
+        assert!(result.found);
         assert_eq!(result.visited, 2);
         assert_eq!(
-            result.spf_record,
-            Some("v=spf1 include:mail.easybill.de ~all".to_string()),
+            result.included_domains,
+            Some(vec![
+                "_spf.provider.net".to_string(),
+                "_spf.provider.net".to_string(),
+                "mail.easybill.de".to_string()
+            ])
         );
         assert_eq!(
-            result.included_domains,
-            Some(vec!["mail.easybill.de".to_string()])
+            result.path,
+            Some(vec![
+                "example.com".to_string(),
+                "_spf.provider.net".to_string(),
+                "mail.easybill.de".to_string()
+            ])
         );
-        assert!(result.fallback_check);
+        assert_eq!(result.default_policy, Some(Qualifier::Fail));
     }
 
     #[tokio::test]
-    async fn test_fallback_mechanism_check_success() {
-        let root_domain = "example.com".to_string();
-        let target_domain = "mail.easybill.de".to_string();
+    async fn test_internationalized_domains() {
+        let root_domain = "Bücher.example".to_string();
+        let target_domain = "mail.münchen.de".to_string();
 
         let mock_resolver = MockResolver::new();
-        // Root domain has individual mechanisms instead of include
-        mock_resolver.add_record(
-            &root_domain,
-            "v=spf1 a:server1.easybill.de mx:server2.easybill.de ~all",
-        );
-        // Target domain has the same mechanisms
         mock_resolver.add_record(
-            &target_domain,
-            "v=spf1 a:server1.easybill.de mx:server2.easybill.de ~all",
+            "xn--bcher-kva.example",
+            "v=spf1 include:mail.xn--mnchen-3ya.de -all",
         );
 
         let checker = SpfChecker::new(mock_resolver.clone());
         let result = checker.check(&root_domain, &target_domain).await.unwrap();
 
         assert!(result.found);
-        assert!(result.fallback_check);
+        assert_eq!(
+            result.path,
+            Some(vec![
+                "xn--bcher-kva.example".to_string(),
+                "mail.xn--mnchen-3ya.de".to_string()
+            ])
+        );
+        assert_eq!(
+            domain_to_unicode("mail.xn--mnchen-3ya.de."),
+            "mail.münchen.de"
+        );
+        assert_eq!(domain_to_unicode("Example.COM"), "example.com");
+        assert!(domain_to_ascii("bü cher.example").is_err());
     }
 
-    #[tokio::test]
-    async fn test_fallback_mechanism_check_partial_match() {
-        let root_domain = "example.com".to_string();
-        let target_domain = "mail.easybill.de".to_string();
-
-        let mock_resolver = MockResolver::new();
-        // Root domain has only one of the target mechanisms
-        mock_resolver.add_record(&root_domain, "v=spf1 a:server1.easybill.de ~all");
-        // Target domain has multiple mechanisms
-        mock_resolver.add_record(
-            &target_domain,
-            "v=spf1 a:server1.easybill.de mx:server2.easybill.de ~all",
+    #[test]
+    fn test_address_and_organizational_domain() {
+        assert_eq!(
+            address_domain(" bounce@mail.example.com "),
+            Some("mail.example.com")
         );
+        assert_eq!(address_domain("\"a@b\"@example.com"), Some("example.com"));
+        assert_eq!(address_domain("mail.example.com"), None);
 
-        let checker = SpfChecker::new(mock_resolver.clone());
-        let result = checker.check(&root_domain, &target_domain).await.unwrap();
-
-        assert!(!result.found);
-        assert!(result.fallback_check);
+        assert_eq!(
+            organizational_domain("Mail.Example.co.uk."),
+            Some("example.co.uk".to_string())
+        );
+        assert_eq!(
+            organizational_domain("example.com"),
+            Some("example.com".to_string())
+        );
+        assert_eq!(organizational_domain("co.uk"), None);
     }
 
     #[tokio::test]
@@ -455,7 +4411,10 @@ mod tests {
         mock_resolver.add_record(&target_include_domain, "v=spf1 ip4:167.235.178.61");
 
         // Setting up the root domain spf structure -> redirect -> direct ip4 mechanism with ips which are in the includes of the target domain
-        mock_resolver.add_record(&root_domain, "v=spf1 redirect=auc-online.de.spf.hornetdmarc.com");
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 redirect=auc-online.de.spf.hornetdmarc.com",
+        );
         mock_resolver.add_record(&redirect_domain, "v=spf1 ip4:167.235.178.61 ~all");
 
         let checker = SpfChecker::new(mock_resolver.clone());
@@ -492,4 +4451,360 @@ mod tests {
         );
         assert!(result.fallback_check);
     }
+
+    #[tokio::test]
+    async fn test_redirect_chain() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("example.com", "v=spf1 redirect=_spf.example.com");
+        mock_resolver.add_record(
+            "_spf.example.com",
+            "v=spf1 include:_spf.example.net redirect=_spf.example.org",
+        );
+        mock_resolver.add_record("_spf.example.net", "v=spf1 ip4:192.0.2.0/24 -all");
+        mock_resolver.add_record("_spf.example.org", "v=spf1 include:mail.example.org -all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker
+            .check("example.com", "mail.example.org")
+            .await
+            .unwrap();
+
+        assert!(result.found);
+        assert_eq!(
+            result.redirect_chain,
+            vec![
+                Redirect {
+                    domain: "example.com".to_string(),
+                    target: "_spf.example.com".to_string(),
+                },
+                Redirect {
+                    domain: "_spf.example.com".to_string(),
+                    target: "_spf.example.org".to_string(),
+                },
+            ]
+        );
+        // Redirect targets are not reported as includes.
+        assert_eq!(
+            result.included_domains,
+            Some(vec![
+                "_spf.example.net".to_string(),
+                "mail.example.org".to_string()
+            ])
+        );
+        assert_eq!(
+            result.default_policy_domain.as_deref(),
+            Some("_spf.example.org")
+        );
+
+        // Without redirects, the chain is empty.
+        let result = checker
+            .check("_spf.example.org", "mail.example.org")
+            .await
+            .unwrap();
+        assert!(result.redirect_chain.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_target_authorized_via_mx_host() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mx.easybill-mail.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(&root_domain, "v=spf1 mx -all");
+        mock_resolver.add_exchanges(&root_domain, &["mx.easybill-mail.de"]);
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(result.found);
+        assert!(!result.fallback_check);
+        assert_eq!(result.mechanism, Some("mx".to_string()));
+        assert_eq!(result.mechanism_domain, Some(root_domain));
+    }
+
+    #[tokio::test]
+    async fn test_target_authorized_via_a_mechanism_addresses() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(&root_domain, "v=spf1 include:_spf.example.com -all");
+        mock_resolver.add_record("_spf.example.com", "v=spf1 a:relay.example.com/24 -all");
+        mock_resolver.add_addresses("relay.example.com", &["192.0.2.1"]);
+        mock_resolver.add_addresses(&target_domain, &["192.0.2.25", "192.0.2.26"]);
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(result.found);
+        assert_eq!(result.mechanism, Some("a:relay.example.com/24".to_string()));
+        assert_eq!(
+            result.mechanism_domain,
+            Some("_spf.example.com".to_string())
+        );
+
+        mock_resolver.add_addresses(&target_domain, &["192.0.2.25", "198.51.100.1"]);
+
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+        assert!(!result.found);
+    }
+
+    #[tokio::test]
+    async fn test_ip_target_covered_by_included_network() {
+        let root_domain = "example.com".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 ip4:203.0.113.7 include:spf.easybill-mail.de -all",
+        );
+        mock_resolver.add_record(
+            "spf.easybill-mail.de",
+            "v=spf1 ip4:167.235.178.0/24 ip6:2a01:4f8:c17::/48 ~all",
+        );
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+
+        for (target, mechanism) in [
+            ("167.235.178.61", "ip4:167.235.178.0/24"),
+            ("2a01:4f8:c17::1", "ip6:2a01:4f8:c17::/48"),
+        ] {
+            let result = checker.check(&root_domain, target).await.unwrap();
+
+            assert!(result.found, "{target}");
+            assert_eq!(result.mechanism, Some(mechanism.to_string()));
+            assert_eq!(
+                result.mechanism_domain,
+                Some("spf.easybill-mail.de".to_string())
+            );
+        }
+
+        let result = checker.check(&root_domain, "198.51.100.1").await.unwrap();
+
+        assert!(!result.found);
+        assert!(!result.fallback_check);
+        assert_eq!(result.visited, 2);
+    }
+
+    #[tokio::test]
+    async fn test_network_target_covered_or_overlapped() {
+        let root_domain = "example.com".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 ip4:198.51.100.0/25 include:spf.easybill-mail.de -all",
+        );
+        mock_resolver.add_record(
+            "spf.easybill-mail.de",
+            "v=spf1 ip4:167.235.178.0/24 ip6:2a01:4f8:c17::/48 ~all",
+        );
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+
+        for (target, mechanism) in [
+            ("167.235.178.128/25", "ip4:167.235.178.0/24"),
+            ("2a01:4f8:c17:1::/64", "ip6:2a01:4f8:c17::/48"),
+        ] {
+            let result = checker.check(&root_domain, target).await.unwrap();
+
+            assert!(result.found, "{target}");
+            assert_eq!(result.mechanism, Some(mechanism.to_string()));
+            assert_eq!(
+                result.mechanism_domain,
+                Some("spf.easybill-mail.de".to_string())
+            );
+            assert!(result.overlapping_mechanisms.is_empty());
+        }
+
+        let result = checker
+            .check(&root_domain, "198.51.100.0/24")
+            .await
+            .unwrap();
+
+        assert!(!result.found);
+        assert!(!result.fallback_check);
+        assert_eq!(
+            result.overlapping_mechanisms,
+            vec![MechanismMatch {
+                mechanism: "ip4:198.51.100.0/25".to_string(),
+                domain: "example.com".to_string(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_void_lookup_limit_exceeded_during_traversal() {
+        let root_domain = "example.com".to_string();
+        let target_domain = "mail.easybill.de".to_string();
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(&root_domain, "v=spf1 a:gone1.example.com mx -all");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+
+        assert!(!result.found);
+        assert_eq!(result.void_lookups, 2);
+
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 a:gone1.example.com mx a:gone2.example.com -all",
+        );
+
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+        assert!(!result.found);
+        assert_eq!(result.void_lookups, 3);
+        assert_eq!(
+            result.errors,
+            vec![DomainError {
+                domain: root_domain.clone(),
+                error: CheckError::VoidLookupLimitExceeded,
+            }]
+        );
+        assert_eq!(result.result(), SpfResult::PermError);
+
+        // In an include, the traversal stops there as well.
+        mock_resolver.add_record(
+            &root_domain,
+            "v=spf1 include:_spf.example.net include:_spf.example.org -all",
+        );
+        mock_resolver.add_record(
+            "_spf.example.net",
+            "v=spf1 a:gone1.example.com a:gone2.example.com a:gone3.example.com -all",
+        );
+        mock_resolver.add_record("_spf.example.org", "v=spf1 ip4:192.0.2.1 -all");
+
+        let result = checker.check(&root_domain, &target_domain).await.unwrap();
+        assert_eq!(
+            result.errors,
+            vec![DomainError {
+                domain: "_spf.example.net".to_string(),
+                error: CheckError::VoidLookupLimitExceeded,
+            }]
+        );
+        assert_eq!(result.result(), SpfResult::PermError);
+    }
+
+    #[tokio::test]
+    async fn test_failed_mx_lookup_of_include() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 include:_spf.example.org include:broken.example.net -all",
+        );
+        mock_resolver.add_record("broken.example.net", "v=spf1 mx -all");
+        mock_resolver.add_record("_spf.example.org", "v=spf1 a:mail.example.org -all");
+        mock_resolver.fail_mx_lookups("broken.example.net");
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+        let result = checker
+            .check("example.com", "mail.example.org")
+            .await
+            .unwrap();
+
+        // The failed include is skipped, while the check goes on with the next one.
+        assert!(result.found);
+        assert_eq!(result.mechanism_domain.as_deref(), Some("_spf.example.org"));
+        assert_eq!(
+            result.errors,
+            vec![DomainError {
+                domain: "broken.example.net".to_string(),
+                error: CheckError::DnsLookupFailed,
+            }]
+        );
+        assert_eq!(result.result(), SpfResult::TempError);
+
+        // Failing lookups of the root domain still fail the check.
+        mock_resolver.fail_mx_lookups("example.com");
+        mock_resolver.add_record("example.com", "v=spf1 mx -all");
+        let err = checker
+            .check("example.com", "mail.example.org")
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "DNS_LOOKUP_FAILED");
+    }
+
+    #[tokio::test]
+    async fn test_target_in_macro_include() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("example.com", "v=spf1 include:%{ir}._spf.%{d} -all");
+        mock_resolver.add_record(
+            "10.2.0.192._spf.example.com",
+            "v=spf1 include:_spf.easybill.de -all",
+        );
+
+        let root_domain = "example.com".to_string();
+        let target_domain = "_spf.easybill.de".to_string();
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+
+        let without_ip = checker.check(&root_domain, &target_domain).await.unwrap();
+        assert!(!without_ip.found);
+        assert_eq!(without_ip.included_domains, Some(Vec::new()));
+        assert_eq!(
+            without_ip.skipped_macro_terms,
+            vec![SkippedTerm {
+                term: "include:%{ir}._spf.%{d}".to_string(),
+                domain: "example.com".to_string(),
+                reason: "no value available for macro `%{i}`".to_string(),
+            }]
+        );
+
+        let options = CheckOptions {
+            ip: Some("192.0.2.10".parse().unwrap()),
+            ..CheckOptions::default()
+        };
+        let result = checker
+            .check_with_options(&root_domain, &target_domain, &options)
+            .await
+            .unwrap();
+
+        assert!(result.found);
+        assert_eq!(
+            result.included_domains,
+            Some(vec![
+                "10.2.0.192._spf.example.com".to_string(),
+                "_spf.easybill.de".to_string()
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_txt_records() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_records(
+            "example.com",
+            &[
+                "google-site-verification=abc",
+                "v=spf 1 include:_spf.example.com -all",
+                "v=spf1 -all",
+            ],
+        );
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+
+        let records = checker.txt_records("example.com").await.unwrap();
+
+        let records: Vec<_> = records
+            .iter()
+            .map(|record| {
+                (
+                    record.text.as_str(),
+                    record.is_spf(),
+                    record.looks_like_spf(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            records,
+            vec![
+                ("google-site-verification=abc", false, false),
+                ("v=spf 1 include:_spf.example.com -all", false, true),
+                ("v=spf1 -all", true, false),
+            ]
+        );
+    }
 }
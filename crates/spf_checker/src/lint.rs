@@ -0,0 +1,413 @@
+use crate::record::{Mechanism, SpfRecord};
+use crate::{
+    dns_term_count, expand_target, has_all_mechanism, is_spf_record, only_spf_record,
+    record_warnings, CheckOptions, MultipleSpfRecords, RecordSize, SpnResolver, Warning,
+    DNS_LOOKUP_LIMIT, MAX_TREE_DOMAINS,
+};
+use anyhow::Result;
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+/// How severe a [`Finding`] is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The record makes the SPF evaluation fail with `permerror` or is invalid by RFC 7208.
+    Error,
+    /// The record works, but is risky or discouraged.
+    Warning,
+}
+
+impl Severity {
+    /// The lowercase name, e.g. `error`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warning => "warning",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A problem found in a record by [`SpfChecker::validate`](crate::SpfChecker::validate).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    /// Stable identifier of the kind of finding, e.g. `DUPLICATE_INCLUDE`.
+    pub code: &'static str,
+    /// The domain whose record the finding is about.
+    pub domain: String,
+    /// Human-readable description of the finding.
+    pub message: String,
+}
+
+impl Finding {
+    pub(crate) fn error(code: &'static str, domain: &str, message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            code,
+            domain: domain.to_owned(),
+            message,
+        }
+    }
+
+    pub(crate) fn warning(code: &'static str, domain: &str, message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            code,
+            domain: domain.to_owned(),
+            message,
+        }
+    }
+}
+
+impl From<Warning> for Finding {
+    fn from(warning: Warning) -> Self {
+        Self {
+            severity: Severity::Warning,
+            code: warning.code,
+            domain: warning.domain,
+            message: warning.message,
+        }
+    }
+}
+
+/// The outcome of [`SpfChecker::validate`](crate::SpfChecker::validate).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Validation {
+    /// Number of domains whose records were validated.
+    pub checked_domains: usize,
+    /// Number of DNS-querying terms in the validated records.
+    pub dns_lookups: usize,
+    /// All findings, in the order the records were traversed.
+    pub findings: Vec<Finding>,
+}
+
+impl Validation {
+    /// Returns `true` if no finding is an error.
+    pub fn is_valid(&self) -> bool {
+        self.findings
+            .iter()
+            .all(|finding| finding.severity != Severity::Error)
+    }
+}
+
+/// How a domain was reached during the validation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Reference {
+    Root,
+    Include,
+    Redirect,
+}
+
+/// Validates the record of a domain and every record referenced through `include` and
+/// `redirect`.
+pub(crate) struct Linter<'a> {
+    resolver: &'a (dyn SpnResolver + Send + Sync),
+    options: &'a CheckOptions,
+    findings: Vec<Finding>,
+}
+
+impl<'a> Linter<'a> {
+    pub fn new(resolver: &'a (dyn SpnResolver + Send + Sync), options: &'a CheckOptions) -> Self {
+        Self {
+            resolver,
+            options,
+            findings: Vec::new(),
+        }
+    }
+
+    pub async fn validate(mut self, root_domain: &str) -> Result<Validation> {
+        let context = &self.options.macro_context();
+
+        // Each domain is stacked together with the chain of domains that referenced it and
+        // how the last of them did so.
+        let mut to_visit_stack = vec![(root_domain.to_owned(), Vec::new(), Reference::Root)];
+        let mut visited = HashSet::new();
+        let mut dns_lookups = 0;
+
+        while let Some((domain, referrers, reference)) = to_visit_stack.pop() {
+            if referrers.contains(&domain) {
+                let cycle = [referrers.clone(), vec![domain.clone()]].concat();
+
+                self.findings.push(Finding::error(
+                    "SPF_LOOP",
+                    referrers.last().map_or(&domain, |referrer| referrer),
+                    format!("SPF loop detected: {}", cycle.join(" -> ")),
+                ));
+                continue;
+            }
+
+            if !visited.insert(domain.clone()) {
+                if reference == Reference::Include {
+                    self.findings.push(Finding::warning(
+                        "DUPLICATE_INCLUDE",
+                        referrers.last().map_or(&domain, |referrer| referrer),
+                        format!(
+                            "{domain} is included more than once, which costs additional DNS lookups without authorizing more senders"
+                        ),
+                    ));
+                }
+                continue;
+            }
+
+            if visited.len() > MAX_TREE_DOMAINS {
+                self.findings.push(Finding::error(
+                    "TREE_SIZE_LIMIT_EXCEEDED",
+                    root_domain,
+                    format!("More than {MAX_TREE_DOMAINS} domains are referenced, the remaining ones are not validated"),
+                ));
+                break;
+            }
+
+            let Some(spf) = self.validate_record(&domain, &referrers, reference).await? else {
+                continue;
+            };
+
+            dns_lookups += dns_term_count(&spf);
+            self.findings.extend(
+                record_warnings(&spf, &domain)
+                    .into_iter()
+                    .map(Finding::from),
+            );
+
+            // https://datatracker.ietf.org/doc/html/rfc7208#section-4.7
+            //
+            // > If none of the mechanisms match and there is no "redirect" modifier, then the
+            // > check_host() returns a result of "neutral", just as if "?all" were specified as
+            // > the last directive.
+            let ends_evaluation = reference != Reference::Include;
+
+            if ends_evaluation && !has_all_mechanism(&spf) && spf.redirect().is_none() {
+                self.findings.push(Finding::warning(
+                    "MISSING_ALL",
+                    &domain,
+                    "The record has neither an `all` mechanism nor a `redirect` modifier, so senders it does not list get a neutral result".to_owned(),
+                ));
+            }
+
+            if !self.options.descends_below(referrers.len()) {
+                continue;
+            }
+
+            let chain = [referrers, vec![domain.clone()]].concat();
+
+            // https://datatracker.ietf.org/doc/html/rfc7208#section-6.1
+            //
+            // > Any "redirect" modifier MUST be ignored if there is an "all" mechanism anywhere in
+            // > the record."
+            if self.options.follow_redirects && !has_all_mechanism(&spf) {
+                let redirect = spf.redirect().and_then(|redirect| {
+                    Some((
+                        expand_target(redirect, &domain, context)?,
+                        chain.clone(),
+                        Reference::Redirect,
+                    ))
+                });
+
+                to_visit_stack.extend(redirect);
+            }
+
+            // Includes are pushed in reverse, so that they are validated in record order.
+            let includes: Vec<_> = spf
+                .directives()
+                .filter_map(|directive| match &directive.mechanism {
+                    Mechanism::Include(include) => Some((
+                        expand_target(include, &domain, context)?,
+                        chain.clone(),
+                        Reference::Include,
+                    )),
+                    _ => None,
+                })
+                .collect();
+
+            to_visit_stack.extend(includes.into_iter().rev());
+        }
+
+        if dns_lookups > DNS_LOOKUP_LIMIT {
+            self.findings.push(Finding::error(
+                "DNS_LOOKUP_LIMIT_EXCEEDED",
+                root_domain,
+                format!(
+                    "The records contain {dns_lookups} DNS-querying terms, more than the limit of {DNS_LOOKUP_LIMIT}, which makes the SPF result permerror"
+                ),
+            ));
+        }
+
+        Ok(Validation {
+            checked_domains: visited.len(),
+            dns_lookups,
+            findings: self.findings,
+        })
+    }
+
+    /// Looks up and parses the record of `domain`, reporting findings about its presence, size
+    /// and syntax. Returns `None` if there is no record to traverse further.
+    async fn validate_record(
+        &mut self,
+        domain: &str,
+        referrers: &[String],
+        reference: Reference,
+    ) -> Result<Option<SpfRecord>> {
+        // All TXT records are needed to estimate the size of the answer.
+        let txts = self.resolver.lookup_txt(domain).await?;
+        let spf_records = txts.iter().filter(|txt| is_spf_record(txt)).cloned();
+
+        let spf_txt = match only_spf_record(domain, spf_records.collect()) {
+            Ok(spf_txt) => spf_txt,
+            Err(err) => {
+                let Some(multiple) = err.downcast_ref::<MultipleSpfRecords>() else {
+                    return Err(err);
+                };
+
+                self.findings.push(Finding::error(
+                    "MULTIPLE_SPF_RECORDS",
+                    domain,
+                    format!(
+                        "The domain publishes {} SPF records, which makes the SPF result permerror",
+                        multiple.records.len()
+                    ),
+                ));
+                return Ok(None);
+            }
+        };
+
+        let Some(spf_txt) = spf_txt else {
+            let referrer = referrers.last().map_or(domain, |referrer| referrer);
+
+            self.findings.push(match reference {
+                Reference::Root => Finding::error(
+                    "NO_SPF_RECORD",
+                    domain,
+                    "The domain has no SPF record".to_owned(),
+                ),
+                // https://datatracker.ietf.org/doc/html/rfc7208#section-5.2
+                //
+                // > If the <domain-spec> has no SPF record [...] return "permerror"
+                Reference::Include => Finding::error(
+                    "INCLUDE_TARGET_MISSING",
+                    referrer,
+                    format!("The included domain {domain} has no SPF record, which makes the SPF result permerror"),
+                ),
+                Reference::Redirect => Finding::error(
+                    "REDIRECT_TARGET_MISSING",
+                    referrer,
+                    format!("The redirect target {domain} has no SPF record, which makes the SPF result permerror"),
+                ),
+            });
+            return Ok(None);
+        };
+
+        let record_size = RecordSize::new(domain, &spf_txt, &txts);
+        self.findings
+            .extend(record_size.warnings().into_iter().map(Finding::from));
+
+        let err = match SpfRecord::parse_strict(&spf_txt) {
+            Ok(spf) => return Ok(Some(spf)),
+            Err(err) => err,
+        };
+
+        match SpfRecord::from_str(&spf_txt) {
+            // The syntax violation is tolerated by receivers that are not strict.
+            Ok(spf) => {
+                self.findings.push(Finding::warning(
+                    "SYNTAX_VIOLATION",
+                    domain,
+                    format!("The record violates the SPF syntax: {err}"),
+                ));
+                Ok(Some(spf))
+            }
+            Err(err) => {
+                let code = if err.is_unknown_mechanism() {
+                    "UNKNOWN_MECHANISM"
+                } else {
+                    "SYNTAX_ERROR"
+                };
+
+                self.findings.push(Finding::error(
+                    code,
+                    domain,
+                    format!(
+                        "The record cannot be parsed, which makes the SPF result permerror: {err}"
+                    ),
+                ));
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockResolver;
+    use crate::SpfChecker;
+
+    #[tokio::test]
+    async fn test_validate() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 include:_spf.a.net include:_spf.b.net include:_spf.missing.net ptr",
+        );
+        mock_resolver.add_record("_spf.a.net", "v=spf1 include:_spf.b.net -all");
+        mock_resolver.add_record("_spf.b.net", "v=spf1 foo:bar -all");
+
+        let hosts: Vec<String> = (1..=10).map(|i| format!("a:h{i}.example.org")).collect();
+        mock_resolver.add_record(
+            "example.org",
+            &format!("v=spf1 a:localhost {} -all", hosts.join(" ")),
+        );
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+
+        let validation = checker
+            .validate("example.com", &CheckOptions::default())
+            .await
+            .unwrap();
+
+        let findings: Vec<_> = validation
+            .findings
+            .iter()
+            .map(|finding| (finding.severity, finding.code, finding.domain.as_str()))
+            .collect();
+
+        assert_eq!(
+            findings,
+            vec![
+                (Severity::Warning, "PTR_MECHANISM", "example.com"),
+                (Severity::Warning, "MISSING_ALL", "example.com"),
+                (Severity::Error, "UNKNOWN_MECHANISM", "_spf.b.net"),
+                (Severity::Warning, "DUPLICATE_INCLUDE", "example.com"),
+                (Severity::Error, "INCLUDE_TARGET_MISSING", "example.com"),
+            ]
+        );
+        assert_eq!(validation.checked_domains, 4);
+        assert!(!validation.is_valid());
+
+        let validation = checker
+            .validate("example.org", &CheckOptions::default())
+            .await
+            .unwrap();
+
+        let findings: Vec<_> = validation
+            .findings
+            .iter()
+            .map(|finding| (finding.severity, finding.code))
+            .collect();
+
+        assert_eq!(
+            findings,
+            vec![
+                (Severity::Warning, "SYNTAX_VIOLATION"),
+                (Severity::Error, "DNS_LOOKUP_LIMIT_EXCEEDED"),
+            ]
+        );
+        assert_eq!(validation.dns_lookups, 11);
+    }
+}
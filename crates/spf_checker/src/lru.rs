@@ -0,0 +1,112 @@
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+/// A map remembering the order its entries were used in, so that the least recently used one
+/// can make room for a new one while it is full.
+#[derive(Debug)]
+pub(crate) struct Lru<K, V> {
+    /// The values with the tick they were last used at, their key in `recency`.
+    entries: HashMap<K, (V, u64)>,
+    /// The keys by the tick they were last used at, least recently used first.
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+impl<K, V> Default for Lru<K, V> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash, V> Lru<K, V> {
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The value of `key`, without marking it as used.
+    pub(crate) fn peek<Q: Eq + Hash + ?Sized>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        self.entries.get(key).map(|(value, _)| value)
+    }
+
+    /// The value of `key`, marked as the most recently used.
+    pub(crate) fn get<Q: Eq + Hash + ?Sized>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+    {
+        let (value, used) = self.entries.get_mut(key)?;
+        if let Some(key) = self.recency.remove(used) {
+            self.tick += 1;
+            *used = self.tick;
+            self.recency.insert(self.tick, key);
+        }
+
+        Some(value)
+    }
+
+    /// Inserts `value` as the most recently used, returning the value it replaces.
+    pub(crate) fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let replaced = self.remove(&key);
+
+        self.tick += 1;
+        self.recency.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, self.tick));
+
+        replaced
+    }
+
+    pub(crate) fn remove<Q: Eq + Hash + ?Sized>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+    {
+        let (value, used) = self.entries.remove(key)?;
+        self.recency.remove(&used);
+
+        Some(value)
+    }
+
+    /// Removes the least recently used entry, returning whether there was one.
+    pub(crate) fn evict(&mut self) -> bool {
+        let Some((_, key)) = self.recency.pop_first() else {
+            return false;
+        };
+
+        self.entries.remove(&key).is_some()
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(key, (value, _))| (key, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut lru = Lru::default();
+        lru.insert("a", 1);
+        lru.insert("b", 2);
+        lru.insert("c", 3);
+
+        assert_eq!(lru.get(&"a"), Some(&1));
+        assert_eq!(lru.peek(&"b"), Some(&2));
+        assert!(lru.evict());
+        assert_eq!(lru.peek(&"b"), None);
+
+        assert_eq!(lru.insert("c", 4), Some(3));
+        assert!(lru.evict());
+        assert_eq!(lru.peek(&"a"), None);
+        assert_eq!(lru.remove(&"c"), Some(4));
+        assert_eq!(lru.len(), 0);
+        assert!(!lru.evict());
+    }
+}
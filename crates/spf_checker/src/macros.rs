@@ -0,0 +1,433 @@
+use std::fmt;
+use std::net::IpAddr;
+
+/// https://datatracker.ietf.org/doc/html/rfc7208#section-7.3
+///
+/// > When the result of macro expansion is used in a domain name query, if the expanded
+/// > domain name exceeds 253 characters [...], the left side is truncated to fit, by removing
+/// > successive domain labels (and their following dots) until the total length does not
+/// > exceed 253 characters.
+const MAX_DOMAIN_LENGTH: usize = 253;
+
+const DELIMITERS: &str = ".-+,/_=";
+
+/// Values substituted for macro letters (RFC 7208 §7.2).
+///
+/// Letters whose value is missing fail with [`MacroError::Unavailable`].
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct MacroContext<'a> {
+    /// The MAIL FROM identity, providing `%{s}`, `%{l}` and `%{o}`.
+    pub sender: Option<&'a str>,
+    /// The client IP, providing `%{i}` and `%{v}`.
+    pub ip: Option<IpAddr>,
+    /// The HELO identity, providing `%{h}`.
+    pub helo: Option<&'a str>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum MacroError {
+    /// The domain spec is not a valid macro-string.
+    Syntax(String),
+    /// The macro letter needs a value that was not provided, e.g. `%{i}` without a client IP.
+    Unavailable(char),
+}
+
+impl fmt::Display for MacroError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syntax(reason) => write!(f, "invalid macro: {reason}"),
+            Self::Unavailable(letter) => write!(f, "no value available for macro `%{{{letter}}}`"),
+        }
+    }
+}
+
+pub(crate) fn contains_macro(spec: &str) -> bool {
+    spec.contains('%')
+}
+
+/// Expands the macros of a domain spec found in the record of `domain`.
+pub(crate) fn expand_domain(
+    spec: &str,
+    domain: &str,
+    context: &MacroContext,
+) -> Result<String, MacroError> {
+    let expanded = expand(spec, domain, context, false)?;
+
+    Ok(truncate_domain(&expanded).to_owned())
+}
+
+/// Checks that `spec` is a valid macro-string of a domain spec (RFC 7208 §7.1) without
+/// needing values for its macros.
+pub(crate) fn validate_domain(spec: &str) -> Result<(), MacroError> {
+    if let Some(c) = spec.chars().find(|c| !('!'..='~').contains(c)) {
+        return Err(MacroError::Syntax(format!(
+            "invalid character `{}` in `{spec}`",
+            c.escape_default()
+        )));
+    }
+
+    let context = MacroContext {
+        sender: Some("postmaster@example.com"),
+        ip: Some(IpAddr::from([192, 0, 2, 1])),
+        helo: Some("example.com"),
+    };
+
+    expand(spec, "example.com", &context, false).map(drop)
+}
+
+/// Expands the macros of the explanation string published for an `exp` modifier of the
+/// record of `domain`, which may additionally use `%{c}`, `%{r}` and `%{t}`.
+pub(crate) fn expand_explanation(
+    spec: &str,
+    domain: &str,
+    context: &MacroContext,
+) -> Result<String, MacroError> {
+    expand(spec, domain, context, true)
+}
+
+fn expand(
+    spec: &str,
+    domain: &str,
+    context: &MacroContext,
+    explanation: bool,
+) -> Result<String, MacroError> {
+    let mut output = String::with_capacity(spec.len());
+    let mut rest = spec;
+
+    while let Some(index) = rest.find('%') {
+        output.push_str(&rest[..index]);
+
+        let after = &rest[index + 1..];
+        let mut consumed = 1;
+
+        match after.chars().next() {
+            Some('%') => output.push('%'),
+            Some('_') => output.push(' '),
+            Some('-') => output.push_str("%20"),
+            Some('{') => {
+                let end = after
+                    .find('}')
+                    .ok_or_else(|| MacroError::Syntax(format!("unterminated macro in `{spec}`")))?;
+
+                output.push_str(&expand_macro(&after[1..end], domain, context, explanation)?);
+                consumed = end + 1;
+            }
+            _ => {
+                return Err(MacroError::Syntax(format!(
+                    "`%` must be followed by `{{`, `%`, `_` or `-` in `{spec}`"
+                )))
+            }
+        }
+
+        rest = &after[consumed..];
+    }
+
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+/// Expands a single `%{...}` macro given its body, e.g. `l1r-`.
+fn expand_macro(
+    body: &str,
+    domain: &str,
+    context: &MacroContext,
+    explanation: bool,
+) -> Result<String, MacroError> {
+    let mut chars = body.chars();
+    let letter = chars
+        .next()
+        .ok_or_else(|| MacroError::Syntax("empty macro".to_owned()))?;
+
+    let transformers = chars.as_str();
+    let (digits, rest) = transformers.split_at(
+        transformers
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(transformers.len()),
+    );
+    let (reverse, delimiters) = match rest.strip_prefix(['r', 'R']) {
+        Some(delimiters) => (true, delimiters),
+        None => (false, rest),
+    };
+
+    if delimiters.chars().any(|c| !DELIMITERS.contains(c)) {
+        return Err(MacroError::Syntax(format!(
+            "invalid transformers in `%{{{body}}}`"
+        )));
+    }
+
+    let keep = match digits {
+        "" => None,
+        digits => Some(
+            digits
+                .parse::<usize>()
+                .ok()
+                .filter(|keep| *keep > 0)
+                .ok_or_else(|| MacroError::Syntax(format!("invalid digits in `%{{{body}}}`")))?,
+        ),
+    };
+
+    let value = macro_value(letter.to_ascii_lowercase(), domain, context, explanation)?;
+    let delimiters = if delimiters.is_empty() {
+        "."
+    } else {
+        delimiters
+    };
+
+    let mut parts: Vec<&str> = value.split(|c| delimiters.contains(c)).collect();
+
+    if reverse {
+        parts.reverse();
+    }
+
+    if let Some(keep) = keep {
+        parts.drain(..parts.len().saturating_sub(keep));
+    }
+
+    let expanded = parts.join(".");
+
+    Ok(if letter.is_ascii_uppercase() {
+        url_escape(&expanded)
+    } else {
+        expanded
+    })
+}
+
+fn macro_value(
+    letter: char,
+    domain: &str,
+    context: &MacroContext,
+    explanation: bool,
+) -> Result<String, MacroError> {
+    let value = match letter {
+        's' => context.sender.map(str::to_owned),
+        'l' => context
+            .sender
+            .map(|sender| split_sender(sender).0.to_owned()),
+        'o' => context
+            .sender
+            .map(|sender| split_sender(sender).1.to_owned()),
+        'd' => Some(domain.to_owned()),
+        'i' => context.ip.map(dotted_ip),
+        // https://datatracker.ietf.org/doc/html/rfc7208#section-7.3
+        //
+        // > This macro SHOULD NOT be used. [...] If a validated domain cannot be determined,
+        // > the string "unknown" is used.
+        'p' => Some("unknown".to_owned()),
+        'v' => context.ip.map(|ip| match ip {
+            IpAddr::V4(_) => "in-addr".to_owned(),
+            IpAddr::V6(_) => "ip6".to_owned(),
+        }),
+        'h' => context.helo.map(str::to_owned),
+        // https://datatracker.ietf.org/doc/html/rfc7208#section-7.2
+        //
+        // > The following macro letters are allowed only in "exp" text: c, r, t
+        'c' if explanation => context.ip.map(|ip| ip.to_string()),
+        'r' if explanation => Some("unknown".to_owned()),
+        't' if explanation => Some(
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs())
+                .to_string(),
+        ),
+        _ => {
+            return Err(MacroError::Syntax(format!(
+                "unknown macro letter `{letter}`"
+            )))
+        }
+    };
+
+    value.ok_or(MacroError::Unavailable(letter))
+}
+
+/// Splits a MAIL FROM identity into local-part and domain, defaulting the local-part to
+/// `postmaster` (RFC 7208 §4.3).
+fn split_sender(sender: &str) -> (&str, &str) {
+    match sender.rsplit_once('@') {
+        Some(("", domain)) => ("postmaster", domain),
+        Some((local, domain)) => (local, domain),
+        None => ("postmaster", sender),
+    }
+}
+
+/// Formats IPv4 addresses in dotted quad and IPv6 addresses as dot-separated nibbles
+/// (RFC 7208 §7.3).
+fn dotted_ip(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => {
+            let mut output = String::with_capacity(63);
+
+            for byte in ip.octets() {
+                for nibble in [byte >> 4, byte & 0x0f] {
+                    if !output.is_empty() {
+                        output.push('.');
+                    }
+                    output.push(char::from_digit(u32::from(nibble), 16).expect("nibble < 16"));
+                }
+            }
+
+            output
+        }
+    }
+}
+
+fn url_escape(value: &str) -> String {
+    let mut output = String::with_capacity(value.len());
+
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            output.push(char::from(byte));
+        } else {
+            output.push_str(&format!("%{byte:02X}"));
+        }
+    }
+
+    output
+}
+
+fn truncate_domain(domain: &str) -> &str {
+    let mut domain = domain;
+
+    while domain.len() > MAX_DOMAIN_LENGTH {
+        match domain.split_once('.') {
+            Some((_, rest)) => domain = rest,
+            None => break,
+        }
+    }
+
+    domain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// https://datatracker.ietf.org/doc/html/rfc7208#section-7.4
+    #[test]
+    fn test_expand_rfc_examples() {
+        let context = MacroContext {
+            sender: Some("strong-bad@email.example.com"),
+            ip: Some("192.0.2.3".parse().unwrap()),
+            helo: Some("mx.example.org"),
+        };
+
+        for (spec, expected) in [
+            ("%{s}", "strong-bad@email.example.com"),
+            ("%{o}", "email.example.com"),
+            ("%{d}", "email.example.com"),
+            ("%{d4}", "email.example.com"),
+            ("%{d3}", "email.example.com"),
+            ("%{d2}", "example.com"),
+            ("%{d1}", "com"),
+            ("%{dr}", "com.example.email"),
+            ("%{d2r}", "example.email"),
+            ("%{l}", "strong-bad"),
+            ("%{l-}", "strong.bad"),
+            ("%{lr}", "strong-bad"),
+            ("%{lr-}", "bad.strong"),
+            ("%{l1r-}", "strong"),
+            ("%{h}", "mx.example.org"),
+            (
+                "%{ir}.%{v}._spf.%{d2}",
+                "3.2.0.192.in-addr._spf.example.com",
+            ),
+            ("%{lr-}.lp._spf.%{d2}", "bad.strong.lp._spf.example.com"),
+            (
+                "%{ir}.%{v}.%{l1r-}.lp._spf.%{d2}",
+                "3.2.0.192.in-addr.strong.lp._spf.example.com",
+            ),
+            (
+                "%{d2}.trusted-domains.example.net",
+                "example.com.trusted-domains.example.net",
+            ),
+            ("%%%_%-", "% %20"),
+        ] {
+            assert_eq!(
+                expand_domain(spec, "email.example.com", &context).as_deref(),
+                Ok(expected),
+                "{spec}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_expand_ipv6_address() {
+        let context = MacroContext {
+            ip: Some("2001:db8::cb01".parse().unwrap()),
+            ..MacroContext::default()
+        };
+
+        assert_eq!(
+            expand_domain("%{ir}.%{v}._spf.%{d2}", "email.example.com", &context).as_deref(),
+            Ok("1.0.b.c.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6._spf.example.com")
+        );
+    }
+
+    #[test]
+    fn test_expand_explanation() {
+        let context = MacroContext {
+            sender: Some("strong-bad@email.example.com"),
+            ip: Some("2001:db8::cb01".parse().unwrap()),
+            helo: None,
+        };
+
+        assert_eq!(
+            expand_explanation(
+                "%{c} is not one of %{d}'s designated mail servers.",
+                "email.example.com",
+                &context
+            )
+            .as_deref(),
+            Ok("2001:db8::cb01 is not one of email.example.com's designated mail servers.")
+        );
+
+        assert!(matches!(
+            expand_domain("%{c}.example.com", "example.com", &context),
+            Err(MacroError::Syntax(_))
+        ));
+    }
+
+    #[test]
+    fn test_expand_errors() {
+        let context = MacroContext::default();
+
+        assert_eq!(
+            expand_domain("%{i}._spf.example.com", "example.com", &context),
+            Err(MacroError::Unavailable('i'))
+        );
+
+        for spec in ["%{x}", "%{d0}", "%{d", "%a", "%{d2r*}"] {
+            assert!(
+                matches!(
+                    expand_domain(spec, "example.com", &context),
+                    Err(MacroError::Syntax(_))
+                ),
+                "{spec}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_validate_domain() {
+        for spec in [
+            "%{ir}.%{v}._spf.%{d2}",
+            "%{l1r-}.example.com",
+            "_spf.example.com",
+        ] {
+            assert_eq!(validate_domain(spec), Ok(()), "{spec}");
+        }
+
+        for spec in [
+            "%{x}.example.com",
+            "%{c}.example.com",
+            "%{d",
+            "spf\u{e4}.example.com",
+        ] {
+            assert!(
+                matches!(validate_domain(spec), Err(MacroError::Syntax(_))),
+                "{spec}"
+            );
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{MxRecord, SpnResolver, TxtRecord};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+
+type Answers<K, V> = Mutex<HashMap<K, V>>;
+
+/// Remembers the successful answers of a resolver, so that checks sharing it look up each
+/// name only once. Failures are not remembered.
+#[derive(Debug)]
+pub(crate) struct MemoResolver {
+    resolver: Arc<dyn SpnResolver + Send + Sync + 'static>,
+    spf: Answers<String, Vec<String>>,
+    txt: Answers<String, Vec<String>>,
+    txt_records: Answers<String, Vec<TxtRecord>>,
+    a: Answers<String, Vec<Ipv4Addr>>,
+    aaaa: Answers<String, Vec<Ipv6Addr>>,
+    mx: Answers<String, Vec<String>>,
+    mx_records: Answers<String, Vec<MxRecord>>,
+    ptr: Answers<IpAddr, Vec<String>>,
+    spf_rr: Answers<String, Vec<String>>,
+}
+
+impl MemoResolver {
+    pub fn new(resolver: Arc<dyn SpnResolver + Send + Sync + 'static>) -> Self {
+        Self {
+            resolver,
+            spf: Answers::default(),
+            txt: Answers::default(),
+            txt_records: Answers::default(),
+            a: Answers::default(),
+            aaaa: Answers::default(),
+            mx: Answers::default(),
+            mx_records: Answers::default(),
+            ptr: Answers::default(),
+            spf_rr: Answers::default(),
+        }
+    }
+}
+
+/// Returns the remembered answer for `key`, or looks it up and remembers it if successful.
+async fn remembered<K, V>(
+    answers: &Answers<K, V>,
+    key: K,
+    lookup: impl Future<Output = Result<V>>,
+) -> Result<V>
+where
+    K: Eq + Hash,
+    V: Clone,
+{
+    if let Some(answer) = answers.lock().expect("mutex poisoned").get(&key) {
+        return Ok(answer.clone());
+    }
+
+    let answer = lookup.await?;
+    answers
+        .lock()
+        .expect("mutex poisoned")
+        .insert(key, answer.clone());
+
+    Ok(answer)
+}
+
+#[async_trait]
+impl SpnResolver for MemoResolver {
+    async fn find_spf_records(&self, domain: &str) -> Result<Vec<String>> {
+        remembered(
+            &self.spf,
+            domain.to_owned(),
+            self.resolver.find_spf_records(domain),
+        )
+        .await
+    }
+
+    async fn lookup_txt(&self, domain: &str) -> Result<Vec<String>> {
+        remembered(
+            &self.txt,
+            domain.to_owned(),
+            self.resolver.lookup_txt(domain),
+        )
+        .await
+    }
+
+    async fn lookup_txt_records(&self, domain: &str) -> Result<Vec<TxtRecord>> {
+        remembered(
+            &self.txt_records,
+            domain.to_owned(),
+            self.resolver.lookup_txt_records(domain),
+        )
+        .await
+    }
+
+    async fn lookup_a(&self, domain: &str) -> Result<Vec<Ipv4Addr>> {
+        remembered(&self.a, domain.to_owned(), self.resolver.lookup_a(domain)).await
+    }
+
+    async fn lookup_aaaa(&self, domain: &str) -> Result<Vec<Ipv6Addr>> {
+        remembered(
+            &self.aaaa,
+            domain.to_owned(),
+            self.resolver.lookup_aaaa(domain),
+        )
+        .await
+    }
+
+    async fn lookup_mx(&self, domain: &str) -> Result<Vec<String>> {
+        remembered(&self.mx, domain.to_owned(), self.resolver.lookup_mx(domain)).await
+    }
+
+    async fn lookup_mx_records(&self, domain: &str) -> Result<Vec<MxRecord>> {
+        remembered(
+            &self.mx_records,
+            domain.to_owned(),
+            self.resolver.lookup_mx_records(domain),
+        )
+        .await
+    }
+
+    async fn lookup_ptr(&self, ip: IpAddr) -> Result<Vec<String>> {
+        remembered(&self.ptr, ip, self.resolver.lookup_ptr(ip)).await
+    }
+
+    async fn lookup_spf_rr(&self, domain: &str) -> Result<Vec<String>> {
+        remembered(
+            &self.spf_rr,
+            domain.to_owned(),
+            self.resolver.lookup_spf_rr(domain),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::MockResolver;
+    use crate::SpfChecker;
+    use std::sync::atomic::Ordering;
+
+    #[tokio::test]
+    async fn test_lookup_cache_shares_lookups_between_checks() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 include:_spf.a.net include:_spf.b.net -all",
+        );
+        mock_resolver.add_record("_spf.a.net", "v=spf1 ip4:192.0.2.0/24 -all");
+        mock_resolver.add_record("_spf.b.net", "v=spf1 ip4:198.51.100.0/24 -all");
+
+        let checker = SpfChecker::new(mock_resolver.clone()).with_lookup_cache();
+
+        let mut found = Vec::new();
+        for target in ["_spf.b.net", "_spf.a.net", "_spf.c.net"] {
+            let result = checker.check("example.com", target).await.unwrap();
+            found.push(result.found);
+        }
+
+        assert_eq!(found, vec![true, true, false]);
+        // Once for each record of the tree and the target of the fallback, plus once for the
+        // SPF records of example.com when looking up its default policy
+        assert_eq!(mock_resolver.txt_lookups.load(Ordering::Relaxed), 5);
+    }
+}
@@ -0,0 +1,592 @@
+use crate::lint::Finding;
+use crate::CheckError;
+use crate::SpnResolver;
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use std::fmt::{self, Debug};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Maximum size of a policy file. RFC 8461 §3.3 allows senders to limit it, suggesting 64 KiB.
+const MAX_POLICY_SIZE: usize = 64 * 1024;
+
+/// Timeout for fetching a policy file, as recommended by RFC 8461 §3.3.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// `max_age` values below one day are discouraged, as RFC 8461 §3.2 recommends weeks.
+const MIN_RECOMMENDED_MAX_AGE: u64 = 86400;
+
+/// The largest `max_age` allowed by RFC 8461 §3.2, about one year.
+const MAX_MAX_AGE: u64 = 31557600;
+
+/// HTTPS requests needed to fetch MTA-STS policies.
+#[async_trait]
+pub trait PolicyFetcher: Debug {
+    /// Fetches the `text/plain` body of `url` without following redirects.
+    async fn fetch_policy(&self, url: &str) -> Result<String>;
+}
+
+#[async_trait]
+impl PolicyFetcher for reqwest::Client {
+    async fn fetch_policy(&self, url: &str) -> Result<String> {
+        let response = self
+            .get(url)
+            .timeout(FETCH_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // https://datatracker.ietf.org/doc/html/rfc8461#section-3.3
+        //
+        // > The policy resource MUST be served with a media type of "text/plain"
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+
+        if !content_type.starts_with("text/plain") {
+            return Err(anyhow!(
+                "Expected the media type text/plain, got \"{content_type}\""
+            ));
+        }
+
+        if response
+            .content_length()
+            .is_some_and(|length| length > MAX_POLICY_SIZE as u64)
+        {
+            return Err(anyhow!("The policy is larger than {MAX_POLICY_SIZE} bytes"));
+        }
+
+        let policy = response.text().await?;
+
+        if policy.len() > MAX_POLICY_SIZE {
+            return Err(anyhow!("The policy is larger than {MAX_POLICY_SIZE} bytes"));
+        }
+
+        Ok(policy)
+    }
+}
+
+/// Creates the client used to fetch policies. Redirects are not followed (RFC 8461 §3.3).
+pub(crate) fn default_fetcher() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .expect("the TLS backend cannot be initialized")
+}
+
+/// How a sending server treats a delivery that fails the policy (RFC 8461 §5).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MtaStsMode {
+    /// The delivery is not made.
+    Enforce,
+    /// The delivery is made, but the failure is reported through TLS-RPT.
+    Testing,
+    /// The domain no longer has an active policy.
+    None,
+}
+
+impl MtaStsMode {
+    /// The mode as written in a policy, e.g. `enforce`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Enforce => "enforce",
+            Self::Testing => "testing",
+            Self::None => "none",
+        }
+    }
+}
+
+impl fmt::Display for MtaStsMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A parsed MTA-STS policy file (RFC 8461 §3.2).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MtaStsPolicy {
+    pub mode: MtaStsMode,
+    /// The `mx` patterns, e.g. `mail.example.com` or `*.example.net`.
+    pub mx: Vec<String>,
+    /// How long senders may cache the policy, in seconds.
+    pub max_age: u64,
+}
+
+impl MtaStsPolicy {
+    /// Returns `true` if `host` matches one of the `mx` patterns. A leading `*.` matches
+    /// exactly one label (RFC 8461 §4.1).
+    pub fn matches_mx(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.');
+
+        self.mx.iter().any(|pattern| {
+            let pattern = pattern.trim_end_matches('.');
+
+            match pattern.strip_prefix("*.") {
+                Some(suffix) => host
+                    .split_once('.')
+                    .is_some_and(|(_, rest)| rest.eq_ignore_ascii_case(suffix)),
+                None => host.eq_ignore_ascii_case(pattern),
+            }
+        })
+    }
+}
+
+impl FromStr for MtaStsPolicy {
+    type Err = anyhow::Error;
+
+    /// Parses the `key: value` lines of a policy file. Unknown keys are ignored, line endings
+    /// may be CRLF or LF.
+    fn from_str(policy: &str) -> Result<Self> {
+        let mut version = None;
+        let mut mode = None;
+        let mut mx = Vec::new();
+        let mut max_age = None;
+
+        for line in policy
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+        {
+            let (key, value) = line
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Expected `key: value`, got \"{line}\""))?;
+            let value = value.trim();
+
+            match key.trim() {
+                "version" => version = Some(value.to_owned()),
+                "mode" => {
+                    mode = Some(match value {
+                        "enforce" => MtaStsMode::Enforce,
+                        "testing" => MtaStsMode::Testing,
+                        "none" => MtaStsMode::None,
+                        _ => return Err(anyhow!("Invalid mode \"{value}\"")),
+                    })
+                }
+                "mx" => mx.push(value.to_owned()),
+                "max_age" => {
+                    max_age = Some(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|max_age| *max_age <= MAX_MAX_AGE)
+                            .ok_or_else(|| {
+                                anyhow!("Invalid max_age \"{value}\", expected 0 to {MAX_MAX_AGE}")
+                            })?,
+                    )
+                }
+                _ => {}
+            }
+        }
+
+        if version.as_deref() != Some("STSv1") {
+            return Err(anyhow!("Expected `version: STSv1`"));
+        }
+
+        let mode = mode.ok_or_else(|| anyhow!("Missing `mode`"))?;
+        let max_age = max_age.ok_or_else(|| anyhow!("Missing `max_age`"))?;
+
+        if mx.is_empty() && mode != MtaStsMode::None {
+            return Err(anyhow!("Missing `mx`"));
+        }
+
+        Ok(Self { mode, mx, max_age })
+    }
+}
+
+/// The outcome of [`SpfChecker::check_mta_sts`](crate::SpfChecker::check_mta_sts).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MtaStsReport {
+    /// The `v=STSv1` record at `_mta-sts.<domain>`, if there is exactly one.
+    pub sts_record: Option<String>,
+    /// The `id` of [`MtaStsReport::sts_record`], changed whenever the policy changes.
+    pub policy_id: Option<String>,
+    /// The URL the policy was fetched from, if [`MtaStsReport::sts_record`] is valid.
+    pub policy_url: Option<String>,
+    pub policy: Option<MtaStsPolicy>,
+    /// The `v=TLSRPTv1` record at `_smtp._tls.<domain>`, if there is exactly one.
+    pub tls_rpt_record: Option<String>,
+    /// The `rua` URIs of [`MtaStsReport::tls_rpt_record`] receiving TLS reports.
+    pub tls_rpt_uris: Vec<String>,
+    pub findings: Vec<Finding>,
+}
+
+impl MtaStsReport {
+    /// Returns `true` if no finding is an error.
+    pub fn is_valid(&self) -> bool {
+        self.findings
+            .iter()
+            .all(|finding| finding.severity != crate::Severity::Error)
+    }
+}
+
+/// Splits a `;`-separated record into its `name=value` tags, or returns `None` if its first
+/// tag is not `v=<version>`.
+fn record_tags<'a>(record: &'a str, version: &str) -> Option<Vec<(&'a str, &'a str)>> {
+    let tags: Vec<_> = record
+        .split(';')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| {
+            let (name, value) = tag.split_once('=').unwrap_or((tag, ""));
+            (name.trim(), value.trim())
+        })
+        .collect();
+
+    match tags.first() {
+        Some(&("v", value)) if value == version => Some(tags),
+        _ => None,
+    }
+}
+
+/// Looks up the single TXT record of `domain` starting with `v=<version>`, reporting a missing
+/// or duplicate record as finding with the codes `NO_<kind>_RECORD` and
+/// `MULTIPLE_<kind>_RECORDS`.
+async fn find_record(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    domain: &str,
+    version: &str,
+    missing: (&'static str, &str),
+    multiple: &'static str,
+    findings: &mut Vec<Finding>,
+) -> Result<Option<String>> {
+    let mut records: Vec<String> = resolver
+        .lookup_txt(domain)
+        .await
+        .context(CheckError::DnsLookupFailed)?
+        .into_iter()
+        .filter(|txt| record_tags(txt, version).is_some())
+        .collect();
+
+    match records.len() {
+        0 => {
+            findings.push(Finding::warning(missing.0, domain, missing.1.to_owned()));
+            Ok(None)
+        }
+        1 => Ok(records.pop()),
+        count => {
+            findings.push(Finding::error(
+                multiple,
+                domain,
+                format!("The domain publishes {count} records starting with v={version}, so none of them is used"),
+            ));
+            Ok(None)
+        }
+    }
+}
+
+/// Looks up the MTA-STS and TLS-RPT records of `domain`, fetches and parses its MTA-STS policy
+/// and checks whether the policy covers the `MX` hosts of `domain`.
+pub(crate) async fn check_mta_sts(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    fetcher: &(dyn PolicyFetcher + Send + Sync),
+    domain: &str,
+) -> Result<MtaStsReport> {
+    let mut report = MtaStsReport {
+        sts_record: None,
+        policy_id: None,
+        policy_url: None,
+        policy: None,
+        tls_rpt_record: None,
+        tls_rpt_uris: Vec::new(),
+        findings: Vec::new(),
+    };
+
+    let sts_domain = format!("_mta-sts.{domain}");
+    report.sts_record = find_record(
+        resolver,
+        &sts_domain,
+        "STSv1",
+        (
+            "NO_MTA_STS_RECORD",
+            "The domain publishes no MTA-STS record, so senders do not require TLS",
+        ),
+        "MULTIPLE_MTA_STS_RECORDS",
+        &mut report.findings,
+    )
+    .await?;
+
+    if let Some(record) = &report.sts_record {
+        // https://datatracker.ietf.org/doc/html/rfc8461#section-3.1
+        //
+        // > sts-id = 1*32(ALPHA / DIGIT)
+        let id = record_tags(record, "STSv1")
+            .into_iter()
+            .flatten()
+            .find(|(name, _)| *name == "id")
+            .map(|(_, id)| id)
+            .filter(|id| {
+                (1..=32).contains(&id.len()) && id.chars().all(|c| c.is_ascii_alphanumeric())
+            });
+
+        match id {
+            Some(id) => report.policy_id = Some(id.to_owned()),
+            None => report.findings.push(Finding::error(
+                "MTA_STS_SYNTAX_ERROR",
+                &sts_domain,
+                "The record has no valid `id` of 1 to 32 letters and digits, so senders ignore it"
+                    .to_owned(),
+            )),
+        }
+    }
+
+    if report.policy_id.is_some() {
+        let policy_host = format!("mta-sts.{domain}");
+        let url = format!("https://{policy_host}/.well-known/mta-sts.txt");
+
+        match fetcher.fetch_policy(&url).await {
+            Ok(body) => match MtaStsPolicy::from_str(&body) {
+                Ok(policy) => {
+                    policy_findings(
+                        resolver,
+                        domain,
+                        &policy_host,
+                        &policy,
+                        &mut report.findings,
+                    )
+                    .await;
+                    report.policy = Some(policy);
+                }
+                Err(err) => report.findings.push(Finding::error(
+                    "POLICY_SYNTAX_ERROR",
+                    &policy_host,
+                    format!("The policy cannot be parsed, so senders ignore it: {err}"),
+                )),
+            },
+            Err(err) => report.findings.push(Finding::error(
+                "POLICY_FETCH_FAILED",
+                &policy_host,
+                format!("The policy cannot be fetched from {url}: {err:#}"),
+            )),
+        }
+
+        report.policy_url = Some(url);
+    }
+
+    let tls_rpt_domain = format!("_smtp._tls.{domain}");
+    report.tls_rpt_record = find_record(
+        resolver,
+        &tls_rpt_domain,
+        "TLSRPTv1",
+        (
+            "NO_TLS_RPT_RECORD",
+            "The domain publishes no TLS-RPT record, so no reports about failed TLS connections are sent",
+        ),
+        "MULTIPLE_TLS_RPT_RECORDS",
+        &mut report.findings,
+    )
+    .await?;
+
+    if let Some(record) = &report.tls_rpt_record {
+        // https://datatracker.ietf.org/doc/html/rfc8460#section-3
+        //
+        // > tlsrpt-uri = URI ; "mailto" or "https"
+        let uris: Vec<_> = record_tags(record, "TLSRPTv1")
+            .into_iter()
+            .flatten()
+            .filter(|(name, _)| *name == "rua")
+            .flat_map(|(_, value)| value.split(','))
+            .map(str::trim)
+            .collect();
+
+        let is_valid = !uris.is_empty()
+            && uris
+                .iter()
+                .all(|uri| uri.starts_with("mailto:") || uri.starts_with("https://"));
+
+        if is_valid {
+            report.tls_rpt_uris = uris.into_iter().map(str::to_owned).collect();
+        } else {
+            report.findings.push(Finding::error(
+                "TLS_RPT_SYNTAX_ERROR",
+                &tls_rpt_domain,
+                "The record needs a `rua` tag with `mailto:` or `https://` URIs".to_owned(),
+            ));
+        }
+    }
+
+    Ok(report)
+}
+
+async fn policy_findings(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    domain: &str,
+    policy_host: &str,
+    policy: &MtaStsPolicy,
+    findings: &mut Vec<Finding>,
+) {
+    match policy.mode {
+        MtaStsMode::Enforce => {}
+        MtaStsMode::Testing => findings.push(Finding::warning(
+            "MODE_TESTING",
+            policy_host,
+            "The policy is in `testing` mode, so deliveries without valid TLS are only reported"
+                .to_owned(),
+        )),
+        MtaStsMode::None => findings.push(Finding::warning(
+            "MODE_NONE",
+            policy_host,
+            "The policy is in `none` mode, so it has no effect".to_owned(),
+        )),
+    }
+
+    if policy.max_age < MIN_RECOMMENDED_MAX_AGE {
+        findings.push(Finding::warning(
+            "SHORT_MAX_AGE",
+            policy_host,
+            format!(
+                "The policy may only be cached for {} seconds, less than one day",
+                policy.max_age
+            ),
+        ));
+    }
+
+    if policy.mode == MtaStsMode::None {
+        return;
+    }
+
+    // MX hosts that cannot be looked up are not reported, as the policy itself is still valid.
+    let exchanges = resolver.lookup_mx(domain).await.unwrap_or_default();
+
+    for exchange in exchanges {
+        if policy.matches_mx(&exchange) {
+            continue;
+        }
+
+        // In `testing` mode, senders deliver anyway and only report the failure.
+        findings.push(if policy.mode == MtaStsMode::Enforce {
+            Finding::error(
+                "MX_NOT_COVERED",
+                policy_host,
+                format!("The MX host {exchange} matches no `mx` pattern of the policy, so senders refuse to deliver to it"),
+            )
+        } else {
+            Finding::warning(
+                "MX_NOT_COVERED",
+                policy_host,
+                format!("The MX host {exchange} matches no `mx` pattern of the policy, so senders report deliveries to it as failed"),
+            )
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockFetcher;
+    use crate::test_support::MockResolver;
+    use crate::Severity;
+    use crate::SpfChecker;
+
+    #[test]
+    fn test_parse_policy() {
+        let policy = MtaStsPolicy::from_str(
+            "version: STSv1\r\nmode: enforce\r\nmx: mail.example.com\r\nmx: *.example.net\r\nmax_age: 604800\r\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            policy,
+            MtaStsPolicy {
+                mode: MtaStsMode::Enforce,
+                mx: vec!["mail.example.com".to_owned(), "*.example.net".to_owned()],
+                max_age: 604800,
+            }
+        );
+
+        assert!(policy.matches_mx("MAIL.example.com."));
+        assert!(policy.matches_mx("mx1.example.net"));
+        assert!(!policy.matches_mx("example.net"));
+        assert!(!policy.matches_mx("a.mx1.example.net"));
+
+        for invalid in [
+            "mode: enforce\nmx: a.example.com\nmax_age: 1",
+            "version: STSv1\nmode: block\nmx: a.example.com\nmax_age: 1",
+            "version: STSv1\nmode: enforce\nmax_age: 1",
+            "version: STSv1\nmode: enforce\nmx: a.example.com\nmax_age: 31557601",
+            "version: STSv1\nmode: enforce\nmx: a.example.com",
+        ] {
+            assert!(MtaStsPolicy::from_str(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_mta_sts() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_txt("_mta-sts.example.com", "v=STSv1; id=20240101T000000");
+        mock_resolver.add_txt(
+            "_smtp._tls.example.com",
+            "v=TLSRPTv1; rua=mailto:tls@example.com",
+        );
+        mock_resolver.add_exchanges("example.com", &["mx1.example.com", "mx.other.net"]);
+        mock_resolver.add_txt("_mta-sts.example.org", "v=STSv1; id=1");
+        mock_resolver.add_txt("_smtp._tls.example.org", "v=TLSRPTv1; rua=tls@example.org");
+        mock_resolver.add_txt("_mta-sts.example.net", "v=STSv1; id=not-valid");
+
+        let mock_fetcher = MockFetcher::default();
+        mock_fetcher.add_policy(
+            "https://mta-sts.example.com/.well-known/mta-sts.txt",
+            "version: STSv1\r\nmode: testing\r\nmx: *.example.com\r\nmax_age: 604800\r\n",
+        );
+
+        let checker = SpfChecker::new(mock_resolver.clone()).with_policy_fetcher(mock_fetcher);
+
+        let report = checker.check_mta_sts("example.com").await.unwrap();
+
+        let findings: Vec<_> = report
+            .findings
+            .iter()
+            .map(|finding| (finding.severity, finding.code))
+            .collect();
+
+        assert_eq!(
+            findings,
+            vec![
+                (Severity::Warning, "MODE_TESTING"),
+                (Severity::Warning, "MX_NOT_COVERED"),
+            ]
+        );
+        assert_eq!(report.policy_id.as_deref(), Some("20240101T000000"));
+        assert_eq!(
+            report.policy.map(|policy| (policy.mode, policy.max_age)),
+            Some((MtaStsMode::Testing, 604800))
+        );
+        assert_eq!(report.tls_rpt_uris, vec!["mailto:tls@example.com"]);
+
+        for (domain, expected) in [
+            (
+                "example.org",
+                vec![
+                    (Severity::Error, "POLICY_FETCH_FAILED"),
+                    (Severity::Error, "TLS_RPT_SYNTAX_ERROR"),
+                ],
+            ),
+            (
+                "example.net",
+                vec![
+                    (Severity::Error, "MTA_STS_SYNTAX_ERROR"),
+                    (Severity::Warning, "NO_TLS_RPT_RECORD"),
+                ],
+            ),
+            (
+                "example.de",
+                vec![
+                    (Severity::Warning, "NO_MTA_STS_RECORD"),
+                    (Severity::Warning, "NO_TLS_RPT_RECORD"),
+                ],
+            ),
+        ] {
+            let report = checker.check_mta_sts(domain).await.unwrap();
+
+            let findings: Vec<_> = report
+                .findings
+                .iter()
+                .map(|finding| (finding.severity, finding.code))
+                .collect();
+
+            assert_eq!(findings, expected, "{domain}");
+        }
+    }
+}
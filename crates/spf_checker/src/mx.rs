@@ -0,0 +1,252 @@
+use crate::lint::Finding;
+use crate::CheckError;
+use crate::SpnResolver;
+use anyhow::{Context, Result};
+use std::net::IpAddr;
+
+/// An `MX` record: the host accepting mail for a domain and its preference, lower values
+/// being preferred.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MxRecord {
+    pub preference: u16,
+    /// The exchange host name without trailing dot, `.` for a Null MX.
+    pub exchange: String,
+}
+
+impl MxRecord {
+    /// Returns `true` for the Null MX record of RFC 7505, whose exchange is the root domain.
+    pub fn is_null(&self) -> bool {
+        self.exchange == "."
+    }
+}
+
+/// An exchange of [`MxReport::hosts`] with its resolved addresses.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MxHost {
+    pub preference: u16,
+    pub exchange: String,
+    /// The `A` and `AAAA` addresses of the exchange, empty for a Null MX.
+    pub addresses: Vec<IpAddr>,
+}
+
+impl MxHost {
+    pub fn resolves(&self) -> bool {
+        !self.addresses.is_empty()
+    }
+}
+
+/// The outcome of [`SpfChecker::check_mx`](crate::SpfChecker::check_mx).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MxReport {
+    /// Whether the domain declares with a Null MX record that it does not accept mail.
+    pub null_mx: bool,
+    /// The `MX` records ordered by preference.
+    pub hosts: Vec<MxHost>,
+    pub findings: Vec<Finding>,
+}
+
+impl MxReport {
+    /// Returns `true` if no finding is an error.
+    pub fn is_valid(&self) -> bool {
+        self.findings
+            .iter()
+            .all(|finding| finding.severity != crate::Severity::Error)
+    }
+}
+
+/// Looks up the `MX` records of `domain` and the addresses of their exchanges.
+pub(crate) async fn check_mx(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    domain: &str,
+) -> Result<MxReport> {
+    let mut records = resolver
+        .lookup_mx_records(domain)
+        .await
+        .context(CheckError::DnsLookupFailed)?;
+    records.sort_by(|a, b| (a.preference, &a.exchange).cmp(&(b.preference, &b.exchange)));
+
+    let null_mx = records.iter().any(MxRecord::is_null);
+    let mut findings = Vec::new();
+
+    if records.is_empty() {
+        // https://datatracker.ietf.org/doc/html/rfc5321#section-5.1
+        //
+        // > If an empty list of MXs is returned, the address is treated as if it was associated
+        // > with an implicit MX RR, with a preference of 0, pointing to that host.
+        findings.push(Finding::warning(
+            "NO_MX_RECORDS",
+            domain,
+            "The domain has no MX records, so mail is delivered to its own address records if there are any".to_owned(),
+        ));
+    }
+
+    // https://datatracker.ietf.org/doc/html/rfc7505#section-3
+    //
+    // > A domain that advertises a null MX MUST NOT advertise any other MX RR.
+    if null_mx && records.len() > 1 {
+        findings.push(Finding::error(
+            "NULL_MX_WITH_OTHER_RECORDS",
+            domain,
+            "The domain publishes a Null MX together with other MX records, which makes its mail handling undefined".to_owned(),
+        ));
+    }
+
+    let mut hosts = Vec::with_capacity(records.len());
+
+    for record in records {
+        let is_null = record.is_null();
+        let mut host = MxHost {
+            preference: record.preference,
+            exchange: record.exchange,
+            addresses: Vec::new(),
+        };
+
+        if is_null {
+            if host.preference != 0 {
+                findings.push(Finding::warning(
+                    "NULL_MX_PREFERENCE",
+                    domain,
+                    format!(
+                        "The Null MX has the preference {} instead of 0",
+                        host.preference
+                    ),
+                ));
+            }
+            hosts.push(host);
+            continue;
+        }
+
+        // https://datatracker.ietf.org/doc/html/rfc5321#section-5.1
+        //
+        // > [...] the domain name [...] MUST NOT be an IP address literal
+        if host.exchange.parse::<IpAddr>().is_ok() {
+            findings.push(Finding::error(
+                "MX_HOST_IS_IP_ADDRESS",
+                domain,
+                format!(
+                    "The exchange {} is an IP address instead of a host name",
+                    host.exchange
+                ),
+            ));
+            hosts.push(host);
+            continue;
+        }
+
+        let addresses = match resolve_host(resolver, &host.exchange).await {
+            Ok(addresses) => addresses,
+            Err(err) => {
+                findings.push(Finding::error(
+                    "MX_HOST_LOOKUP_FAILED",
+                    domain,
+                    format!(
+                        "The addresses of {} cannot be resolved: {err}",
+                        host.exchange
+                    ),
+                ));
+                hosts.push(host);
+                continue;
+            }
+        };
+
+        if addresses.is_empty() {
+            findings.push(Finding::error(
+                "MX_HOST_UNRESOLVABLE",
+                domain,
+                format!("The exchange {} has no A or AAAA records", host.exchange),
+            ));
+        }
+
+        host.addresses = addresses;
+        hosts.push(host);
+    }
+
+    Ok(MxReport {
+        null_mx,
+        hosts,
+        findings,
+    })
+}
+
+pub(crate) async fn resolve_host(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    host: &str,
+) -> Result<Vec<IpAddr>> {
+    let ipv4 = resolver.lookup_a(host).await?;
+    let ipv6 = resolver.lookup_aaaa(host).await?;
+
+    Ok(ipv4
+        .into_iter()
+        .map(IpAddr::V4)
+        .chain(ipv6.into_iter().map(IpAddr::V6))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::MockResolver;
+    use crate::Severity;
+    use crate::SpfChecker;
+
+    #[tokio::test]
+    async fn test_check_mx() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_mx_records(
+            "example.com",
+            &[
+                (20, "mx2.example.com"),
+                (10, "mx1.example.com"),
+                (30, "192.0.2.1"),
+            ],
+        );
+        mock_resolver.add_addresses("mx1.example.com", &["192.0.2.10", "2001:db8::10"]);
+        mock_resolver.add_mx_records("example.org", &[(0, ".")]);
+        mock_resolver.add_mx_records("example.net", &[(0, "."), (10, "mx.example.net")]);
+        mock_resolver.add_addresses("mx.example.net", &["192.0.2.20"]);
+
+        let checker = SpfChecker::new(mock_resolver.clone());
+
+        let report = checker.check_mx("example.com").await.unwrap();
+
+        let hosts: Vec<_> = report
+            .hosts
+            .iter()
+            .map(|host| (host.preference, host.exchange.as_str(), host.resolves()))
+            .collect();
+        let findings: Vec<_> = report
+            .findings
+            .iter()
+            .map(|finding| (finding.severity, finding.code))
+            .collect();
+
+        assert!(!report.null_mx);
+        assert_eq!(
+            hosts,
+            vec![
+                (10, "mx1.example.com", true),
+                (20, "mx2.example.com", false),
+                (30, "192.0.2.1", false),
+            ]
+        );
+        assert_eq!(
+            findings,
+            vec![
+                (Severity::Error, "MX_HOST_UNRESOLVABLE"),
+                (Severity::Error, "MX_HOST_IS_IP_ADDRESS"),
+            ]
+        );
+
+        let report = checker.check_mx("example.org").await.unwrap();
+        assert!(report.null_mx);
+        assert!(report.is_valid());
+        assert!(report.findings.is_empty());
+
+        let report = checker.check_mx("example.net").await.unwrap();
+        assert!(report.null_mx);
+        assert_eq!(report.findings[0].code, "NULL_MX_WITH_OTHER_RECORDS");
+
+        let report = checker.check_mx("example.de").await.unwrap();
+        assert!(!report.null_mx);
+        assert!(report.hosts.is_empty());
+        assert_eq!(report.findings[0].code, "NO_MX_RECORDS");
+    }
+}
@@ -0,0 +1,377 @@
+use crate::record::{Directive, Mechanism, Modifier, Qualifier, SpfRecord, Term};
+use crate::{find_spf_record, parsed, IpNetwork, SpnResolver};
+use anyhow::Result;
+
+/// A change to an SPF record suggested by [`SpfChecker::optimize`](crate::SpfChecker::optimize).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Optimization {
+    /// What to change, one of:
+    /// - `REMOVE_UNREACHABLE`: remove terms that are never evaluated, as they follow `all`
+    /// - `REMOVE_DUPLICATE_INCLUDE`: remove an include repeating an earlier one
+    /// - `INLINE_INCLUDE`: replace an include of a record authorizing a single network with
+    ///   that network
+    /// - `MERGE_NETWORKS`: replace two networks by the one covering exactly both
+    pub operation: &'static str,
+    /// The terms to remove or replace.
+    pub terms: Vec<String>,
+    /// The terms replacing `terms` at the position of the first of them, empty for removals.
+    pub replacement: Vec<String>,
+    pub message: String,
+}
+
+/// The outcome of [`SpfChecker::optimize`](crate::SpfChecker::optimize).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OptimizedRecord {
+    pub record: String,
+    /// The suggestions in the order they apply, each to the record resulting from the
+    /// previous ones.
+    pub suggestions: Vec<Optimization>,
+    /// `record` with all suggestions applied, or `record` itself without suggestions.
+    pub optimized_record: String,
+}
+
+/// Suggests changes to `spf` that keep its results but save DNS lookups or space.
+pub(crate) async fn optimize_record(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    spf_txt: &str,
+    spf: &SpfRecord,
+) -> Result<OptimizedRecord> {
+    let mut terms = spf.terms().to_vec();
+    let mut suggestions = Vec::new();
+
+    remove_unreachable(&mut terms, &mut suggestions);
+    remove_duplicate_includes(&mut terms, &mut suggestions);
+    inline_includes(resolver, &mut terms, &mut suggestions).await?;
+    merge_networks(&mut terms, &mut suggestions);
+
+    let optimized_record = if suggestions.is_empty() {
+        spf_txt.to_owned()
+    } else {
+        SpfRecord::from_terms(terms).to_string()
+    };
+
+    Ok(OptimizedRecord {
+        record: spf_txt.to_owned(),
+        suggestions,
+        optimized_record,
+    })
+}
+
+/// Removes the directives following `all` and a `redirect` next to it, which RFC 7208 §5.1
+/// and §6.1 make unreachable.
+fn remove_unreachable(terms: &mut Vec<Term>, suggestions: &mut Vec<Optimization>) {
+    let Some(all) = terms.iter().position(|term| {
+        matches!(
+            term,
+            Term::Directive(Directive {
+                mechanism: Mechanism::All,
+                ..
+            })
+        )
+    }) else {
+        return;
+    };
+    let all_term = terms[all].to_string();
+
+    let (unreachable, reachable): (Vec<_>, Vec<_>) = std::mem::take(terms)
+        .into_iter()
+        .enumerate()
+        .partition(|(index, term)| match term {
+            Term::Directive(_) => *index > all,
+            Term::Modifier(modifier) => matches!(modifier, Modifier::Redirect(_)),
+        });
+
+    *terms = reachable.into_iter().map(|(_, term)| term).collect();
+
+    if unreachable.is_empty() {
+        return;
+    }
+
+    suggestions.push(Optimization {
+        operation: "REMOVE_UNREACHABLE",
+        terms: unreachable
+            .iter()
+            .map(|(_, term)| term.to_string())
+            .collect(),
+        replacement: Vec::new(),
+        message: format!(
+            "Receivers stop at `{all_term}`, so mechanisms after it are never evaluated and a redirect is ignored"
+        ),
+    });
+}
+
+/// Removes includes of a domain that is already included, which can never match as the
+/// earlier include returns the same result.
+fn remove_duplicate_includes(terms: &mut Vec<Term>, suggestions: &mut Vec<Optimization>) {
+    let mut included: Vec<String> = Vec::new();
+    let mut index = 0;
+
+    while index < terms.len() {
+        if let Term::Directive(Directive {
+            mechanism: Mechanism::Include(domain),
+            ..
+        }) = &terms[index]
+        {
+            let domain = domain.to_ascii_lowercase();
+
+            if included.contains(&domain) {
+                let duplicate = terms.remove(index);
+
+                suggestions.push(Optimization {
+                    operation: "REMOVE_DUPLICATE_INCLUDE",
+                    terms: vec![duplicate.to_string()],
+                    replacement: Vec::new(),
+                    message: format!(
+                        "`{domain}` is already included, so this include never matches but still costs a DNS lookup"
+                    ),
+                });
+                continue;
+            }
+
+            included.push(domain);
+        }
+
+        index += 1;
+    }
+}
+
+/// Replaces includes of records authorizing a single network with that network, saving the
+/// DNS lookups of the includes.
+async fn inline_includes(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    terms: &mut [Term],
+    suggestions: &mut Vec<Optimization>,
+) -> Result<()> {
+    let includes: Vec<(usize, Qualifier, String)> = terms
+        .iter()
+        .enumerate()
+        .filter_map(|(index, term)| match term {
+            Term::Directive(Directive {
+                qualifier,
+                mechanism: Mechanism::Include(domain),
+            }) if !domain.contains('%') => Some((index, *qualifier, domain.clone())),
+            _ => None,
+        })
+        .collect();
+
+    for (index, qualifier, domain) in includes {
+        let Some(network) = single_network(resolver, &domain).await? else {
+            continue;
+        };
+
+        let replacement = Term::Directive(Directive {
+            qualifier,
+            mechanism: Mechanism::from_network(network),
+        });
+
+        suggestions.push(Optimization {
+            operation: "INLINE_INCLUDE",
+            terms: vec![terms[index].to_string()],
+            replacement: vec![replacement.to_string()],
+            message: format!(
+                "The record of `{domain}` only authorizes {network}, listing it directly saves a DNS lookup but must be kept in sync by hand"
+            ),
+        });
+
+        terms[index] = replacement;
+    }
+
+    Ok(())
+}
+
+/// Returns the network authorized by the SPF record of `domain` if the record consists of a
+/// single `ip4` or `ip6` mechanism, optionally followed by an `all` not resulting in `pass`.
+async fn single_network(
+    resolver: &(dyn SpnResolver + Send + Sync),
+    domain: &str,
+) -> Result<Option<IpNetwork>> {
+    let Some(spf_txt) = find_spf_record(resolver, domain).await? else {
+        return Ok(None);
+    };
+
+    let Ok(spf) = parsed::parse(&spf_txt, false) else {
+        return Ok(None);
+    };
+
+    if spf.redirect().is_some() {
+        return Ok(None);
+    }
+
+    let mut directives = spf.directives();
+
+    let Some(network) = directives.next().and_then(pass_network) else {
+        return Ok(None);
+    };
+
+    match directives.next() {
+        None => Ok(Some(network)),
+        Some(Directive {
+            qualifier,
+            mechanism: Mechanism::All,
+        }) if *qualifier != Qualifier::Pass => Ok(Some(network)),
+        Some(_) => Ok(None),
+    }
+}
+
+/// Replaces pairs of networks by the one covering exactly both, either because one covers the
+/// other or because they are adjacent halves.
+///
+/// Only networks resulting in `pass` are merged, and only if no directive with another
+/// qualifier lies between them, so that moving the later one forward keeps all results.
+fn merge_networks(terms: &mut Vec<Term>, suggestions: &mut Vec<Optimization>) {
+    while let Some((first, second, merged, message)) = find_mergeable_networks(terms) {
+        let replacement = Term::Directive(Directive {
+            qualifier: Qualifier::Pass,
+            mechanism: Mechanism::from_network(merged),
+        });
+
+        suggestions.push(Optimization {
+            operation: "MERGE_NETWORKS",
+            terms: vec![terms[first].to_string(), terms[second].to_string()],
+            replacement: vec![replacement.to_string()],
+            message,
+        });
+
+        terms[first] = replacement;
+        terms.remove(second);
+    }
+}
+
+/// Returns the positions of the first pair of networks that can be merged, with the merged
+/// network and why they can be merged.
+fn find_mergeable_networks(terms: &[Term]) -> Option<(usize, usize, IpNetwork, String)> {
+    for (first, term) in terms.iter().enumerate() {
+        let Some(network) = directive_network(term) else {
+            continue;
+        };
+
+        for (second, term) in terms.iter().enumerate().skip(first + 1) {
+            let Term::Directive(directive) = term else {
+                continue;
+            };
+
+            if directive.qualifier != Qualifier::Pass {
+                break;
+            }
+
+            let Some(other) = pass_network(directive) else {
+                continue;
+            };
+
+            if network.covers(&other) {
+                let message = format!("{network} already covers {other}");
+                return Some((first, second, network, message));
+            }
+
+            if other.covers(&network) {
+                let message = format!("{other} already covers {network}");
+                return Some((first, second, other, message));
+            }
+
+            if let Some(merged) = network.merge(&other) {
+                let message =
+                    format!("{network} and {other} are adjacent and together form {merged}");
+                return Some((first, second, merged, message));
+            }
+        }
+    }
+
+    None
+}
+
+/// The network of an `ip4` or `ip6` directive resulting in `pass`.
+fn directive_network(term: &Term) -> Option<IpNetwork> {
+    match term {
+        Term::Directive(directive) => pass_network(directive),
+        Term::Modifier(_) => None,
+    }
+}
+
+fn pass_network(directive: &Directive) -> Option<IpNetwork> {
+    match directive {
+        Directive {
+            qualifier: Qualifier::Pass,
+            mechanism: Mechanism::Ip4(network) | Mechanism::Ip6(network),
+        } => Some(*network),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::MockResolver;
+    use crate::SpfChecker;
+
+    #[tokio::test]
+    async fn test_optimize() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 ip4:192.0.2.0/25 include:_spf.provider.net ip4:192.0.2.128/25 include:relay.example.net include:_SPF.provider.net ip4:192.0.2.7 -all mx redirect=other.example.com",
+        );
+        mock_resolver.add_record(
+            "_spf.provider.net",
+            "v=spf1 ip4:198.51.100.0/24 ip4:203.0.113.0/24 -all",
+        );
+        mock_resolver.add_record("relay.example.net", "v=spf1 ip6:2001:db8::1 -all");
+
+        let checker = SpfChecker::new(mock_resolver);
+        let optimized = checker.optimize("example.com").await.unwrap();
+
+        assert_eq!(
+            optimized
+                .suggestions
+                .iter()
+                .map(|suggestion| (
+                    suggestion.operation,
+                    suggestion.terms.join(" "),
+                    suggestion.replacement.join(" ")
+                ))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    "REMOVE_UNREACHABLE",
+                    "mx redirect=other.example.com".to_string(),
+                    String::new()
+                ),
+                (
+                    "REMOVE_DUPLICATE_INCLUDE",
+                    "include:_SPF.provider.net".to_string(),
+                    String::new()
+                ),
+                (
+                    "INLINE_INCLUDE",
+                    "include:relay.example.net".to_string(),
+                    "ip6:2001:db8::1".to_string()
+                ),
+                (
+                    "MERGE_NETWORKS",
+                    "ip4:192.0.2.0/25 ip4:192.0.2.128/25".to_string(),
+                    "ip4:192.0.2.0/24".to_string()
+                ),
+                (
+                    "MERGE_NETWORKS",
+                    "ip4:192.0.2.0/24 ip4:192.0.2.7".to_string(),
+                    "ip4:192.0.2.0/24".to_string()
+                ),
+            ]
+        );
+        assert_eq!(
+            optimized.optimized_record,
+            "v=spf1 ip4:192.0.2.0/24 include:_spf.provider.net ip6:2001:db8::1 -all"
+        );
+
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 ip4:192.0.2.0/25 -ip4:192.0.2.200 ip4:192.0.2.128/25 ~all",
+        );
+        let checker = SpfChecker::new(mock_resolver);
+        let optimized = checker.optimize("example.com").await.unwrap();
+        assert!(optimized.suggestions.is_empty());
+        assert_eq!(optimized.optimized_record, optimized.record);
+
+        let err = checker.optimize("missing.example.com").await.unwrap_err();
+        assert_eq!(err.to_string(), "NO_SPF_RECORD");
+    }
+}
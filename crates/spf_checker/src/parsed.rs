@@ -0,0 +1,65 @@
+use crate::lru::Lru;
+use crate::record::{ParseError, SpfRecord};
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock, Mutex};
+
+/// How many distinct records are kept parsed.
+const CAPACITY: usize = 4_096;
+
+/// The parsed records shared by all checkers of the process.
+static PARSED: LazyLock<Mutex<ParsedRecords>> = LazyLock::new(Mutex::default);
+
+type Parsed = Result<Arc<SpfRecord>, ParseError>;
+
+/// Remembers records by whether they were parsed strictly and their content, so that a record
+/// referenced by many domains, e.g. `_spf.google.com`, is parsed once rather than on every
+/// check. Parse errors are remembered as well, as parsing the same content fails the same way.
+type ParsedRecords = Lru<(bool, String), Parsed>;
+
+/// Parses `record` like [`SpfRecord::from_str`], or like [`SpfRecord::parse_strict`] if
+/// `strict`, returning the remembered result if the same record was parsed before.
+pub(crate) fn parse(record: &str, strict: bool) -> Parsed {
+    let key = (strict, record.to_owned());
+    if let Some(parsed) = PARSED.lock().expect("mutex poisoned").get(&key) {
+        return parsed.clone();
+    }
+
+    let parsed = if strict {
+        SpfRecord::parse_strict(record)
+    } else {
+        SpfRecord::from_str(record)
+    }
+    .map(Arc::new);
+
+    let mut records = PARSED.lock().expect("mutex poisoned");
+    // Records of arbitrary checked domains must not grow the cache without bound. Frequently
+    // parsed records are used again before they become the least recently used.
+    if records.peek(&key).is_none() && records.len() >= CAPACITY {
+        records.evict();
+    }
+    records.insert(key, parsed.clone());
+
+    parsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_remembers_records() {
+        let record = "v=spf1 include:_spf.parsed.example ip4:192.0.2.0/24 -all";
+        let parsed = parse(record, false).unwrap();
+
+        assert!(Arc::ptr_eq(&parsed, &parse(record, false).unwrap()));
+        assert!(!Arc::ptr_eq(&parsed, &parse(record, true).unwrap()));
+        assert_eq!(*parsed, SpfRecord::from_str(record).unwrap());
+
+        let tolerated = "v=spf1 include:localhost ?all";
+        assert!(parse(tolerated, false).is_ok());
+        assert_eq!(
+            parse(tolerated, true).unwrap_err(),
+            SpfRecord::parse_strict(tolerated).unwrap_err()
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use crate::SpfTreeNode;
+
+/// A well-known email service provider, identified by the domains its customers include.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EmailProvider {
+    pub name: &'static str,
+    /// The domains of the provider's SPF records. Subdomains match as well, e.g.
+    /// `u123.wl.sendgrid.net` for `sendgrid.net`.
+    pub domains: &'static [&'static str],
+}
+
+/// The providers recognized by [`SpfTreeNode::providers`].
+pub const EMAIL_PROVIDERS: &[EmailProvider] = &[
+    EmailProvider {
+        name: "Google Workspace",
+        domains: &["_spf.google.com"],
+    },
+    EmailProvider {
+        name: "Microsoft 365",
+        domains: &["spf.protection.outlook.com"],
+    },
+    EmailProvider {
+        name: "Amazon SES",
+        domains: &["amazonses.com"],
+    },
+    EmailProvider {
+        name: "SendGrid",
+        domains: &["sendgrid.net"],
+    },
+    EmailProvider {
+        name: "Mailgun",
+        domains: &["mailgun.org"],
+    },
+    EmailProvider {
+        name: "Mailchimp",
+        domains: &["servers.mcsv.net", "spf.mandrillapp.com"],
+    },
+    EmailProvider {
+        name: "Postmark",
+        domains: &["spf.mtasv.net"],
+    },
+    EmailProvider {
+        name: "SparkPost",
+        domains: &["sparkpostmail.com"],
+    },
+    EmailProvider {
+        name: "Brevo",
+        domains: &["spf.brevo.com", "spf.sendinblue.com"],
+    },
+    EmailProvider {
+        name: "Mailjet",
+        domains: &["spf.mailjet.com"],
+    },
+    EmailProvider {
+        name: "Salesforce",
+        domains: &["_spf.salesforce.com", "exacttarget.com"],
+    },
+    EmailProvider {
+        name: "HubSpot",
+        domains: &["hubspotemail.net"],
+    },
+    EmailProvider {
+        name: "Zendesk",
+        domains: &["mail.zendesk.com"],
+    },
+    EmailProvider {
+        name: "Freshdesk",
+        domains: &["email.freshdesk.com"],
+    },
+    EmailProvider {
+        name: "Zoho Mail",
+        domains: &["zoho.com", "zoho.eu"],
+    },
+    EmailProvider {
+        name: "Proton Mail",
+        domains: &["_spf.protonmail.ch"],
+    },
+    EmailProvider {
+        name: "Fastmail",
+        domains: &["spf.messagingengine.com"],
+    },
+    EmailProvider {
+        name: "iCloud Mail",
+        domains: &["icloud.com"],
+    },
+    EmailProvider {
+        name: "IONOS",
+        domains: &["_spf.perfora.net", "_spf-eu.ionos.com", "_spf-us.ionos.com"],
+    },
+    EmailProvider {
+        name: "OVHcloud",
+        domains: &["mx.ovh.com"],
+    },
+];
+
+/// A provider authorized by an SPF tree, see [`SpfTreeNode::providers`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProviderMatch {
+    pub provider: &'static EmailProvider,
+    /// The first domain of the tree identifying the provider, e.g. `_spf.google.com`.
+    pub domain: String,
+}
+
+/// Returns the provider publishing the SPF record of `domain`, if it is well-known.
+pub fn identify_provider(domain: &str) -> Option<&'static EmailProvider> {
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+
+    EMAIL_PROVIDERS.iter().find(|provider| {
+        provider.domains.iter().any(|provider_domain| {
+            domain == *provider_domain
+                || domain
+                    .strip_suffix(provider_domain)
+                    .is_some_and(|prefix| prefix.ends_with('.'))
+        })
+    })
+}
+
+impl SpfTreeNode {
+    /// Returns the well-known providers whose records are part of the tree, in the order they
+    /// are referenced.
+    pub fn providers(&self) -> Vec<ProviderMatch> {
+        let mut matches: Vec<ProviderMatch> = Vec::new();
+        let mut nodes = vec![self];
+
+        while let Some(node) = nodes.pop() {
+            nodes.extend(node.children.iter().rev());
+
+            let Some(provider) = identify_provider(&node.domain) else {
+                continue;
+            };
+
+            if matches.iter().all(|found| found.provider != provider) {
+                matches.push(ProviderMatch {
+                    provider,
+                    domain: node.domain.clone(),
+                });
+            }
+        }
+
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockResolver;
+    use crate::CheckOptions;
+    use crate::SpfChecker;
+
+    #[test]
+    fn test_identify_provider() {
+        for (domain, name) in [
+            ("_spf.google.com", "Google Workspace"),
+            ("u1234.wl.sendgrid.net", "SendGrid"),
+            ("SPF.Protection.Outlook.com.", "Microsoft 365"),
+            ("eu.mailgun.org", "Mailgun"),
+        ] {
+            assert_eq!(identify_provider(domain).map(|p| p.name), Some(name));
+        }
+
+        for domain in ["notsendgrid.net", "google.com", "example.com"] {
+            assert_eq!(identify_provider(domain), None, "{domain}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tree_providers() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record(
+            "example.com",
+            "v=spf1 include:_spf.example.com include:spf.protection.outlook.com -all",
+        );
+        mock_resolver.add_record(
+            "_spf.example.com",
+            "v=spf1 include:_spf.google.com include:u123.wl.sendgrid.net ~all",
+        );
+        mock_resolver.add_record(
+            "_spf.google.com",
+            "v=spf1 include:_netblocks.google.com ~all",
+        );
+        mock_resolver.add_record("u123.wl.sendgrid.net", "v=spf1 include:sendgrid.net ~all");
+        mock_resolver.add_record("sendgrid.net", "v=spf1 ip4:167.89.0.0/17 ~all");
+
+        let checker = SpfChecker::new(mock_resolver);
+        let tree = checker
+            .tree("example.com", &CheckOptions::default())
+            .await
+            .unwrap();
+
+        let providers: Vec<_> = tree
+            .providers()
+            .into_iter()
+            .map(|found| (found.provider.name, found.domain))
+            .collect();
+
+        assert_eq!(
+            providers,
+            vec![
+                ("Google Workspace", "_spf.google.com".to_string()),
+                ("SendGrid", "u123.wl.sendgrid.net".to_string()),
+                ("Microsoft 365", "spf.protection.outlook.com".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,769 @@
+use crate::ip_network::IpNetwork;
+use crate::macros;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+const VERSION: &str = "v=spf1";
+
+const UNKNOWN_MECHANISM: &str = "unknown mechanism";
+
+/// The qualifier of a directive, which determines the result when its mechanism matches
+/// (RFC 7208 §4.6.2).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Qualifier {
+    Pass,
+    Fail,
+    SoftFail,
+    Neutral,
+}
+
+impl Qualifier {
+    fn from_symbol(symbol: char) -> Option<Self> {
+        match symbol {
+            '+' => Some(Self::Pass),
+            '-' => Some(Self::Fail),
+            '~' => Some(Self::SoftFail),
+            '?' => Some(Self::Neutral),
+            _ => None,
+        }
+    }
+
+    /// The symbol as written in a record: `+`, `-`, `~` or `?`.
+    pub fn symbol(self) -> char {
+        match self {
+            Self::Pass => '+',
+            Self::Fail => '-',
+            Self::SoftFail => '~',
+            Self::Neutral => '?',
+        }
+    }
+}
+
+/// The CIDR prefix lengths of an `a` or `mx` mechanism, one per address family
+/// (RFC 7208 §5.6).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DualCidr {
+    pub ip4: u8,
+    pub ip6: u8,
+}
+
+impl DualCidr {
+    /// The network of the matching prefix length around `addr`.
+    pub fn network(self, addr: IpAddr) -> IpNetwork {
+        let prefix = match addr {
+            IpAddr::V4(_) => self.ip4,
+            IpAddr::V6(_) => self.ip6,
+        };
+
+        IpNetwork::new(addr, prefix).expect("prefix lengths are validated while parsing")
+    }
+}
+
+impl Default for DualCidr {
+    fn default() -> Self {
+        Self { ip4: 32, ip6: 128 }
+    }
+}
+
+impl fmt::Display for DualCidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let default = Self::default();
+
+        if self.ip4 != default.ip4 {
+            write!(f, "/{}", self.ip4)?;
+        }
+
+        if self.ip6 != default.ip6 {
+            write!(f, "//{}", self.ip6)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A mechanism together with its arguments (RFC 7208 §5).
+///
+/// Domain specifications are kept verbatim, including any unexpanded macros.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Mechanism {
+    All,
+    Include(String),
+    A {
+        domain: Option<String>,
+        cidr: DualCidr,
+    },
+    Mx {
+        domain: Option<String>,
+        cidr: DualCidr,
+    },
+    Ptr(Option<String>),
+    Ip4(IpNetwork),
+    Ip6(IpNetwork),
+    Exists(String),
+}
+
+impl Mechanism {
+    /// The `ip4` or `ip6` mechanism matching `network`, depending on its address family.
+    pub(crate) fn from_network(network: IpNetwork) -> Self {
+        if network.addr().is_ipv4() {
+            Self::Ip4(network)
+        } else {
+            Self::Ip6(network)
+        }
+    }
+
+    /// The mechanism name as written in a record, e.g. `include`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Include(_) => "include",
+            Self::A { .. } => "a",
+            Self::Mx { .. } => "mx",
+            Self::Ptr(_) => "ptr",
+            Self::Ip4(_) => "ip4",
+            Self::Ip6(_) => "ip6",
+            Self::Exists(_) => "exists",
+        }
+    }
+
+    /// The domain spec of the mechanism, if one is given, e.g. `_spf.example.com` for
+    /// `include:_spf.example.com`.
+    pub fn domain_spec(&self) -> Option<&str> {
+        match self {
+            Self::Include(domain) | Self::Exists(domain) | Self::Ptr(Some(domain)) => Some(domain),
+            Self::A { domain, .. } | Self::Mx { domain, .. } => domain.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if evaluating the mechanism counts against the DNS lookup limit
+    /// (RFC 7208 §4.6.4).
+    pub fn is_dns_lookup(&self) -> bool {
+        matches!(
+            self,
+            Self::Include(_) | Self::A { .. } | Self::Mx { .. } | Self::Ptr(_) | Self::Exists(_)
+        )
+    }
+}
+
+impl fmt::Display for Mechanism {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())?;
+
+        match self {
+            Self::All | Self::Ptr(None) => Ok(()),
+            Self::Include(domain) | Self::Exists(domain) | Self::Ptr(Some(domain)) => {
+                write!(f, ":{domain}")
+            }
+            Self::A { domain, cidr } | Self::Mx { domain, cidr } => {
+                if let Some(domain) = domain {
+                    write!(f, ":{domain}")?;
+                }
+                write!(f, "{cidr}")
+            }
+            Self::Ip4(network) | Self::Ip6(network) => write!(f, ":{network}"),
+        }
+    }
+}
+
+/// A qualified mechanism (RFC 7208 §4.6.1).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Directive {
+    pub qualifier: Qualifier,
+    pub mechanism: Mechanism,
+}
+
+impl fmt::Display for Directive {
+    /// Formats the directive, omitting the default `+` qualifier.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.qualifier != Qualifier::Pass {
+            write!(f, "{}", self.qualifier.symbol())?;
+        }
+
+        write!(f, "{}", self.mechanism)
+    }
+}
+
+/// A `name=value` modifier (RFC 7208 §6).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Modifier {
+    Redirect(String),
+    Explanation(String),
+    Unknown { name: String, value: String },
+}
+
+impl fmt::Display for Modifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Redirect(domain) => write!(f, "redirect={domain}"),
+            Self::Explanation(domain) => write!(f, "exp={domain}"),
+            Self::Unknown { name, value } => write!(f, "{name}={value}"),
+        }
+    }
+}
+
+/// A single space-separated term of an SPF record.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Term {
+    Directive(Directive),
+    Modifier(Modifier),
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Directive(directive) => directive.fmt(f),
+            Self::Modifier(modifier) => modifier.fmt(f),
+        }
+    }
+}
+
+/// A parsed `v=spf1` record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpfRecord {
+    terms: Vec<Term>,
+}
+
+impl SpfRecord {
+    /// Assembles a record from terms that are known to form a valid record.
+    pub(crate) fn from_terms(terms: Vec<Term>) -> Self {
+        Self { terms }
+    }
+
+    /// All terms in record order.
+    pub fn terms(&self) -> &[Term] {
+        &self.terms
+    }
+
+    /// All directives in evaluation order.
+    pub fn directives(&self) -> impl Iterator<Item = &Directive> {
+        self.terms.iter().filter_map(|term| match term {
+            Term::Directive(directive) => Some(directive),
+            Term::Modifier(_) => None,
+        })
+    }
+
+    /// The domain specification of the `redirect=` modifier, if present.
+    pub fn redirect(&self) -> Option<&str> {
+        self.terms.iter().find_map(|term| match term {
+            Term::Modifier(Modifier::Redirect(domain)) => Some(domain.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The domain specification of the `exp=` modifier, if present.
+    pub fn explanation(&self) -> Option<&str> {
+        self.terms.iter().find_map(|term| match term {
+            Term::Modifier(Modifier::Explanation(domain)) => Some(domain.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Parses a record like [`SpfRecord::from_str`], additionally rejecting what the ABNF of
+    /// RFC 7208 §12 does not allow but is otherwise tolerated: terms separated by other
+    /// whitespace than spaces, invalid macros and domain specs not ending in a top-level label
+    /// or a macro, e.g. `include:localhost`.
+    pub fn parse_strict(record: &str) -> Result<Self, ParseError> {
+        if let Some(index) = record.find(|c: char| c.is_whitespace() && c != ' ') {
+            let term = record[index..]
+                .split_whitespace()
+                .next()
+                .unwrap_or_default();
+
+            return Err(ParseError::new(term, "terms must be separated by spaces"));
+        }
+
+        if record.starts_with(' ') {
+            let term = record.split_whitespace().next().unwrap_or_default();
+
+            return Err(ParseError::new(term, "unexpected leading space"));
+        }
+
+        let spf = Self::from_str(record)?;
+
+        for term in &spf.terms {
+            let (spec, is_domain) = match term {
+                Term::Directive(directive) => match directive.mechanism.domain_spec() {
+                    Some(spec) => (spec, true),
+                    None => continue,
+                },
+                Term::Modifier(Modifier::Redirect(spec) | Modifier::Explanation(spec)) => {
+                    (spec.as_str(), true)
+                }
+                Term::Modifier(Modifier::Unknown { value, .. }) => (value.as_str(), false),
+            };
+
+            macros::validate_domain(spec)
+                .map_err(|err| ParseError::new(term.to_string(), err.to_string()))?;
+
+            if is_domain && !has_valid_domain_end(spec) {
+                return Err(ParseError::new(
+                    term.to_string(),
+                    "domain must end in a top-level label or a macro",
+                ));
+            }
+        }
+
+        Ok(spf)
+    }
+}
+
+impl FromStr for SpfRecord {
+    type Err = ParseError;
+
+    /// Parses a record according to the ABNF of RFC 7208 §12.
+    ///
+    /// Unknown mechanisms, malformed arguments and repeated `redirect`/`exp` modifiers are
+    /// rejected, as they result in a `permerror` during evaluation.
+    fn from_str(record: &str) -> Result<Self, Self::Err> {
+        let mut parts = record.split_whitespace();
+        let version = parts.next().unwrap_or_default();
+
+        if !version.eq_ignore_ascii_case(VERSION) {
+            return Err(ParseError::new(version, "expected `v=spf1`"));
+        }
+
+        let terms = parts.map(parse_term).collect::<Result<Vec<_>, _>>()?;
+
+        for name in ["redirect", "exp"] {
+            let mut occurrences = terms.iter().filter(|term| match term {
+                Term::Modifier(Modifier::Redirect(_)) => name == "redirect",
+                Term::Modifier(Modifier::Explanation(_)) => name == "exp",
+                _ => false,
+            });
+
+            if let (Some(_), Some(duplicate)) = (occurrences.next(), occurrences.next()) {
+                return Err(ParseError::new(
+                    duplicate.to_string(),
+                    "modifier must not appear more than once",
+                ));
+            }
+        }
+
+        Ok(Self { terms })
+    }
+}
+
+impl fmt::Display for SpfRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(VERSION)?;
+
+        for term in &self.terms {
+            write!(f, " {term}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A syntax error in an SPF record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    term: String,
+    reason: String,
+}
+
+impl ParseError {
+    fn new(term: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            term: term.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// The offending term as written in the record.
+    pub fn term(&self) -> &str {
+        &self.term
+    }
+
+    /// Why the term is invalid, e.g. `unknown mechanism`.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// Returns `true` if the term is a directive naming a mechanism not defined by RFC 7208.
+    pub fn is_unknown_mechanism(&self) -> bool {
+        self.reason == UNKNOWN_MECHANISM
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid term `{}`: {}", self.term, self.reason)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+fn parse_term(term: &str) -> Result<Term, ParseError> {
+    let name_end = term.find([':', '/', '=']).unwrap_or(term.len());
+
+    match term[name_end..].strip_prefix('=') {
+        Some(value) => parse_modifier(term, &term[..name_end], value).map(Term::Modifier),
+        None => parse_directive(term).map(Term::Directive),
+    }
+}
+
+fn parse_modifier(term: &str, name: &str, value: &str) -> Result<Modifier, ParseError> {
+    let is_valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+
+    if !is_valid_name {
+        return Err(ParseError::new(term, "invalid modifier name"));
+    }
+
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "redirect" => Modifier::Redirect(domain_spec(term, value)?),
+        "exp" => Modifier::Explanation(domain_spec(term, value)?),
+        _ => Modifier::Unknown {
+            name: name.to_owned(),
+            value: value.to_owned(),
+        },
+    })
+}
+
+fn parse_directive(term: &str) -> Result<Directive, ParseError> {
+    let (qualifier, rest) = match term.chars().next().and_then(Qualifier::from_symbol) {
+        Some(qualifier) => (qualifier, &term[1..]),
+        None => (Qualifier::Pass, term),
+    };
+
+    let (name, args) = rest.split_at(rest.find([':', '/']).unwrap_or(rest.len()));
+
+    let mechanism = match name.to_ascii_lowercase().as_str() {
+        "all" if args.is_empty() => Mechanism::All,
+        "include" => Mechanism::Include(required_domain_spec(term, args)?),
+        "exists" => Mechanism::Exists(required_domain_spec(term, args)?),
+        "a" => {
+            let (domain, cidr) = domain_spec_with_cidr(term, args)?;
+            Mechanism::A { domain, cidr }
+        }
+        "mx" => {
+            let (domain, cidr) = domain_spec_with_cidr(term, args)?;
+            Mechanism::Mx { domain, cidr }
+        }
+        "ptr" if args.is_empty() => Mechanism::Ptr(None),
+        "ptr" => Mechanism::Ptr(Some(required_domain_spec(term, args)?)),
+        "ip4" => match ip_network(term, args)? {
+            network if network.addr().is_ipv4() => Mechanism::Ip4(network),
+            _ => return Err(ParseError::new(term, "expected an IPv4 network")),
+        },
+        "ip6" => match ip_network(term, args)? {
+            network if network.addr().is_ipv6() => Mechanism::Ip6(network),
+            _ => return Err(ParseError::new(term, "expected an IPv6 network")),
+        },
+        _ => return Err(ParseError::new(term, UNKNOWN_MECHANISM)),
+    };
+
+    Ok(Directive {
+        qualifier,
+        mechanism,
+    })
+}
+
+fn domain_spec(term: &str, value: &str) -> Result<String, ParseError> {
+    if value.is_empty() {
+        return Err(ParseError::new(term, "missing domain"));
+    }
+
+    Ok(value.to_owned())
+}
+
+fn required_domain_spec(term: &str, args: &str) -> Result<String, ParseError> {
+    let value = args
+        .strip_prefix(':')
+        .ok_or_else(|| ParseError::new(term, "expected `:` followed by a domain"))?;
+
+    domain_spec(term, value)
+}
+
+fn domain_spec_with_cidr(term: &str, args: &str) -> Result<(Option<String>, DualCidr), ParseError> {
+    let (domain, cidr) = split_cidr(args);
+
+    let domain = match domain {
+        "" => None,
+        domain => Some(required_domain_spec(term, domain)?),
+    };
+
+    Ok((domain, dual_cidr(term, cidr)?))
+}
+
+/// Splits `args` at the first `/` that is not part of a macro such as `%{l1r/}`.
+fn split_cidr(args: &str) -> (&str, &str) {
+    let mut in_macro = false;
+    let mut previous = None;
+
+    for (index, c) in args.char_indices() {
+        match c {
+            '{' if previous == Some('%') => in_macro = true,
+            '}' => in_macro = false,
+            '/' if !in_macro => return args.split_at(index),
+            _ => {}
+        }
+        previous = Some(c);
+    }
+
+    (args, "")
+}
+
+fn dual_cidr(term: &str, cidr: &str) -> Result<DualCidr, ParseError> {
+    let mut result = DualCidr::default();
+
+    let (ip4, ip6) = match cidr.strip_prefix("//") {
+        Some(ip6) => (None, Some(ip6)),
+        None => match cidr.strip_prefix('/') {
+            Some(rest) => match rest.split_once("//") {
+                Some((ip4, ip6)) => (Some(ip4), Some(ip6)),
+                None => (Some(rest), None),
+            },
+            None => (None, None),
+        },
+    };
+
+    if let Some(ip4) = ip4 {
+        result.ip4 = prefix_length(term, ip4, 32)?;
+    }
+
+    if let Some(ip6) = ip6 {
+        result.ip6 = prefix_length(term, ip6, 128)?;
+    }
+
+    Ok(result)
+}
+
+fn prefix_length(term: &str, value: &str, max: u8) -> Result<u8, ParseError> {
+    let has_leading_zero = value.len() > 1 && value.starts_with('0');
+
+    value
+        .parse::<u8>()
+        .ok()
+        .filter(|prefix| *prefix <= max && !has_leading_zero)
+        .ok_or_else(|| ParseError::new(term, format!("invalid prefix length `{value}`")))
+}
+
+/// https://datatracker.ietf.org/doc/html/rfc7208#section-7.1
+///
+/// > domain-end       = ( "." toplabel [ "." ] ) / macro-expand
+fn has_valid_domain_end(spec: &str) -> bool {
+    let is_macro_expand = ["}", "%%", "%_", "%-"]
+        .iter()
+        .any(|suffix| spec.ends_with(suffix));
+
+    if is_macro_expand && spec.contains('%') {
+        return true;
+    }
+
+    let spec = spec.strip_suffix('.').unwrap_or(spec);
+
+    match spec.rsplit_once('.') {
+        Some((_, label)) => is_toplabel(label),
+        None => false,
+    }
+}
+
+/// > toplabel         = ( *alphanum ALPHA *alphanum ) /
+/// >                    ( 1*alphanum "-" *( alphanum / "-" ) alphanum )
+fn is_toplabel(label: &str) -> bool {
+    let is_alphanumeric = |c: char| c.is_ascii_alphanumeric();
+
+    if !label.chars().all(|c| is_alphanumeric(c) || c == '-') {
+        return false;
+    }
+
+    if label.contains('-') {
+        label.starts_with(is_alphanumeric) && label.ends_with(is_alphanumeric)
+    } else {
+        label.chars().any(|c| c.is_ascii_alphabetic())
+    }
+}
+
+fn ip_network(term: &str, args: &str) -> Result<IpNetwork, ParseError> {
+    let value = args
+        .strip_prefix(':')
+        .ok_or_else(|| ParseError::new(term, "expected `:` followed by a network"))?;
+
+    IpNetwork::from_str(value).map_err(|reason| ParseError::new(term, reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_record_terms() {
+        let record = SpfRecord::from_str(
+            "v=spf1 ip4:192.0.2.0/24 -ip6:2001:db8::/32 ~a:mail.example.com/28//64 ?mx include:_spf.example.net redirect=_spf.example.org",
+        )
+        .unwrap();
+
+        let directives: Vec<String> = record.directives().map(ToString::to_string).collect();
+
+        assert_eq!(
+            directives,
+            vec![
+                "ip4:192.0.2.0/24",
+                "-ip6:2001:db8::/32",
+                "~a:mail.example.com/28//64",
+                "?mx",
+                "include:_spf.example.net",
+            ]
+        );
+        assert_eq!(record.redirect(), Some("_spf.example.org"));
+        assert_eq!(record.explanation(), None);
+    }
+
+    #[test]
+    fn test_parse_record_keeps_macros_verbatim() {
+        let record = SpfRecord::from_str("v=spf1 exists:%{ir}.%{l1r/}._spf.%{d} -all").unwrap();
+
+        let mechanism = &record.directives().next().unwrap().mechanism;
+
+        assert_eq!(
+            mechanism,
+            &Mechanism::Exists("%{ir}.%{l1r/}._spf.%{d}".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_record_qualifiers() {
+        let record = SpfRecord::from_str("v=spf1 +a -mx ~ptr ?all").unwrap();
+
+        let qualifiers: Vec<Qualifier> = record
+            .directives()
+            .map(|directive| directive.qualifier)
+            .collect();
+
+        assert_eq!(
+            qualifiers,
+            vec![
+                Qualifier::Pass,
+                Qualifier::Fail,
+                Qualifier::SoftFail,
+                Qualifier::Neutral,
+            ]
+        );
+        assert_eq!(record.to_string(), "v=spf1 a -mx ~ptr ?all");
+
+        for record in ["v=spf1 !all", "v=spf1 --all", "v=spf1 -"] {
+            let err = SpfRecord::from_str(record).unwrap_err();
+            assert!(err.is_unknown_mechanism(), "{record}");
+        }
+    }
+
+    #[test]
+    fn test_parse_record_macros_with_cidr() {
+        let record = SpfRecord::from_str(
+            "v=spf1 a:%{l1r/}.%{d}/24//64 mx:%{d2}//48 exp=explain.%{d} redirect=%{d}",
+        )
+        .unwrap();
+
+        let mechanisms: Vec<&Mechanism> = record
+            .directives()
+            .map(|directive| &directive.mechanism)
+            .collect();
+
+        assert_eq!(
+            mechanisms,
+            vec![
+                &Mechanism::A {
+                    domain: Some("%{l1r/}.%{d}".to_string()),
+                    cidr: DualCidr { ip4: 24, ip6: 64 },
+                },
+                &Mechanism::Mx {
+                    domain: Some("%{d2}".to_string()),
+                    cidr: DualCidr { ip4: 32, ip6: 48 },
+                },
+            ]
+        );
+        assert_eq!(record.explanation(), Some("explain.%{d}"));
+        assert_eq!(record.redirect(), Some("%{d}"));
+    }
+
+    #[test]
+    fn test_parse_record_unknown_modifiers() {
+        let record =
+            SpfRecord::from_str("v=spf1 -all Foo.Bar_1=%{d} empty= REDIRECT=example.com").unwrap();
+
+        assert_eq!(
+            record.terms()[1..],
+            [
+                Term::Modifier(Modifier::Unknown {
+                    name: "Foo.Bar_1".to_string(),
+                    value: "%{d}".to_string(),
+                }),
+                Term::Modifier(Modifier::Unknown {
+                    name: "empty".to_string(),
+                    value: String::new(),
+                }),
+                Term::Modifier(Modifier::Redirect("example.com".to_string())),
+            ]
+        );
+        assert_eq!(
+            record.to_string(),
+            "v=spf1 -all Foo.Bar_1=%{d} empty= redirect=example.com"
+        );
+
+        for (record, term) in [
+            ("v=spf1 1foo=bar", "1foo=bar"),
+            ("v=spf1 +foo=bar", "+foo=bar"),
+            ("v=spf1 =bar", "=bar"),
+            ("v=spf1 exp=", "exp="),
+        ] {
+            assert_eq!(
+                SpfRecord::from_str(record).map_err(|err| err.term().to_owned()),
+                Err(term.to_owned()),
+                "{record}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_record_rejects_invalid_terms() {
+        for record in [
+            "v=spf2 -all",
+            "v=spf1 foo:example.com",
+            "v=spf1 ip4:192.0.2.0/33",
+            "v=spf1 ip4:2001:db8::1",
+            "v=spf1 include:",
+            "v=spf1 redirect=a.example redirect=b.example",
+        ] {
+            assert!(SpfRecord::from_str(record).is_err(), "{record}");
+        }
+    }
+
+    #[test]
+    fn test_parse_strict_record() {
+        for record in [
+            "v=spf1 include:_spf.example.com a:mail.example.com. -all",
+            "v=spf1 exists:%{ir}.%{v}._spf.%{d2} redirect=%{d}",
+            "v=spf1 ip4:192.0.2.0/24 a/24 foo=bar -all",
+        ] {
+            assert!(SpfRecord::parse_strict(record).is_ok(), "{record}");
+        }
+
+        for (record, term) in [
+            (
+                "v=spf1\tinclude:_spf.example.com -all",
+                "include:_spf.example.com",
+            ),
+            (" v=spf1 -all", "v=spf1"),
+            ("v=spf1 include:localhost -all", "include:localhost"),
+            ("v=spf1 a:mail.example.123 -all", "a:mail.example.123"),
+            (
+                "v=spf1 exists:%{x}.example.com -all",
+                "exists:%{x}.example.com",
+            ),
+            ("v=spf1 redirect=example.com-", "redirect=example.com-"),
+            ("v=spf1 -all foo=%{d", "foo=%{d"),
+        ] {
+            assert_eq!(
+                SpfRecord::parse_strict(record).map_err(|err| err.term().to_owned()),
+                Err(term.to_owned()),
+                "{record}"
+            );
+        }
+    }
+}
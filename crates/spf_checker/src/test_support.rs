@@ -0,0 +1,239 @@
+//! Test doubles for the DNS resolver and the MTA-STS policy fetcher.
+
+use crate::{is_spf_record, CheckError, MxRecord, PolicyFetcher, SpnResolver, TxtRecord};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone)]
+pub(crate) struct MockResolver {
+    pub(crate) records: Arc<Mutex<HashMap<String, Vec<String>>>>,
+    pub(crate) addresses: Arc<Mutex<HashMap<String, Vec<IpAddr>>>>,
+    pub(crate) exchanges: Arc<Mutex<HashMap<String, Vec<MxRecord>>>>,
+    pub(crate) pointers: Arc<Mutex<HashMap<IpAddr, Vec<String>>>>,
+    pub(crate) spf_rrs: Arc<Mutex<HashMap<String, Vec<String>>>>,
+    /// TTLs of the TXT records of a domain, 300 if not set.
+    pub(crate) ttls: Arc<Mutex<HashMap<String, u32>>>,
+    /// Domains whose TXT lookups fail.
+    pub(crate) failing: Arc<Mutex<HashSet<String>>>,
+    /// Domains whose MX lookups fail.
+    pub(crate) failing_mx: Arc<Mutex<HashSet<String>>>,
+    /// Number of TXT lookups made.
+    pub(crate) txt_lookups: Arc<AtomicUsize>,
+    /// Whether TXT lookups yield to other tasks before answering, like a name server would.
+    pub(crate) yielding: Arc<AtomicBool>,
+}
+
+impl MockResolver {
+    pub(crate) fn new() -> Self {
+        Self {
+            records: Arc::new(Mutex::new(HashMap::new())),
+            addresses: Arc::new(Mutex::new(HashMap::new())),
+            exchanges: Arc::new(Mutex::new(HashMap::new())),
+            pointers: Arc::new(Mutex::new(HashMap::new())),
+            spf_rrs: Arc::new(Mutex::new(HashMap::new())),
+            ttls: Arc::new(Mutex::new(HashMap::new())),
+            failing: Arc::new(Mutex::new(HashSet::new())),
+            failing_mx: Arc::new(Mutex::new(HashSet::new())),
+            txt_lookups: Arc::new(AtomicUsize::new(0)),
+            yielding: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub(crate) fn add_record(&self, domain: &str, spf_record: &str) {
+        self.add_records(domain, &[spf_record]);
+    }
+
+    pub(crate) fn add_txt(&self, domain: &str, txt: &str) {
+        self.add_records(domain, &[txt]);
+    }
+
+    pub(crate) fn add_records(&self, domain: &str, spf_records: &[&str]) {
+        let mut records = self.records.lock().unwrap();
+        records.insert(
+            domain.to_string(),
+            spf_records
+                .iter()
+                .map(|record| record.to_string())
+                .collect(),
+        );
+    }
+
+    pub(crate) fn add_addresses(&self, domain: &str, addresses: &[&str]) {
+        let mut records = self.addresses.lock().unwrap();
+        records.insert(
+            domain.to_string(),
+            addresses.iter().map(|addr| addr.parse().unwrap()).collect(),
+        );
+    }
+
+    pub(crate) fn add_exchanges(&self, domain: &str, exchanges: &[&str]) {
+        let exchanges: Vec<_> = exchanges.iter().map(|name| (10, *name)).collect();
+        self.add_mx_records(domain, &exchanges);
+    }
+
+    pub(crate) fn add_mx_records(&self, domain: &str, exchanges: &[(u16, &str)]) {
+        let mut records = self.exchanges.lock().unwrap();
+        records.insert(
+            domain.to_string(),
+            exchanges
+                .iter()
+                .map(|(preference, exchange)| MxRecord {
+                    preference: *preference,
+                    exchange: exchange.to_string(),
+                })
+                .collect(),
+        );
+    }
+
+    pub(crate) fn add_pointers(&self, ip: &str, names: &[&str]) {
+        let mut records = self.pointers.lock().unwrap();
+        records.insert(
+            ip.parse().unwrap(),
+            names.iter().map(|name| name.to_string()).collect(),
+        );
+    }
+
+    pub(crate) fn set_ttl(&self, domain: &str, ttl: u32) {
+        let mut ttls = self.ttls.lock().unwrap();
+        ttls.insert(domain.to_string(), ttl);
+    }
+
+    pub(crate) fn fail_lookups(&self, domain: &str) {
+        let mut failing = self.failing.lock().unwrap();
+        failing.insert(domain.to_string());
+    }
+
+    pub(crate) fn fail_mx_lookups(&self, domain: &str) {
+        let mut failing = self.failing_mx.lock().unwrap();
+        failing.insert(domain.to_string());
+    }
+
+    pub(crate) fn add_spf_rr(&self, domain: &str, record: &str) {
+        let mut records = self.spf_rrs.lock().unwrap();
+        records.insert(domain.to_string(), vec![record.to_string()]);
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MockFetcher {
+    pub(crate) policies: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl MockFetcher {
+    pub(crate) fn add_policy(&self, url: &str, policy: &str) {
+        let mut policies = self.policies.lock().unwrap();
+        policies.insert(url.to_string(), policy.to_string());
+    }
+}
+
+#[async_trait]
+impl PolicyFetcher for MockFetcher {
+    async fn fetch_policy(&self, url: &str) -> Result<String> {
+        let policies = self.policies.lock().expect("mutex poisoned");
+        policies
+            .get(url)
+            .cloned()
+            .ok_or_else(|| anyhow!("HTTP status client error (404 Not Found)"))
+    }
+}
+
+#[async_trait]
+impl SpnResolver for MockResolver {
+    async fn find_spf_records(&self, domain: &str) -> Result<Vec<String>> {
+        let records = self.lookup_txt(domain).await?;
+        Ok(records
+            .into_iter()
+            .filter(|txt| is_spf_record(txt))
+            .collect())
+    }
+
+    async fn lookup_txt(&self, domain: &str) -> Result<Vec<String>> {
+        self.txt_lookups.fetch_add(1, Ordering::Relaxed);
+        if self.yielding.load(Ordering::Relaxed) {
+            tokio::task::yield_now().await;
+        }
+        if self
+            .failing
+            .lock()
+            .expect("mutex poisoned")
+            .contains(domain)
+        {
+            return Err(anyhow!("request timed out")).context(CheckError::DnsLookupFailed);
+        }
+        let records = self.records.lock().expect("mutex poisoned");
+        Ok(records.get(domain).cloned().unwrap_or_default())
+    }
+
+    async fn lookup_txt_records(&self, domain: &str) -> Result<Vec<TxtRecord>> {
+        let records = self.lookup_txt(domain).await?;
+        let ttl = self
+            .ttls
+            .lock()
+            .expect("mutex poisoned")
+            .get(domain)
+            .copied()
+            .unwrap_or(300);
+        Ok(records
+            .into_iter()
+            .map(|text| TxtRecord { text, ttl })
+            .collect())
+    }
+
+    async fn lookup_a(&self, domain: &str) -> Result<Vec<Ipv4Addr>> {
+        let records = self.addresses.lock().expect("mutex poisoned");
+        Ok(records
+            .get(domain)
+            .into_iter()
+            .flatten()
+            .filter_map(|addr| match addr {
+                IpAddr::V4(addr) => Some(*addr),
+                IpAddr::V6(_) => None,
+            })
+            .collect())
+    }
+
+    async fn lookup_aaaa(&self, domain: &str) -> Result<Vec<Ipv6Addr>> {
+        let records = self.addresses.lock().expect("mutex poisoned");
+        Ok(records
+            .get(domain)
+            .into_iter()
+            .flatten()
+            .filter_map(|addr| match addr {
+                IpAddr::V4(_) => None,
+                IpAddr::V6(addr) => Some(*addr),
+            })
+            .collect())
+    }
+
+    async fn lookup_mx(&self, domain: &str) -> Result<Vec<String>> {
+        let records = self.lookup_mx_records(domain).await?;
+        Ok(records.into_iter().map(|record| record.exchange).collect())
+    }
+
+    async fn lookup_mx_records(&self, domain: &str) -> Result<Vec<MxRecord>> {
+        if self
+            .failing_mx
+            .lock()
+            .expect("mutex poisoned")
+            .contains(domain)
+        {
+            return Err(anyhow!("request timed out")).context(CheckError::DnsLookupFailed);
+        }
+        let records = self.exchanges.lock().expect("mutex poisoned");
+        Ok(records.get(domain).cloned().unwrap_or_default())
+    }
+
+    async fn lookup_ptr(&self, ip: IpAddr) -> Result<Vec<String>> {
+        let records = self.pointers.lock().expect("mutex poisoned");
+        Ok(records.get(&ip).cloned().unwrap_or_default())
+    }
+
+    async fn lookup_spf_rr(&self, domain: &str) -> Result<Vec<String>> {
+        let records = self.spf_rrs.lock().expect("mutex poisoned");
+        Ok(records.get(domain).cloned().unwrap_or_default())
+    }
+}
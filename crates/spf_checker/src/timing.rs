@@ -0,0 +1,166 @@
+use crate::{MxRecord, SpnResolver, TxtRecord};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a DNS query of a check took.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryTiming {
+    /// The queried name, or the address of a `PTR` query.
+    pub domain: String,
+    /// The queried record type, e.g. `TXT` or `MX`.
+    pub record_type: &'static str,
+    /// The time until the answer or the failure of the query.
+    pub duration: Duration,
+}
+
+/// The timings recorded by a checker returned from [`crate::SpfChecker::with_query_timings`].
+#[derive(Clone, Debug, Default)]
+pub struct QueryTimings(Arc<Mutex<Vec<QueryTiming>>>);
+
+impl QueryTimings {
+    /// Returns the timings of the queries made so far, in the order they were answered.
+    pub fn take(&self) -> Vec<QueryTiming> {
+        std::mem::take(&mut *self.0.lock().expect("mutex poisoned"))
+    }
+}
+
+/// Records how long each query of a resolver takes, including failed ones.
+#[derive(Debug)]
+pub(crate) struct TimingResolver {
+    resolver: Arc<dyn SpnResolver + Send + Sync + 'static>,
+    timings: QueryTimings,
+}
+
+impl TimingResolver {
+    pub fn new(
+        resolver: Arc<dyn SpnResolver + Send + Sync + 'static>,
+        timings: QueryTimings,
+    ) -> Self {
+        Self { resolver, timings }
+    }
+
+    async fn timed<V>(
+        &self,
+        domain: String,
+        record_type: &'static str,
+        lookup: impl Future<Output = Result<V>>,
+    ) -> Result<V> {
+        let start = Instant::now();
+        let answer = lookup.await;
+
+        self.timings
+            .0
+            .lock()
+            .expect("mutex poisoned")
+            .push(QueryTiming {
+                domain,
+                record_type,
+                duration: start.elapsed(),
+            });
+
+        answer
+    }
+}
+
+#[async_trait]
+impl SpnResolver for TimingResolver {
+    async fn find_spf_records(&self, domain: &str) -> Result<Vec<String>> {
+        self.timed(
+            domain.to_owned(),
+            "TXT",
+            self.resolver.find_spf_records(domain),
+        )
+        .await
+    }
+
+    async fn lookup_txt(&self, domain: &str) -> Result<Vec<String>> {
+        self.timed(domain.to_owned(), "TXT", self.resolver.lookup_txt(domain))
+            .await
+    }
+
+    async fn lookup_txt_records(&self, domain: &str) -> Result<Vec<TxtRecord>> {
+        self.timed(
+            domain.to_owned(),
+            "TXT",
+            self.resolver.lookup_txt_records(domain),
+        )
+        .await
+    }
+
+    async fn lookup_a(&self, domain: &str) -> Result<Vec<Ipv4Addr>> {
+        self.timed(domain.to_owned(), "A", self.resolver.lookup_a(domain))
+            .await
+    }
+
+    async fn lookup_aaaa(&self, domain: &str) -> Result<Vec<Ipv6Addr>> {
+        self.timed(domain.to_owned(), "AAAA", self.resolver.lookup_aaaa(domain))
+            .await
+    }
+
+    async fn lookup_mx(&self, domain: &str) -> Result<Vec<String>> {
+        self.timed(domain.to_owned(), "MX", self.resolver.lookup_mx(domain))
+            .await
+    }
+
+    async fn lookup_mx_records(&self, domain: &str) -> Result<Vec<MxRecord>> {
+        self.timed(
+            domain.to_owned(),
+            "MX",
+            self.resolver.lookup_mx_records(domain),
+        )
+        .await
+    }
+
+    async fn lookup_ptr(&self, ip: IpAddr) -> Result<Vec<String>> {
+        self.timed(ip.to_string(), "PTR", self.resolver.lookup_ptr(ip))
+            .await
+    }
+
+    async fn lookup_spf_rr(&self, domain: &str) -> Result<Vec<String>> {
+        self.timed(
+            domain.to_owned(),
+            "SPF",
+            self.resolver.lookup_spf_rr(domain),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::MockResolver;
+    use crate::SpfChecker;
+
+    #[tokio::test]
+    async fn test_query_timings() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("example.com", "v=spf1 include:_spf.example.net -all");
+        mock_resolver.add_record("_spf.example.net", "v=spf1 ip4:192.0.2.0/24 -all");
+
+        let (checker, timings) = SpfChecker::new(mock_resolver).with_query_timings();
+        let checker = checker.with_lookup_cache();
+
+        checker
+            .check("example.com", "mail.easybill.de")
+            .await
+            .unwrap();
+        let queries: Vec<_> = timings
+            .take()
+            .into_iter()
+            .map(|timing| (timing.domain, timing.record_type))
+            .collect();
+        assert!(queries.contains(&("example.com".to_string(), "TXT")));
+        assert!(queries.contains(&("_spf.example.net".to_string(), "TXT")));
+
+        // Answered from the cache without querying again
+        checker
+            .check("example.com", "mail.easybill.de")
+            .await
+            .unwrap();
+        assert!(timings.take().is_empty());
+    }
+}
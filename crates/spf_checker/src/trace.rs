@@ -0,0 +1,305 @@
+use crate::{CheckResult, MxRecord, SpnResolver, TxtRecord};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::fmt::{self, Debug, Display};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+
+/// A step taken by [`SpfChecker::explain`](crate::SpfChecker::explain).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceStep {
+    /// What happened, one of:
+    /// - `DNS_QUERY`: a DNS query was answered or failed
+    /// - `RECORD`: the SPF record of `domain` was found or is missing
+    /// - `SKIPPED`: `domain` was not traversed, e.g. as it was already visited
+    /// - `MECHANISM`: a mechanism of `domain` was compared with the target
+    /// - `FOLLOW`: an include or redirect of `domain` is traversed
+    /// - `LIMIT_EXCEEDED`: the traversal stopped at the DNS lookup limit
+    /// - `FALLBACK`: the mechanisms of the target's own record are compared
+    /// - `DEFAULT_POLICY`: the `all` mechanism ending the evaluation was found
+    pub kind: &'static str,
+    pub domain: String,
+    /// The mechanism or modifier, or the record type of a DNS query.
+    pub term: Option<String>,
+    /// Whether `term` matched the target, for `MECHANISM` steps.
+    pub matched: Option<bool>,
+    pub message: String,
+    /// Number of DNS-querying terms counted against the lookup limit at this step.
+    pub dns_mechanism_count: usize,
+}
+
+/// The outcome of [`SpfChecker::explain`](crate::SpfChecker::explain).
+pub struct Explanation {
+    /// The steps in the order they were taken, up to a failure of the check.
+    pub steps: Vec<TraceStep>,
+    pub result: Result<CheckResult>,
+}
+
+#[derive(Debug, Default)]
+struct TraceState {
+    steps: Vec<TraceStep>,
+    dns_mechanism_count: usize,
+}
+
+/// Receives each step of a check as soon as it is taken, see
+/// [`SpfChecker::with_progress`](crate::SpfChecker::with_progress).
+pub type ProgressListener = dyn Fn(&TraceStep) + Send + Sync;
+
+/// Collects the steps of a check, shared by the checker and its [`TracingResolver`].
+#[derive(Default)]
+pub(crate) struct Trace {
+    state: Mutex<TraceState>,
+    /// Receives the steps instead of collecting them.
+    listener: Option<Box<ProgressListener>>,
+}
+
+impl Debug for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Trace")
+            .field("state", &self.state)
+            .field("listener", &self.listener.is_some())
+            .finish()
+    }
+}
+
+impl Trace {
+    pub fn with_listener(listener: Box<ProgressListener>) -> Self {
+        Self {
+            state: Mutex::default(),
+            listener: Some(listener),
+        }
+    }
+
+    pub fn push(
+        &self,
+        kind: &'static str,
+        domain: &str,
+        term: Option<String>,
+        matched: Option<bool>,
+        message: String,
+    ) {
+        let mut state = self.state.lock().expect("mutex poisoned");
+
+        let step = TraceStep {
+            kind,
+            domain: domain.to_owned(),
+            term,
+            matched,
+            message,
+            dns_mechanism_count: state.dns_mechanism_count,
+        };
+
+        match &self.listener {
+            Some(listener) => {
+                drop(state);
+                listener(&step);
+            }
+            None => state.steps.push(step),
+        }
+    }
+
+    /// Sets the DNS lookup counter reported by the following steps.
+    pub fn set_dns_mechanism_count(&self, count: usize) {
+        self.state
+            .lock()
+            .expect("mutex poisoned")
+            .dns_mechanism_count = count;
+    }
+
+    pub fn take_steps(&self) -> Vec<TraceStep> {
+        std::mem::take(&mut self.state.lock().expect("mutex poisoned").steps)
+    }
+}
+
+/// Records each query of a resolver and its answer as a `DNS_QUERY` step.
+#[derive(Debug)]
+pub(crate) struct TracingResolver {
+    resolver: Arc<dyn SpnResolver + Send + Sync + 'static>,
+    trace: Arc<Trace>,
+}
+
+impl TracingResolver {
+    pub fn new(resolver: Arc<dyn SpnResolver + Send + Sync + 'static>, trace: Arc<Trace>) -> Self {
+        Self { resolver, trace }
+    }
+
+    fn record<T: Display>(&self, record_type: &str, name: &str, answer: &Result<Vec<T>>) {
+        let message = match answer {
+            Ok(records) if records.is_empty() => "No records".to_owned(),
+            Ok(records) => records
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+            Err(err) => format!("Failed: {err:#}"),
+        };
+
+        self.trace.push(
+            "DNS_QUERY",
+            name,
+            Some(record_type.to_owned()),
+            None,
+            message,
+        );
+    }
+}
+
+#[async_trait]
+impl SpnResolver for TracingResolver {
+    async fn find_spf_records(&self, domain: &str) -> Result<Vec<String>> {
+        let answer = self.resolver.find_spf_records(domain).await;
+        self.record("TXT", domain, &answer);
+        answer
+    }
+
+    async fn lookup_txt(&self, domain: &str) -> Result<Vec<String>> {
+        let answer = self.resolver.lookup_txt(domain).await;
+        self.record("TXT", domain, &answer);
+        answer
+    }
+
+    async fn lookup_txt_records(&self, domain: &str) -> Result<Vec<TxtRecord>> {
+        let answer = self.resolver.lookup_txt_records(domain).await;
+        let texts = answer
+            .as_ref()
+            .map(|records| {
+                records
+                    .iter()
+                    .map(|txt| txt.text.clone())
+                    .collect::<Vec<_>>()
+            })
+            .map_err(|err| anyhow!("{err:#}"));
+        self.record("TXT", domain, &texts);
+        answer
+    }
+
+    async fn lookup_a(&self, domain: &str) -> Result<Vec<Ipv4Addr>> {
+        let answer = self.resolver.lookup_a(domain).await;
+        self.record("A", domain, &answer);
+        answer
+    }
+
+    async fn lookup_aaaa(&self, domain: &str) -> Result<Vec<Ipv6Addr>> {
+        let answer = self.resolver.lookup_aaaa(domain).await;
+        self.record("AAAA", domain, &answer);
+        answer
+    }
+
+    async fn lookup_mx(&self, domain: &str) -> Result<Vec<String>> {
+        let answer = self.resolver.lookup_mx(domain).await;
+        self.record("MX", domain, &answer);
+        answer
+    }
+
+    async fn lookup_mx_records(&self, domain: &str) -> Result<Vec<MxRecord>> {
+        let answer = self.resolver.lookup_mx_records(domain).await;
+        let exchanges = answer
+            .as_ref()
+            .map(|records| {
+                records
+                    .iter()
+                    .map(|mx| mx.exchange.clone())
+                    .collect::<Vec<_>>()
+            })
+            .map_err(|err| anyhow!("{err:#}"));
+        self.record("MX", domain, &exchanges);
+        answer
+    }
+
+    async fn lookup_ptr(&self, ip: IpAddr) -> Result<Vec<String>> {
+        let answer = self.resolver.lookup_ptr(ip).await;
+        self.record("PTR", &ip.to_string(), &answer);
+        answer
+    }
+
+    async fn lookup_spf_rr(&self, domain: &str) -> Result<Vec<String>> {
+        let answer = self.resolver.lookup_spf_rr(domain).await;
+        self.record("SPF", domain, &answer);
+        answer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::MockResolver;
+    use crate::CheckOptions;
+    use crate::SpfChecker;
+
+    #[tokio::test]
+    async fn test_explain() {
+        let mock_resolver = MockResolver::new();
+        mock_resolver.add_record("example.com", "v=spf1 include:_spf.a.net -all");
+        mock_resolver.add_record(
+            "_spf.a.net",
+            "v=spf1 include:_spf.b.net include:_spf.target.com ~all",
+        );
+        mock_resolver.add_record("_spf.b.net", "v=spf1 ip4:192.0.2.0/24 -all");
+
+        let checker = SpfChecker::new(mock_resolver);
+        let explanation = checker
+            .explain("example.com", "_spf.target.com", &CheckOptions::default())
+            .await;
+
+        assert!(explanation.result.unwrap().found);
+
+        let steps: Vec<_> = explanation
+            .steps
+            .iter()
+            .map(|step| {
+                (
+                    step.kind,
+                    step.domain.as_str(),
+                    step.term.as_deref(),
+                    step.matched,
+                    step.dns_mechanism_count,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            steps,
+            vec![
+                ("DNS_QUERY", "example.com", Some("TXT"), None, 0),
+                (
+                    "RECORD",
+                    "example.com",
+                    Some("v=spf1 include:_spf.a.net -all"),
+                    None,
+                    1
+                ),
+                (
+                    "MECHANISM",
+                    "example.com",
+                    Some("include:_spf.a.net"),
+                    Some(false),
+                    1
+                ),
+                ("FOLLOW", "example.com", Some("include:_spf.a.net"), None, 1),
+                ("DNS_QUERY", "_spf.a.net", Some("TXT"), None, 1),
+                (
+                    "RECORD",
+                    "_spf.a.net",
+                    Some("v=spf1 include:_spf.b.net include:_spf.target.com ~all"),
+                    None,
+                    3
+                ),
+                (
+                    "MECHANISM",
+                    "_spf.a.net",
+                    Some("include:_spf.b.net"),
+                    Some(false),
+                    3
+                ),
+                (
+                    "MECHANISM",
+                    "_spf.a.net",
+                    Some("include:_spf.target.com"),
+                    Some(true),
+                    3
+                ),
+                ("DNS_QUERY", "example.com", Some("TXT"), None, 3),
+                ("DEFAULT_POLICY", "example.com", None, None, 3),
+            ]
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use crate::config::Config;
+use crate::error::ServiceError;
+use crate::Result;
+use anyhow::bail;
+use axum::extract::{Request, State};
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use std::sync::Arc;
+
+/// The bearer token of the routes under `/admin`, which are only served with a token configured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdminToken(Arc<str>);
+
+impl AdminToken {
+    pub fn new(token: &str) -> Self {
+        Self(Arc::from(token))
+    }
+
+    /// Reads `admin.token` or `ADMIN_TOKEN`, `None` if it is not set.
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        match config.get("admin.token") {
+            Some(token) if token.trim().is_empty() => {
+                bail!("ADMIN_TOKEN (`admin.token`) must not be empty")
+            }
+            token => Ok(token.map(Self::new)),
+        }
+    }
+
+    /// Whether `given` is the token, compared in constant time so that the response time does
+    /// not tell how much of it matches.
+    fn matches(&self, given: &str) -> bool {
+        let (token, given) = (self.0.as_bytes(), given.as_bytes());
+
+        token.len() == given.len()
+            && token
+                .iter()
+                .zip(given)
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+}
+
+/// Answers requests without `Authorization: Bearer <token>` with `401 Unauthorized`.
+pub async fn require_token(
+    State(token): State<AdminToken>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| token.matches(given));
+    if authorized {
+        return next.run(request).await;
+    }
+
+    let mut response = ServiceError::Unauthorized.into_response();
+    response
+        .headers_mut()
+        .insert(header::WWW_AUTHENTICATE, "Bearer".parse().unwrap());
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admin_token_from_config() {
+        assert_eq!(AdminToken::from_config(&Config::default()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_matches() {
+        let token = AdminToken::new("secret");
+        assert!(token.matches("secret"));
+        assert!(!token.matches("secreT"));
+        assert!(!token.matches("secret2"));
+        assert!(!token.matches(""));
+    }
+}
@@ -0,0 +1,578 @@
+use crate::config::Config;
+use crate::{log_message, Result};
+use anyhow::{anyhow, bail, Context};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use spf_checker::{
+    CachedRecords, DnsCache, SpfCache, SpfChecker, TxtRecord, DEFAULT_CACHE_CAPACITY,
+    DEFAULT_NEGATIVE_TTL,
+};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufStream,
+};
+use tokio::net::TcpStream;
+
+/// Port of a Redis URL without one.
+const DEFAULT_REDIS_PORT: u16 = 6379;
+/// Prefix of the keys of the cached records, followed by the domain.
+const KEY_PREFIX: &str = "spf-check:txt:";
+/// How long a Redis command may take before the lookup queries DNS instead.
+const REDIS_TIMEOUT: Duration = Duration::from_millis(500);
+/// How many idle connections to Redis are kept for later commands.
+const MAX_IDLE_CONNECTIONS: usize = 8;
+/// The shortest time between two refreshes of a warmed domain, however low its TTLs.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+/// How long until a warmed domain is tried again after its lookup failed or found no records.
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Where and how long the DNS cache keeps its records, see [`Config`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct CacheConfig {
+    store: CacheStore,
+    /// How many domains the memory store holds before evicting the least recently used.
+    capacity: usize,
+    /// How long domains without TXT records are remembered, not at all if zero.
+    negative_ttl: Duration,
+    /// How long records are answered past their TTL while they are looked up again.
+    stale_window: Duration,
+    /// The domains whose SPF trees are looked up at startup and kept fresh, see [`warm`].
+    pub warm_domains: Vec<String>,
+    /// How long complete responses of `/api/v1/check-spf` are cached, not at all if zero,
+    /// see [`ResponseCache`](crate::response_cache::ResponseCache).
+    pub response_ttl: Duration,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            store: CacheStore::Memory,
+            capacity: DEFAULT_CACHE_CAPACITY,
+            negative_ttl: DEFAULT_NEGATIVE_TTL,
+            stale_window: Duration::ZERO,
+            warm_domains: Vec::new(),
+            response_ttl: Duration::ZERO,
+        }
+    }
+}
+
+impl CacheConfig {
+    /// Reads the `dns_cache` settings `store`, `capacity`, `negative_ttl`, `stale_window` and
+    /// `warm_domains` and `response_cache.ttl`, or `DNS_CACHE`, `DNS_CACHE_CAPACITY`,
+    /// `DNS_CACHE_NEGATIVE_TTL`, `DNS_CACHE_STALE_WINDOW`, `DNS_CACHE_WARM_DOMAINS` and
+    /// `RESPONSE_CACHE_TTL`.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let store = match config.get("dns_cache.store") {
+            Some(cache) => CacheStore::parse(cache)?,
+            None => CacheStore::Memory,
+        };
+
+        let capacity = config
+            .parse("dns_cache.capacity", "a number of domains", |capacity| {
+                capacity.parse().ok()
+            })?
+            .unwrap_or(DEFAULT_CACHE_CAPACITY);
+
+        let negative_ttl =
+            seconds(config, "dns_cache.negative_ttl")?.unwrap_or(DEFAULT_NEGATIVE_TTL);
+        let stale_window = seconds(config, "dns_cache.stale_window")?.unwrap_or(Duration::ZERO);
+
+        let warm_domains = match config.get("dns_cache.warm_domains") {
+            Some(domains) => domains
+                .split(',')
+                .map(str::trim)
+                .filter(|domain| !domain.is_empty())
+                .map(|domain| {
+                    spf_checker::domain_to_ascii(domain).with_context(|| {
+                        format!("DNS_CACHE_WARM_DOMAINS (`dns_cache.warm_domains`) lists the invalid domain `{domain}`")
+                    })
+                })
+                .collect::<Result<_>>()?,
+            None => Vec::new(),
+        };
+
+        let response_ttl = seconds(config, "response_cache.ttl")?.unwrap_or(Duration::ZERO);
+
+        Ok(Self {
+            store,
+            capacity,
+            negative_ttl,
+            stale_window,
+            warm_domains,
+            response_ttl,
+        })
+    }
+
+    /// Returns the cache, which connects to Redis on its first lookup.
+    pub fn open(self) -> DnsCache {
+        let cache = match self.store {
+            CacheStore::Memory => DnsCache::new(self.capacity),
+            CacheStore::Redis(config) => DnsCache::with_store(RedisCache::new(config)),
+        };
+
+        cache
+            .with_negative_ttl(self.negative_ttl)
+            .with_stale_window(self.stale_window)
+    }
+}
+
+/// The setting `key` as a number of seconds.
+fn seconds(config: &Config, key: &str) -> Result<Option<Duration>> {
+    config.parse(key, "a number of seconds", |seconds| {
+        seconds.parse().map(Duration::from_secs).ok()
+    })
+}
+
+/// Looks up the SPF trees of `domains` into `cache` in the background and looks them up again
+/// shortly before their records expire, so that checks including them, e.g. of the include
+/// hubs of large providers, are always answered from the cache. `checker` must not answer from
+/// `cache` itself.
+pub fn warm(checker: &SpfChecker, cache: &DnsCache, domains: Vec<String>) {
+    for domain in domains {
+        let checker = checker.clone();
+        let cache = cache.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let delay = match checker.refresh_dns_cache(&cache, &domain).await {
+                    Ok(Some(ttl)) => refresh_delay(ttl),
+                    Ok(None) => {
+                        log_message(format!(
+                            "Warning: The SPF tree of \"{domain}\" has no records to cache"
+                        ));
+                        RETRY_INTERVAL
+                    }
+                    Err(err) => {
+                        log_message(format!(
+                            "Warning: Failed to warm the DNS cache with \"{domain}\": {err:#}"
+                        ));
+                        RETRY_INTERVAL
+                    }
+                };
+
+                tokio::time::sleep(delay).await;
+            }
+        });
+    }
+}
+
+/// Returns when to refresh records whose lowest remaining TTL is `ttl`, leaving a tenth of it
+/// for the lookups.
+fn refresh_delay(ttl: Duration) -> Duration {
+    (ttl * 9 / 10).max(MIN_REFRESH_INTERVAL)
+}
+
+/// Where the DNS cache keeps its records.
+#[derive(Debug, PartialEq, Eq)]
+enum CacheStore {
+    /// In the memory of this instance.
+    Memory,
+    /// In Redis, shared by all instances using the same server and database.
+    Redis(RedisConfig),
+}
+
+impl CacheStore {
+    /// Parses `dns_cache.store`, either `memory` or a `redis://` URL.
+    fn parse(cache: &str) -> Result<Self> {
+        if cache == "memory" {
+            return Ok(Self::Memory);
+        }
+
+        RedisConfig::parse(cache).map(Self::Redis).context(
+            "DNS_CACHE (`dns_cache.store`) must be `memory` or a URL like `redis://host:6379/0`",
+        )
+    }
+}
+
+/// The server and database of a `redis://[[username]:password@]host[:port][/database]` URL.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RedisConfig {
+    address: String,
+    username: Option<String>,
+    password: Option<String>,
+    database: u32,
+}
+
+impl RedisConfig {
+    fn parse(url: &str) -> Result<Self> {
+        let rest = url
+            .strip_prefix("redis://")
+            .with_context(|| format!("unsupported cache URL `{url}`"))?;
+        let (authority, database) = rest.split_once('/').unwrap_or((rest, ""));
+        let (credentials, host) = match authority.rsplit_once('@') {
+            Some((credentials, host)) => (Some(credentials), host),
+            None => (None, authority),
+        };
+        let (username, password) = match credentials.map(|c| c.split_once(':')) {
+            Some(Some((username, password))) => {
+                (Some(username).filter(|u| !u.is_empty()), Some(password))
+            }
+            Some(None) => bail!("the credentials of `{url}` lack the password"),
+            None => (None, None),
+        };
+
+        if host.is_empty() {
+            bail!("`{url}` lacks the host");
+        }
+        let has_port = host
+            .rsplit_once(':')
+            .is_some_and(|(_, port)| !port.contains(']'));
+        let address = if has_port {
+            host.to_owned()
+        } else {
+            format!("{host}:{DEFAULT_REDIS_PORT}")
+        };
+        let database = match database {
+            "" => 0,
+            database => database
+                .parse()
+                .with_context(|| format!("invalid database `{database}`"))?,
+        };
+
+        Ok(Self {
+            address,
+            username: username.map(str::to_owned),
+            password: password.map(str::to_owned),
+            database,
+        })
+    }
+}
+
+/// The records of a domain as stored in Redis, with the time they were cached at and how long
+/// they are fresh, so that their age can be answered without a second command.
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredRecords {
+    /// Milliseconds since the Unix epoch.
+    cached_at: u64,
+    /// Milliseconds.
+    ttl: u64,
+    records: Vec<StoredRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredRecord {
+    text: String,
+    ttl: u32,
+}
+
+/// A reply of Redis in the RESP2 protocol, except arrays, which the commands used do not return.
+#[derive(Debug, PartialEq, Eq)]
+enum Reply {
+    Status(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+}
+
+/// Keeps the records in Redis, which expires them, speaking RESP2. Concurrent commands use
+/// connections of their own, which are kept for later commands unless they failed.
+#[derive(Debug)]
+pub struct RedisCache {
+    config: RedisConfig,
+    /// The connections not used by a command.
+    idle: Mutex<Vec<BufStream<TcpStream>>>,
+}
+
+impl RedisCache {
+    pub fn new(config: RedisConfig) -> Self {
+        Self {
+            config,
+            idle: Mutex::default(),
+        }
+    }
+
+    /// Runs the command `args` over an idle connection, or a new one if there is none. A
+    /// connection is closed if the command fails or takes too long, as a reply may be left
+    /// unread.
+    async fn command(&self, args: &[&[u8]]) -> Result<Reply> {
+        let idle = self.idle.lock().expect("mutex poisoned").pop();
+        let (stream, reply) = tokio::time::timeout(REDIS_TIMEOUT, self.send(idle, args))
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("Redis did not answer in time")))?;
+
+        let mut idle = self.idle.lock().expect("mutex poisoned");
+        if idle.len() < MAX_IDLE_CONNECTIONS {
+            idle.push(stream);
+        }
+
+        Ok(reply)
+    }
+
+    async fn send(
+        &self,
+        stream: Option<BufStream<TcpStream>>,
+        args: &[&[u8]],
+    ) -> Result<(BufStream<TcpStream>, Reply)> {
+        let mut stream = match stream {
+            Some(stream) => stream,
+            None => self.connect().await?,
+        };
+
+        write_command(&mut stream, args).await?;
+        let reply = read_reply(&mut stream).await?;
+
+        Ok((stream, reply))
+    }
+
+    async fn connect(&self) -> Result<BufStream<TcpStream>> {
+        let stream = TcpStream::connect(&self.config.address)
+            .await
+            .with_context(|| format!("connecting to Redis at {}", self.config.address))?;
+        let mut stream = BufStream::new(stream);
+
+        if let Some(password) = &self.config.password {
+            match &self.config.username {
+                Some(username) => {
+                    let args: [&[u8]; 3] = [b"AUTH", username.as_bytes(), password.as_bytes()];
+                    write_command(&mut stream, &args).await?;
+                }
+                None => write_command(&mut stream, &[b"AUTH", password.as_bytes()]).await?,
+            }
+            read_reply(&mut stream).await?;
+        }
+
+        if self.config.database != 0 {
+            let database = self.config.database.to_string();
+            write_command(&mut stream, &[b"SELECT", database.as_bytes()]).await?;
+            read_reply(&mut stream).await?;
+        }
+
+        Ok(stream)
+    }
+}
+
+#[async_trait]
+impl SpfCache for RedisCache {
+    async fn get(&self, domain: &str) -> Result<Option<CachedRecords>> {
+        let key = format!("{KEY_PREFIX}{domain}");
+        let value = match self.command(&[b"GET", key.as_bytes()]).await? {
+            Reply::Bulk(Some(value)) => value,
+            Reply::Bulk(None) => return Ok(None),
+            reply => bail!("unexpected reply to GET: {reply:?}"),
+        };
+
+        let stored: StoredRecords =
+            serde_json::from_slice(&value).context("malformed cached records")?;
+        let records = stored
+            .records
+            .into_iter()
+            .map(|record| TxtRecord {
+                text: record.text,
+                ttl: record.ttl,
+            })
+            .collect();
+
+        Ok(Some(CachedRecords {
+            records,
+            ttl: Duration::from_millis(stored.ttl),
+            age: unix_time().saturating_sub(Duration::from_millis(stored.cached_at)),
+        }))
+    }
+
+    async fn insert(
+        &self,
+        domain: &str,
+        records: &[TxtRecord],
+        ttl: Duration,
+        stale: Duration,
+    ) -> Result<()> {
+        let key = format!("{KEY_PREFIX}{domain}");
+        let stored = StoredRecords {
+            cached_at: unix_time().as_millis() as u64,
+            ttl: ttl.as_millis() as u64,
+            records: records
+                .iter()
+                .map(|record| StoredRecord {
+                    text: record.text.clone(),
+                    ttl: record.ttl,
+                })
+                .collect(),
+        };
+        let value = serde_json::to_vec(&stored)?;
+        let expiry_ms = (ttl + stale).as_millis().to_string();
+
+        let args: [&[u8]; 5] = [b"SET", key.as_bytes(), &value, b"PX", expiry_ms.as_bytes()];
+        self.command(&args).await?;
+
+        Ok(())
+    }
+
+    async fn remove(&self, domain: &str) -> Result<bool> {
+        let key = format!("{KEY_PREFIX}{domain}");
+
+        match self.command(&[b"DEL", key.as_bytes()]).await? {
+            Reply::Integer(removed) => Ok(removed > 0),
+            reply => bail!("unexpected reply to DEL: {reply:?}"),
+        }
+    }
+}
+
+fn unix_time() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Writes the command `args` as an array of bulk strings.
+async fn write_command(stream: &mut (impl AsyncWrite + Unpin), args: &[&[u8]]) -> Result<()> {
+    let mut command = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        command.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        command.extend_from_slice(arg);
+        command.extend_from_slice(b"\r\n");
+    }
+
+    stream.write_all(&command).await?;
+    stream.flush().await?;
+
+    Ok(())
+}
+
+/// Reads a reply, failing with the message of an error reply.
+async fn read_reply(stream: &mut (impl AsyncBufRead + Unpin)) -> Result<Reply> {
+    let mut line = Vec::new();
+    stream.read_until(b'\n', &mut line).await?;
+    let line = line
+        .strip_suffix(b"\r\n")
+        .context("connection closed by Redis")?;
+    let (kind, value) = line.split_first().context("empty reply")?;
+    let value = String::from_utf8_lossy(value);
+
+    match kind {
+        b'+' => Ok(Reply::Status(value.into_owned())),
+        b'-' => bail!("Redis failed: {value}"),
+        b':' => Ok(Reply::Integer(value.parse().context("malformed integer")?)),
+        b'$' => {
+            let Ok(len) = usize::try_from(value.parse::<i64>().context("malformed length")?) else {
+                return Ok(Reply::Bulk(None));
+            };
+
+            let mut data = vec![0; len + 2];
+            stream.read_exact(&mut data).await?;
+            data.truncate(len);
+
+            Ok(Reply::Bulk(Some(data)))
+        }
+        _ => bail!("unsupported reply `{}`", char::from(*kind)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_write_command() {
+        let mut command = Vec::new();
+        write_command(&mut command, &[b"GET", b"spf-check:txt:example.com"])
+            .await
+            .unwrap();
+        assert_eq!(
+            command,
+            b"*2\r\n$3\r\nGET\r\n$25\r\nspf-check:txt:example.com\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_reply() {
+        let mut replies: &[u8] = b"+OK\r\n:42\r\n$5\r\nv=spf\r\n$-1\r\n-WRONGPASS invalid\r\n";
+        assert_eq!(
+            read_reply(&mut replies).await.unwrap(),
+            Reply::Status("OK".to_string())
+        );
+        assert_eq!(read_reply(&mut replies).await.unwrap(), Reply::Integer(42));
+        assert_eq!(
+            read_reply(&mut replies).await.unwrap(),
+            Reply::Bulk(Some(b"v=spf".to_vec()))
+        );
+        assert_eq!(read_reply(&mut replies).await.unwrap(), Reply::Bulk(None));
+        assert_eq!(
+            read_reply(&mut replies).await.unwrap_err().to_string(),
+            "Redis failed: WRONGPASS invalid"
+        );
+        assert!(read_reply(&mut replies).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_redis_cache_reuses_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let accepted = connections.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                accepted.fetch_add(1, Ordering::Relaxed);
+                tokio::spawn(async move {
+                    let mut stream = BufStream::new(stream);
+                    let mut line = String::new();
+                    // Answers each GET, five lines of an array of two bulk strings, with nil.
+                    let mut lines = 0;
+                    while stream.read_line(&mut line).await.unwrap_or(0) > 0 {
+                        lines += 1;
+                        if lines % 5 == 0 {
+                            tokio::time::sleep(Duration::from_millis(20)).await;
+                            stream.write_all(b"$-1\r\n").await.unwrap();
+                            stream.flush().await.unwrap();
+                        }
+                    }
+                });
+            }
+        });
+
+        let cache = RedisCache::new(RedisConfig {
+            address,
+            username: None,
+            password: None,
+            database: 0,
+        });
+
+        assert_eq!(cache.get("example.com").await.unwrap(), None);
+        assert_eq!(cache.get("example.com").await.unwrap(), None);
+        assert_eq!(connections.load(Ordering::Relaxed), 1);
+
+        // Concurrent commands do not wait for each other's connection.
+        let (first, second) = tokio::join!(cache.get("example.com"), cache.get("example.net"));
+        assert_eq!((first.unwrap(), second.unwrap()), (None, None));
+        assert_eq!(connections.load(Ordering::Relaxed), 2);
+        assert_eq!(cache.idle.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_cache_store() {
+        assert_eq!(CacheStore::parse("memory").unwrap(), CacheStore::Memory);
+        assert_eq!(
+            CacheStore::parse("redis://:secret@cache.internal/2").unwrap(),
+            CacheStore::Redis(RedisConfig {
+                address: "cache.internal:6379".to_string(),
+                username: None,
+                password: Some("secret".to_string()),
+                database: 2,
+            })
+        );
+        assert_eq!(
+            CacheStore::parse("redis://[::1]:6380").unwrap(),
+            CacheStore::Redis(RedisConfig {
+                address: "[::1]:6380".to_string(),
+                username: None,
+                password: None,
+                database: 0,
+            })
+        );
+        assert!(CacheStore::parse("rediss://cache.internal").is_err());
+        assert!(CacheStore::parse("redis://user@cache.internal").is_err());
+        assert!(CacheStore::parse("redis://cache.internal/db").is_err());
+    }
+
+    #[test]
+    fn test_refresh_delay() {
+        assert_eq!(
+            refresh_delay(Duration::from_secs(3600)),
+            Duration::from_secs(3240)
+        );
+        assert_eq!(refresh_delay(Duration::from_secs(5)), MIN_REFRESH_INTERVAL);
+    }
+}
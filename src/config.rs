@@ -0,0 +1,324 @@
+use crate::Result;
+use anyhow::{bail, Context};
+use std::collections::HashMap;
+use std::path::Path;
+use toml_edit::{DocumentMut, Item, Value};
+
+/// The settings of the config file as `section.key`, each with the environment variable
+/// overriding it.
+const SETTINGS: &[(&str, &str)] = &[
+    ("listen.http", "SPF_CHECK_LISTEN"),
+    ("listen.grpc", "SPF_CHECK_GRPC_LISTEN"),
+    ("resolver.timeout", "DNS_TIMEOUT"),
+    ("resolver.attempts", "DNS_ATTEMPTS"),
+    ("resolver.ip_strategy", "DNS_IP_STRATEGY"),
+    ("resolver.ndots", "DNS_NDOTS"),
+    ("resolver.use_hosts_file", "DNS_USE_HOSTS_FILE"),
+    ("resolver.nameservers", "DNS_NAMESERVERS"),
+    ("resolver.system_conf", "DNS_SYSTEM_CONF"),
+    ("resolver.upstream", "DNS_UPSTREAM"),
+    ("resolver.tls_name", "DNS_TLS_NAME"),
+    ("history.file", "HISTORY_FILE"),
+    ("history.retention_days", "HISTORY_RETENTION_DAYS"),
+    ("dns_cache.store", "DNS_CACHE"),
+    ("dns_cache.capacity", "DNS_CACHE_CAPACITY"),
+    ("dns_cache.negative_ttl", "DNS_CACHE_NEGATIVE_TTL"),
+    ("dns_cache.stale_window", "DNS_CACHE_STALE_WINDOW"),
+    ("dns_cache.warm_domains", "DNS_CACHE_WARM_DOMAINS"),
+    ("response_cache.ttl", "RESPONSE_CACHE_TTL"),
+    ("features.grpc", "GRPC_ENABLED"),
+    ("features.graphql", "GRAPHQL_ENABLED"),
+    ("features.ui", "UI_ENABLED"),
+    ("admin.token", "ADMIN_TOKEN"),
+];
+
+/// The command line options overriding a setting.
+const OPTIONS: &[(&str, &str)] = &[
+    ("--listen", "listen.http"),
+    ("--grpc-listen", "listen.grpc"),
+];
+
+/// The settings of the service, each taken from the command line, the environment or the
+/// config file, in that order.
+#[derive(Debug, Default)]
+pub struct Config {
+    /// The settings given on the command line.
+    args: HashMap<&'static str, String>,
+    /// The settings of the config file.
+    file: HashMap<&'static str, String>,
+    /// The environment variables, read once so that the settings do not change meanwhile.
+    env: HashMap<String, String>,
+}
+
+impl Config {
+    /// Reads `--config`, `--listen` and `--grpc-listen` of `args`, the arguments without the
+    /// program name, and the config file of `--config` or `SPF_CHECK_CONFIG`.
+    pub fn load(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        Self::from_args(args, std::env::vars().collect())
+    }
+
+    fn from_args(
+        args: impl IntoIterator<Item = String>,
+        env: HashMap<String, String>,
+    ) -> Result<Self> {
+        let mut path = None;
+        let mut settings = HashMap::new();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+
+            let setting = match OPTIONS.iter().find(|(option, _)| *option == name) {
+                Some((_, setting)) => Some(*setting),
+                None if name == "--config" => None,
+                None => {
+                    bail!("Unknown argument {name}, expected --config, --listen or --grpc-listen")
+                }
+            };
+            let value = value
+                .or_else(|| args.next())
+                .with_context(|| format!("{name} requires a value"))?;
+            match setting {
+                Some(setting) => settings.insert(setting, value),
+                None => path.replace(value),
+            };
+        }
+
+        let file = match path.or_else(|| env.get("SPF_CHECK_CONFIG").cloned()) {
+            Some(path) => read_file(Path::new(&path))?,
+            None => HashMap::new(),
+        };
+
+        Ok(Self {
+            args: settings,
+            file,
+            env,
+        })
+    }
+
+    /// The settings of the environment variables `env` only.
+    #[cfg(test)]
+    pub fn from_env(env: &[(&str, &str)]) -> Self {
+        let env = env
+            .iter()
+            .map(|(var, value)| (var.to_string(), value.to_string()))
+            .collect();
+
+        Self {
+            env,
+            ..Self::default()
+        }
+    }
+
+    /// The value of the setting `key`, `None` if it is not set.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let (_, var) = setting(key);
+
+        self.args
+            .get(key)
+            .or_else(|| self.env.get(*var))
+            .or_else(|| self.file.get(key))
+            .map(String::as_str)
+    }
+
+    /// The value of the setting `key` parsed by `parse`, `None` if it is not set. Fails with
+    /// "`<key>` must be `<expected>`" naming the setting and its variable if it does not parse.
+    pub fn parse<T>(
+        &self,
+        key: &str,
+        expected: &str,
+        parse: impl FnOnce(&str) -> Option<T>,
+    ) -> Result<Option<T>> {
+        let Some(value) = self.get(key) else {
+            return Ok(None);
+        };
+
+        let (_, var) = setting(key);
+        parse(value)
+            .with_context(|| format!("{var} (`{key}`) must be {expected}"))
+            .map(Some)
+    }
+
+    /// The value of the boolean setting `key`, `default` if it is not set.
+    pub fn flag(&self, key: &str, default: bool) -> Result<bool> {
+        let flag = self.parse(key, "`true` or `false`", |value| value.parse().ok())?;
+
+        Ok(flag.unwrap_or(default))
+    }
+}
+
+/// The optional interfaces of the service, all enabled unless disabled in `[features]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Features {
+    /// The gRPC server.
+    pub grpc: bool,
+    /// `/graphql`.
+    pub graphql: bool,
+    /// The web UI at `/` and `/ui`.
+    pub ui: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self {
+            grpc: true,
+            graphql: true,
+            ui: true,
+        }
+    }
+}
+
+impl Features {
+    /// Reads `features.grpc`, `features.graphql` and `features.ui`, or `GRPC_ENABLED`,
+    /// `GRAPHQL_ENABLED` and `UI_ENABLED`.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Ok(Self {
+            grpc: config.flag("features.grpc", true)?,
+            graphql: config.flag("features.graphql", true)?,
+            ui: config.flag("features.ui", true)?,
+        })
+    }
+}
+
+/// The setting `key` with its environment variable.
+fn setting(key: &str) -> &'static (&'static str, &'static str) {
+    SETTINGS
+        .iter()
+        .find(|(setting, _)| *setting == key)
+        .unwrap_or_else(|| panic!("unknown setting {key}"))
+}
+
+fn read_file(path: &Path) -> Result<HashMap<&'static str, String>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read the config file {}", path.display()))?;
+
+    parse_file(&content).with_context(|| format!("Invalid config file {}", path.display()))
+}
+
+/// Reads the settings of a TOML config file. Numbers and booleans are read as their text and
+/// arrays as their elements separated by commas, like the environment variables. Unknown
+/// sections and settings are rejected, so that misspelled ones do not go unnoticed.
+fn parse_file(content: &str) -> Result<HashMap<&'static str, String>> {
+    let document: DocumentMut = content.parse()?;
+
+    let mut settings = HashMap::new();
+    for (section, item) in document.iter() {
+        let Some(table) = item.as_table_like() else {
+            bail!("`{section}` must be a section like `[{section}]`");
+        };
+
+        for (name, item) in table.iter() {
+            let key = format!("{section}.{name}");
+            let Some((key, _)) = SETTINGS.iter().find(|(setting, _)| *setting == key) else {
+                bail!("Unknown setting `{key}`");
+            };
+            let value = match item {
+                Item::Value(value) => text(value),
+                _ => None,
+            };
+            let value = value.with_context(|| {
+                format!("`{key}` must be a string, number, boolean or array of strings")
+            })?;
+
+            settings.insert(*key, value);
+        }
+    }
+
+    Ok(settings)
+}
+
+/// The text of a setting as it would be given in its environment variable.
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(string) => Some(string.value().clone()),
+        Value::Integer(integer) => Some(integer.value().to_string()),
+        Value::Float(float) => Some(float.value().to_string()),
+        Value::Boolean(boolean) => Some(boolean.value().to_string()),
+        Value::Array(array) => array
+            .iter()
+            .map(|value| value.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+            .map(|values| values.join(",")),
+        Value::Datetime(_) | Value::InlineTable(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let settings = parse_file(
+            r#"
+            [listen]
+            http = ":8081"
+
+            [dns_cache]
+            capacity = 500
+            warm_domains = ["_spf.google.com", "spf.protection.outlook.com"]
+
+            [features]
+            graphql = false
+            "#,
+        )
+        .unwrap();
+        assert_eq!(settings["listen.http"], ":8081");
+        assert_eq!(settings["dns_cache.capacity"], "500");
+        assert_eq!(
+            settings["dns_cache.warm_domains"],
+            "_spf.google.com,spf.protection.outlook.com"
+        );
+        assert_eq!(settings["features.graphql"], "false");
+
+        assert!(parse_file("[dns_cache]\ncapacty = 500").is_err());
+        assert!(parse_file("[dns]\ncapacity = 500").is_err());
+        assert!(parse_file("capacity = 500").is_err());
+        assert!(parse_file("[dns_cache]\nwarm_domains = [1, 2]").is_err());
+        assert!(parse_file("[dns_cache\n").is_err());
+    }
+
+    #[test]
+    fn test_config_precedence() {
+        let path = std::env::temp_dir().join(format!("spf-check-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[listen]\nhttp = \":8081\"\ngrpc = \":50052\"\n[resolver]\nattempts = 3\ntimeout = 1\n",
+        )
+        .unwrap();
+        let env = HashMap::from([
+            ("SPF_CHECK_CONFIG".to_string(), path.display().to_string()),
+            ("SPF_CHECK_GRPC_LISTEN".to_string(), ":50053".to_string()),
+            ("DNS_ATTEMPTS".to_string(), "many".to_string()),
+        ]);
+
+        let config = Config::from_args(args(&["--listen", ":8082"]), env.clone()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(config.get("listen.http"), Some(":8082"));
+        assert_eq!(config.get("listen.grpc"), Some(":50053"));
+        assert_eq!(config.get("resolver.timeout"), Some("1"));
+        assert_eq!(config.get("history.file"), None);
+        assert_eq!(
+            config
+                .parse("resolver.attempts", "a number", |value| value
+                    .parse::<usize>()
+                    .ok())
+                .unwrap_err()
+                .to_string(),
+            "DNS_ATTEMPTS (`resolver.attempts`) must be a number"
+        );
+        assert!(config.flag("features.ui", true).unwrap());
+
+        // The config file of the command line takes precedence, here a missing one.
+        assert!(Config::from_args(args(&["--config=/nonexistent.toml"]), env).is_err());
+        assert!(Config::from_args(args(&["--config"]), HashMap::new()).is_err());
+        assert!(Config::from_args(args(&["--port", "8080"]), HashMap::new()).is_err());
+    }
+}
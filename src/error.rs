@@ -0,0 +1,222 @@
+use crate::problem::Problem;
+use crate::{ErrorResponse, MAX_BATCH_SIZE, MAX_JOB_SIZE, MAX_QUEUED_JOBS, MAX_TARGET_DOMAINS};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+
+/// The errors of requests the service rejects or fails itself, e.g. with invalid parameters, as
+/// opposed to the [`spf_checker::CheckError`]s of failed checks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServiceError {
+    /// `mechanisms` does not parse, with the reason.
+    InvalidMechanisms(String),
+    /// Not between 1 and `max` targets were given.
+    InvalidTargetCount { max: usize },
+    /// The BIMI selector is not a valid label.
+    InvalidSelector,
+    /// A WebSocket request does not parse.
+    InvalidRequest,
+    /// A check by IP address was requested without IP address.
+    MissingTargetOrIp,
+    /// A target is checked in more than [`MAX_TARGET_DOMAINS`] domains.
+    TooManyDomains,
+    /// A batch has more than [`MAX_BATCH_SIZE`] checks.
+    BatchTooLarge,
+    /// A job has more than [`MAX_JOB_SIZE`] checks.
+    JobTooLarge,
+    /// [`MAX_QUEUED_JOBS`] jobs are already queued.
+    JobQueueFull,
+    /// No job has the ID, or its results have expired.
+    JobNotFound,
+    /// No schema has the name.
+    SchemaNotFound,
+    /// The DNS cache failed, with the reason.
+    CacheUnavailable(String),
+    /// An admin route was requested without the configured token.
+    Unauthorized,
+    /// A check failed unexpectedly, e.g. by panicking.
+    InternalError,
+}
+
+/// The code and title of the errors of a kind, which do not change between occurrences.
+struct Kind {
+    code: &'static str,
+    title: &'static str,
+}
+
+const INVALID_MECHANISMS: Kind = Kind {
+    code: "INVALID_MECHANISMS",
+    title: "Invalid mechanisms",
+};
+const INVALID_TARGET_COUNT: Kind = Kind {
+    code: "INVALID_TARGET_COUNT",
+    title: "Invalid number of targets",
+};
+const INVALID_SELECTOR: Kind = Kind {
+    code: "INVALID_SELECTOR",
+    title: "Invalid selector",
+};
+const INVALID_REQUEST: Kind = Kind {
+    code: "INVALID_REQUEST",
+    title: "Invalid request",
+};
+const MISSING_TARGET_OR_IP: Kind = Kind {
+    code: "MISSING_TARGET_OR_IP",
+    title: "Missing target or IP address",
+};
+const BATCH_TOO_LARGE: Kind = Kind {
+    code: "BATCH_TOO_LARGE",
+    title: "Batch too large",
+};
+const JOB_TOO_LARGE: Kind = Kind {
+    code: "JOB_TOO_LARGE",
+    title: "Job too large",
+};
+const JOB_QUEUE_FULL: Kind = Kind {
+    code: "JOB_QUEUE_FULL",
+    title: "Job queue full",
+};
+const JOB_NOT_FOUND: Kind = Kind {
+    code: "JOB_NOT_FOUND",
+    title: "Job not found",
+};
+const SCHEMA_NOT_FOUND: Kind = Kind {
+    code: "SCHEMA_NOT_FOUND",
+    title: "Schema not found",
+};
+const CACHE_UNAVAILABLE: Kind = Kind {
+    code: "CACHE_UNAVAILABLE",
+    title: "Cache unavailable",
+};
+const UNAUTHORIZED: Kind = Kind {
+    code: "UNAUTHORIZED",
+    title: "Unauthorized",
+};
+const INTERNAL_ERROR: Kind = Kind {
+    code: "INTERNAL_ERROR",
+    title: "Internal error",
+};
+
+const KINDS: &[Kind] = &[
+    INVALID_MECHANISMS,
+    INVALID_TARGET_COUNT,
+    INVALID_SELECTOR,
+    INVALID_REQUEST,
+    MISSING_TARGET_OR_IP,
+    BATCH_TOO_LARGE,
+    JOB_TOO_LARGE,
+    JOB_QUEUE_FULL,
+    JOB_NOT_FOUND,
+    SCHEMA_NOT_FOUND,
+    CACHE_UNAVAILABLE,
+    UNAUTHORIZED,
+    INTERNAL_ERROR,
+];
+
+impl ServiceError {
+    fn kind(&self) -> Kind {
+        match self {
+            Self::InvalidMechanisms(_) => INVALID_MECHANISMS,
+            Self::InvalidTargetCount { .. } => INVALID_TARGET_COUNT,
+            Self::InvalidSelector => INVALID_SELECTOR,
+            Self::InvalidRequest => INVALID_REQUEST,
+            Self::MissingTargetOrIp => MISSING_TARGET_OR_IP,
+            Self::TooManyDomains | Self::BatchTooLarge => BATCH_TOO_LARGE,
+            Self::JobTooLarge => JOB_TOO_LARGE,
+            Self::JobQueueFull => JOB_QUEUE_FULL,
+            Self::JobNotFound => JOB_NOT_FOUND,
+            Self::SchemaNotFound => SCHEMA_NOT_FOUND,
+            Self::CacheUnavailable(_) => CACHE_UNAVAILABLE,
+            Self::Unauthorized => UNAUTHORIZED,
+            Self::InternalError => INTERNAL_ERROR,
+        }
+    }
+
+    /// The error code, e.g. `BATCH_TOO_LARGE`, which clients can rely on.
+    pub fn code(&self) -> &'static str {
+        self.kind().code
+    }
+
+    /// The title of the problem of the error code `code`, `None` if no service error has it.
+    pub fn title_of(code: &str) -> Option<&'static str> {
+        KINDS
+            .iter()
+            .find(|kind| kind.code == code)
+            .map(|kind| kind.title)
+    }
+
+    /// A description of the error for humans, which unlike the code may change.
+    pub fn message(&self) -> String {
+        match self {
+            Self::InvalidMechanisms(reason) => format!("Invalid `mechanisms`: {reason}"),
+            Self::InvalidTargetCount { max: 1 } => "Exactly one target must be given".to_string(),
+            Self::InvalidTargetCount { max } => {
+                format!("Between 1 and {max} targets must be given")
+            }
+            Self::InvalidSelector => "The BIMI selector is not a valid label".to_string(),
+            Self::InvalidRequest => "The request does not parse".to_string(),
+            Self::MissingTargetOrIp => "Either a target or an IP address must be given".to_string(),
+            Self::TooManyDomains => format!("At most {MAX_TARGET_DOMAINS} domains are accepted"),
+            Self::BatchTooLarge => format!("At most {MAX_BATCH_SIZE} checks are accepted"),
+            Self::JobTooLarge => format!("At most {MAX_JOB_SIZE} checks are accepted"),
+            Self::JobQueueFull => format!("{MAX_QUEUED_JOBS} jobs are already queued, retry later"),
+            Self::JobNotFound => "No job has the ID, or its results have expired".to_string(),
+            Self::SchemaNotFound => "No schema has the name".to_string(),
+            Self::CacheUnavailable(reason) => format!("The cache is unavailable: {reason}"),
+            Self::Unauthorized => {
+                "The admin routes require the configured token as bearer token".to_string()
+            }
+            Self::InternalError => "The request failed unexpectedly".to_string(),
+        }
+    }
+
+    /// The status of responses with the error.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::JobNotFound | Self::SchemaNotFound => StatusCode::NOT_FOUND,
+            Self::JobQueueFull | Self::CacheUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl From<ServiceError> for ErrorResponse {
+    fn from(error: ServiceError) -> Self {
+        Self {
+            error: error.code().to_string(),
+            message: error.message(),
+            spf_records: None,
+            records: None,
+        }
+    }
+}
+
+/// Responds with the error as problem details.
+impl IntoResponse for ServiceError {
+    fn into_response(self) -> Response {
+        Problem(self.status(), self.into()).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_error() {
+        let error = ServiceError::InvalidTargetCount { max: 1 };
+        assert_eq!(error.code(), "INVALID_TARGET_COUNT");
+        assert_eq!(error.message(), "Exactly one target must be given");
+        assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            ServiceError::title_of(error.code()),
+            Some("Invalid number of targets")
+        );
+        assert_eq!(ServiceError::title_of("DNS_LOOKUP_FAILED"), None);
+
+        let response = ErrorResponse::from(ServiceError::TooManyDomains);
+        assert_eq!(response.error, "BATCH_TOO_LARGE");
+        assert_eq!(response.message, "At most 5000 domains are accepted");
+    }
+}
@@ -0,0 +1,218 @@
+use crate::{
+    build_tree, run_check, run_check_dmarc, run_domain_report, DmarcCheckParams,
+    DmarcCheckResponse, DomainReportParams, DomainReportResponse, ErrorResponse, MatchParam,
+    Nameservers, SpfCheckParams, SpfCheckResponse, SpfTreeParams, SpfTreeResponse, Targets,
+    TraversalParams,
+};
+use async_graphql::{
+    EmptyMutation, EmptySubscription, Error, ErrorExtensions, Object, Result, Schema,
+};
+use spf_checker::SpfChecker;
+use std::net::IpAddr;
+
+/// The schema served at `/graphql`.
+pub type SpfCheckSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+pub fn schema(checker: SpfChecker, nameservers: Nameservers) -> SpfCheckSchema {
+    Schema::new(
+        QueryRoot {
+            checker,
+            nameservers,
+        },
+        EmptyMutation,
+        EmptySubscription,
+    )
+}
+
+/// The checks of the HTTP API as GraphQL fields. Only the requested fields are resolved, so a
+/// query for `dmarc` alone does not look up SPF records. Errors are returned with their error
+/// code, e.g. `DNS_LOOKUP_FAILED`, as message.
+pub struct QueryRoot {
+    checker: SpfChecker,
+    nameservers: Nameservers,
+}
+
+#[Object]
+impl QueryRoot {
+    /// Checks if `target` is included in the SPF record chain of `domain`, like
+    /// `GET /api/v1/check-spf`.
+    #[allow(clippy::too_many_arguments)]
+    async fn check_spf(
+        &self,
+        domain: String,
+        target: String,
+        ip: Option<String>,
+        sender: Option<String>,
+        helo: Option<String>,
+        #[graphql(name = "match", default)] match_mode: MatchParam,
+        mechanisms: Option<String>,
+        #[graphql(default)] strict: bool,
+        max_depth: Option<usize>,
+        follow_redirects: Option<bool>,
+        #[graphql(default)] spf_rr: bool,
+        #[graphql(default)] all_matches: bool,
+        #[graphql(default)] dnssec: bool,
+        #[graphql(default)] tree: bool,
+        #[graphql(default)] complexity: bool,
+    ) -> Result<SpfCheckResponse> {
+        let params = SpfCheckParams {
+            domain,
+            target: Targets::One(target.clone()),
+            ip: parse_ip(ip)?,
+            sender,
+            helo,
+            match_mode,
+            mechanisms,
+            strict,
+            max_depth,
+            follow_redirects,
+            spf_rr,
+            all_matches,
+            org_domain: false,
+            dnssec,
+            tree,
+            complexity,
+        };
+
+        run_check(&self.checker, &self.nameservers, &params, &target)
+            .await
+            .map_err(|(_, error)| graphql_error(error))
+    }
+
+    /// The SPF tree of `domain`, like `GET /api/v1/spf-tree`.
+    #[allow(clippy::too_many_arguments)]
+    async fn spf_tree(
+        &self,
+        domain: String,
+        ip: Option<String>,
+        sender: Option<String>,
+        helo: Option<String>,
+        #[graphql(default)] strict: bool,
+        max_depth: Option<usize>,
+        follow_redirects: Option<bool>,
+    ) -> Result<SpfTreeResponse> {
+        let params = SpfTreeParams {
+            domain,
+            traversal: TraversalParams {
+                ip: parse_ip(ip)?,
+                sender,
+                helo,
+                strict: Some(strict),
+                max_depth,
+                follow_redirects,
+            },
+        };
+
+        build_tree(&self.checker, params)
+            .await
+            .map_err(graphql_error)
+    }
+
+    /// The DMARC record of `domain`, like `GET /api/v1/check-dmarc`.
+    async fn dmarc(&self, domain: String) -> Result<DmarcCheckResponse> {
+        run_check_dmarc(&self.checker, DmarcCheckParams { domain })
+            .await
+            .map_err(graphql_error)
+    }
+
+    /// The SPF, DMARC, DKIM, MX and MTA-STS checks of `domain`, like
+    /// `GET /api/v1/domain-report`.
+    async fn domain_report(&self, domain: String) -> DomainReportResponse {
+        run_domain_report(&self.checker, DomainReportParams { domain }).await
+    }
+}
+
+fn parse_ip(ip: Option<String>) -> Result<Option<IpAddr>> {
+    ip.as_deref()
+        .map(str::parse)
+        .transpose()
+        .map_err(|_| Error::new("INVALID_IP"))
+}
+
+/// The error code as message, and the conflicting records of `MULTIPLE_SPF_RECORDS` as
+/// `spfRecords` extension.
+fn graphql_error(error: ErrorResponse) -> Error {
+    let records = error.spf_records;
+
+    Error::new(error.error).extend_with(|_, extensions| {
+        if let Some(records) = records {
+            extensions.set("spfRecords", records);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockResolver;
+    use async_graphql::Value;
+    use std::sync::Arc;
+
+    fn schema_of(resolver: &MockResolver) -> SpfCheckSchema {
+        schema(resolver.checker(), Nameservers(Arc::from([])))
+    }
+
+    #[tokio::test]
+    async fn test_check_spf() {
+        let resolver = MockResolver::default();
+        resolver.add_record("example.com", "v=spf1 include:_spf.example.net -all");
+        resolver.add_record("_spf.example.net", "v=spf1 ip4:192.0.2.0/24 -all");
+
+        let response = schema_of(&resolver)
+            .execute(
+                r#"{ checkSpf(domain: "example.com", target: "_spf.example.net") {
+                    found mechanism includedDomains
+                } }"#,
+            )
+            .await;
+
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let data = response.data.into_json().unwrap();
+        assert_eq!(data["checkSpf"]["found"], true);
+        assert_eq!(data["checkSpf"]["mechanism"], "include:_spf.example.net");
+        assert_eq!(data["checkSpf"]["includedDomains"][0], "_spf.example.net");
+    }
+
+    #[tokio::test]
+    async fn test_errors() {
+        let resolver = MockResolver::default();
+        resolver.add_records(
+            "example.com",
+            &["v=spf1 -all", "v=spf1 include:_spf.example.net -all"],
+        );
+
+        let schema = schema_of(&resolver);
+
+        let response = schema
+            .execute(r#"{ checkSpf(domain: "example.com", target: "_spf.example.net") { found } }"#)
+            .await;
+        let error = &response.errors[0];
+        assert_eq!(error.message, "MULTIPLE_SPF_RECORDS");
+        assert_eq!(
+            error.extensions.as_ref().unwrap().get("spfRecords"),
+            Some(&Value::List(vec![
+                Value::from("v=spf1 -all"),
+                Value::from("v=spf1 include:_spf.example.net -all"),
+            ]))
+        );
+
+        let response = schema
+            .execute(r#"{ spfTree(domain: "example.com", ip: "192.0.2") { domain } }"#)
+            .await;
+        assert_eq!(response.errors[0].message, "INVALID_IP");
+    }
+
+    #[tokio::test]
+    async fn test_dmarc() {
+        let resolver = MockResolver::default();
+        resolver.add_record("_dmarc.example.com", "v=DMARC1; p=reject");
+
+        let response = schema_of(&resolver)
+            .execute(r#"{ dmarc(domain: "example.com") { record } }"#)
+            .await;
+
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let data = response.data.into_json().unwrap();
+        assert_eq!(data["dmarc"]["record"], "v=DMARC1; p=reject");
+    }
+}
@@ -0,0 +1,371 @@
+use crate::{
+    log_message, run_batch, run_check, run_check_ip, BatchCheckResponse, BatchItemResponse,
+    CheckIpParams, CheckIpResponse, ErrorResponse, MatchParam, Nameservers, SpfCheckParams,
+    SpfCheckResponse, Targets, WarningResponse, MAX_BATCH_SIZE,
+};
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use spf_checker::SpfChecker;
+use tonic::{Request, Response, Status};
+
+mod proto {
+    tonic::include_proto!("spf_check.v1");
+}
+
+pub use proto::spf_check_server::SpfCheckServer;
+
+/// The gRPC service `spf_check.v1.SpfCheck` defined in `proto/spf_check.proto`, answering like
+/// the HTTP handlers. Errors are returned with their error code, e.g. `DNS_LOOKUP_FAILED`, as
+/// message: `INVALID_ARGUMENT` where the HTTP API answers `400 Bad Request`, else `NOT_FOUND`.
+pub struct SpfCheckService {
+    checker: SpfChecker,
+    nameservers: Nameservers,
+}
+
+impl SpfCheckService {
+    pub fn new(checker: SpfChecker, nameservers: Nameservers) -> Self {
+        Self {
+            checker,
+            nameservers,
+        }
+    }
+}
+
+#[async_trait]
+impl proto::spf_check_server::SpfCheck for SpfCheckService {
+    async fn check_spf(
+        &self,
+        request: Request<proto::CheckSpfRequest>,
+    ) -> Result<Response<proto::CheckSpfResponse>, Status> {
+        let request = request.into_inner();
+        let target = request.target.clone();
+        let params = SpfCheckParams::try_from(request)?;
+
+        let response = run_check(&self.checker, &self.nameservers, &params, &target)
+            .await
+            .map_err(|(status, error)| error_status(status, error))?;
+
+        Ok(Response::new(response.into()))
+    }
+
+    async fn check_ip(
+        &self,
+        request: Request<proto::CheckIpRequest>,
+    ) -> Result<Response<proto::CheckIpResponse>, Status> {
+        let request = request.into_inner();
+
+        let params = CheckIpParams {
+            domain: request.domain,
+            ip: request
+                .ip
+                .parse()
+                .map_err(|_| Status::invalid_argument("INVALID_IP"))?,
+        };
+
+        let response = run_check_ip(&self.checker, params)
+            .await
+            .map_err(|error| error_status(StatusCode::NOT_FOUND, error))?;
+
+        Ok(Response::new(response.into()))
+    }
+
+    async fn batch_check(
+        &self,
+        request: Request<proto::BatchCheckRequest>,
+    ) -> Result<Response<proto::BatchCheckResponse>, Status> {
+        let start = std::time::Instant::now();
+        let checks = request.into_inner().checks;
+
+        if checks.len() > MAX_BATCH_SIZE {
+            log_message(format!(
+                "Rejected gRPC batch of {} checks, at most {MAX_BATCH_SIZE} are allowed",
+                checks.len()
+            ));
+
+            return Err(Status::invalid_argument("BATCH_TOO_LARGE"));
+        }
+
+        let items = checks
+            .into_iter()
+            .map(SpfCheckParams::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let results = run_batch(&self.checker, &self.nameservers, items).await;
+        let response = BatchCheckResponse::new(results, start.elapsed().as_millis() as u64);
+
+        log_message(format!(
+            "Checked gRPC batch of {} ({} found, {} failed) ({}ms)",
+            response.total, response.found, response.failed, response.elapsed_ms
+        ));
+
+        Ok(Response::new(response.into()))
+    }
+}
+
+fn error_status(status: StatusCode, error: ErrorResponse) -> Status {
+    match status {
+        StatusCode::BAD_REQUEST => Status::invalid_argument(error.error),
+        _ => Status::not_found(error.error),
+    }
+}
+
+impl TryFrom<proto::CheckSpfRequest> for SpfCheckParams {
+    type Error = Status;
+
+    fn try_from(request: proto::CheckSpfRequest) -> Result<Self, Status> {
+        let ip = request
+            .ip
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(|_| Status::invalid_argument("INVALID_IP"))?;
+
+        let match_mode = match request.match_mode() {
+            proto::MatchMode::Exact => MatchParam::Exact,
+            proto::MatchMode::Subdomain => MatchParam::Subdomain,
+        };
+
+        Ok(Self {
+            domain: request.domain,
+            target: Targets::One(request.target),
+            ip,
+            sender: request.sender,
+            helo: request.helo,
+            match_mode,
+            mechanisms: request.mechanisms,
+            strict: request.strict,
+            max_depth: request.max_depth.map(|depth| depth as usize),
+            follow_redirects: request.follow_redirects,
+            spf_rr: request.spf_rr,
+            all_matches: false,
+            org_domain: false,
+            dnssec: false,
+            tree: false,
+            complexity: false,
+        })
+    }
+}
+
+impl From<SpfCheckResponse> for proto::CheckSpfResponse {
+    fn from(response: SpfCheckResponse) -> Self {
+        Self {
+            found: response.found,
+            checked_domains: response.checked_domains as u64,
+            domain: response.domain,
+            target: response.target,
+            has_spf_record: response.has_spf_record,
+            spf_record: response.spf_record,
+            included_domains: response.included_domains.unwrap_or_default(),
+            fallback_check: response.fallback_check,
+            mechanism: response.mechanism,
+            mechanism_domain: response.mechanism_domain,
+            qualifier: response.qualifier,
+            default_policy: response.default_policy,
+            dns_mechanism_count: response.dns_mechanism_count as u64,
+            void_lookups: response.void_lookups as u64,
+            path: response.path.unwrap_or_default(),
+            warnings: response
+                .warnings
+                .into_iter()
+                .map(proto::Warning::from)
+                .collect(),
+            elapsed_ms: response.elapsed_ms,
+        }
+    }
+}
+
+impl From<WarningResponse> for proto::Warning {
+    fn from(warning: WarningResponse) -> Self {
+        Self {
+            code: warning.code,
+            domain: warning.domain,
+            message: warning.message,
+        }
+    }
+}
+
+impl From<CheckIpResponse> for proto::CheckIpResponse {
+    fn from(response: CheckIpResponse) -> Self {
+        Self {
+            domain: response.domain,
+            ip: response.ip.to_string(),
+            authorized: response.authorized,
+            result: response.result,
+            mechanism: response.mechanism,
+            mechanism_domain: response.mechanism_domain,
+            dns_lookups: response.dns_lookups as u64,
+            void_lookups: response.void_lookups as u64,
+            error: response.error,
+            elapsed_ms: response.elapsed_ms,
+        }
+    }
+}
+
+impl From<BatchCheckResponse> for proto::BatchCheckResponse {
+    fn from(response: BatchCheckResponse) -> Self {
+        Self {
+            total: response.total as u64,
+            found: response.found as u64,
+            failed: response.failed as u64,
+            elapsed_ms: response.elapsed_ms,
+            results: response
+                .results
+                .into_iter()
+                .map(proto::BatchCheckResult::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<BatchItemResponse> for proto::BatchCheckResult {
+    fn from(result: BatchItemResponse) -> Self {
+        let outcome = match result {
+            BatchItemResponse::Checked(response) => {
+                proto::batch_check_result::Outcome::Response((*response).into())
+            }
+            BatchItemResponse::Failed {
+                domain,
+                target,
+                error,
+            } => proto::batch_check_result::Outcome::Error(proto::CheckError {
+                domain,
+                target,
+                error: error.error,
+                spf_records: error.spf_records.unwrap_or_default(),
+            }),
+        };
+
+        Self {
+            outcome: Some(outcome),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockResolver;
+    use proto::spf_check_server::SpfCheck;
+    use std::sync::Arc;
+    use tonic::Code;
+
+    fn service(resolver: &MockResolver) -> SpfCheckService {
+        SpfCheckService::new(resolver.checker(), Nameservers(Arc::from([])))
+    }
+
+    fn check_spf_request(domain: &str, target: &str) -> proto::CheckSpfRequest {
+        proto::CheckSpfRequest {
+            domain: domain.to_string(),
+            target: target.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_spf() {
+        let resolver = MockResolver::default();
+        resolver.add_record("example.com", "v=spf1 include:_spf.example.net -all");
+        resolver.add_record("_spf.example.net", "v=spf1 ip4:192.0.2.0/24 -all");
+        let service = service(&resolver);
+
+        let response = service
+            .check_spf(Request::new(check_spf_request(
+                "example.com",
+                "_spf.example.net",
+            )))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(response.found);
+        assert_eq!(
+            response.mechanism.as_deref(),
+            Some("include:_spf.example.net")
+        );
+        assert_eq!(response.included_domains, ["_spf.example.net"]);
+    }
+
+    #[tokio::test]
+    async fn test_status_of_errors() {
+        let resolver = MockResolver::default();
+        resolver.add_records(
+            "example.com",
+            &["v=spf1 -all", "v=spf1 include:_spf.example.net -all"],
+        );
+        resolver.fail_lookups("broken.example.com");
+        let service = service(&resolver);
+
+        // Rejected parameters are invalid arguments, like a `400 Bad Request`.
+        let request = proto::CheckSpfRequest {
+            ip: Some("192.0.2".to_string()),
+            ..check_spf_request("example.com", "_spf.example.net")
+        };
+        let status = service.check_spf(Request::new(request)).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "INVALID_IP");
+
+        let request = proto::CheckSpfRequest {
+            mechanisms: Some("include,bogus".to_string()),
+            ..check_spf_request("example.com", "_spf.example.net")
+        };
+        let status = service.check_spf(Request::new(request)).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "INVALID_MECHANISMS");
+
+        // Failed checks are not found, like a `404 Not Found`, with their error code.
+        for (domain, code) in [
+            ("example.com", "MULTIPLE_SPF_RECORDS"),
+            ("broken.example.com", "DNS_LOOKUP_FAILED"),
+        ] {
+            let request = check_spf_request(domain, "_spf.example.net");
+            let status = service.check_spf(Request::new(request)).await.unwrap_err();
+            assert_eq!(status.code(), Code::NotFound);
+            assert_eq!(status.message(), code);
+        }
+
+        let request = proto::CheckIpRequest {
+            domain: "example.com".to_string(),
+            ip: "not an ip".to_string(),
+        };
+        let status = service.check_ip(Request::new(request)).await.unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "INVALID_IP");
+    }
+
+    #[tokio::test]
+    async fn test_batch_check() {
+        let resolver = MockResolver::default();
+        resolver.add_record("example.com", "v=spf1 include:_spf.example.net -all");
+        resolver.fail_lookups("broken.example.com");
+        let service = service(&resolver);
+
+        let request = proto::BatchCheckRequest {
+            checks: vec![
+                check_spf_request("example.com", "_spf.example.net"),
+                check_spf_request("broken.example.com", "_spf.example.net"),
+            ],
+        };
+        let response = service
+            .batch_check(Request::new(request))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!((response.total, response.found, response.failed), (2, 1, 1));
+        match &response.results[1].outcome {
+            Some(proto::batch_check_result::Outcome::Error(error)) => {
+                assert_eq!(error.domain, "broken.example.com");
+                assert_eq!(error.error, "DNS_LOOKUP_FAILED");
+            }
+            outcome => panic!("Expected an error, got {outcome:?}"),
+        }
+
+        let request = proto::BatchCheckRequest {
+            checks: vec![check_spf_request("example.com", "_spf.example.net"); MAX_BATCH_SIZE + 1],
+        };
+        let status = service
+            .batch_check(Request::new(request))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "BATCH_TOO_LARGE");
+    }
+}
@@ -0,0 +1,469 @@
+use crate::config::Config;
+use crate::{log_message, ErrorResponse, Result, SpfCheckResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+
+/// How long results are kept if `history.retention_days` is not set.
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+/// Maximum number of results kept, the oldest are dropped first.
+const MAX_HISTORY_ENTRIES: usize = 100_000;
+/// Number of results appended to the history file before it is rewritten without the expired
+/// ones.
+const COMPACTION_INTERVAL: usize = 10_000;
+
+/// Where and for how long past results are stored, see [`Config`].
+#[derive(Debug)]
+pub struct HistoryConfig {
+    /// The file the results are appended to as JSON lines, kept in memory only if `None`.
+    path: Option<PathBuf>,
+    retention: chrono::Duration,
+}
+
+impl HistoryConfig {
+    /// Reads `history.file` and `history.retention_days`, or `HISTORY_FILE` and
+    /// `HISTORY_RETENTION_DAYS`.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let path = config.get("history.file").map(PathBuf::from);
+
+        let days = config
+            .parse(
+                "history.retention_days",
+                "a positive number of days",
+                |days| days.parse::<i64>().ok().filter(|days| *days > 0),
+            )?
+            .unwrap_or(DEFAULT_RETENTION_DAYS);
+
+        Ok(Self {
+            path,
+            retention: chrono::Duration::days(days),
+        })
+    }
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            retention: chrono::Duration::days(DEFAULT_RETENTION_DAYS),
+        }
+    }
+}
+
+/// A past result of `GET /api/v1/check-spf`.
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct HistoryEntry {
+    #[schema(value_type = String, format = DateTime)]
+    checked_at: DateTime<Utc>,
+    /// The normalized domain and target, see `domain_ascii` and `target_ascii` of the check.
+    domain: String,
+    target: String,
+    found: bool,
+    /// The error of a failed check, e.g. `DNS_LOOKUP_FAILED`.
+    error: Option<String>,
+    mechanism: Option<String>,
+    spf_record: Option<String>,
+    included_domains: Option<Vec<String>>,
+}
+
+impl HistoryEntry {
+    pub fn checked(response: &SpfCheckResponse) -> Self {
+        Self {
+            checked_at: Utc::now(),
+            domain: history_key(&response.domain),
+            target: history_key(&response.target),
+            found: response.found,
+            error: None,
+            mechanism: response.mechanism.clone(),
+            spf_record: response.spf_record.clone(),
+            included_domains: response.included_domains.clone(),
+        }
+    }
+
+    pub fn failed(domain: &str, target: &str, error: &ErrorResponse) -> Self {
+        Self {
+            checked_at: Utc::now(),
+            domain: history_key(domain),
+            target: history_key(target),
+            found: false,
+            error: Some(error.error.clone()),
+            mechanism: None,
+            spf_record: None,
+            included_domains: None,
+        }
+    }
+
+    fn is_expired(&self, retention: chrono::Duration) -> bool {
+        Utc::now() - self.checked_at > retention
+    }
+}
+
+/// The normalized form under which results are stored and looked up, so that e.g.
+/// `Example.com.` and `example.com` share one history.
+pub fn history_key(name: &str) -> String {
+    spf_checker::domain_to_ascii(name).unwrap_or_else(|_| name.trim().to_lowercase())
+}
+
+/// The stored results by domain and target, so that looking up the results of a check does not
+/// scan those of all others, along with the key of each result in chronological order.
+#[derive(Debug, Default)]
+struct Entries {
+    results: HashMap<(String, String), VecDeque<HistoryEntry>>,
+    order: VecDeque<(String, String)>,
+}
+
+impl Entries {
+    fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    fn oldest(&self) -> Option<&HistoryEntry> {
+        let key = self.order.front()?;
+        self.results.get(key)?.front()
+    }
+
+    fn push(&mut self, entry: HistoryEntry) {
+        let key = (entry.domain.clone(), entry.target.clone());
+        self.order.push_back(key.clone());
+        self.results.entry(key).or_default().push_back(entry);
+    }
+
+    fn pop_oldest(&mut self) {
+        let Some(key) = self.order.pop_front() else {
+            return;
+        };
+
+        if let Some(results) = self.results.get_mut(&key) {
+            results.pop_front();
+
+            if results.is_empty() {
+                self.results.remove(&key);
+            }
+        }
+    }
+
+    /// The oldest `count` results in chronological order.
+    fn oldest_n(&self, count: usize) -> VecDeque<HistoryEntry> {
+        let mut taken = HashMap::<&(String, String), usize>::new();
+
+        self.order
+            .iter()
+            .take(count)
+            .map(|key| {
+                let index = taken.entry(key).or_default();
+                let entry = self.results[key][*index].clone();
+                *index += 1;
+                entry
+            })
+            .collect()
+    }
+}
+
+/// The results of past checks, kept for the configured retention and appended to the history
+/// file by a writer task, so that they survive restarts.
+#[derive(Clone, Debug)]
+pub struct History {
+    entries: Arc<Mutex<Entries>>,
+    retention: chrono::Duration,
+    writer: Option<mpsc::UnboundedSender<HistoryEntry>>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(Entries::default())),
+            retention: HistoryConfig::default().retention,
+            writer: None,
+        }
+    }
+}
+
+impl History {
+    /// Loads the unexpired results of the history file and spawns the task appending new ones
+    /// to it. Lines that do not parse are skipped.
+    pub fn open(config: HistoryConfig) -> Result<Self> {
+        Self::spawn(config).map(|(history, _)| history)
+    }
+
+    /// Like [`History::open`], also returning the writer task, which finishes once all clones of
+    /// the history are dropped and the results they stored are appended.
+    fn spawn(config: HistoryConfig) -> Result<(Self, Option<JoinHandle<()>>)> {
+        let Some(path) = config.path else {
+            let history = Self {
+                retention: config.retention,
+                ..Self::default()
+            };
+
+            return Ok((history, None));
+        };
+
+        let mut entries = VecDeque::new();
+
+        match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                for line in content.lines().filter(|line| !line.trim().is_empty()) {
+                    match serde_json::from_str::<HistoryEntry>(line) {
+                        Ok(entry) if !entry.is_expired(config.retention) => {
+                            entries.push_back(entry)
+                        }
+                        Ok(_) => {}
+                        Err(err) => log_message(format!(
+                            "Skipped malformed line of \"{}\": {}",
+                            path.display(),
+                            err
+                        )),
+                    }
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read \"{}\"", path.display()))
+            }
+        }
+
+        while entries.len() > MAX_HISTORY_ENTRIES {
+            entries.pop_front();
+        }
+
+        write_entries(&path, &entries)?;
+
+        log_message(format!(
+            "Loaded {} past results from \"{}\"",
+            entries.len(),
+            path.display()
+        ));
+
+        let mut indexed = Entries::default();
+        entries.into_iter().for_each(|entry| indexed.push(entry));
+
+        let entries = Arc::new(Mutex::new(indexed));
+        let (writer, receiver) = mpsc::unbounded_channel();
+
+        let task = tokio::spawn(append_entries(path, entries.clone(), receiver));
+
+        let history = Self {
+            entries,
+            retention: config.retention,
+            writer: Some(writer),
+        };
+
+        Ok((history, Some(task)))
+    }
+
+    /// Stores `entry`, dropping the results older than the retention.
+    pub fn record(&self, entry: HistoryEntry) {
+        let mut entries = self.entries.lock().unwrap();
+
+        while entries
+            .oldest()
+            .is_some_and(|oldest| oldest.is_expired(self.retention))
+            || entries.len() >= MAX_HISTORY_ENTRIES
+        {
+            entries.pop_oldest();
+        }
+
+        entries.push(entry.clone());
+
+        // Sent while holding the lock, so that the writer receives the entries in the order
+        // they are stored.
+        if let Some(writer) = &self.writer {
+            let _ = writer.send(entry);
+        }
+    }
+
+    /// The unexpired results for `domain` and `target`, oldest first.
+    pub fn results(&self, domain: &str, target: &str) -> Vec<HistoryEntry> {
+        let key = (history_key(domain), history_key(target));
+        let entries = self.entries.lock().unwrap();
+
+        entries
+            .results
+            .get(&key)
+            .into_iter()
+            .flatten()
+            .filter(|entry| !entry.is_expired(self.retention))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Appends each received entry to `path`, rewriting the file from `entries` every
+/// `COMPACTION_INTERVAL` entries so that expired results do not accumulate.
+async fn append_entries(
+    path: PathBuf,
+    entries: Arc<Mutex<Entries>>,
+    mut receiver: mpsc::UnboundedReceiver<HistoryEntry>,
+) {
+    let mut appended = 0;
+
+    while let Some(entry) = receiver.recv().await {
+        if let Err(err) = append_entry(&path, &entry).await {
+            log_message(format!(
+                "Failed to append to \"{}\": {:#}",
+                path.display(),
+                err
+            ));
+        }
+
+        appended += 1;
+
+        if appended >= COMPACTION_INTERVAL {
+            appended = 0;
+
+            // Leaves out the entries still waiting in the channel, which are appended after the
+            // rewrite.
+            let snapshot = {
+                let entries = entries.lock().unwrap();
+                entries.oldest_n(entries.len().saturating_sub(receiver.len()))
+            };
+            let path = path.clone();
+            let compaction = tokio::task::spawn_blocking(move || write_entries(&path, &snapshot));
+
+            if let Ok(Err(err)) = compaction.await {
+                log_message(format!("Failed to compact the history: {:#}", err));
+            }
+        }
+    }
+}
+
+async fn append_entry(path: &PathBuf, entry: &HistoryEntry) -> Result<()> {
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(&line).await?;
+    // Unlike a std file, a tokio file may still be writing in the background when dropped.
+    file.flush().await?;
+
+    Ok(())
+}
+
+/// Replaces the content of `path` with `entries`, writing a temporary file first so that a
+/// crash cannot leave it truncated.
+fn write_entries(path: &PathBuf, entries: &VecDeque<HistoryEntry>) -> Result<()> {
+    let temporary = path.with_extension("tmp");
+
+    let mut file = std::io::BufWriter::new(
+        std::fs::File::create(&temporary)
+            .with_context(|| format!("Failed to create \"{}\"", temporary.display()))?,
+    );
+
+    for entry in entries {
+        serde_json::to_writer(&mut file, entry)?;
+        file.write_all(b"\n")?;
+    }
+
+    file.flush()?;
+    drop(file);
+
+    std::fs::rename(&temporary, path)
+        .with_context(|| format!("Failed to replace \"{}\"", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(domain: &str, target: &str, checked_at: DateTime<Utc>) -> HistoryEntry {
+        HistoryEntry {
+            checked_at,
+            domain: history_key(domain),
+            target: history_key(target),
+            found: true,
+            error: None,
+            mechanism: Some(format!("include:{target}")),
+            spf_record: None,
+            included_domains: None,
+        }
+    }
+
+    #[test]
+    fn test_results_by_domain_and_target() {
+        let now = Utc::now();
+        let mut entries = Entries::default();
+
+        for (domain, target) in [
+            ("a.example.com", "spf.example.net"),
+            ("b.example.com", "spf.example.net"),
+            ("a.example.com", "mail.example.net"),
+            ("a.example.com", "spf.example.net"),
+        ] {
+            entries.push(entry(domain, target, now));
+        }
+
+        entries.pop_oldest();
+
+        let order: Vec<_> = entries
+            .oldest_n(entries.len())
+            .into_iter()
+            .map(|entry| (entry.domain, entry.target))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                ("b.example.com".to_string(), "spf.example.net".to_string()),
+                ("a.example.com".to_string(), "mail.example.net".to_string()),
+                ("a.example.com".to_string(), "spf.example.net".to_string()),
+            ]
+        );
+
+        let history = History {
+            entries: Arc::new(Mutex::new(entries)),
+            ..History::default()
+        };
+        assert_eq!(
+            history.results("A.example.com.", "spf.example.net").len(),
+            1
+        );
+        assert_eq!(history.results("b.example.com", "spf.example.net").len(), 1);
+        assert!(history
+            .results("b.example.com", "mail.example.net")
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_history_is_reloaded_without_expired_results() {
+        let path =
+            std::env::temp_dir().join(format!("spf-check-history-{}.jsonl", std::process::id()));
+        let retention = chrono::Duration::days(1);
+
+        let expired = entry(
+            "example.com",
+            "spf.example.net",
+            Utc::now() - chrono::Duration::days(2),
+        );
+        write_entries(&path, &VecDeque::from([expired])).unwrap();
+
+        let config = || HistoryConfig {
+            path: Some(path.clone()),
+            retention,
+        };
+
+        let (history, writer) = History::spawn(config()).unwrap();
+        assert!(history.results("example.com", "spf.example.net").is_empty());
+
+        history.record(entry("Example.com.", "spf.example.net", Utc::now()));
+        drop(history);
+        writer.unwrap().await.unwrap();
+
+        let results = History::open(config())
+            .unwrap()
+            .results("EXAMPLE.com", "spf.example.net");
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].domain, "example.com");
+    }
+}
@@ -0,0 +1,73 @@
+use crate::config::Config;
+use crate::Result;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+/// Address of the HTTP server if not configured.
+const DEFAULT_HTTP_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 8080);
+/// Address of the gRPC server if not configured.
+const DEFAULT_GRPC_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 50051);
+
+/// The addresses the servers listen on, see [`Config`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct ListenConfig {
+    pub http: SocketAddr,
+    pub grpc: SocketAddr,
+}
+
+impl ListenConfig {
+    /// Reads `listen.http` and `listen.grpc`, given by `--listen` and `--grpc-listen` or
+    /// `SPF_CHECK_LISTEN` and `SPF_CHECK_GRPC_LISTEN`.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Ok(Self {
+            http: addr(config, "listen.http")?.unwrap_or(DEFAULT_HTTP_ADDR),
+            grpc: addr(config, "listen.grpc")?.unwrap_or(DEFAULT_GRPC_ADDR),
+        })
+    }
+}
+
+fn addr(config: &Config, key: &str) -> Result<Option<SocketAddr>> {
+    config.parse(
+        key,
+        "an address like `127.0.0.1:8080` or `:8080`",
+        parse_addr,
+    )
+}
+
+/// Parses a socket address like `127.0.0.1:8080` or `[::1]:8080`, or only a port like `:8080`
+/// to listen on all IPv4 addresses. Port `0` picks a free port.
+fn parse_addr(addr: &str) -> Option<SocketAddr> {
+    match addr.strip_prefix(':') {
+        Some(port) => Some(SocketAddr::new(
+            Ipv4Addr::UNSPECIFIED.into(),
+            port.parse().ok()?,
+        )),
+        None => addr.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_addr() {
+        assert_eq!(parse_addr(":0"), Some("0.0.0.0:0".parse().unwrap()));
+        assert_eq!(
+            parse_addr("[::1]:50052"),
+            Some("[::1]:50052".parse().unwrap())
+        );
+        assert_eq!(
+            parse_addr("127.0.0.1:8081"),
+            Some("127.0.0.1:8081".parse().unwrap())
+        );
+        assert_eq!(parse_addr("localhost"), None);
+        assert_eq!(parse_addr(":99999"), None);
+    }
+
+    #[test]
+    fn test_listen_config_from_config() {
+        let config = ListenConfig::from_config(&Config::default()).unwrap();
+        assert_eq!(config.http, DEFAULT_HTTP_ADDR);
+        assert_eq!(config.grpc, DEFAULT_GRPC_ADDR);
+    }
+}
@@ -1,139 +1,4606 @@
-use spf_checker::{CheckResult, SpfChecker};
+mod admin;
+mod cache;
+mod config;
+mod error;
+mod graphql;
+mod grpc;
+mod history;
+mod listen;
+mod openapi;
+mod problem;
+mod render;
+mod resolver;
+mod response_cache;
+#[cfg(test)]
+mod test_support;
+mod v2;
+
+use admin::AdminToken;
+use anyhow::Context;
+use async_graphql::{Enum, SimpleObject};
+use async_graphql_axum::GraphQL;
+use axum::response::sse::{Event, KeepAlive, Sse};
 use axum::response::Html;
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
+    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    extract::{FromRef, Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware,
     response::{IntoResponse, Json, Response},
-    routing::get,
+    routing::{get, post},
     Router,
 };
+use axum_extra::extract::Query;
+use cache::CacheConfig;
+use config::{Config, Features};
+use error::ServiceError;
+use history::{History, HistoryConfig, HistoryEntry};
+use listen::ListenConfig;
+use problem::{Problem, ProblemResponse};
+use render::{FormatParams, ResponseFormat};
+use resolver::ResolverSettings;
+use response_cache::ResponseCache;
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+use spf_checker::record::Qualifier;
+use spf_checker::{
+    BimiRecord, BudgetTerm, CheckError, CheckOptions, CheckResult, Complexity, Coverage,
+    DanglingTerm, DmarcRecord, DnsCache, DnsCacheStats, DnssecReport, DomainAuthentication,
+    DomainError, DuplicateMechanism, Evaluation, Explanation, Finding, IpNetwork, LookupBudget,
+    MatchMode, MechanismFilter, MechanismMatch, MtaStsPolicy, MultipleSpfRecords, MxHost,
+    Optimization, ProviderMatch, QueryTiming, RecordSize, RecordSpec, Redirect, Severity,
+    SkippedTerm, SourcedMechanism, SpfChecker, SpfDiff, SpfResult, SpfTreeNode, TraceStep,
+    TxtRecord, Warning, COMMON_DKIM_SELECTORS, DEFAULT_SELECTOR,
+};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::net::TcpListener;
-use trust_dns_resolver::config::{ResolverConfig, ResolverOpts};
-use trust_dns_resolver::TokioAsyncResolver;
+use tokio::sync::{mpsc, Notify, Semaphore};
+use tokio::task::JoinSet;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tonic::transport::server::TcpIncoming;
+use trust_dns_resolver::config::ResolverConfig;
+use utoipa::{IntoParams, ToSchema};
+
+static CARGO_PKG_NAME: &str = env!("CARGO_PKG_NAME");
+static CARGO_PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+type Result<T> = anyhow::Result<T>;
+
+/// The name of the resolver of the checks, configured by [`ResolverSettings`].
+const SYSTEM_RESOLVER: &str = "system";
+
+/// Maximum number of checks accepted by `POST /api/v1/check-spf/batch`.
+const MAX_BATCH_SIZE: usize = 1000;
+/// Number of checks of a batch that run concurrently.
+const BATCH_CONCURRENCY: usize = 16;
+/// Maximum number of messages of a WebSocket connection waiting to be sent. Checks wait for
+/// room before sending their answers, while progress steps are dropped.
+const SOCKET_BUFFER: usize = 64;
+/// Maximum number of targets checked against one domain in a single request.
+const MAX_TARGETS: usize = 100;
+/// Maximum number of domains accepted by `POST /api/v1/check-spf/domains`.
+const MAX_TARGET_DOMAINS: usize = 5000;
+/// Maximum number of checks accepted by `POST /api/v1/jobs`.
+const MAX_JOB_SIZE: usize = 50_000;
+/// Maximum number of jobs waiting for the worker before new ones are rejected.
+const MAX_QUEUED_JOBS: usize = 16;
+/// Maximum number of results returned by one `GET /api/v1/jobs/{id}`.
+const MAX_JOB_PAGE_SIZE: usize = 1000;
+/// How long the results of a completed job are kept.
+const JOB_RETENTION: Duration = Duration::from_secs(60 * 60);
+
+/// Points each check of `GET /api/v1/domain-report` contributes to the score of 100.
+const SPF_WEIGHT: u8 = 30;
+const DMARC_WEIGHT: u8 = 30;
+const DKIM_WEIGHT: u8 = 20;
+const MX_WEIGHT: u8 = 10;
+const MTA_STS_WEIGHT: u8 = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, ToSchema, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SpfCheckParams {
+    /// The domain to check, or an email address like `bounce@mail.example.com` whose domain
+    /// is checked.
+    domain: String,
+    target: Targets,
+    /// Values for macros in include and redirect targets, e.g. `%{i}._spf.example.com`.
+    #[param(value_type = Option<String>)]
+    #[schema(value_type = Option<String>)]
+    ip: Option<IpAddr>,
+    sender: Option<String>,
+    helo: Option<String>,
+    #[serde(rename = "match", default)]
+    match_mode: MatchParam,
+    /// Comma-separated mechanism kinds compared with the target, e.g. `include,a,mx,exists`.
+    mechanisms: Option<String>,
+    /// Rejects syntax violations in any traversed record that are tolerated otherwise.
+    #[serde(default)]
+    strict: bool,
+    /// Levels of includes and redirects traversed below `domain`, unlimited if omitted.
+    max_depth: Option<usize>,
+    /// Whether `redirect` modifiers are followed, `true` if omitted.
+    follow_redirects: Option<bool>,
+    /// Also queries the deprecated `SPF` record type of each traversed domain.
+    #[serde(default)]
+    spf_rr: bool,
+    /// Traverses the whole chain after the first match to report every mechanism matching
+    /// the target in `matches`.
+    #[serde(default)]
+    all_matches: bool,
+    /// Also checks the organizational domain of `domain`, e.g. `example.com` for
+    /// `mail.example.com`.
+    #[serde(default)]
+    org_domain: bool,
+    /// Also validates the TXT records of each traversed domain with DNSSEC.
+    #[serde(default)]
+    dnssec: bool,
+    /// Also returns the include and redirect tree of `domain` with the error of each domain.
+    #[serde(default)]
+    tree: bool,
+    /// Also measures the complexity of the whole include and redirect tree of `domain`.
+    #[serde(default)]
+    complexity: bool,
+}
+
+impl SpfCheckParams {
+    /// The checked domain: the domain of `domain` if it is an email address, else `domain`.
+    fn mail_domain(&self) -> &str {
+        spf_checker::address_domain(&self.domain).unwrap_or(&self.domain)
+    }
+
+    /// `domain` if it is an email address.
+    fn address(&self) -> Option<String> {
+        spf_checker::address_domain(&self.domain).map(|_| self.domain.trim().to_string())
+    }
+
+    /// The options of the check, or a `400 Bad Request` for invalid `mechanisms`.
+    fn check_options(&self) -> std::result::Result<CheckOptions, (StatusCode, ErrorResponse)> {
+        let mechanisms = match self
+            .mechanisms
+            .as_deref()
+            .map(str::parse::<MechanismFilter>)
+            .transpose()
+        {
+            Ok(mechanisms) => mechanisms.unwrap_or_default(),
+            Err(err) => {
+                log_message(format!(
+                    "Rejected mechanisms \"{}\": {err}",
+                    self.mechanisms.as_deref().unwrap_or_default()
+                ));
+
+                let error = ServiceError::InvalidMechanisms(err.to_string());
+                return Err((error.status(), error.into()));
+            }
+        };
+
+        Ok(CheckOptions {
+            ip: self.ip,
+            sender: self.sender.clone().or_else(|| self.address()),
+            helo: self.helo.clone(),
+            match_mode: self.match_mode.into(),
+            mechanisms,
+            strict: self.strict,
+            max_depth: self.max_depth,
+            follow_redirects: self.follow_redirects.unwrap_or(true),
+            lookup_spf_rr: self.spf_rr,
+            all_matches: self.all_matches,
+        })
+    }
+}
+
+/// Body of `POST /api/v1/check-spf/domains`: one target checked against many domains, with
+/// the options of [`SpfCheckParams`].
+#[derive(Debug, Deserialize, ToSchema)]
+struct TargetDomainsParams {
+    target: String,
+    domains: Vec<String>,
+    #[schema(value_type = Option<String>)]
+    ip: Option<IpAddr>,
+    sender: Option<String>,
+    helo: Option<String>,
+    #[serde(rename = "match", default)]
+    match_mode: MatchParam,
+    mechanisms: Option<String>,
+    #[serde(default)]
+    strict: bool,
+    max_depth: Option<usize>,
+    follow_redirects: Option<bool>,
+    #[serde(default)]
+    spf_rr: bool,
+}
+
+impl TargetDomainsParams {
+    fn check_params(&self, domain: &str) -> SpfCheckParams {
+        SpfCheckParams {
+            domain: domain.to_string(),
+            target: Targets::One(self.target.clone()),
+            ip: self.ip,
+            sender: self.sender.clone(),
+            helo: self.helo.clone(),
+            match_mode: self.match_mode,
+            mechanisms: self.mechanisms.clone(),
+            strict: self.strict,
+            max_depth: self.max_depth,
+            follow_redirects: self.follow_redirects,
+            spf_rr: self.spf_rr,
+            all_matches: false,
+            org_domain: false,
+            dnssec: false,
+            tree: false,
+            complexity: false,
+        }
+    }
+}
+
+/// Body of `POST /api/v1/evaluate-record`, checking `target` or evaluating `ip` against `record`
+/// as if `domain` published it.
+#[derive(Debug, Deserialize, ToSchema)]
+struct RecordParams {
+    record: String,
+    domain: String,
+    target: Option<Targets>,
+    #[schema(value_type = Option<String>)]
+    ip: Option<IpAddr>,
+    sender: Option<String>,
+    helo: Option<String>,
+    #[serde(rename = "match", default)]
+    match_mode: MatchParam,
+    mechanisms: Option<String>,
+    #[serde(default)]
+    strict: bool,
+    max_depth: Option<usize>,
+    follow_redirects: Option<bool>,
+    #[serde(default)]
+    spf_rr: bool,
+    /// Whether includes and other terms of `record` are resolved through DNS (default `true`).
+    resolve_dns: Option<bool>,
+}
+
+impl RecordParams {
+    fn check_params(&self, target: Targets) -> SpfCheckParams {
+        SpfCheckParams {
+            domain: self.domain.clone(),
+            target,
+            ip: self.ip,
+            sender: self.sender.clone(),
+            helo: self.helo.clone(),
+            match_mode: self.match_mode,
+            mechanisms: self.mechanisms.clone(),
+            strict: self.strict,
+            max_depth: self.max_depth,
+            follow_redirects: self.follow_redirects,
+            spf_rr: self.spf_rr,
+            all_matches: false,
+            org_domain: false,
+            dnssec: false,
+            tree: false,
+            complexity: false,
+        }
+    }
+}
+
+/// Body of `POST /api/v1/build-spf`, describing the record to build for `domain`.
+#[derive(Debug, Deserialize, ToSchema)]
+struct BuildSpfParams {
+    domain: String,
+    #[serde(default)]
+    includes: Vec<String>,
+    #[serde(default)]
+    ip4: Vec<String>,
+    #[serde(default)]
+    ip6: Vec<String>,
+    #[serde(default)]
+    all: AllParam,
+}
+
+/// Qualifier of the `all` mechanism ending a built record, as symbol or result name.
+#[derive(Debug, Default, Clone, Copy, Deserialize, ToSchema)]
+enum AllParam {
+    #[serde(rename = "+", alias = "pass")]
+    Pass,
+    #[default]
+    #[serde(rename = "-", alias = "fail")]
+    Fail,
+    #[serde(rename = "~", alias = "softfail")]
+    SoftFail,
+    #[serde(rename = "?", alias = "neutral")]
+    Neutral,
+}
+
+impl From<AllParam> for Qualifier {
+    fn from(param: AllParam) -> Self {
+        match param {
+            AllParam::Pass => Qualifier::Pass,
+            AllParam::Fail => Qualifier::Fail,
+            AllParam::SoftFail => Qualifier::SoftFail,
+            AllParam::Neutral => Qualifier::Neutral,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct BuildSpfResponse {
+    domain: String,
+    record: String,
+    dns_lookups: usize,
+    lookup_limit: usize,
+    record_size: RecordSizeResponse,
+    elapsed_ms: u64,
+    warnings: Vec<WarningResponse>,
+    /// The terms of the record and of the included records counting against `lookup_limit`.
+    lookup_terms: Vec<BudgetTermResponse>,
+}
+
+/// Response of `POST /api/v1/check-spf/domains`, listing the results in request order.
+#[derive(Debug, Serialize, ToSchema)]
+struct TargetDomainsResponse {
+    target: String,
+    total: usize,
+    found: usize,
+    failed: usize,
+    elapsed_ms: u64,
+    /// The domains whose SPF tree includes the target.
+    matching_domains: Vec<String>,
+    results: Vec<TargetDomainResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct TargetDomainResponse {
+    domain: String,
+    found: bool,
+    mechanism: Option<String>,
+    mechanism_domain: Option<String>,
+    /// The error code if the domain could not be checked, e.g. `MULTIPLE_SPF_RECORDS`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// A single target, or several given as repeated query parameter or JSON array. Several
+/// targets are checked against the same SPF tree, looking up each record only once.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, ToSchema)]
+#[serde(untagged)]
+enum Targets {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Targets {
+    fn to_vec(&self) -> Vec<String> {
+        match self {
+            Self::One(target) => vec![target.clone()],
+            Self::Many(targets) => targets.clone(),
+        }
+    }
+}
+
+/// How hosts in the SPF chain are compared with the target, see [`MatchMode`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Enum, ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[graphql(name = "MatchMode")]
+enum MatchParam {
+    #[default]
+    Exact,
+    Subdomain,
+}
+
+impl From<MatchParam> for MatchMode {
+    fn from(param: MatchParam) -> Self {
+        match param {
+            MatchParam::Exact => MatchMode::Exact,
+            MatchParam::Subdomain => MatchMode::Subdomain,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, SimpleObject, ToSchema)]
+struct SpfCheckResponse {
+    /// The result receivers give mail from the target, in the terms of RFC 7208: `pass`,
+    /// `fail`, `softfail`, `neutral`, `none`, `temperror` or `permerror`.
+    result: String,
+    found: bool,
+    checked_domains: usize,
+    /// The domains counted in `checked_domains`, in traversal order.
+    visited_domains: Vec<String>,
+    domain: String,
+    /// The email address given as `domain`, whose domain was checked.
+    address: Option<String>,
+    target: String,
+    /// ASCII-compatible (punycode) and Unicode forms of the normalized domain and target.
+    domain_ascii: String,
+    domain_unicode: String,
+    target_ascii: String,
+    target_unicode: String,
+    elapsed_ms: u64,
+    /// How long each DNS query of the check took, in the order they were answered.
+    timings: Vec<QueryTimingResponse>,
+    has_spf_record: bool,
+    spf_record: Option<String>,
+    included_domains: Option<Vec<String>>,
+    /// The `redirect` modifiers followed, in traversal order. Their targets replace the
+    /// evaluation of the redirecting record rather than adding to it, so they are not part of
+    /// `included_domains`.
+    redirect_chain: Vec<RedirectResponse>,
+    fallback_check: bool,
+    mechanism: Option<String>,
+    mechanism_domain: Option<String>,
+    /// Symbol of the matched mechanism's qualifier: `+`, `-`, `~` or `?`.
+    qualifier: Option<String>,
+    /// Symbol of the qualifier of the terminal `all` mechanism.
+    default_policy: Option<String>,
+    /// The terminal `all` mechanism, e.g. `?all`, `null` if the record has none and
+    /// `default_policy` is only implied.
+    all_mechanism: Option<String>,
+    /// The domain of the record declaring the terminal `all` mechanism, following redirects.
+    default_policy_domain: Option<String>,
+    explanation: Option<String>,
+    dns_mechanism_count: usize,
+    /// The DNS-querying terms counted against the limit of 10, same as `dns_mechanism_count`.
+    dns_lookups: usize,
+    /// Whether `dns_lookups` exceeds the limit of 10, which makes receivers fail with
+    /// permerror. The traversal stops there, so `found` may be `false` only because of it.
+    limit_exceeded: bool,
+    void_lookups: usize,
+    /// Include or redirect cycles, e.g. `["example.com", "b.com", "example.com"]`.
+    loops: Vec<Vec<String>>,
+    /// Domains from `domain` to the one declaring `mechanism`, followed by the target.
+    path: Option<Vec<String>>,
+    warnings: Vec<WarningResponse>,
+    /// The included or redirected domains whose record could not be looked up or parsed. Their
+    /// branches are skipped, so the result is partial if any are reported.
+    errors: Vec<DomainErrorResponse>,
+    record_sizes: Vec<RecordSizeResponse>,
+    /// The SPF record of each traversed domain, `null` for those without one.
+    records: BTreeMap<String, Option<String>>,
+    /// The remaining TTL in seconds of the SPF record of each traversed domain with one.
+    ttls: BTreeMap<String, u32>,
+    /// The lowest of `ttls`: how long resolvers may keep answering with a record of the chain
+    /// that has since been changed.
+    min_ttl: Option<u32>,
+    overlapping_mechanisms: Vec<MechanismMatchResponse>,
+    skipped_macro_terms: Vec<SkippedTermResponse>,
+    matches: Vec<MechanismMatchResponse>,
+    /// Mechanisms declared more than once across the traversed records, with the domain of
+    /// each occurrence.
+    duplicates: Vec<DuplicateMechanismResponse>,
+    /// Legacy Sender ID records (`spf2.0/...`) published by `domain`.
+    sender_id_records: Vec<String>,
+    /// The check of the organizational domain, if requested through `org_domain` and it
+    /// differs from `domain`.
+    #[graphql(skip)]
+    #[schema(no_recursion)]
+    organizational_domain: Option<BatchItemResponse>,
+    /// The DNSSEC validation of the traversed domains, if requested through `dnssec`.
+    dnssec: Option<DnssecResponse>,
+    /// The include and redirect tree of `domain`, if requested through `tree`.
+    tree: Option<SpfTreeNodeResponse>,
+    /// The complexity of the include and redirect tree of `domain`, if requested through
+    /// `complexity`.
+    complexity: Option<ComplexityResponse>,
+    /// The context of the request, to tell apart stored or forwarded responses.
+    meta: MetaResponse,
+}
+
+impl SpfCheckResponse {
+    /// Sets `meta.cached` of the check and of the check of its organizational domain.
+    fn mark_cached(&mut self) {
+        self.meta.cached = true;
+        if let Some(BatchItemResponse::Checked(response)) = &mut self.organizational_domain {
+            response.mark_cached();
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, SimpleObject, ToSchema)]
+struct MetaResponse {
+    /// When the check was started, in RFC 3339 format.
+    #[schema(format = DateTime)]
+    checked_at: String,
+    /// The normalized domain and target, same as `domain_ascii` and `target_ascii`.
+    domain: String,
+    target: String,
+    /// The name of the resolver that answered the queries, as listed by
+    /// `GET /api/v1/propagation`.
+    resolver: String,
+    /// The addresses of the name servers of `resolver`.
+    nameservers: Vec<String>,
+    /// Whether the response was served from the cache of `/api/v1/check-spf` responses,
+    /// if enabled, in which case `checked_at` tells its age. Otherwise its records may still
+    /// come from the DNS cache of the service or of the name servers until their TTL runs out,
+    /// see `ttls` and `GET /admin/cache/stats`.
+    cached: bool,
+}
+
+impl MetaResponse {
+    fn new(
+        checked_at: chrono::DateTime<chrono::Utc>,
+        nameservers: &Nameservers,
+        domain: &str,
+        target: &str,
+    ) -> Self {
+        Self {
+            checked_at: checked_at.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            domain: domain.to_string(),
+            target: target.to_string(),
+            resolver: SYSTEM_RESOLVER.to_string(),
+            nameservers: nameservers.0.to_vec(),
+            cached: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, SimpleObject, ToSchema)]
+struct ComplexityResponse {
+    /// Number of mechanisms of the records, counted each time a domain is referenced.
+    mechanisms: usize,
+    /// The deepest nesting level of a record, 0 if `domain` references none.
+    depth: usize,
+    /// Number of DNS-querying terms of the records, limited to 10 by RFC 7208.
+    dns_lookups: usize,
+    /// Number of distinct `ip4` and `ip6` mechanisms of the flattened record.
+    ip_networks: usize,
+    /// Total size in bytes of the records of the distinct domains.
+    record_bytes: usize,
+}
+
+impl From<Complexity> for ComplexityResponse {
+    fn from(complexity: Complexity) -> Self {
+        Self {
+            mechanisms: complexity.mechanisms,
+            depth: complexity.depth,
+            dns_lookups: complexity.dns_lookups,
+            ip_networks: complexity.ip_networks,
+            record_bytes: complexity.record_bytes,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, SimpleObject, ToSchema)]
+struct DnssecResponse {
+    /// Whether the TXT records of every traversed domain are signed and passed validation, so
+    /// that none of the answers of the check could have been spoofed.
+    fully_signed: bool,
+    domains: Vec<DomainAuthenticationResponse>,
+}
+
+impl From<DnssecReport> for DnssecResponse {
+    fn from(report: DnssecReport) -> Self {
+        Self {
+            fully_signed: report.fully_signed,
+            domains: report
+                .domains
+                .into_iter()
+                .map(DomainAuthenticationResponse::from)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, SimpleObject, ToSchema)]
+struct DomainAuthenticationResponse {
+    domain: String,
+    /// Whether the TXT records of the domain are signed and passed DNSSEC validation.
+    authenticated_data: bool,
+}
+
+impl From<DomainAuthentication> for DomainAuthenticationResponse {
+    fn from(domain: DomainAuthentication) -> Self {
+        Self {
+            domain: domain.domain,
+            authenticated_data: domain.authenticated_data,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, SimpleObject, ToSchema)]
+struct RedirectResponse {
+    /// The domain whose record declares the `redirect` modifier.
+    domain: String,
+    /// The domain the evaluation is redirected to.
+    target: String,
+}
+
+impl From<Redirect> for RedirectResponse {
+    fn from(redirect: Redirect) -> Self {
+        Self {
+            domain: redirect.domain,
+            target: redirect.target,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, SimpleObject, ToSchema)]
+struct QueryTimingResponse {
+    /// The queried name, or the address of a `PTR` query.
+    domain: String,
+    /// The queried record type, e.g. `TXT` or `MX`.
+    record_type: String,
+    ms: u64,
+}
+
+impl From<QueryTiming> for QueryTimingResponse {
+    fn from(timing: QueryTiming) -> Self {
+        Self {
+            domain: timing.domain,
+            record_type: timing.record_type.to_string(),
+            ms: timing.duration.as_millis() as u64,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, SimpleObject, ToSchema)]
+struct DomainErrorResponse {
+    domain: String,
+    /// The error code, e.g. `DNS_LOOKUP_FAILED` or `SPF_PARSE_FAILED`.
+    error: String,
+}
+
+impl From<DomainError> for DomainErrorResponse {
+    fn from(error: DomainError) -> Self {
+        Self {
+            domain: error.domain,
+            error: error.error.code().to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, SimpleObject, ToSchema)]
+struct WarningResponse {
+    code: String,
+    domain: String,
+    message: String,
+}
+
+impl From<Warning> for WarningResponse {
+    fn from(warning: Warning) -> Self {
+        Self {
+            code: warning.code.to_string(),
+            domain: warning.domain,
+            message: warning.message,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, SimpleObject, ToSchema)]
+struct RecordSizeResponse {
+    domain: String,
+    record_bytes: usize,
+    answer_bytes: usize,
+}
+
+impl From<RecordSize> for RecordSizeResponse {
+    fn from(size: RecordSize) -> Self {
+        Self {
+            domain: size.domain,
+            record_bytes: size.record_bytes,
+            answer_bytes: size.answer_bytes,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, SimpleObject, ToSchema)]
+struct MechanismMatchResponse {
+    mechanism: String,
+    domain: String,
+}
+
+impl From<MechanismMatch> for MechanismMatchResponse {
+    fn from(matched: MechanismMatch) -> Self {
+        Self {
+            mechanism: matched.mechanism,
+            domain: matched.domain,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, SimpleObject, ToSchema)]
+struct DuplicateMechanismResponse {
+    mechanism: String,
+    domains: Vec<String>,
+}
+
+impl From<DuplicateMechanism> for DuplicateMechanismResponse {
+    fn from(duplicate: DuplicateMechanism) -> Self {
+        Self {
+            mechanism: duplicate.mechanism,
+            domains: duplicate.domains,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, SimpleObject, ToSchema)]
+struct SkippedTermResponse {
+    term: String,
+    domain: String,
+    reason: String,
+}
+
+impl From<SkippedTerm> for SkippedTermResponse {
+    fn from(skipped: SkippedTerm) -> Self {
+        Self {
+            term: skipped.term,
+            domain: skipped.domain,
+            reason: skipped.reason,
+        }
+    }
+}
+
+/// The parameters of the endpoints traversing the SPF tree, flattened into theirs. As utoipa
+/// cannot flatten parameters, the paths list them next to those of the endpoint.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct TraversalParams {
+    /// Values for macros in include and redirect targets, e.g. `%{i}._spf.example.com`.
+    #[param(value_type = Option<String>)]
+    ip: Option<IpAddr>,
+    sender: Option<String>,
+    helo: Option<String>,
+    #[serde(default, deserialize_with = "flattened_param")]
+    strict: Option<bool>,
+    #[serde(default, deserialize_with = "flattened_param")]
+    max_depth: Option<usize>,
+    #[serde(default, deserialize_with = "flattened_param")]
+    follow_redirects: Option<bool>,
+}
+
+impl TraversalParams {
+    fn check_options(&self) -> CheckOptions {
+        CheckOptions {
+            ip: self.ip,
+            sender: self.sender.clone(),
+            helo: self.helo.clone(),
+            strict: self.strict.unwrap_or_default(),
+            max_depth: self.max_depth,
+            follow_redirects: self.follow_redirects.unwrap_or(true),
+            ..CheckOptions::default()
+        }
+    }
+}
+
+/// Parses a parameter of a flattened struct, whose values serde passes on as strings rather
+/// than as the types of the query.
+fn flattened_param<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| value.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SpfTreeParams {
+    domain: String,
+    #[serde(flatten)]
+    #[param(ignore)]
+    traversal: TraversalParams,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SpfGraphParams {
+    domain: String,
+    /// A domain whose nodes and the references leading to them are highlighted.
+    target: Option<String>,
+    #[serde(default)]
+    format: GraphFormat,
+    #[serde(flatten)]
+    #[param(ignore)]
+    traversal: TraversalParams,
+}
+
+/// The language `GET /api/v1/spf-graph` renders the graph in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum GraphFormat {
+    /// Graphviz DOT, e.g. for `dot -Tsvg`.
+    #[default]
+    Dot,
+    /// A Mermaid flowchart, e.g. for Markdown documents.
+    Mermaid,
+}
+
+#[derive(Debug, Serialize, SimpleObject, ToSchema)]
+struct SpfTreeResponse {
+    domain: String,
+    elapsed_ms: u64,
+    /// The well-known email service providers whose records are part of the tree.
+    providers: Vec<ProviderResponse>,
+    tree: SpfTreeNodeResponse,
+}
+
+#[derive(Debug, Serialize, SimpleObject, ToSchema)]
+struct ProviderResponse {
+    name: String,
+    domain: String,
+}
+
+impl From<ProviderMatch> for ProviderResponse {
+    fn from(found: ProviderMatch) -> Self {
+        Self {
+            name: found.provider.name.to_string(),
+            domain: found.domain,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, SimpleObject, ToSchema)]
+struct SpfTreeNodeResponse {
+    domain: String,
+    term: Option<String>,
+    record: Option<String>,
+    #[serde(rename = "loop")]
+    #[graphql(name = "loop")]
+    is_loop: bool,
+    /// Why the record of the domain could not be looked up or parsed, e.g.
+    /// `MULTIPLE_SPF_RECORDS`. Only set in the tree of a check, other trees fail instead.
+    error: Option<String>,
+    #[schema(no_recursion)]
+    children: Vec<SpfTreeNodeResponse>,
+}
+
+impl From<SpfTreeNode> for SpfTreeNodeResponse {
+    fn from(node: SpfTreeNode) -> Self {
+        Self {
+            domain: node.domain,
+            term: node.term,
+            record: node.record,
+            is_loop: node.is_loop,
+            error: node.error,
+            children: node.children.into_iter().map(Self::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct DanglingResponse {
+    domain: String,
+    elapsed_ms: u64,
+    dangling: Vec<DanglingTermResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct DanglingTermResponse {
+    term: String,
+    domain: String,
+    parent_domain: String,
+    parent_record: String,
+    reason: String,
+}
+
+impl From<DanglingTerm> for DanglingTermResponse {
+    fn from(dangling: DanglingTerm) -> Self {
+        Self {
+            term: dangling.term,
+            domain: dangling.domain,
+            parent_domain: dangling.parent_domain,
+            parent_record: dangling.parent_record,
+            reason: dangling.reason.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct OptimizeParams {
+    domain: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct OptimizeResponse {
+    domain: String,
+    record: String,
+    /// `record` with all suggestions applied.
+    optimized_record: String,
+    elapsed_ms: u64,
+    suggestions: Vec<OptimizationResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct OptimizationResponse {
+    operation: String,
+    terms: Vec<String>,
+    replacement: Vec<String>,
+    message: String,
+}
+
+impl From<Optimization> for OptimizationResponse {
+    fn from(optimization: Optimization) -> Self {
+        Self {
+            operation: optimization.operation.to_string(),
+            terms: optimization.terms,
+            replacement: optimization.replacement,
+            message: optimization.message,
+        }
+    }
+}
+
+/// Response of `GET /api/v1/spf-mechanisms`.
+#[derive(Debug, Serialize, ToSchema)]
+struct SpfMechanismsResponse {
+    domain: String,
+    elapsed_ms: u64,
+    /// The mechanisms of each record followed by those of the records it references.
+    mechanisms: Vec<SourcedMechanismResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct SourcedMechanismResponse {
+    /// The mechanism with its qualifier, e.g. `~ip4:192.0.2.0/24`.
+    mechanism: String,
+    /// The domain whose record declares the mechanism.
+    domain: String,
+    /// The nesting level of the record, 0 for `domain` of the request.
+    depth: usize,
+    /// The domains through which the mechanism is reached, from the checked domain on.
+    path: Vec<String>,
+}
+
+impl From<SourcedMechanism> for SourcedMechanismResponse {
+    fn from(mechanism: SourcedMechanism) -> Self {
+        Self {
+            mechanism: mechanism.mechanism,
+            domain: mechanism.domain,
+            depth: mechanism.depth,
+            path: mechanism.path,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DiffSpfParams {
+    domain_a: String,
+    domain_b: String,
+    #[serde(flatten)]
+    #[param(ignore)]
+    traversal: TraversalParams,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct DiffSpfResponse {
+    domain_a: String,
+    domain_b: String,
+    elapsed_ms: u64,
+    mechanisms: DiffSetResponse,
+    networks: DiffSetResponse,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct DiffSetResponse {
+    only_in_a: Vec<String>,
+    only_in_b: Vec<String>,
+    common: Vec<String>,
+}
+
+impl DiffSpfResponse {
+    fn new(domain_a: String, domain_b: String, elapsed_ms: u64, diff: SpfDiff) -> Self {
+        let strings = |networks: Vec<IpNetwork>| networks.iter().map(ToString::to_string).collect();
+
+        Self {
+            domain_a,
+            domain_b,
+            elapsed_ms,
+            mechanisms: DiffSetResponse {
+                only_in_a: diff.mechanisms_only_in_a,
+                only_in_b: diff.mechanisms_only_in_b,
+                common: diff.common_mechanisms,
+            },
+            networks: DiffSetResponse {
+                only_in_a: strings(diff.networks_only_in_a),
+                only_in_b: strings(diff.networks_only_in_b),
+                common: strings(diff.common_networks),
+            },
+        }
+    }
+}
+
+/// Response of `GET /api/v1/spf-coverage`.
+#[derive(Debug, Serialize, ToSchema)]
+struct CoverageResponse {
+    domain_a: String,
+    domain_b: String,
+    elapsed_ms: u64,
+    /// Whether `domain_a` authorizes every address `domain_b` authorizes.
+    covers: bool,
+    /// The networks authorized by `domain_b` but not by `domain_a`.
+    uncovered_networks: Vec<String>,
+    /// The `pass` mechanisms of either tree whose addresses cannot be enumerated, e.g. `ptr`
+    /// or `exists`, which the comparison ignores.
+    skipped_mechanisms: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ValidateParams {
+    domain: String,
+    /// Values for macros in include and redirect targets, e.g. `%{i}._spf.example.com`.
+    #[param(value_type = Option<String>)]
+    ip: Option<IpAddr>,
+    sender: Option<String>,
+    helo: Option<String>,
+    max_depth: Option<usize>,
+    follow_redirects: Option<bool>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct ValidateResponse {
+    domain: String,
+    /// Whether none of the findings is an error.
+    valid: bool,
+    checked_domains: usize,
+    dns_lookups: usize,
+    elapsed_ms: u64,
+    findings: Vec<FindingResponse>,
+}
+
+#[derive(Debug, Serialize, SimpleObject, ToSchema)]
+struct FindingResponse {
+    severity: String,
+    code: String,
+    domain: String,
+    message: String,
+}
+
+impl From<Finding> for FindingResponse {
+    fn from(finding: Finding) -> Self {
+        Self {
+            severity: finding.severity.to_string(),
+            code: finding.code.to_string(),
+            domain: finding.domain,
+            message: finding.message,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct LookupBudgetParams {
+    domain: String,
+    /// Values for macros in include and redirect targets, e.g. `%{i}._spf.example.com`.
+    #[param(value_type = Option<String>)]
+    ip: Option<IpAddr>,
+    sender: Option<String>,
+    helo: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct LookupBudgetResponse {
+    domain: String,
+    total: usize,
+    limit: usize,
+    over_limit: bool,
+    elapsed_ms: u64,
+    /// Number of counted terms per nesting level, starting with the record of `domain`.
+    levels: Vec<LevelResponse>,
+    terms: Vec<BudgetTermResponse>,
+    loops: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct LevelResponse {
+    depth: usize,
+    lookups: usize,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct BudgetTermResponse {
+    term: String,
+    domain: String,
+    depth: usize,
+}
+
+impl From<BudgetTerm> for BudgetTermResponse {
+    fn from(term: BudgetTerm) -> Self {
+        Self {
+            term: term.term,
+            domain: term.domain,
+            depth: term.depth,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DmarcCheckParams {
+    domain: String,
+}
+
+#[derive(Debug, Serialize, SimpleObject, ToSchema)]
+struct DmarcCheckResponse {
+    domain: String,
+    /// The domain queried for the record, e.g. `_dmarc.example.com`.
+    record_domain: String,
+    record: Option<String>,
+    /// Whether a valid record is published and none of the findings is an error.
+    valid: bool,
+    /// The parsed record, `null` if there is no single valid record.
+    policy: Option<DmarcPolicyResponse>,
+    elapsed_ms: u64,
+    findings: Vec<FindingResponse>,
+}
+
+#[derive(Debug, Serialize, SimpleObject, ToSchema)]
+struct DmarcPolicyResponse {
+    /// `p`: `none`, `quarantine` or `reject`, `null` if missing.
+    p: Option<String>,
+    /// `sp`, `null` if missing, in which case `p` applies to subdomains.
+    sp: Option<String>,
+    rua: Vec<String>,
+    ruf: Vec<String>,
+    pct: u8,
+    adkim: String,
+    aspf: String,
+    fo: String,
+    ri: u32,
+    unknown_tags: Vec<String>,
+}
+
+impl From<DmarcRecord> for DmarcPolicyResponse {
+    fn from(record: DmarcRecord) -> Self {
+        Self {
+            p: record.policy.map(|policy| policy.to_string()),
+            sp: record.subdomain_policy.map(|policy| policy.to_string()),
+            rua: record.aggregate_report_uris,
+            ruf: record.failure_report_uris,
+            pct: record.percentage,
+            adkim: record.dkim_alignment.to_string(),
+            aspf: record.spf_alignment.to_string(),
+            fo: record.failure_options,
+            ri: record.report_interval,
+            unknown_tags: record.unknown_tags,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct MxCheckParams {
+    domain: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct MxCheckResponse {
+    domain: String,
+    /// Whether the domain publishes a Null MX (RFC 7505), declaring that it accepts no mail.
+    null_mx: bool,
+    /// Whether none of the findings is an error.
+    valid: bool,
+    elapsed_ms: u64,
+    hosts: Vec<MxHostResponse>,
+    findings: Vec<FindingResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct MxHostResponse {
+    preference: u16,
+    exchange: String,
+    resolves: bool,
+    #[schema(value_type = Vec<String>)]
+    addresses: Vec<IpAddr>,
+}
+
+impl From<MxHost> for MxHostResponse {
+    fn from(host: MxHost) -> Self {
+        Self {
+            preference: host.preference,
+            resolves: host.resolves(),
+            exchange: host.exchange,
+            addresses: host.addresses,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct MtaStsCheckParams {
+    domain: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct MtaStsCheckResponse {
+    domain: String,
+    /// The `v=STSv1` record at `_mta-sts.<domain>`.
+    sts_record: Option<String>,
+    policy_id: Option<String>,
+    policy_url: Option<String>,
+    /// The parsed policy file, `null` if it was not fetched or is invalid.
+    policy: Option<MtaStsPolicyResponse>,
+    /// The `v=TLSRPTv1` record at `_smtp._tls.<domain>`.
+    tls_rpt_record: Option<String>,
+    tls_rpt_uris: Vec<String>,
+    /// Whether none of the findings is an error.
+    valid: bool,
+    elapsed_ms: u64,
+    findings: Vec<FindingResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct MtaStsPolicyResponse {
+    mode: String,
+    mx: Vec<String>,
+    max_age: u64,
+}
+
+impl From<MtaStsPolicy> for MtaStsPolicyResponse {
+    fn from(policy: MtaStsPolicy) -> Self {
+        Self {
+            mode: policy.mode.to_string(),
+            mx: policy.mx,
+            max_age: policy.max_age,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct BimiCheckParams {
+    domain: String,
+    /// The selector of the record, `default` if omitted.
+    selector: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct BimiCheckResponse {
+    domain: String,
+    selector: String,
+    /// The domain queried for the record, e.g. `default._bimi.example.com`.
+    record_domain: String,
+    record: Option<String>,
+    /// Whether a valid record is published and none of the findings is an error.
+    valid: bool,
+    /// The parsed record, `null` if there is no single valid record.
+    bimi: Option<BimiResponse>,
+    elapsed_ms: u64,
+    findings: Vec<FindingResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct BimiResponse {
+    /// `l`: The URL of the SVG logo, empty if declined.
+    logo_url: String,
+    /// `a`: The URL of the Verified Mark Certificate, empty if not given.
+    authority_url: String,
+    declined: bool,
+}
+
+impl From<BimiRecord> for BimiResponse {
+    fn from(record: BimiRecord) -> Self {
+        Self {
+            declined: record.is_declination(),
+            logo_url: record.logo_url,
+            authority_url: record.authority_url,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DomainReportParams {
+    domain: String,
+}
+
+/// Response of `GET /api/v1/domain-report`, summarizing all checks of a domain.
+#[derive(Debug, Serialize, SimpleObject, ToSchema)]
+struct DomainReportResponse {
+    domain: String,
+    /// 0 to 100, the sum of the points of all checks.
+    score: u8,
+    elapsed_ms: u64,
+    spf: ReportCheckResponse,
+    dmarc: ReportCheckResponse,
+    dkim: DkimReportCheckResponse,
+    mx: ReportCheckResponse,
+    mta_sts: ReportCheckResponse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Enum, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum CheckStatus {
+    /// No findings.
+    Pass,
+    /// Only warnings.
+    Warn,
+    /// At least one error.
+    Fail,
+    /// The check could not be completed, e.g. because of a DNS failure.
+    Error,
+}
+
+#[derive(Debug, Serialize, SimpleObject, ToSchema)]
+struct ReportCheckResponse {
+    status: CheckStatus,
+    /// Points contributed to the score: all for `pass`, half for `warn`, none otherwise.
+    points: u8,
+    max_points: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    findings: Vec<FindingResponse>,
+}
+
+impl ReportCheckResponse {
+    fn new(max_points: u8, findings: anyhow::Result<Vec<Finding>>) -> Self {
+        let findings = match findings {
+            Ok(findings) => findings,
+            Err(err) => {
+                return Self {
+                    status: CheckStatus::Error,
+                    points: 0,
+                    max_points,
+                    error: Some(err.to_string()),
+                    findings: Vec::new(),
+                }
+            }
+        };
+
+        let status = if findings
+            .iter()
+            .any(|finding| finding.severity == Severity::Error)
+        {
+            CheckStatus::Fail
+        } else if findings.is_empty() {
+            CheckStatus::Pass
+        } else {
+            CheckStatus::Warn
+        };
+
+        let points = match status {
+            CheckStatus::Pass => max_points,
+            CheckStatus::Warn => max_points / 2,
+            CheckStatus::Fail | CheckStatus::Error => 0,
+        };
+
+        Self {
+            status,
+            points,
+            max_points,
+            error: None,
+            findings: findings.into_iter().map(FindingResponse::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, SimpleObject, ToSchema)]
+struct DkimReportCheckResponse {
+    #[serde(flatten)]
+    #[graphql(flatten)]
+    check: ReportCheckResponse,
+    /// The checked selectors with a published key.
+    selectors: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct TxtParams {
+    domain: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct TxtResponse {
+    domain: String,
+    elapsed_ms: u64,
+    records: Vec<TxtRecordResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct TxtRecordResponse {
+    text: String,
+    ttl: u32,
+    /// Whether the record starts with `v=spf1` and is therefore used as SPF record.
+    spf: bool,
+    /// Whether the record is not used as SPF record, but was likely meant to be one, e.g.
+    /// `v=spf 1 ...`.
+    malformed_spf: bool,
+    /// Whether the record is a legacy Sender ID record, e.g. `spf2.0/pra ...`.
+    sender_id: bool,
+}
+
+impl From<TxtRecord> for TxtRecordResponse {
+    fn from(record: TxtRecord) -> Self {
+        Self {
+            spf: record.is_spf(),
+            malformed_spf: record.looks_like_spf(),
+            sender_id: record.is_sender_id(),
+            text: record.text,
+            ttl: record.ttl,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct PropagationParams {
+    domain: String,
+}
+
+/// Response of `GET /api/v1/propagation`.
+#[derive(Debug, Serialize, ToSchema)]
+struct PropagationResponse {
+    domain: String,
+    /// Whether every resolver answered with the same SPF records. Differing answers after a
+    /// DNS change usually mean that some resolvers still serve the old records from cache.
+    consistent: bool,
+    elapsed_ms: u64,
+    /// The answer of each resolver, in the order they are configured.
+    resolvers: Vec<ResolverAnswerResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct ResolverAnswerResponse {
+    /// The name of the resolver, e.g. `google`, or `system` for the one of `/etc/resolv.conf`.
+    resolver: String,
+    /// The SPF records returned by the resolver, sorted.
+    spf_records: Vec<String>,
+    /// The lowest remaining TTL of the records in seconds: how long the resolver may keep
+    /// returning them.
+    ttl: Option<u32>,
+    error: Option<String>,
+    elapsed_ms: u64,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct HistoryParams {
+    domain: String,
+    target: String,
+}
+
+/// Response of `GET /api/v1/history`.
+#[derive(Debug, Serialize, ToSchema)]
+struct HistoryResponse {
+    domain: String,
+    target: String,
+    /// The stored results of `GET /api/v1/check-spf` for `domain` and `target`, oldest first.
+    results: Vec<HistoryEntry>,
+}
+
+/// Response of `GET /admin/cache/stats`, counting the lookups since the service started.
+#[derive(Debug, Serialize, ToSchema)]
+struct CacheStatsResponse {
+    /// The domains whose TXT records are cached, including expired ones not yet removed.
+    /// `null` for Redis, which holds the records of all instances.
+    entries: Option<usize>,
+    /// The approximate bytes taken by the cached records. `null` for Redis.
+    memory_bytes: Option<usize>,
+    /// The share of TXT lookups answered from the cache, `hits / (hits + misses)`. `null`
+    /// before the first lookup.
+    hit_ratio: Option<f64>,
+    /// The TXT lookups answered from the cache, including `negative_hits` and `stale_hits`.
+    hits: u64,
+    /// The TXT lookups answered from the cache with a domain having no TXT records or not
+    /// existing.
+    negative_hits: u64,
+    /// The TXT lookups answered from the cache with records past their TTL, while they were
+    /// looked up again in the background.
+    stale_hits: u64,
+    /// The TXT lookups that queried DNS, including those of expired domains.
+    misses: u64,
+    /// The TXT lookups that queried DNS because the cached records had expired, including
+    /// their stale window. `null` for Redis, which expires the records itself.
+    expired: Option<u64>,
+    /// The domains removed from memory before their records expired to make room for others.
+    /// `null` for Redis, which evicts keys according to its `maxmemory-policy`.
+    evictions: Option<u64>,
+    /// The lookups and insertions the cache failed, e.g. while Redis was unreachable. The
+    /// failed lookups queried DNS instead.
+    errors: u64,
+}
+
+impl From<DnsCacheStats> for CacheStatsResponse {
+    fn from(stats: DnsCacheStats) -> Self {
+        Self {
+            entries: stats.entries,
+            memory_bytes: stats.memory,
+            hit_ratio: stats.hit_ratio(),
+            hits: stats.hits,
+            negative_hits: stats.negative_hits,
+            stale_hits: stats.stale_hits,
+            misses: stats.misses,
+            expired: stats.expired,
+            evictions: stats.evictions,
+            errors: stats.errors,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct PurgeParams {
+    /// The domain whose cached records are removed, e.g. after it fixed its SPF record.
+    domain: String,
+}
+
+/// Response of `POST /admin/cache/purge`.
+#[derive(Debug, Serialize, ToSchema)]
+struct PurgeResponse {
+    /// The normalized domain whose records were removed.
+    domain: String,
+    /// Whether records or `/api/v1/check-spf` responses of `domain` were cached.
+    purged: bool,
+}
+
+/// Response of `POST /api/v1/check-spf/batch`, listing the results in request order.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+struct BatchCheckResponse {
+    total: usize,
+    found: usize,
+    failed: usize,
+    elapsed_ms: u64,
+    results: Vec<BatchItemResponse>,
+}
+
+impl BatchCheckResponse {
+    fn new(results: Vec<BatchItemResponse>, elapsed_ms: u64) -> Self {
+        let found = results
+            .iter()
+            .filter(
+                |result| matches!(result, BatchItemResponse::Checked(response) if response.found),
+            )
+            .count();
+        let failed = results
+            .iter()
+            .filter(|result| matches!(result, BatchItemResponse::Failed { .. }))
+            .count();
+
+        Self {
+            total: results.len(),
+            found,
+            failed,
+            elapsed_ms,
+            results,
+        }
+    }
+}
+
+/// Response of `GET /api/v1/check-spf`, depending on whether one or several targets are checked.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+#[serde(untagged)]
+enum CheckTargetsResponse {
+    One(Box<SpfCheckResponse>),
+    Many(BatchCheckResponse),
+}
+
+impl CheckTargetsResponse {
+    /// Sets `meta.cached` of the checks, as they are served from the [`ResponseCache`].
+    fn mark_cached(&mut self) {
+        match self {
+            Self::One(response) => response.mark_cached(),
+            Self::Many(response) => response.results.iter_mut().for_each(|result| {
+                if let BatchItemResponse::Checked(response) = result {
+                    response.mark_cached();
+                }
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+#[serde(untagged)]
+enum BatchItemResponse {
+    Checked(Box<SpfCheckResponse>),
+    Failed {
+        domain: String,
+        target: String,
+        #[serde(flatten)]
+        error: ErrorResponse,
+    },
+}
+
+/// A check sent as text message to `/ws`.
+#[derive(Debug, Deserialize)]
+struct SocketRequest {
+    /// Echoed in the messages answering the request, to tell concurrent requests apart.
+    id: Option<String>,
+    /// Also sends the steps taken by the check as `step` messages.
+    #[serde(default)]
+    progress: bool,
+    #[serde(flatten)]
+    params: SpfCheckParams,
+}
+
+/// A message sent by `/ws`, tagged with its `type`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum SocketMessage {
+    Step {
+        id: Option<String>,
+        step: TraceStepResponse,
+    },
+    Result {
+        id: Option<String>,
+        result: CheckTargetsResponse,
+    },
+    Error {
+        id: Option<String>,
+        #[serde(flatten)]
+        error: ErrorResponse,
+    },
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct JobParams {
+    /// The number of results to skip, usually the `next_offset` of the previous response.
+    #[serde(default)]
+    offset: usize,
+    /// The maximum number of results to return (default and at most `MAX_JOB_PAGE_SIZE`).
+    limit: Option<usize>,
+}
+
+/// Response of `POST /api/v1/jobs` and `GET /api/v1/jobs/{id}`, listing a page of the results
+/// in the order the checks completed.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+struct JobResponse {
+    id: String,
+    status: JobStatus,
+    total: usize,
+    completed: usize,
+    found: usize,
+    failed: usize,
+    elapsed_ms: u64,
+    offset: usize,
+    next_offset: usize,
+    results: Vec<BatchItemResponse>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum JobStatus {
+    #[default]
+    Queued,
+    Running,
+    Completed,
+}
+
+/// A batch of checks submitted through `POST /api/v1/jobs`.
+#[derive(Debug)]
+struct Job {
+    id: String,
+    /// The number of checks, counting a check with a list of targets once per target.
+    total: usize,
+    state: Mutex<JobState>,
+    /// Notified whenever results are recorded and when the job completes.
+    progress: Notify,
+}
+
+#[derive(Debug, Default)]
+struct JobState {
+    status: JobStatus,
+    started: Option<Instant>,
+    finished: Option<Instant>,
+    found: usize,
+    failed: usize,
+    results: Vec<BatchItemResponse>,
+}
+
+impl Job {
+    fn start(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.status = JobStatus::Running;
+        state.started = Some(Instant::now());
+    }
+
+    fn record(&self, results: Vec<BatchItemResponse>) {
+        let mut state = self.state.lock().unwrap();
+
+        for result in results {
+            match &result {
+                BatchItemResponse::Checked(response) if response.found => state.found += 1,
+                BatchItemResponse::Failed { .. } => state.failed += 1,
+                BatchItemResponse::Checked(_) => {}
+            }
+
+            state.results.push(result);
+        }
+
+        drop(state);
+        self.progress.notify_waiters();
+    }
+
+    fn finish(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.status = JobStatus::Completed;
+        state.finished = Some(Instant::now());
+
+        drop(state);
+        self.progress.notify_waiters();
+    }
+
+    fn is_expired(&self) -> bool {
+        let state = self.state.lock().unwrap();
+
+        state
+            .finished
+            .is_some_and(|finished| finished.elapsed() > JOB_RETENTION)
+    }
+
+    /// The status of the job with at most `limit` results from `offset` on.
+    fn response(&self, offset: usize, limit: usize) -> JobResponse {
+        let state = self.state.lock().unwrap();
+
+        let offset = offset.min(state.results.len());
+        let end = offset.saturating_add(limit).min(state.results.len());
+
+        let elapsed_ms = match (state.started, state.finished) {
+            (Some(started), Some(finished)) => finished.duration_since(started).as_millis() as u64,
+            (Some(started), None) => started.elapsed().as_millis() as u64,
+            _ => 0,
+        };
+
+        JobResponse {
+            id: self.id.clone(),
+            status: state.status,
+            total: self.total,
+            completed: state.results.len(),
+            found: state.found,
+            failed: state.failed,
+            elapsed_ms,
+            offset,
+            next_offset: end,
+            results: state.results[offset..end].to_vec(),
+        }
+    }
+}
+
+/// The submitted jobs and the queue feeding them to a single worker, which runs them one after
+/// another with `BATCH_CONCURRENCY` concurrent checks.
+#[derive(Clone, Debug)]
+struct JobQueue {
+    jobs: Arc<Mutex<HashMap<String, Arc<Job>>>>,
+    sender: mpsc::Sender<(Arc<Job>, Vec<SpfCheckParams>)>,
+    /// Random keys making job ids unpredictable, so that only the submitter can fetch results.
+    id_keys: RandomState,
+    next_id: Arc<AtomicU64>,
+}
+
+impl JobQueue {
+    /// Creates the queue and spawns its worker.
+    fn new(checker: SpfChecker, nameservers: Nameservers) -> Self {
+        let (sender, mut receiver) =
+            mpsc::channel::<(Arc<Job>, Vec<SpfCheckParams>)>(MAX_QUEUED_JOBS);
+
+        tokio::spawn(async move {
+            while let Some((job, items)) = receiver.recv().await {
+                run_job(&checker, &nameservers, &job, items).await;
+            }
+        });
+
+        Self {
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            sender,
+            id_keys: RandomState::new(),
+            next_id: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Queues the checks as a new job, or returns `None` if `MAX_QUEUED_JOBS` jobs are already
+    /// waiting. Jobs completed longer than `JOB_RETENTION` ago are dropped.
+    fn submit(&self, items: Vec<SpfCheckParams>, total: usize) -> Option<Arc<Job>> {
+        let mut hasher = self.id_keys.build_hasher();
+        hasher.write_u64(self.next_id.fetch_add(1, Ordering::Relaxed));
+
+        let job = Arc::new(Job {
+            id: format!("{:016x}", hasher.finish()),
+            total,
+            state: Mutex::new(JobState::default()),
+            progress: Notify::new(),
+        });
+
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.retain(|_, job| !job.is_expired());
+
+        self.sender.try_send((job.clone(), items)).ok()?;
+        jobs.insert(job.id.clone(), job.clone());
+
+        Some(job)
+    }
+
+    fn get(&self, id: &str) -> Option<Arc<Job>> {
+        self.jobs.lock().unwrap().get(id).cloned()
+    }
+}
+
+/// Checkers that each query another resolver, compared by `GET /api/v1/propagation`.
+#[derive(Clone, Debug)]
+struct Resolvers(Arc<Vec<(&'static str, SpfChecker)>>);
+
+/// The addresses of the name servers of the checks, or the URL of the upstream resolver,
+/// reported in `meta.nameservers`.
+#[derive(Clone, Debug)]
+struct Nameservers(Arc<[String]>);
+
+/// The state shared by all handlers.
+#[derive(Clone, Debug)]
+struct AppState {
+    checker: SpfChecker,
+    nameservers: Nameservers,
+    jobs: JobQueue,
+    resolvers: Resolvers,
+    history: History,
+    /// The TXT records shared by the checks of `checker` until their TTL runs out.
+    dns_cache: DnsCache,
+    /// The complete responses of `/api/v1/check-spf`, if enabled.
+    response_cache: ResponseCache,
+}
+
+impl FromRef<AppState> for SpfChecker {
+    fn from_ref(state: &AppState) -> Self {
+        state.checker.clone()
+    }
+}
+
+impl FromRef<AppState> for Nameservers {
+    fn from_ref(state: &AppState) -> Self {
+        state.nameservers.clone()
+    }
+}
+
+impl FromRef<AppState> for JobQueue {
+    fn from_ref(state: &AppState) -> Self {
+        state.jobs.clone()
+    }
+}
+
+impl FromRef<AppState> for Resolvers {
+    fn from_ref(state: &AppState) -> Self {
+        state.resolvers.clone()
+    }
+}
+
+impl FromRef<AppState> for History {
+    fn from_ref(state: &AppState) -> Self {
+        state.history.clone()
+    }
+}
+
+impl FromRef<AppState> for DnsCache {
+    fn from_ref(state: &AppState) -> Self {
+        state.dns_cache.clone()
+    }
+}
+
+impl FromRef<AppState> for ResponseCache {
+    fn from_ref(state: &AppState) -> Self {
+        state.response_cache.clone()
+    }
+}
+
+/// The state shared by the `GET` and `POST` handlers of `/api/v1/check-spf`.
+#[derive(Clone, Debug)]
+struct CheckSpfState {
+    checker: SpfChecker,
+    nameservers: Nameservers,
+    history: History,
+    response_cache: ResponseCache,
+}
+
+impl FromRef<AppState> for CheckSpfState {
+    fn from_ref(state: &AppState) -> Self {
+        Self {
+            checker: state.checker.clone(),
+            nameservers: state.nameservers.clone(),
+            history: state.history.clone(),
+            response_cache: state.response_cache.clone(),
+        }
+    }
+}
+
+impl CheckSpfState {
+    /// Runs the checks of `params`, or takes their response from the response cache, and
+    /// stores their results in the history.
+    async fn respond(&self, params: &SpfCheckParams, format: ResponseFormat) -> Response {
+        let check = run_targets(&self.checker, &self.nameservers, params);
+        let (result, age) = self.response_cache.get_or_check(params, check).await;
+        record_history(&self.history, params, &result);
+
+        with_age(render::render(format, params, result), age)
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct EvaluateParams {
+    #[param(value_type = String)]
+    ip: IpAddr,
+    #[serde(default)]
+    sender: String,
+    helo: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct EvaluateResponse {
+    result: String,
+    #[schema(value_type = String)]
+    ip: IpAddr,
+    sender: String,
+    helo: String,
+    mechanism: Option<String>,
+    mechanism_domain: Option<String>,
+    dns_lookups: usize,
+    void_lookups: usize,
+    error: Option<String>,
+    explanation: Option<String>,
+    elapsed_ms: u64,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CheckIpParams {
+    domain: String,
+    #[param(value_type = String)]
+    ip: IpAddr,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct CheckIpResponse {
+    domain: String,
+    #[schema(value_type = String)]
+    ip: IpAddr,
+    /// Whether the evaluation results in `pass`.
+    authorized: bool,
+    result: String,
+    mechanism: Option<String>,
+    mechanism_domain: Option<String>,
+    dns_lookups: usize,
+    void_lookups: usize,
+    error: Option<String>,
+    elapsed_ms: u64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct ExplainResponse {
+    domain: String,
+    target: String,
+    found: bool,
+    mechanism: Option<String>,
+    mechanism_domain: Option<String>,
+    /// The error the check failed with, after the steps taken up to it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    elapsed_ms: u64,
+    steps: Vec<TraceStepResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct TraceStepResponse {
+    kind: String,
+    domain: String,
+    term: Option<String>,
+    matched: Option<bool>,
+    message: String,
+    dns_mechanism_count: usize,
+}
+
+impl From<TraceStep> for TraceStepResponse {
+    fn from(step: TraceStep) -> Self {
+        Self {
+            kind: step.kind.to_string(),
+            domain: step.domain,
+            term: step.term,
+            matched: step.matched,
+            message: step.message,
+            dns_mechanism_count: step.dns_mechanism_count,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, ToSchema)]
+struct ErrorResponse {
+    /// The error code, e.g. `DNS_LOOKUP_FAILED`, which clients can rely on.
+    error: String,
+    /// A description of the error for humans, which unlike `error` may change.
+    message: String,
+    /// The conflicting records of a `MULTIPLE_SPF_RECORDS` error.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spf_records: Option<Vec<String>>,
+    /// The SPF record of each domain of the SPF tree of a failed check, `null` for those
+    /// without one, to find the broken record.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    records: Option<BTreeMap<String, Option<String>>>,
+}
+
+impl From<&anyhow::Error> for ErrorResponse {
+    fn from(err: &anyhow::Error) -> Self {
+        Self {
+            error: err.to_string(),
+            message: error_message(err),
+            spf_records: err
+                .downcast_ref::<MultipleSpfRecords>()
+                .map(|err| err.records.clone()),
+            records: None,
+        }
+    }
+}
+
+/// The message of an error of the checker: the description of its code followed by its cause,
+/// e.g. `An SPF record does not parse: invalid CIDR length`.
+fn error_message(err: &anyhow::Error) -> String {
+    if let Some(err) = err.downcast_ref::<MultipleSpfRecords>() {
+        return format!("{} publishes more than one SPF record", err.domain);
+    }
+
+    let Some(error) = CheckError::of(err) else {
+        return format!("{err:#}");
+    };
+
+    match err.chain().nth(1) {
+        Some(cause) => format!("{}: {cause}", error.message()),
+        None => error.message().to_string(),
+    }
+}
+
+fn log_message(msg: impl AsRef<str>) {
+    println!(
+        "[{}] {}",
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f"),
+        msg.as_ref()
+    );
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/check-spf",
+    params(SpfCheckParams, FormatParams),
+    responses(
+        (status = 200, description = "The check, or the checks of several targets", content(
+            (CheckTargetsResponse = "application/json"),
+            (String = "application/yaml"),
+            (String = "text/csv"),
+            (String = "text/plain"),
+        )),
+        (status = 400, description = "Invalid `mechanisms` or too many targets", body = ProblemResponse, content_type = "application/problem+json"),
+        (status = 404, description = "The check failed, e.g. `DNS_LOOKUP_FAILED`", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn check_spf(
+    Query(params): Query<SpfCheckParams>,
+    Query(format): Query<FormatParams>,
+    headers: HeaderMap,
+    State(state): State<CheckSpfState>,
+) -> Response {
+    if accepts_event_stream(&headers) {
+        return progress_events(&state.checker, |checker| async move {
+            let result = run_targets(&checker, &state.nameservers, &params).await;
+            record_history(&state.history, &params, &result);
+
+            match result {
+                Ok(response) => json_event("result", response),
+                Err((_, error)) => json_event("error", error),
+            }
+        });
+    }
+
+    let format = ResponseFormat::negotiate(&format, &headers);
+
+    state.respond(&params, format).await
+}
+
+async fn check_spf_socket(
+    upgrade: WebSocketUpgrade,
+    checker: State<SpfChecker>,
+    nameservers: State<Nameservers>,
+) -> Response {
+    upgrade.on_upgrade(move |socket| serve_socket(socket, checker.0, nameservers.0))
+}
+
+/// Answers the checks sent over `socket` as they complete, running up to `BATCH_CONCURRENCY`
+/// of them concurrently. A client reading slower than the checks complete holds them up rather
+/// than their answers piling up in memory.
+async fn serve_socket(mut socket: WebSocket, checker: SpfChecker, nameservers: Nameservers) {
+    let (sender, mut messages) = mpsc::channel::<SocketMessage>(SOCKET_BUFFER);
+    let semaphore = Arc::new(Semaphore::new(BATCH_CONCURRENCY));
+
+    log_message("Opened WebSocket connection");
+
+    loop {
+        tokio::select! {
+            received = socket.recv() => {
+                let text = match received {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+
+                // Stops reading further requests while all permits are taken, still sending the
+                // answers the running checks wait to send.
+                let permit = loop {
+                    tokio::select! {
+                        permit = semaphore.clone().acquire_owned() => break permit.ok(),
+                        Some(message) = messages.recv() => {
+                            if !send_socket_message(&mut socket, message).await {
+                                break None;
+                            }
+                        }
+                    }
+                };
+                let Some(permit) = permit else {
+                    break;
+                };
+                let checker = checker.clone();
+                let nameservers = nameservers.clone();
+                let sender = sender.clone();
+
+                tokio::spawn(async move {
+                    let _permit = permit;
+                    answer_socket_request(&checker, &nameservers, text.as_str(), &sender).await;
+                });
+            }
+            Some(message) = messages.recv() => {
+                if !send_socket_message(&mut socket, message).await {
+                    break;
+                }
+            }
+        }
+    }
+
+    log_message("Closed WebSocket connection");
+}
+
+/// Sends `message` over `socket`, returning whether the client is still connected.
+async fn send_socket_message(socket: &mut WebSocket, message: SocketMessage) -> bool {
+    let Ok(text) = serde_json::to_string(&message) else {
+        return true;
+    };
+
+    socket.send(Message::Text(text.into())).await.is_ok()
+}
+
+async fn answer_socket_request(
+    checker: &SpfChecker,
+    nameservers: &Nameservers,
+    text: &str,
+    sender: &mpsc::Sender<SocketMessage>,
+) {
+    let request: SocketRequest = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(err) => {
+            log_message(format!("Rejected WebSocket request: {err}"));
+
+            let _ = sender
+                .send(SocketMessage::Error {
+                    id: None,
+                    error: ServiceError::InvalidRequest.into(),
+                })
+                .await;
+            return;
+        }
+    };
+
+    let checker = if request.progress {
+        checker.with_progress({
+            let sender = sender.clone();
+            let id = request.id.clone();
+            // Steps are not worth holding up the check for a slow client.
+            move |step: &TraceStep| {
+                let _ = sender.try_send(SocketMessage::Step {
+                    id: id.clone(),
+                    step: step.clone().into(),
+                });
+            }
+        })
+    } else {
+        checker.clone()
+    };
+
+    let message = match run_targets(&checker, nameservers, &request.params).await {
+        Ok(result) => SocketMessage::Result {
+            id: request.id,
+            result,
+        },
+        Err((_, error)) => SocketMessage::Error {
+            id: request.id,
+            error,
+        },
+    };
+
+    // The client may have disconnected in the meantime.
+    let _ = sender.send(message).await;
+}
+
+/// Whether the client asks for a stream of Server-Sent Events instead of a JSON response.
+fn accepts_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/event-stream"))
+}
+
+/// An event named `name` carrying `data` as JSON.
+fn json_event(name: &str, data: impl Serialize) -> Event {
+    Event::default()
+        .event(name)
+        .json_data(data)
+        .unwrap_or_else(|err| Event::default().event("error").data(err.to_string()))
+}
+
+/// Responds with a stream of Server-Sent Events: a `step` event for each step taken by `run`
+/// with the checker passed to it, e.g. each DNS query and visited record, followed by the event
+/// returned by `run`.
+fn progress_events<Fut>(checker: &SpfChecker, run: impl FnOnce(SpfChecker) -> Fut) -> Response
+where
+    Fut: Future<Output = Event> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::unbounded_channel::<std::result::Result<Event, Infallible>>();
+
+    let checker = checker.with_progress({
+        let sender = sender.clone();
+        move |step: &TraceStep| {
+            // The client may have disconnected, which the check does not need to know about.
+            let _ = sender.send(Ok(json_event(
+                "step",
+                TraceStepResponse::from(step.clone()),
+            )));
+        }
+    });
+    let task = run(checker);
+
+    tokio::spawn(async move {
+        let _ = sender.send(Ok(task.await));
+    });
+
+    Sse::new(UnboundedReceiverStream::new(receiver))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// `POST /api/v1/check-spf`, taking the parameters of `GET /api/v1/check-spf` as JSON body.
+#[utoipa::path(
+    post,
+    path = "/api/v1/check-spf",
+    request_body = SpfCheckParams,
+    responses(
+        (status = 200, description = "The check, or the checks of several targets", content(
+            (CheckTargetsResponse = "application/json"),
+            (String = "application/yaml"),
+            (String = "text/csv"),
+            (String = "text/plain"),
+        )),
+        (status = 400, description = "Invalid `mechanisms` or too many targets", body = ProblemResponse, content_type = "application/problem+json"),
+        (status = 404, description = "The check failed, e.g. `DNS_LOOKUP_FAILED`", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn check_spf_json(
+    State(state): State<CheckSpfState>,
+    Query(format): Query<FormatParams>,
+    headers: HeaderMap,
+    Json(params): Json<SpfCheckParams>,
+) -> Response {
+    let format = ResponseFormat::negotiate(&format, &headers);
+
+    state.respond(&params, format).await
+}
+
+/// Adds the `Age` header of a response served from the [`ResponseCache`].
+fn with_age(mut response: Response, age: Option<Duration>) -> Response {
+    if let Some(age) = age {
+        response
+            .headers_mut()
+            .insert(header::AGE, HeaderValue::from(age.as_secs()));
+    }
+
+    response
+}
+
+/// Stores the results of a check in the history, except for rejected parameters.
+fn record_history(
+    history: &History,
+    params: &SpfCheckParams,
+    result: &std::result::Result<CheckTargetsResponse, (StatusCode, ErrorResponse)>,
+) {
+    let results = match result {
+        Ok(CheckTargetsResponse::One(response)) => {
+            return history.record(HistoryEntry::checked(response));
+        }
+        Ok(CheckTargetsResponse::Many(response)) => &response.results,
+        Err((StatusCode::NOT_FOUND, error)) => {
+            if let Targets::One(target) = &params.target {
+                history.record(HistoryEntry::failed(params.mail_domain(), target, error));
+            }
+            return;
+        }
+        Err(_) => return,
+    };
+
+    for result in results {
+        history.record(match result {
+            BatchItemResponse::Checked(response) => HistoryEntry::checked(response),
+            BatchItemResponse::Failed {
+                domain,
+                target,
+                error,
+            } => HistoryEntry::failed(domain, target, error),
+        });
+    }
+}
+
+/// `POST /api/v1/build-spf`, assembling an SPF record from its includes and networks.
+#[utoipa::path(
+    post,
+    path = "/api/v1/build-spf",
+    request_body = BuildSpfParams,
+    responses(
+        (status = 200, description = "The built record", body = BuildSpfResponse),
+        (status = 400, description = "A term does not parse", body = ProblemResponse, content_type = "application/problem+json"),
+        (status = 404, description = "An included record could not be looked up", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn build_spf(checker: State<SpfChecker>, Json(params): Json<BuildSpfParams>) -> Response {
+    let start = std::time::Instant::now();
+
+    let spec = RecordSpec {
+        includes: params.includes,
+        ip4: params.ip4,
+        ip6: params.ip6,
+        all: params.all.into(),
+    };
+
+    match checker.build_record(&params.domain, &spec).await {
+        Ok(built) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Built record for \"{}\" with {} DNS lookups ({}ms)",
+                params.domain,
+                built.lookup_budget.total(),
+                elapsed_ms
+            ));
+
+            let response = BuildSpfResponse {
+                domain: params.domain,
+                record: built.record,
+                dns_lookups: built.lookup_budget.total(),
+                lookup_limit: LookupBudget::LIMIT,
+                record_size: built.record_size.into(),
+                elapsed_ms,
+                warnings: built
+                    .warnings
+                    .into_iter()
+                    .map(WarningResponse::from)
+                    .collect(),
+                lookup_terms: built
+                    .lookup_budget
+                    .terms
+                    .into_iter()
+                    .map(BudgetTermResponse::from)
+                    .collect(),
+            };
+
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(err) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Failed to build record for \"{}\": {} ({}ms)",
+                params.domain, err, elapsed_ms
+            ));
+
+            let status = if CheckError::of(&err) == Some(CheckError::SpfParseFailed) {
+                StatusCode::BAD_REQUEST
+            } else {
+                StatusCode::NOT_FOUND
+            };
+            Problem(status, ErrorResponse::from(&err)).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/evaluate-record",
+    request_body = RecordParams,
+    responses(
+        (status = 200, description = "The evaluation of `ip`, or the response of `GET /api/v1/check-spf` if `target` is given", body = EvaluateResponse),
+        (status = 400, description = "Invalid record, or neither `target` nor `ip` given", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn evaluate_record(
+    checker: State<SpfChecker>,
+    nameservers: State<Nameservers>,
+    Json(mut params): Json<RecordParams>,
+) -> Response {
+    let start = std::time::Instant::now();
+
+    let draft = checker.with_record(
+        &params.domain,
+        &params.record,
+        params.resolve_dns.unwrap_or(true),
+    );
+    let checker = match draft {
+        Ok(checker) => checker,
+        Err(err) => {
+            log_message(format!(
+                "Rejected record for \"{}\": {}",
+                params.domain, err
+            ));
+
+            return Problem(StatusCode::BAD_REQUEST, ErrorResponse::from(&err)).into_response();
+        }
+    };
+
+    if let Some(target) = params.target.take() {
+        return check_targets(&checker, &nameservers, params.check_params(target)).await;
+    }
+
+    let Some(ip) = params.ip else {
+        return ServiceError::MissingTargetOrIp.into_response();
+    };
+
+    let sender = params.sender.unwrap_or_default();
+    let helo = params.helo.unwrap_or_else(|| params.domain.clone());
+
+    let Evaluation {
+        result,
+        mechanism,
+        mechanism_domain,
+        dns_lookups,
+        void_lookups,
+        error,
+        explanation,
+    } = checker.check_host(ip, &sender, &helo).await;
+
+    let elapsed_ms = start.elapsed().as_millis() as u64;
+
+    log_message(format!(
+        "Evaluated {} against the record for \"{}\": {} ({}ms)",
+        ip, params.domain, result, elapsed_ms
+    ));
+
+    let response = EvaluateResponse {
+        result: result.to_string(),
+        ip,
+        sender,
+        helo,
+        mechanism,
+        mechanism_domain,
+        dns_lookups,
+        void_lookups,
+        error,
+        explanation,
+        elapsed_ms,
+    };
+
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/check-spf/domains",
+    request_body = TargetDomainsParams,
+    responses(
+        (status = 200, description = "The checks of the domains in request order", body = TargetDomainsResponse),
+        (status = 400, description = "No target or more than 5000 domains", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn check_target_domains(
+    checker: State<SpfChecker>,
+    nameservers: State<Nameservers>,
+    Json(params): Json<TargetDomainsParams>,
+) -> Response {
+    let start = std::time::Instant::now();
+
+    if params.target.trim().is_empty() {
+        log_message("Rejected check of domains without target");
+
+        return ServiceError::InvalidTargetCount { max: 1 }.into_response();
+    }
+
+    if params.domains.len() > MAX_TARGET_DOMAINS {
+        log_message(format!(
+            "Rejected check of \"{}\" in {} domains, at most {MAX_TARGET_DOMAINS} are allowed",
+            params.target,
+            params.domains.len()
+        ));
+
+        return ServiceError::TooManyDomains.into_response();
+    }
+
+    let semaphore = Arc::new(Semaphore::new(BATCH_CONCURRENCY));
+
+    let tasks: Vec<_> = params
+        .domains
+        .iter()
+        .map(|domain| {
+            let checker = checker.0.clone();
+            let nameservers = nameservers.0.clone();
+            let semaphore = semaphore.clone();
+            let check_params = params.check_params(domain);
+            let target = params.target.clone();
+
+            let task = tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+
+                run_check(&checker, &nameservers, &check_params, &target).await
+            });
+
+            (domain.clone(), task)
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(tasks.len());
+
+    for (domain, task) in tasks {
+        let result = match task.await {
+            Ok(Ok(response)) => TargetDomainResponse {
+                domain,
+                found: response.found,
+                mechanism: response.mechanism,
+                mechanism_domain: response.mechanism_domain,
+                error: None,
+            },
+            Ok(Err((_, error))) => TargetDomainResponse {
+                domain,
+                found: false,
+                mechanism: None,
+                mechanism_domain: None,
+                error: Some(error.error),
+            },
+            Err(err) => {
+                log_message(format!(
+                    "Failed to check \"{domain}\" for \"{}\": {err}",
+                    params.target
+                ));
+
+                TargetDomainResponse {
+                    domain,
+                    found: false,
+                    mechanism: None,
+                    mechanism_domain: None,
+                    error: Some(ServiceError::InternalError.code().to_string()),
+                }
+            }
+        };
+
+        results.push(result);
+    }
+
+    let matching_domains: Vec<String> = results
+        .iter()
+        .filter(|result| result.found)
+        .map(|result| result.domain.clone())
+        .collect();
+    let failed = results
+        .iter()
+        .filter(|result| result.error.is_some())
+        .count();
+    let elapsed_ms = start.elapsed().as_millis() as u64;
+
+    log_message(format!(
+        "Checked \"{}\" in {} domains ({} found, {failed} failed) ({elapsed_ms}ms)",
+        params.target,
+        results.len(),
+        matching_domains.len()
+    ));
+
+    let response = TargetDomainsResponse {
+        target: params.target,
+        total: results.len(),
+        found: matching_domains.len(),
+        failed,
+        elapsed_ms,
+        matching_domains,
+        results,
+    };
+
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// Responds with the result of a single target, or with the results of several targets like
+/// `POST /api/v1/check-spf/batch`.
+async fn check_targets(
+    checker: &SpfChecker,
+    nameservers: &Nameservers,
+    params: SpfCheckParams,
+) -> Response {
+    targets_response(run_targets(checker, nameservers, &params).await)
+}
+
+fn targets_response(
+    result: std::result::Result<CheckTargetsResponse, (StatusCode, ErrorResponse)>,
+) -> Response {
+    match result {
+        Ok(response) => (StatusCode::OK, Json(response)).into_response(),
+        Err((status, error)) => Problem(status, error).into_response(),
+    }
+}
+
+/// Runs the checks of `params` against each of its targets, returning the response body of
+/// `GET /api/v1/check-spf` or the error together with its status code.
+async fn run_targets(
+    checker: &SpfChecker,
+    nameservers: &Nameservers,
+    params: &SpfCheckParams,
+) -> std::result::Result<CheckTargetsResponse, (StatusCode, ErrorResponse)> {
+    let start = std::time::Instant::now();
+
+    let targets = match &params.target {
+        Targets::One(target) => {
+            let response = run_check(checker, nameservers, params, target).await?;
+            return Ok(CheckTargetsResponse::One(Box::new(response)));
+        }
+        Targets::Many(targets) => targets,
+    };
+
+    if targets.is_empty() || targets.len() > MAX_TARGETS {
+        log_message(format!(
+            "Rejected check of \"{}\" for {} targets, 1 to {MAX_TARGETS} are allowed",
+            params.domain,
+            targets.len()
+        ));
+
+        let error = ServiceError::InvalidTargetCount { max: MAX_TARGETS };
+        return Err((error.status(), error.into()));
+    }
+
+    let results = run_checks(checker, nameservers, params).await;
+    let response = BatchCheckResponse::new(results, start.elapsed().as_millis() as u64);
+
+    log_message(format!(
+        "Checked \"{}\" for {} targets ({} found) ({}ms)",
+        params.domain, response.total, response.found, response.elapsed_ms
+    ));
+
+    Ok(CheckTargetsResponse::Many(response))
+}
+
+/// Runs the check of each target of `params`, looking up each record of the SPF tree only once.
+async fn run_checks(
+    checker: &SpfChecker,
+    nameservers: &Nameservers,
+    params: &SpfCheckParams,
+) -> Vec<BatchItemResponse> {
+    let checker = checker.with_lookup_cache();
+    let mut results = Vec::new();
+
+    for target in params.target.to_vec() {
+        let result = match run_check(&checker, nameservers, params, &target).await {
+            Ok(response) => BatchItemResponse::Checked(Box::new(response)),
+            Err((_, error)) => BatchItemResponse::Failed {
+                domain: params.domain.clone(),
+                target,
+                error,
+            },
+        };
+
+        results.push(result);
+    }
+
+    results
+}
+
+/// Runs a single check, returning the response body of `GET /api/v1/check-spf` or the error
+/// together with its status code.
+async fn run_check(
+    checker: &SpfChecker,
+    nameservers: &Nameservers,
+    params: &SpfCheckParams,
+    target: &str,
+) -> std::result::Result<SpfCheckResponse, (StatusCode, ErrorResponse)> {
+    let mut response =
+        check_domain(checker, nameservers, params, params.mail_domain(), target).await?;
+
+    if !params.org_domain {
+        return Ok(response);
+    }
+
+    let Some(org_domain) = spf_checker::organizational_domain(&response.domain_ascii)
+        .filter(|org_domain| org_domain != &response.domain_ascii)
+    else {
+        return Ok(response);
+    };
+
+    response.organizational_domain = Some(
+        match check_domain(checker, nameservers, params, &org_domain, target).await {
+            Ok(org_response) => BatchItemResponse::Checked(Box::new(org_response)),
+            Err((_, error)) => BatchItemResponse::Failed {
+                domain: org_domain,
+                target: target.to_string(),
+                error,
+            },
+        },
+    );
+
+    Ok(response)
+}
+
+/// Checks `domain` for `target` with the options of `params`.
+async fn check_domain(
+    checker: &SpfChecker,
+    nameservers: &Nameservers,
+    params: &SpfCheckParams,
+    domain: &str,
+    target: &str,
+) -> std::result::Result<SpfCheckResponse, (StatusCode, ErrorResponse)> {
+    let start = std::time::Instant::now();
+    let checked_at = chrono::Utc::now();
+
+    let options = params.check_options()?;
+
+    // Timed below the cache, so that only the queries actually made are reported.
+    let (checker, timings) = checker.with_query_timings();
+
+    // All traverse the same records, which the cache looks up only once.
+    let checker = if params.dnssec || params.tree || params.complexity {
+        checker.with_lookup_cache()
+    } else {
+        checker
+    };
+    let (check, dnssec, tree) = tokio::join!(
+        checker.check_with_options(domain, target, &options),
+        async {
+            if params.dnssec {
+                Some(checker.dnssec_report(domain, &options).await)
+            } else {
+                None
+            }
+        },
+        async {
+            if params.tree || params.complexity {
+                Some(checker.partial_tree(domain, &options).await)
+            } else {
+                None
+            }
+        }
+    );
+
+    match check.map(|result| (result.result(), result)) {
+        Ok((
+            result,
+            CheckResult {
+                found,
+                visited,
+                visited_domains,
+                spf_record,
+                included_domains,
+                redirect_chain,
+                fallback_check,
+                mechanism,
+                mechanism_domain,
+                qualifier,
+                default_policy,
+                all_mechanism,
+                default_policy_domain,
+                explanation,
+                dns_mechanism_count,
+                limit_exceeded,
+                void_lookups,
+                loops,
+                path,
+                warnings,
+                record_sizes,
+                records,
+                ttls,
+                overlapping_mechanisms,
+                skipped_macro_terms,
+                matches,
+                duplicates,
+                sender_id_records,
+                errors,
+            },
+        )) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            let status_msg = match (fallback_check, found) {
+                (true, true) => {
+                    format!(
+                        "Successfully found all mechanisms from \"{}\" in \"{}\" via fallback check ({}ms)",
+                        target, domain, elapsed_ms
+                    )
+                }
+                (true, false) => {
+                    format!(
+                        "Fallback check completed for \"{}\" in \"{}\" - not all mechanisms found ({}ms)",
+                        target, domain, elapsed_ms
+                    )
+                }
+                (false, _) => {
+                    format!(
+                        "Successfully checked \"{}\" for \"{}\" ({}ms)",
+                        domain, target, elapsed_ms
+                    )
+                }
+            };
+
+            log_message(status_msg);
+
+            let domain_ascii = spf_checker::domain_to_ascii(domain).unwrap_or_default();
+            let target_ascii = spf_checker::domain_to_ascii(target).unwrap_or_default();
+
+            let mut warnings: Vec<_> = warnings.into_iter().map(WarningResponse::from).collect();
+
+            let dnssec = match dnssec {
+                Some(Ok(report)) => Some(DnssecResponse::from(report)),
+                Some(Err(err)) => {
+                    warnings.push(WarningResponse {
+                        code: err.to_string(),
+                        domain: domain.to_string(),
+                        message: "The answers of the check could not be validated with DNSSEC"
+                            .to_string(),
+                    });
+                    None
+                }
+                None => None,
+            };
+
+            let tree = match tree {
+                Some(Ok(tree)) => Some(tree),
+                Some(Err(err)) => {
+                    warnings.push(WarningResponse {
+                        code: err.to_string(),
+                        domain: domain.to_string(),
+                        message: "The include and redirect tree could not be built".to_string(),
+                    });
+                    None
+                }
+                None => None,
+            };
+            let complexity = tree
+                .as_ref()
+                .filter(|_| params.complexity)
+                .map(|tree| ComplexityResponse::from(tree.complexity()));
+            let tree = tree.filter(|_| params.tree).map(SpfTreeNodeResponse::from);
+
+            let meta = MetaResponse::new(checked_at, nameservers, &domain_ascii, &target_ascii);
+
+            let response = SpfCheckResponse {
+                result: result.to_string(),
+                found,
+                checked_domains: visited,
+                visited_domains,
+                domain: domain.to_string(),
+                address: params.address(),
+                target: target.to_string(),
+                domain_unicode: spf_checker::domain_to_unicode(&domain_ascii),
+                domain_ascii,
+                target_unicode: spf_checker::domain_to_unicode(&target_ascii),
+                target_ascii,
+                elapsed_ms,
+                timings: timings
+                    .take()
+                    .into_iter()
+                    .map(QueryTimingResponse::from)
+                    .collect(),
+                has_spf_record: spf_record.is_some(),
+                spf_record,
+                included_domains,
+                redirect_chain: redirect_chain
+                    .into_iter()
+                    .map(RedirectResponse::from)
+                    .collect(),
+                fallback_check,
+                mechanism,
+                mechanism_domain,
+                qualifier: qualifier.map(|qualifier| qualifier.symbol().to_string()),
+                default_policy: default_policy.map(|qualifier| qualifier.symbol().to_string()),
+                all_mechanism,
+                default_policy_domain,
+                explanation,
+                dns_mechanism_count,
+                dns_lookups: dns_mechanism_count,
+                limit_exceeded,
+                void_lookups,
+                loops,
+                path,
+                warnings,
+                errors: errors.into_iter().map(DomainErrorResponse::from).collect(),
+                record_sizes: record_sizes
+                    .into_iter()
+                    .map(RecordSizeResponse::from)
+                    .collect(),
+                records,
+                min_ttl: ttls.values().min().copied(),
+                ttls,
+                overlapping_mechanisms: overlapping_mechanisms
+                    .into_iter()
+                    .map(MechanismMatchResponse::from)
+                    .collect(),
+                skipped_macro_terms: skipped_macro_terms
+                    .into_iter()
+                    .map(SkippedTermResponse::from)
+                    .collect(),
+                matches: matches
+                    .into_iter()
+                    .map(MechanismMatchResponse::from)
+                    .collect(),
+                duplicates: duplicates
+                    .into_iter()
+                    .map(DuplicateMechanismResponse::from)
+                    .collect(),
+                sender_id_records,
+                organizational_domain: None,
+                dnssec,
+                tree,
+                complexity,
+                meta,
+            };
+
+            Ok(response)
+        }
+        Err(err) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Failed to check \"{}\" for \"{}\": {} ({}ms)",
+                domain, target, err, elapsed_ms
+            ));
+
+            // Unlike the check, the partial tree continues past the broken records.
+            let tree = match tree {
+                Some(tree) => tree,
+                None => checker.partial_tree(domain, &options).await,
+            };
+
+            let error = ErrorResponse {
+                records: tree.ok().map(|tree| tree.records()),
+                ..ErrorResponse::from(&err)
+            };
+
+            Err((StatusCode::NOT_FOUND, error))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/explain",
+    params(SpfCheckParams),
+    responses(
+        (status = 200, description = "The steps of the check", body = ExplainResponse),
+        (status = 400, description = "Invalid `mechanisms` or more than one target", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn explain(Query(params): Query<SpfCheckParams>, checker: State<SpfChecker>) -> Response {
+    let start = std::time::Instant::now();
+
+    let Targets::One(target) = &params.target else {
+        return ServiceError::InvalidTargetCount { max: 1 }.into_response();
+    };
+
+    let options = match params.check_options() {
+        Ok(options) => options,
+        Err((status, error)) => return Problem(status, error).into_response(),
+    };
+
+    let domain = params.mail_domain();
+    let Explanation { steps, result } = checker.explain(domain, target, &options).await;
+    let elapsed_ms = start.elapsed().as_millis() as u64;
+
+    log_message(format!(
+        "Explained check of \"{}\" for \"{}\" in {} steps ({}ms)",
+        domain,
+        target,
+        steps.len(),
+        elapsed_ms
+    ));
+
+    let (found, mechanism, mechanism_domain, error) = match result {
+        Ok(result) => (
+            result.found,
+            result.mechanism,
+            result.mechanism_domain,
+            None,
+        ),
+        Err(err) => (false, None, None, Some(err.to_string())),
+    };
+
+    let response = ExplainResponse {
+        domain: domain.to_string(),
+        target: target.clone(),
+        found,
+        mechanism,
+        mechanism_domain,
+        error,
+        elapsed_ms,
+        steps: steps.into_iter().map(TraceStepResponse::from).collect(),
+    };
+
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/check-spf/batch",
+    request_body = Vec<SpfCheckParams>,
+    responses(
+        (status = 200, description = "The checks in request order", body = BatchCheckResponse),
+        (status = 400, description = "More than 1000 checks", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn check_spf_batch(
+    checker: State<SpfChecker>,
+    nameservers: State<Nameservers>,
+    Json(items): Json<Vec<SpfCheckParams>>,
+) -> Response {
+    let start = std::time::Instant::now();
+
+    // A check with a list of targets counts once per target.
+    let size: usize = items
+        .iter()
+        .map(|params| params.target.to_vec().len())
+        .sum();
+
+    if size > MAX_BATCH_SIZE {
+        log_message(format!(
+            "Rejected batch of {size} checks, at most {MAX_BATCH_SIZE} are allowed"
+        ));
+
+        return ServiceError::BatchTooLarge.into_response();
+    }
+
+    let results = run_batch(&checker, &nameservers, items).await;
+    let response = BatchCheckResponse::new(results, start.elapsed().as_millis() as u64);
+
+    log_message(format!(
+        "Checked batch of {} ({} found, {} failed) ({}ms)",
+        response.total, response.found, response.failed, response.elapsed_ms
+    ));
+
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// Runs the checks of a batch with up to `BATCH_CONCURRENCY` of them concurrently, returning
+/// their results in request order.
+async fn run_batch(
+    checker: &SpfChecker,
+    nameservers: &Nameservers,
+    items: Vec<SpfCheckParams>,
+) -> Vec<BatchItemResponse> {
+    let semaphore = Arc::new(Semaphore::new(BATCH_CONCURRENCY));
+
+    let tasks: Vec<_> = items
+        .into_iter()
+        .map(|params| {
+            let checker = checker.clone();
+            let nameservers = nameservers.clone();
+            let semaphore = semaphore.clone();
+            let domain = params.domain.clone();
+            let targets = params.target.to_vec();
+
+            let task = tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+
+                run_checks(&checker, &nameservers, &params).await
+            });
+
+            (domain, targets, task)
+        })
+        .collect();
+
+    let mut results = Vec::with_capacity(tasks.len());
+
+    for (domain, targets, task) in tasks {
+        match task.await {
+            Ok(item_results) => results.extend(item_results),
+            Err(err) => results.extend(internal_errors(&domain, targets, err)),
+        }
+    }
+
+    results
+}
+
+/// The results of checks of `domain` that failed unexpectedly, e.g. because they panicked.
+fn internal_errors(
+    domain: &str,
+    targets: Vec<String>,
+    err: impl std::fmt::Display,
+) -> Vec<BatchItemResponse> {
+    log_message(format!(
+        "Failed to check \"{domain}\" for {targets:?}: {err}"
+    ));
+
+    targets
+        .into_iter()
+        .map(|target| BatchItemResponse::Failed {
+            domain: domain.to_string(),
+            target,
+            error: ServiceError::InternalError.into(),
+        })
+        .collect()
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/jobs",
+    request_body = Vec<SpfCheckParams>,
+    responses(
+        (status = 202, description = "The queued job", body = JobResponse),
+        (status = 400, description = "More than 50000 checks", body = ProblemResponse, content_type = "application/problem+json"),
+        (status = 503, description = "Too many queued jobs", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn submit_job(jobs: State<JobQueue>, Json(items): Json<Vec<SpfCheckParams>>) -> Response {
+    // A check with a list of targets counts once per target.
+    let size: usize = items
+        .iter()
+        .map(|params| params.target.to_vec().len())
+        .sum();
+
+    if size > MAX_JOB_SIZE {
+        log_message(format!(
+            "Rejected job of {size} checks, at most {MAX_JOB_SIZE} are allowed"
+        ));
+
+        return ServiceError::JobTooLarge.into_response();
+    }
+
+    let Some(job) = jobs.submit(items, size) else {
+        log_message(format!(
+            "Rejected job of {size} checks, {MAX_QUEUED_JOBS} jobs are already queued"
+        ));
+
+        return ServiceError::JobQueueFull.into_response();
+    };
+
+    log_message(format!("Queued job {} of {} checks", job.id, size));
+
+    (
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, format!("/api/v1/jobs/{}", job.id))],
+        Json(job.response(0, 0)),
+    )
+        .into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/jobs/{id}",
+    params(("id" = String, Path, description = "The id of the job"), JobParams),
+    responses(
+        (status = 200, description = "The status and a page of the results of the job", body = JobResponse),
+        (status = 404, description = "Unknown or expired job", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn get_job(
+    Path(id): Path<String>,
+    Query(params): Query<JobParams>,
+    headers: HeaderMap,
+    jobs: State<JobQueue>,
+) -> Response {
+    let Some(job) = jobs.get(&id) else {
+        return ServiceError::JobNotFound.into_response();
+    };
+
+    if accepts_event_stream(&headers) {
+        return job_events(job, params.offset);
+    }
+
+    let limit = params
+        .limit
+        .unwrap_or(MAX_JOB_PAGE_SIZE)
+        .min(MAX_JOB_PAGE_SIZE);
+
+    (StatusCode::OK, Json(job.response(params.offset, limit))).into_response()
+}
+
+/// Responds with a stream of Server-Sent Events: a `result` event for each result of `job` from
+/// `offset` on as soon as it is recorded, followed by a `job` event with the status of the
+/// completed job.
+fn job_events(job: Arc<Job>, offset: usize) -> Response {
+    let (sender, receiver) = mpsc::unbounded_channel::<std::result::Result<Event, Infallible>>();
+
+    tokio::spawn(async move {
+        let mut offset = offset;
+
+        loop {
+            // Created before reading the results so that no notification is missed.
+            let progress = job.progress.notified();
+            let response = job.response(offset, usize::MAX);
+
+            for result in &response.results {
+                if sender.send(Ok(json_event("result", result))).is_err() {
+                    return;
+                }
+            }
+            offset = response.next_offset;
+
+            if response.status == JobStatus::Completed {
+                let response = JobResponse {
+                    results: Vec::new(),
+                    ..response
+                };
+                let _ = sender.send(Ok(json_event("job", response)));
+                return;
+            }
+
+            progress.await;
+        }
+    });
+
+    Sse::new(UnboundedReceiverStream::new(receiver))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Runs the checks of `job`, recording the results as they complete.
+async fn run_job(
+    checker: &SpfChecker,
+    nameservers: &Nameservers,
+    job: &Job,
+    items: Vec<SpfCheckParams>,
+) {
+    job.start();
+
+    log_message(format!("Started job {} of {} checks", job.id, job.total));
+
+    let mut tasks = JoinSet::new();
+
+    for params in items {
+        if tasks.len() >= BATCH_CONCURRENCY {
+            if let Some(Ok(results)) = tasks.join_next().await {
+                job.record(results);
+            }
+        }
+
+        let checker = checker.clone();
+        let nameservers = nameservers.clone();
+
+        tasks.spawn(async move {
+            let domain = params.domain.clone();
+            let targets = params.target.to_vec();
+
+            // Runs the check in a task of its own to turn a panic into an error result.
+            tokio::spawn(async move { run_checks(&checker, &nameservers, &params).await })
+                .await
+                .unwrap_or_else(|err| internal_errors(&domain, targets, err))
+        });
+    }
+
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok(results) = joined {
+            job.record(results);
+        }
+    }
+
+    job.finish();
+
+    log_message(format!("Completed job {}", job.id));
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/spf-tree",
+    params(SpfTreeParams, TraversalParams),
+    responses(
+        (status = 200, description = "The SPF tree of the domain", body = SpfTreeResponse),
+        (status = 404, description = "The check failed, e.g. `DNS_LOOKUP_FAILED`", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn spf_tree(
+    Query(params): Query<SpfTreeParams>,
+    headers: HeaderMap,
+    checker: State<SpfChecker>,
+) -> Response {
+    if accepts_event_stream(&headers) {
+        return progress_events(&checker, |checker| async move {
+            match build_tree(&checker, params).await {
+                Ok(response) => json_event("result", response),
+                Err(error) => json_event("error", error),
+            }
+        });
+    }
+
+    match build_tree(&checker, params).await {
+        Ok(response) => (StatusCode::OK, Json(response)).into_response(),
+        Err(error) => Problem(StatusCode::NOT_FOUND, error).into_response(),
+    }
+}
+
+async fn build_tree(
+    checker: &SpfChecker,
+    params: SpfTreeParams,
+) -> std::result::Result<SpfTreeResponse, ErrorResponse> {
+    let start = std::time::Instant::now();
+
+    let options = params.traversal.check_options();
+
+    match checker.tree(&params.domain, &options).await {
+        Ok(tree) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Built SPF tree of \"{}\" ({}ms)",
+                params.domain, elapsed_ms
+            ));
+
+            Ok(SpfTreeResponse {
+                domain: params.domain,
+                elapsed_ms,
+                providers: tree
+                    .providers()
+                    .into_iter()
+                    .map(ProviderResponse::from)
+                    .collect(),
+                tree: tree.into(),
+            })
+        }
+        Err(err) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Failed to build SPF tree of \"{}\": {} ({}ms)",
+                params.domain, err, elapsed_ms
+            ));
+
+            Err(ErrorResponse::from(&err))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/spf-graph",
+    params(SpfGraphParams, TraversalParams),
+    responses(
+        (status = 200, description = "The include and redirect graph of the domain", content(
+            (String = "text/vnd.graphviz"),
+            (String = "text/plain"),
+        )),
+        (status = 404, description = "The check failed, e.g. `DNS_LOOKUP_FAILED`", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn spf_graph(Query(params): Query<SpfGraphParams>, checker: State<SpfChecker>) -> Response {
+    let start = std::time::Instant::now();
+
+    let options = params.traversal.check_options();
+
+    match checker.tree(&params.domain, &options).await {
+        Ok(tree) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Rendered SPF graph of \"{}\" ({}ms)",
+                params.domain, elapsed_ms
+            ));
+
+            let target = params.target.as_deref();
+            let (content_type, graph) = match params.format {
+                GraphFormat::Dot => ("text/vnd.graphviz; charset=utf-8", tree.to_dot(target)),
+                GraphFormat::Mermaid => ("text/plain; charset=utf-8", tree.to_mermaid(target)),
+            };
+
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, content_type)],
+                graph,
+            )
+                .into_response()
+        }
+        Err(err) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Failed to render SPF graph of \"{}\": {} ({}ms)",
+                params.domain, err, elapsed_ms
+            ));
+
+            Problem(StatusCode::NOT_FOUND, ErrorResponse::from(&err)).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/dangling-includes",
+    params(SpfTreeParams, TraversalParams),
+    responses(
+        (status = 200, description = "The terms pointing to domains without a record", body = DanglingResponse),
+        (status = 404, description = "The check failed, e.g. `DNS_LOOKUP_FAILED`", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn dangling_includes(
+    Query(params): Query<SpfTreeParams>,
+    checker: State<SpfChecker>,
+) -> Response {
+    let start = std::time::Instant::now();
+
+    let options = params.traversal.check_options();
+
+    match checker.dangling_terms(&params.domain, &options).await {
+        Ok(dangling) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Found {} dangling includes in the SPF tree of \"{}\" ({}ms)",
+                dangling.len(),
+                params.domain,
+                elapsed_ms
+            ));
+
+            let response = DanglingResponse {
+                domain: params.domain,
+                elapsed_ms,
+                dangling: dangling
+                    .into_iter()
+                    .map(DanglingTermResponse::from)
+                    .collect(),
+            };
+
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(err) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Failed to find dangling includes of \"{}\": {} ({}ms)",
+                params.domain, err, elapsed_ms
+            ));
+
+            Problem(StatusCode::NOT_FOUND, ErrorResponse::from(&err)).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/optimize-spf",
+    params(OptimizeParams),
+    responses(
+        (status = 200, description = "The suggested optimizations", body = OptimizeResponse),
+        (status = 404, description = "The check failed, e.g. `DNS_LOOKUP_FAILED`", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn optimize_spf(
+    Query(params): Query<OptimizeParams>,
+    checker: State<SpfChecker>,
+) -> Response {
+    let start = std::time::Instant::now();
+
+    match checker.optimize(&params.domain).await {
+        Ok(optimized) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Suggested {} optimizations for \"{}\" ({}ms)",
+                optimized.suggestions.len(),
+                params.domain,
+                elapsed_ms
+            ));
+
+            let response = OptimizeResponse {
+                domain: params.domain,
+                record: optimized.record,
+                optimized_record: optimized.optimized_record,
+                elapsed_ms,
+                suggestions: optimized
+                    .suggestions
+                    .into_iter()
+                    .map(OptimizationResponse::from)
+                    .collect(),
+            };
+
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(err) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Failed to optimize the SPF record of \"{}\": {} ({}ms)",
+                params.domain, err, elapsed_ms
+            ));
+
+            Problem(StatusCode::NOT_FOUND, ErrorResponse::from(&err)).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/spf-mechanisms",
+    params(SpfTreeParams, TraversalParams),
+    responses(
+        (status = 200, description = "Every mechanism of the SPF tree with its source", body = SpfMechanismsResponse),
+        (status = 404, description = "The check failed, e.g. `DNS_LOOKUP_FAILED`", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn spf_mechanisms(
+    Query(params): Query<SpfTreeParams>,
+    checker: State<SpfChecker>,
+) -> Response {
+    let start = std::time::Instant::now();
+
+    let options = params.traversal.check_options();
+
+    match checker.tree(&params.domain, &options).await {
+        Ok(tree) => {
+            let mechanisms = tree.mechanisms();
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Listed {} mechanisms of the SPF tree of \"{}\" ({}ms)",
+                mechanisms.len(),
+                params.domain,
+                elapsed_ms
+            ));
+
+            let response = SpfMechanismsResponse {
+                domain: params.domain,
+                elapsed_ms,
+                mechanisms: mechanisms
+                    .into_iter()
+                    .map(SourcedMechanismResponse::from)
+                    .collect(),
+            };
+
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(err) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Failed to list mechanisms of the SPF tree of \"{}\": {} ({}ms)",
+                params.domain, err, elapsed_ms
+            ));
+
+            Problem(StatusCode::NOT_FOUND, ErrorResponse::from(&err)).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/diff-spf",
+    params(DiffSpfParams, TraversalParams),
+    responses(
+        (status = 200, description = "The differences between both trees", body = DiffSpfResponse),
+        (status = 404, description = "The check failed, e.g. `DNS_LOOKUP_FAILED`", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn diff_spf(Query(params): Query<DiffSpfParams>, checker: State<SpfChecker>) -> Response {
+    let start = std::time::Instant::now();
+
+    let options = params.traversal.check_options();
+
+    match checker
+        .diff(&params.domain_a, &params.domain_b, &options)
+        .await
+    {
+        Ok(diff) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Compared SPF trees of \"{}\" and \"{}\" ({}ms)",
+                params.domain_a, params.domain_b, elapsed_ms
+            ));
+
+            let response = DiffSpfResponse::new(params.domain_a, params.domain_b, elapsed_ms, diff);
+
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(err) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Failed to compare SPF trees of \"{}\" and \"{}\": {} ({}ms)",
+                params.domain_a, params.domain_b, err, elapsed_ms
+            ));
+
+            Problem(StatusCode::NOT_FOUND, ErrorResponse::from(&err)).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/history",
+    params(HistoryParams),
+    responses(
+        (status = 200, description = "The stored results of past checks", body = HistoryResponse),
+    )
+)]
+async fn check_history(Query(params): Query<HistoryParams>, history: State<History>) -> Response {
+    let results = history.results(&params.domain, &params.target);
+
+    log_message(format!(
+        "Listed {} past results of \"{}\" for \"{}\"",
+        results.len(),
+        params.domain,
+        params.target
+    ));
+
+    let response = HistoryResponse {
+        domain: history::history_key(&params.domain),
+        target: history::history_key(&params.target),
+        results,
+    };
+
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/cache/stats",
+    responses(
+        (status = 200, description = "The counters of the DNS cache", body = CacheStatsResponse),
+        (status = 401, description = "The request lacks the admin token as bearer token, `UNAUTHORIZED`", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn cache_stats(dns_cache: State<DnsCache>) -> Response {
+    let response = CacheStatsResponse::from(dns_cache.stats());
+
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/cache/purge",
+    params(PurgeParams),
+    responses(
+        (status = 200, description = "The cached records and responses of `domain` were removed, if any", body = PurgeResponse),
+        (status = 400, description = "`domain` is not a valid domain name", body = ProblemResponse, content_type = "application/problem+json"),
+        (status = 401, description = "The request lacks the admin token as bearer token, `UNAUTHORIZED`", body = ProblemResponse, content_type = "application/problem+json"),
+        (status = 503, description = "The cache is unreachable, e.g. Redis", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn purge_cache(
+    Query(params): Query<PurgeParams>,
+    dns_cache: State<DnsCache>,
+    response_cache: State<ResponseCache>,
+) -> Response {
+    let domain = match spf_checker::domain_to_ascii(&params.domain) {
+        Ok(domain) => domain,
+        Err(err) => {
+            return Problem(StatusCode::BAD_REQUEST, ErrorResponse::from(&err)).into_response();
+        }
+    };
+
+    // Otherwise the next checks would still be answered with the purged records.
+    let purged_responses = response_cache.purge(&domain);
+
+    match dns_cache.purge(&domain).await {
+        Ok(purged) => {
+            let purged = purged || purged_responses;
+            log_message(format!(
+                "Purged the cached records of \"{domain}\" ({})",
+                if purged { "cached" } else { "not cached" }
+            ));
+
+            (StatusCode::OK, Json(PurgeResponse { domain, purged })).into_response()
+        }
+        Err(err) => {
+            log_message(format!(
+                "Failed to purge the cached records of \"{domain}\": {err:#}"
+            ));
+
+            ServiceError::CacheUnavailable(format!("{err:#}")).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/spf-coverage",
+    params(DiffSpfParams, TraversalParams),
+    responses(
+        (status = 200, description = "Whether `domain_a` authorizes everything `domain_b` does", body = CoverageResponse),
+        (status = 404, description = "The check failed, e.g. `DNS_LOOKUP_FAILED`", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn spf_coverage(Query(params): Query<DiffSpfParams>, checker: State<SpfChecker>) -> Response {
+    let start = std::time::Instant::now();
+
+    let options = params.traversal.check_options();
+
+    match checker
+        .coverage(&params.domain_a, &params.domain_b, &options)
+        .await
+    {
+        Ok(Coverage {
+            covers,
+            uncovered_networks,
+            skipped_mechanisms,
+        }) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Compared coverage of \"{}\" by \"{}\": {} uncovered networks ({}ms)",
+                params.domain_b,
+                params.domain_a,
+                uncovered_networks.len(),
+                elapsed_ms
+            ));
+
+            let response = CoverageResponse {
+                domain_a: params.domain_a,
+                domain_b: params.domain_b,
+                elapsed_ms,
+                covers,
+                uncovered_networks: uncovered_networks.iter().map(ToString::to_string).collect(),
+                skipped_mechanisms,
+            };
+
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(err) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Failed to compare coverage of \"{}\" by \"{}\": {} ({}ms)",
+                params.domain_b, params.domain_a, err, elapsed_ms
+            ));
+
+            Problem(StatusCode::NOT_FOUND, ErrorResponse::from(&err)).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/validate-spf",
+    params(ValidateParams),
+    responses(
+        (status = 200, description = "The findings of the validation", body = ValidateResponse),
+        (status = 404, description = "The check failed, e.g. `DNS_LOOKUP_FAILED`", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn validate_spf(
+    Query(params): Query<ValidateParams>,
+    checker: State<SpfChecker>,
+) -> Response {
+    let start = std::time::Instant::now();
+
+    let options = CheckOptions {
+        ip: params.ip,
+        sender: params.sender,
+        helo: params.helo,
+        max_depth: params.max_depth,
+        follow_redirects: params.follow_redirects.unwrap_or(true),
+        ..CheckOptions::default()
+    };
+
+    match checker.validate(&params.domain, &options).await {
+        Ok(validation) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Validated \"{}\": {} findings ({}ms)",
+                params.domain,
+                validation.findings.len(),
+                elapsed_ms
+            ));
+
+            let response = ValidateResponse {
+                domain: params.domain,
+                valid: validation.is_valid(),
+                checked_domains: validation.checked_domains,
+                dns_lookups: validation.dns_lookups,
+                elapsed_ms,
+                findings: validation
+                    .findings
+                    .into_iter()
+                    .map(FindingResponse::from)
+                    .collect(),
+            };
+
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(err) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Failed to validate \"{}\": {} ({}ms)",
+                params.domain, err, elapsed_ms
+            ));
+
+            Problem(StatusCode::NOT_FOUND, ErrorResponse::from(&err)).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/lookup-budget",
+    params(LookupBudgetParams),
+    responses(
+        (status = 200, description = "The terms counting against the DNS lookup limit", body = LookupBudgetResponse),
+        (status = 404, description = "The check failed, e.g. `DNS_LOOKUP_FAILED`", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn lookup_budget(
+    Query(params): Query<LookupBudgetParams>,
+    checker: State<SpfChecker>,
+) -> Response {
+    let start = std::time::Instant::now();
+
+    let options = CheckOptions {
+        ip: params.ip,
+        sender: params.sender,
+        helo: params.helo,
+        ..CheckOptions::default()
+    };
+
+    match checker.lookup_budget(&params.domain, &options).await {
+        Ok(budget) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Counted {} DNS lookups for \"{}\" ({}ms)",
+                budget.total(),
+                params.domain,
+                elapsed_ms
+            ));
+
+            let response = LookupBudgetResponse {
+                domain: params.domain,
+                total: budget.total(),
+                limit: LookupBudget::LIMIT,
+                over_limit: budget.over_limit(),
+                elapsed_ms,
+                levels: budget
+                    .per_level()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(depth, lookups)| LevelResponse { depth, lookups })
+                    .collect(),
+                terms: budget
+                    .terms
+                    .into_iter()
+                    .map(BudgetTermResponse::from)
+                    .collect(),
+                loops: budget.loops,
+            };
+
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(err) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Failed to count DNS lookups for \"{}\": {} ({}ms)",
+                params.domain, err, elapsed_ms
+            ));
+
+            Problem(StatusCode::NOT_FOUND, ErrorResponse::from(&err)).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/check-dmarc",
+    params(DmarcCheckParams),
+    responses(
+        (status = 200, description = "The DMARC record and its findings", body = DmarcCheckResponse),
+        (status = 404, description = "The check failed, e.g. `DNS_LOOKUP_FAILED`", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn check_dmarc(
+    Query(params): Query<DmarcCheckParams>,
+    checker: State<SpfChecker>,
+) -> Response {
+    match run_check_dmarc(&checker, params).await {
+        Ok(response) => (StatusCode::OK, Json(response)).into_response(),
+        Err(error) => Problem(StatusCode::NOT_FOUND, error).into_response(),
+    }
+}
+
+async fn run_check_dmarc(
+    checker: &SpfChecker,
+    params: DmarcCheckParams,
+) -> std::result::Result<DmarcCheckResponse, ErrorResponse> {
+    let start = std::time::Instant::now();
+
+    match checker.check_dmarc(&params.domain).await {
+        Ok(report) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Checked DMARC of \"{}\": {} findings ({}ms)",
+                params.domain,
+                report.findings.len(),
+                elapsed_ms
+            ));
+
+            Ok(DmarcCheckResponse {
+                domain: params.domain,
+                valid: report.is_valid(),
+                record_domain: report.record_domain,
+                record: report.record,
+                policy: report.parsed.map(DmarcPolicyResponse::from),
+                elapsed_ms,
+                findings: report
+                    .findings
+                    .into_iter()
+                    .map(FindingResponse::from)
+                    .collect(),
+            })
+        }
+        Err(err) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Failed to check DMARC of \"{}\": {} ({}ms)",
+                params.domain, err, elapsed_ms
+            ));
+
+            Err(ErrorResponse::from(&err))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/check-mx",
+    params(MxCheckParams),
+    responses(
+        (status = 200, description = "The MX hosts and their findings", body = MxCheckResponse),
+        (status = 404, description = "The check failed, e.g. `DNS_LOOKUP_FAILED`", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn check_mx(Query(params): Query<MxCheckParams>, checker: State<SpfChecker>) -> Response {
+    let start = std::time::Instant::now();
+
+    match checker.check_mx(&params.domain).await {
+        Ok(report) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Checked MX of \"{}\": {} hosts ({}ms)",
+                params.domain,
+                report.hosts.len(),
+                elapsed_ms
+            ));
+
+            let response = MxCheckResponse {
+                domain: params.domain,
+                null_mx: report.null_mx,
+                valid: report.is_valid(),
+                elapsed_ms,
+                hosts: report.hosts.into_iter().map(MxHostResponse::from).collect(),
+                findings: report
+                    .findings
+                    .into_iter()
+                    .map(FindingResponse::from)
+                    .collect(),
+            };
+
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(err) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Failed to check MX of \"{}\": {} ({}ms)",
+                params.domain, err, elapsed_ms
+            ));
+
+            Problem(StatusCode::NOT_FOUND, ErrorResponse::from(&err)).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/check-mta-sts",
+    params(MtaStsCheckParams),
+    responses(
+        (status = 200, description = "The MTA-STS policy and its findings", body = MtaStsCheckResponse),
+        (status = 404, description = "The check failed, e.g. `DNS_LOOKUP_FAILED`", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn check_mta_sts(
+    Query(params): Query<MtaStsCheckParams>,
+    checker: State<SpfChecker>,
+) -> Response {
+    let start = std::time::Instant::now();
+
+    match checker.check_mta_sts(&params.domain).await {
+        Ok(report) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Checked MTA-STS of \"{}\": {} findings ({}ms)",
+                params.domain,
+                report.findings.len(),
+                elapsed_ms
+            ));
+
+            let response = MtaStsCheckResponse {
+                domain: params.domain,
+                valid: report.is_valid(),
+                sts_record: report.sts_record,
+                policy_id: report.policy_id,
+                policy_url: report.policy_url,
+                policy: report.policy.map(MtaStsPolicyResponse::from),
+                tls_rpt_record: report.tls_rpt_record,
+                tls_rpt_uris: report.tls_rpt_uris,
+                elapsed_ms,
+                findings: report
+                    .findings
+                    .into_iter()
+                    .map(FindingResponse::from)
+                    .collect(),
+            };
+
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(err) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Failed to check MTA-STS of \"{}\": {} ({}ms)",
+                params.domain, err, elapsed_ms
+            ));
+
+            Problem(StatusCode::NOT_FOUND, ErrorResponse::from(&err)).into_response()
+        }
+    }
+}
+
+/// Returns `true` if `selector` consists of valid DNS labels.
+fn is_valid_selector(selector: &str) -> bool {
+    selector.split('.').all(|label| {
+        (1..=63).contains(&label.len())
+            && label
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/check-bimi",
+    params(BimiCheckParams),
+    responses(
+        (status = 200, description = "The BIMI record and its findings", body = BimiCheckResponse),
+        (status = 400, description = "Invalid selector", body = ProblemResponse, content_type = "application/problem+json"),
+        (status = 404, description = "The check failed, e.g. `DNS_LOOKUP_FAILED`", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn check_bimi(Query(params): Query<BimiCheckParams>, checker: State<SpfChecker>) -> Response {
+    let start = std::time::Instant::now();
+
+    let selector = params
+        .selector
+        .unwrap_or_else(|| DEFAULT_SELECTOR.to_string());
+
+    if !is_valid_selector(&selector) {
+        log_message(format!(
+            "Rejected BIMI check of \"{}\" with invalid selector \"{}\"",
+            params.domain, selector
+        ));
+
+        return ServiceError::InvalidSelector.into_response();
+    }
+
+    match checker.check_bimi(&params.domain, &selector).await {
+        Ok(report) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Checked BIMI of \"{}\" for selector \"{}\": {} findings ({}ms)",
+                params.domain,
+                selector,
+                report.findings.len(),
+                elapsed_ms
+            ));
+
+            let response = BimiCheckResponse {
+                domain: params.domain,
+                selector,
+                valid: report.is_valid(),
+                record_domain: report.record_domain,
+                record: report.record,
+                bimi: report.parsed.map(BimiResponse::from),
+                elapsed_ms,
+                findings: report
+                    .findings
+                    .into_iter()
+                    .map(FindingResponse::from)
+                    .collect(),
+            };
+
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(err) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Failed to check BIMI of \"{}\": {} ({}ms)",
+                params.domain, err, elapsed_ms
+            ));
+
+            Problem(StatusCode::NOT_FOUND, ErrorResponse::from(&err)).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/domain-report",
+    params(DomainReportParams),
+    responses(
+        (status = 200, description = "The checks of the domain and their score", body = DomainReportResponse),
+    )
+)]
+async fn domain_report(
+    Query(params): Query<DomainReportParams>,
+    checker: State<SpfChecker>,
+) -> Response {
+    let response = run_domain_report(&checker, params).await;
+
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+async fn run_domain_report(
+    checker: &SpfChecker,
+    params: DomainReportParams,
+) -> DomainReportResponse {
+    let start = std::time::Instant::now();
+    let domain = &params.domain;
+    let options = CheckOptions::default();
+
+    let (spf, dmarc, dkim, mx, mta_sts) = tokio::join!(
+        checker.validate(domain, &options),
+        checker.check_dmarc(domain),
+        checker.check_dkim(domain, COMMON_DKIM_SELECTORS),
+        checker.check_mx(domain),
+        checker.check_mta_sts(domain),
+    );
+
+    let (dkim, selectors) = match dkim {
+        Ok(report) => {
+            let selectors = report.keys.into_iter().map(|key| key.selector).collect();
+            (Ok(report.findings), selectors)
+        }
+        Err(err) => (Err(err), Vec::new()),
+    };
 
-static CARGO_PKG_NAME: &str = env!("CARGO_PKG_NAME");
-static CARGO_PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
+    let spf = ReportCheckResponse::new(SPF_WEIGHT, spf.map(|validation| validation.findings));
+    let dmarc = ReportCheckResponse::new(DMARC_WEIGHT, dmarc.map(|report| report.findings));
+    let dkim = DkimReportCheckResponse {
+        check: ReportCheckResponse::new(DKIM_WEIGHT, dkim),
+        selectors,
+    };
+    let mx = ReportCheckResponse::new(MX_WEIGHT, mx.map(|report| report.findings));
+    let mta_sts = ReportCheckResponse::new(MTA_STS_WEIGHT, mta_sts.map(|report| report.findings));
 
-type Result<T> = anyhow::Result<T>;
+    let score = spf.points + dmarc.points + dkim.check.points + mx.points + mta_sts.points;
+    let elapsed_ms = start.elapsed().as_millis() as u64;
 
-#[derive(Debug, Deserialize)]
-struct SpfCheckParams {
-    domain: String,
-    target: String,
+    log_message(format!(
+        "Built domain report of \"{}\": score {} ({}ms)",
+        params.domain, score, elapsed_ms
+    ));
+
+    DomainReportResponse {
+        domain: params.domain,
+        score,
+        elapsed_ms,
+        spf,
+        dmarc,
+        dkim,
+        mx,
+        mta_sts,
+    }
 }
 
-#[derive(Debug, Serialize)]
-struct SpfCheckResponse {
-    found: bool,
-    checked_domains: usize,
+#[utoipa::path(
+    get,
+    path = "/api/v1/txt",
+    params(TxtParams),
+    responses(
+        (status = 200, description = "The TXT records of the domain", body = TxtResponse),
+        (status = 404, description = "The check failed, e.g. `DNS_LOOKUP_FAILED`", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn txt_records(Query(params): Query<TxtParams>, checker: State<SpfChecker>) -> Response {
+    let start = std::time::Instant::now();
+
+    match checker.txt_records(&params.domain).await {
+        Ok(records) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Looked up {} TXT records of \"{}\" ({}ms)",
+                records.len(),
+                params.domain,
+                elapsed_ms
+            ));
+
+            let response = TxtResponse {
+                domain: params.domain,
+                elapsed_ms,
+                records: records.into_iter().map(TxtRecordResponse::from).collect(),
+            };
+
+            (StatusCode::OK, Json(response)).into_response()
+        }
+        Err(err) => {
+            let elapsed_ms = start.elapsed().as_millis() as u64;
+
+            log_message(format!(
+                "Failed to look up TXT records of \"{}\": {} ({}ms)",
+                params.domain, err, elapsed_ms
+            ));
+
+            Problem(StatusCode::NOT_FOUND, ErrorResponse::from(&err)).into_response()
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/propagation",
+    params(PropagationParams),
+    responses(
+        (status = 200, description = "The SPF records returned by each resolver", body = PropagationResponse),
+    )
+)]
+async fn check_propagation(
+    Query(params): Query<PropagationParams>,
+    State(resolvers): State<Resolvers>,
+) -> Response {
+    let start = std::time::Instant::now();
+
+    let tasks: Vec<_> = resolvers
+        .0
+        .iter()
+        .map(|(name, checker)| {
+            let checker = checker.clone();
+            let domain = params.domain.clone();
+
+            (*name, tokio::spawn(resolver_answer(name, checker, domain)))
+        })
+        .collect();
+
+    let mut answers = Vec::with_capacity(tasks.len());
+
+    for (name, task) in tasks {
+        answers.push(task.await.unwrap_or_else(|_| ResolverAnswerResponse {
+            resolver: name.to_string(),
+            spf_records: Vec::new(),
+            ttl: None,
+            error: Some(ServiceError::InternalError.code().to_string()),
+            elapsed_ms: 0,
+        }));
+    }
+
+    let consistent = answers
+        .iter()
+        .all(|answer| answer.error.is_none() && answer.spf_records == answers[0].spf_records);
+
+    let elapsed_ms = start.elapsed().as_millis() as u64;
+
+    log_message(format!(
+        "Compared SPF records of \"{}\" across {} resolvers: {} ({}ms)",
+        params.domain,
+        answers.len(),
+        if consistent {
+            "consistent"
+        } else {
+            "inconsistent"
+        },
+        elapsed_ms
+    ));
+
+    let response = PropagationResponse {
+        domain: params.domain,
+        consistent,
+        elapsed_ms,
+        resolvers: answers,
+    };
+
+    (StatusCode::OK, Json(response)).into_response()
+}
+
+/// The SPF records of `domain` returned by the resolver of `checker`.
+async fn resolver_answer(
+    resolver: &'static str,
+    checker: SpfChecker,
     domain: String,
-    target: String,
-    elapsed_ms: u64,
-    has_spf_record: bool,
-    spf_record: Option<String>,
-    included_domains: Option<Vec<String>>,
-    fallback_check: bool,
+) -> ResolverAnswerResponse {
+    let start = std::time::Instant::now();
+    let records = checker.txt_records(&domain).await;
+
+    let mut answer = ResolverAnswerResponse {
+        resolver: resolver.to_string(),
+        spf_records: Vec::new(),
+        ttl: None,
+        error: None,
+        elapsed_ms: start.elapsed().as_millis() as u64,
+    };
+
+    match records {
+        Ok(records) => {
+            let spf_records: Vec<_> = records.into_iter().filter(TxtRecord::is_spf).collect();
+
+            answer.ttl = spf_records.iter().map(|record| record.ttl).min();
+            answer.spf_records = spf_records.into_iter().map(|record| record.text).collect();
+            answer.spf_records.sort();
+        }
+        Err(err) => answer.error = Some(err.to_string()),
+    }
+
+    answer
 }
 
-#[derive(Debug, Serialize)]
-struct ErrorResponse {
-    error: String,
+#[utoipa::path(
+    get,
+    path = "/api/v1/evaluate",
+    params(EvaluateParams),
+    responses(
+        (status = 200, description = "The result of `check_host()` for the client", body = EvaluateResponse),
+    )
+)]
+async fn evaluate(Query(params): Query<EvaluateParams>, checker: State<SpfChecker>) -> Response {
+    let start = std::time::Instant::now();
+
+    let Evaluation {
+        result,
+        mechanism,
+        mechanism_domain,
+        dns_lookups,
+        void_lookups,
+        error,
+        explanation,
+    } = checker
+        .check_host(params.ip, &params.sender, &params.helo)
+        .await;
+
+    let elapsed_ms = start.elapsed().as_millis() as u64;
+
+    log_message(format!(
+        "Evaluated {} for sender \"{}\" (HELO \"{}\"): {} ({}ms)",
+        params.ip, params.sender, params.helo, result, elapsed_ms
+    ));
+
+    let response = EvaluateResponse {
+        result: result.to_string(),
+        ip: params.ip,
+        sender: params.sender,
+        helo: params.helo,
+        mechanism,
+        mechanism_domain,
+        dns_lookups,
+        void_lookups,
+        error,
+        explanation,
+        elapsed_ms,
+    };
+
+    (StatusCode::OK, Json(response)).into_response()
 }
 
-fn log_message(msg: impl AsRef<str>) {
-    println!(
-        "[{}] {}",
-        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f"),
-        msg.as_ref()
-    );
+#[utoipa::path(
+    get,
+    path = "/api/v1/check-ip",
+    params(CheckIpParams),
+    responses(
+        (status = 200, description = "The evaluation of the IP address", body = CheckIpResponse),
+        (status = 404, description = "The check failed, e.g. `DNS_LOOKUP_FAILED`", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+async fn check_ip(Query(params): Query<CheckIpParams>, checker: State<SpfChecker>) -> Response {
+    match run_check_ip(&checker, params).await {
+        Ok(response) => (StatusCode::OK, Json(response)).into_response(),
+        Err(error) => Problem(StatusCode::NOT_FOUND, error).into_response(),
+    }
 }
 
-async fn check_spf(Query(params): Query<SpfCheckParams>, checker: State<SpfChecker>) -> Response {
+async fn run_check_ip(
+    checker: &SpfChecker,
+    params: CheckIpParams,
+) -> std::result::Result<CheckIpResponse, ErrorResponse> {
     let start = std::time::Instant::now();
 
-    match checker.check(&params.domain, &params.target).await {
-        Ok(CheckResult {
-            found,
-            visited,
-            spf_record,
-            included_domains,
-            fallback_check,
-        }) => {
+    match checker.check_ip(&params.domain, params.ip).await {
+        Ok(evaluation) => {
             let elapsed_ms = start.elapsed().as_millis() as u64;
 
-            let status_msg = match (fallback_check, found) {
-                (true, true) => {
-                    format!(
-                        "Successfully found all mechanisms from \"{}\" in \"{}\" via fallback check ({}ms)",
-                        params.target, params.domain, elapsed_ms
-                    )
-                }
-                (true, false) => {
-                    format!(
-                        "Fallback check completed for \"{}\" in \"{}\" - not all mechanisms found ({}ms)",
-                        params.target, params.domain, elapsed_ms
-                    )
-                }
-                (false, _) => {
-                    format!(
-                        "Successfully checked \"{}\" for \"{}\" ({}ms)",
-                        params.domain, params.target, elapsed_ms
-                    )
-                }
-            };
-
-            log_message(status_msg);
+            log_message(format!(
+                "Checked {} for \"{}\": {} ({}ms)",
+                params.ip, params.domain, evaluation.result, elapsed_ms
+            ));
 
-            let response = SpfCheckResponse {
-                found,
-                checked_domains: visited,
+            Ok(CheckIpResponse {
                 domain: params.domain,
-                target: params.target,
+                ip: params.ip,
+                authorized: evaluation.result == SpfResult::Pass,
+                result: evaluation.result.to_string(),
+                mechanism: evaluation.mechanism,
+                mechanism_domain: evaluation.mechanism_domain,
+                dns_lookups: evaluation.dns_lookups,
+                void_lookups: evaluation.void_lookups,
+                error: evaluation.error,
                 elapsed_ms,
-                has_spf_record: spf_record.is_some(),
-                spf_record,
-                included_domains,
-                fallback_check,
-            };
-
-            (StatusCode::OK, Json(response)).into_response()
+            })
         }
         Err(err) => {
             let elapsed_ms = start.elapsed().as_millis() as u64;
 
             log_message(format!(
-                "Failed to check \"{}\" for \"{}\": {} ({}ms)",
-                params.domain, params.target, err, elapsed_ms
+                "Failed to check {} for \"{}\": {} ({}ms)",
+                params.ip, params.domain, err, elapsed_ms
             ));
 
-            let error = ErrorResponse {
-                error: err.to_string(),
-            };
-
-            (StatusCode::NOT_FOUND, Json(error)).into_response()
+            Err(ErrorResponse::from(&err))
         }
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/health",
+    responses(
+        (status = 200, description = "The service is running"),
+    )
+)]
 async fn health() -> StatusCode {
     StatusCode::OK
 }
 
-fn create_tokio_async_resolver() -> TokioAsyncResolver {
-    let mut opts = ResolverOpts::default();
-    opts.timeout = std::time::Duration::from_secs(2);
-    opts.attempts = 2;
-    TokioAsyncResolver::tokio(ResolverConfig::default(), opts)
+#[cfg(test)]
+fn app(checker: SpfChecker) -> Router {
+    router(test_state(checker), Features::default(), None)
+}
+
+/// The state of the handler tests, checking with `checker` without name servers to report.
+#[cfg(test)]
+fn test_state(checker: SpfChecker) -> AppState {
+    shared_state(
+        checker,
+        Nameservers(Arc::from([])),
+        Vec::new(),
+        History::default(),
+        CacheConfig::default(),
+    )
 }
 
-fn app() -> Router {
-    Router::new()
+/// The routes of the HTTP server, without those of disabled `features` and without `/admin`
+/// unless `admin_token` is configured.
+fn router(state: AppState, features: Features, admin_token: Option<AdminToken>) -> Router {
+    let mut router = Router::new()
         .route("/health", get(health))
-        .route("/api/v1/check-spf", get(check_spf))
-        .route("/ui", get(serve_ui))
-        .with_state(SpfChecker::new(create_tokio_async_resolver()))
+        .route("/api/openapi.json", get(openapi::openapi_json))
+        .nest("/api/v1", api_v1())
+        .nest(
+            "/api/v2",
+            v2::routes().layer(middleware::from_fn(openapi::add_schema_version)),
+        )
+        .route("/ws", get(check_spf_socket));
+
+    if let Some(admin_token) = admin_token {
+        router = router.nest(
+            "/admin",
+            admin()
+                .layer(middleware::from_fn(openapi::add_schema_version))
+                .layer(middleware::from_fn_with_state(
+                    admin_token,
+                    admin::require_token,
+                )),
+        );
+    }
+    if features.graphql {
+        router = router.route_service(
+            "/graphql",
+            GraphQL::new(graphql::schema(
+                state.checker.clone(),
+                state.nameservers.clone(),
+            )),
+        );
+    }
+    if features.ui {
+        router = router.route("/", get(serve_ui)).route("/ui", get(serve_ui));
+    }
+    let router = router.with_state(state);
+
+    #[cfg(feature = "swagger-ui")]
+    let router = router.merge(openapi::swagger_ui());
+
+    router
+}
+
+/// The routes of `/api/v1`, whose responses must stay unchanged for existing integrations.
+/// Redesigned responses go into a new version, see [`v2::routes`].
+fn api_v1() -> Router<AppState> {
+    Router::new()
+        .route("/check-spf", get(check_spf).post(check_spf_json))
+        .route("/check-spf/batch", post(check_spf_batch))
+        .route("/check-spf/domains", post(check_target_domains))
+        .route("/jobs", post(submit_job))
+        .route("/jobs/{id}", get(get_job))
+        .route("/evaluate-record", post(evaluate_record))
+        .route("/build-spf", post(build_spf))
+        .route("/explain", get(explain))
+        .route("/spf-tree", get(spf_tree))
+        .route("/spf-graph", get(spf_graph))
+        .route("/spf-mechanisms", get(spf_mechanisms))
+        .route("/diff-spf", get(diff_spf))
+        .route("/spf-coverage", get(spf_coverage))
+        .route("/dangling-includes", get(dangling_includes))
+        .route("/optimize-spf", get(optimize_spf))
+        .route("/validate-spf", get(validate_spf))
+        .route("/lookup-budget", get(lookup_budget))
+        .route("/check-dmarc", get(check_dmarc))
+        .route("/check-mx", get(check_mx))
+        .route("/check-mta-sts", get(check_mta_sts))
+        .route("/check-bimi", get(check_bimi))
+        .route("/domain-report", get(domain_report))
+        .route("/txt", get(txt_records))
+        .route("/evaluate", get(evaluate))
+        .route("/check-ip", get(check_ip))
+        .route("/propagation", get(check_propagation))
+        .route("/history", get(check_history))
+        // Only wraps the routes above, the served JSON Schemas are left as they are.
+        .layer(middleware::from_fn(openapi::add_schema_version))
+        .route("/schema", get(openapi::schema_names))
+        .route("/schema/{name}", get(openapi::json_schema))
+}
+
+/// The routes for operators of the service.
+fn admin() -> Router<AppState> {
+    Router::new()
+        .route("/cache/stats", get(cache_stats))
+        .route("/cache/purge", post(purge_cache))
+}
+
+fn app_state(
+    history: History,
+    cache_config: CacheConfig,
+    resolver: &ResolverSettings,
+) -> Result<AppState> {
+    let nameservers = Nameservers(resolver.nameservers().into());
+    let uncached = resolver
+        .checker()?
+        .with_dnssec_validator(resolver.validating_resolver());
+
+    let public_resolvers = [
+        ("google", ResolverConfig::google()),
+        ("cloudflare", ResolverConfig::cloudflare()),
+        ("quad9", ResolverConfig::quad9()),
+    ]
+    .into_iter()
+    .map(|(name, config)| (name, SpfChecker::new(resolver.resolver(config))))
+    .collect();
+
+    Ok(shared_state(
+        uncached,
+        nameservers,
+        public_resolvers,
+        history,
+        cache_config,
+    ))
+}
+
+/// The state of the checks of `uncached`, which answers the propagation check for the system
+/// resolver next to `public_resolvers`.
+fn shared_state(
+    uncached: SpfChecker,
+    nameservers: Nameservers,
+    public_resolvers: Vec<(&'static str, SpfChecker)>,
+    history: History,
+    mut cache_config: CacheConfig,
+) -> AppState {
+    let warm_domains = std::mem::take(&mut cache_config.warm_domains);
+    let response_cache = ResponseCache::new(cache_config.response_ttl);
+    let dns_cache = cache_config.open();
+    cache::warm(&uncached, &dns_cache, warm_domains);
+    let checker = uncached.with_dns_cache(dns_cache.clone()).with_coalescing();
+
+    // The propagation check compares the current answers of the resolvers, not cached ones.
+    let resolvers = std::iter::once((SYSTEM_RESOLVER, uncached))
+        .chain(public_resolvers)
+        .collect();
+
+    AppState {
+        jobs: JobQueue::new(checker.clone(), nameservers.clone()),
+        checker,
+        nameservers,
+        resolvers: Resolvers(Arc::new(resolvers)),
+        history,
+        dns_cache,
+        response_cache,
+    }
 }
 
 async fn serve_ui() -> Html<&'static str> {
@@ -144,12 +4611,60 @@ async fn serve_ui() -> Html<&'static str> {
 async fn main() -> Result<()> {
     log_message(format!("> {CARGO_PKG_NAME} v{CARGO_PKG_VERSION}"));
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
+    let config = Config::load(std::env::args().skip(1))?;
+    let listen = ListenConfig::from_config(&config)?;
+    let features = Features::from_config(&config)?;
+    let admin_token = AdminToken::from_config(&config)?;
+    let state = app_state(
+        History::open(HistoryConfig::from_config(&config)?)?,
+        CacheConfig::from_config(&config)?,
+        &ResolverSettings::from_config(&config)?,
+    )?;
+    let grpc_service = grpc::SpfCheckServer::new(grpc::SpfCheckService::new(
+        state.checker.clone(),
+        state.nameservers.clone(),
+    ));
+
+    let listener = TcpListener::bind(listen.http)
+        .await
+        .with_context(|| format!("Failed to listen on {}", listen.http))?;
+    let grpc_listener = if features.grpc {
+        let grpc_listener = TcpListener::bind(listen.grpc)
+            .await
+            .with_context(|| format!("Failed to listen for gRPC on {}", listen.grpc))?;
+        Some(grpc_listener)
+    } else {
+        None
+    };
+
+    // The bound addresses, which tell the ports picked for port 0.
+    match &grpc_listener {
+        Some(grpc_listener) => log_message(format!(
+            "Listening on {} (gRPC on {})",
+            listener.local_addr()?,
+            grpc_listener.local_addr()?
+        )),
+        None => log_message(format!("Listening on {}", listener.local_addr()?)),
+    }
 
-    log_message(format!("Listening on {}", addr));
+    tokio::try_join!(
+        async {
+            axum::serve(listener, router(state, features, admin_token))
+                .await
+                .context("HTTP server failed")
+        },
+        async {
+            let Some(grpc_listener) = grpc_listener else {
+                return Ok(());
+            };
 
-    let listener = TcpListener::bind(addr).await?;
-    axum::serve(listener, app()).await?;
+            tonic::transport::Server::builder()
+                .add_service(grpc_service)
+                .serve_with_incoming(TcpIncoming::from(grpc_listener))
+                .await
+                .context("gRPC server failed")
+        },
+    )?;
 
     Ok(())
 }
@@ -158,25 +4673,611 @@ async fn main() -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use axum::{
-        body::Body,
-        http::Request,
-    };
+    use crate::test_support::MockResolver;
+    use axum::body::Body;
+    use axum::http::{Request, Uri};
     use tower::ServiceExt;
+    use utoipa::OpenApi;
+
+    #[tokio::test]
+    async fn test_check_spf() {
+        let resolver = MockResolver::default();
+        resolver.add_record("example.com", "v=spf1 include:_spf.example.net -all");
+        resolver.add_record("_spf.example.net", "v=spf1 ip4:192.0.2.0/24 -all");
+        resolver.fail_lookups("broken.example.com");
+        let app = app(resolver.checker());
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/api/v1/check-spf?domain=example.com&target=_spf.example.net")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["found"], true);
+        assert_eq!(body["included_domains"][0], "_spf.example.net");
+
+        let response = app
+            .oneshot(
+                Request::get("/api/v1/check-spf?domain=broken.example.com&target=_spf.example.net")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    /// Sends `request` to `app`, returning the status and the JSON body, `null` if it has none.
+    async fn send(app: &Router, request: Request<Body>) -> (StatusCode, serde_json::Value) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    fn post_json(uri: &str, body: serde_json::Value) -> Request<Body> {
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_check_spf_meta() {
+        let resolver = MockResolver::default();
+        resolver.add_record("example.com", "v=spf1 include:_spf.example.net -all");
+        let mut cache_config = CacheConfig::default();
+        cache_config.response_ttl = Duration::from_secs(30);
+        let state = shared_state(
+            resolver.checker(),
+            Nameservers(Arc::from(["192.0.2.53:53".to_string()])),
+            Vec::new(),
+            History::default(),
+            cache_config,
+        );
+        let app = router(state, Features::default(), None);
+
+        let uri = "/api/v1/check-spf?domain=Example.COM.&target=_spf.example.net";
+        let (status, body) = send(&app, Request::get(uri).body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["schema_version"], openapi::SCHEMA_VERSION);
+        assert!(body["elapsed_ms"].is_u64());
+        let meta = &body["meta"];
+        let checked_at = meta["checked_at"].as_str().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(checked_at).is_ok());
+        assert_eq!(meta["domain"], "example.com");
+        assert_eq!(meta["target"], "_spf.example.net");
+        assert_eq!(meta["resolver"], SYSTEM_RESOLVER);
+        assert_eq!(meta["nameservers"], serde_json::json!(["192.0.2.53:53"]));
+        assert_eq!(meta["cached"], false);
+
+        // Served from the response cache, also for the same check as JSON body.
+        let response = app
+            .clone()
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(response.headers().contains_key(header::AGE));
+        let params = serde_json::json!({ "domain": "example.com", "target": "_spf.example.net" });
+        let (status, body) = send(&app, post_json("/api/v1/check-spf", params)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["meta"]["cached"], true);
+        assert_eq!(body["meta"]["checked_at"], checked_at);
+    }
+
+    #[tokio::test]
+    async fn test_check_spf_result() {
+        let resolver = MockResolver::default();
+        resolver.add_record("pass.example.com", "v=spf1 include:_spf.example.net -all");
+        resolver.add_record("fail.example.com", "v=spf1 ip4:192.0.2.1 -all");
+        resolver.add_record(
+            "softfail.example.com",
+            "v=spf1 ~include:_spf.example.net -all",
+        );
+        resolver.add_record("neutral.example.com", "v=spf1 ip4:192.0.2.1");
+        resolver.add_record("none.example.com", "google-site-verification=abc");
+        resolver.add_record(
+            "permerror.example.com",
+            "v=spf1 include:broken.example.com -all",
+        );
+        resolver.add_record("broken.example.com", "v=spf1 ip4:192.0.2.1/99 -all");
+        resolver.add_record(
+            "temperror.example.com",
+            "v=spf1 include:timeout.example.com -all",
+        );
+        resolver.fail_lookups("timeout.example.com");
+        let app = app(resolver.checker());
+
+        for result in [
+            "pass",
+            "fail",
+            "softfail",
+            "neutral",
+            "none",
+            "permerror",
+            "temperror",
+        ] {
+            let uri =
+                format!("/api/v1/check-spf?domain={result}.example.com&target=_spf.example.net");
+            let (status, body) = send(&app, Request::get(uri).body(Body::empty()).unwrap()).await;
+            assert_eq!(status, StatusCode::OK, "{result}");
+            assert_eq!(body["result"], result);
+        }
+    }
 
     #[tokio::test]
-    #[ignore = "An example for integration test against axum. Not yet fully implemented"]
-    async fn test_check_spf_with_provided_domains() {
-        let app = app();
+    async fn test_check_spf_json() {
+        let resolver = MockResolver::default();
+        resolver.add_record(
+            "example.com",
+            "v=spf1 include:_spf.example.net ip4:192.0.2.0/24 -all",
+        );
+        resolver.add_record("_spf.example.net", "v=spf1 ip4:198.51.100.0/24 -all");
+        let app = app(resolver.checker());
+
+        let params = serde_json::json!({ "domain": "example.com", "target": "_spf.example.net" });
+        let (status, body) = send(&app, post_json("/api/v1/check-spf", params)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["found"], true);
+        assert_eq!(body["result"], "pass");
+
+        let params = serde_json::json!({
+            "domain": "example.com",
+            "target": ["_spf.example.net", "192.0.2.1", "203.0.113.1"],
+        });
+        let (status, body) = send(&app, post_json("/api/v1/check-spf", params)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total"], 3);
+        assert_eq!(body["found"], 2);
 
-        let url = format!("/api/v1/check-spf?domain={}&target={}", "auc-online.de", "spf.easybill-mail.de");
+        let params = serde_json::json!({
+            "domain": "example.com",
+            "target": "_spf.example.net",
+            "mechanisms": "ip4,foo",
+        });
+        let (status, body) = send(&app, post_json("/api/v1/check-spf", params)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "INVALID_MECHANISMS");
 
+        let params = serde_json::json!({ "domain": "example.com", "target": "_spf.example.net" });
         let response = app
-            .oneshot(Request::get(url).body(Body::empty()).unwrap())
+            .clone()
+            .oneshot(post_json("/api/v1/check-spf?format=text", params))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()[header::CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain"));
+    }
+
+    #[tokio::test]
+    async fn test_check_spf_tree() {
+        let resolver = MockResolver::default();
+        resolver.add_record(
+            "example.com",
+            "v=spf1 include:_spf.example.net include:broken.example.com -all",
+        );
+        resolver.add_record("_spf.example.net", "v=spf1 ip4:192.0.2.0/24 -all");
+        resolver.add_records(
+            "broken.example.com",
+            &["v=spf1 ip4:198.51.100.1 -all", "v=spf1 -all"],
+        );
+        let app = app(resolver.checker());
+
+        let uri = "/api/v1/check-spf?domain=example.com&target=_spf.example.net";
+        let (status, body) = send(&app, Request::get(uri).body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["result"], "pass");
+        assert!(body["tree"].is_null());
+
+        let (status, body) = send(
+            &app,
+            Request::get(format!("{uri}&tree=true"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["result"], "pass");
+        let tree = &body["tree"];
+        assert_eq!(tree["domain"], "example.com");
+        assert!(tree["error"].is_null());
+        let children = tree["children"].as_array().unwrap();
+        assert_eq!(children.len(), 2);
+        assert_eq!(children[0]["domain"], "_spf.example.net");
+        assert_eq!(children[0]["term"], "include:_spf.example.net");
+        assert_eq!(children[0]["record"], "v=spf1 ip4:192.0.2.0/24 -all");
+        assert!(children[0]["error"].is_null());
+        assert_eq!(children[1]["domain"], "broken.example.com");
+        assert_eq!(children[1]["error"], "MULTIPLE_SPF_RECORDS");
+        assert!(children[1]["record"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_check_spf_batch() {
+        let resolver = MockResolver::default();
+        resolver.add_record("example.com", "v=spf1 include:_spf.example.net -all");
+        resolver.add_record("_spf.example.net", "v=spf1 ip4:192.0.2.0/24 -all");
+        resolver.add_records("multiple.example.com", &["v=spf1 -all", "v=spf1 ~all"]);
+        resolver.fail_lookups("broken.example.com");
+        let app = app(resolver.checker());
+
+        let items = serde_json::json!([
+            { "domain": "example.com", "target": "_spf.example.net" },
+            { "domain": "example.com", "target": "_spf.example.org" },
+            { "domain": "broken.example.com", "target": "_spf.example.net" },
+            { "domain": "multiple.example.com", "target": "_spf.example.net" },
+        ]);
+        let (status, body) = send(&app, post_json("/api/v1/check-spf/batch", items)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            (&body["total"], &body["found"], &body["failed"]),
+            (&4.into(), &1.into(), &2.into())
+        );
+        let results = &body["results"];
+        assert_eq!(results[0]["found"], true);
+        assert_eq!(results[1]["found"], false);
+        assert_eq!(results[2]["domain"], "broken.example.com");
+        assert_eq!(results[2]["error"], "DNS_LOOKUP_FAILED");
+        assert_eq!(results[3]["error"], "MULTIPLE_SPF_RECORDS");
+        assert_eq!(
+            results[3]["spf_records"],
+            serde_json::json!(["v=spf1 -all", "v=spf1 ~all"])
+        );
+
+        // A check with a list of targets counts once per target.
+        let targets = vec!["_spf.example.net"; MAX_BATCH_SIZE / 2];
+        let item = serde_json::json!({ "domain": "example.com", "target": targets });
+        let items = serde_json::json!([item, item]);
+        let (status, body) = send(&app, post_json("/api/v1/check-spf/batch", items)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total"], MAX_BATCH_SIZE);
+
+        let items = serde_json::json!([item, item, { "domain": "example.com", "target": "a.net" }]);
+        let (status, body) = send(&app, post_json("/api/v1/check-spf/batch", items)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "BATCH_TOO_LARGE");
+    }
+
+    #[tokio::test]
+    async fn test_check_target_domains() {
+        let resolver = MockResolver::default();
+        resolver.add_record("a.example.com", "v=spf1 include:_spf.example.net -all");
+        resolver.add_record("b.example.com", "v=spf1 ip4:198.51.100.0/24 -all");
+        resolver.add_record("_spf.example.net", "v=spf1 ip4:192.0.2.0/24 -all");
+        resolver.fail_lookups("c.example.com");
+        let app = app(resolver.checker());
+
+        let params = serde_json::json!({
+            "target": "_spf.example.net",
+            "domains": ["a.example.com", "b.example.com", "c.example.com"],
+        });
+        let (status, body) = send(&app, post_json("/api/v1/check-spf/domains", params)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            (&body["total"], &body["found"], &body["failed"]),
+            (&3.into(), &1.into(), &1.into())
+        );
+        assert_eq!(
+            body["matching_domains"],
+            serde_json::json!(["a.example.com"])
+        );
+        let results = &body["results"];
+        assert_eq!(results[0]["mechanism"], "include:_spf.example.net");
+        assert_eq!(results[1]["found"], false);
+        assert_eq!(results[2]["error"], "DNS_LOOKUP_FAILED");
+
+        let params = serde_json::json!({ "target": " ", "domains": ["a.example.com"] });
+        let (status, body) = send(&app, post_json("/api/v1/check-spf/domains", params)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "INVALID_TARGET_COUNT");
+
+        let domains = vec!["a.example.com"; MAX_TARGET_DOMAINS + 1];
+        let params = serde_json::json!({ "target": "_spf.example.net", "domains": domains });
+        let (status, body) = send(&app, post_json("/api/v1/check-spf/domains", params)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "BATCH_TOO_LARGE");
+    }
+
+    #[tokio::test]
+    async fn test_check_propagation() {
+        let updated = MockResolver::default();
+        updated.add_records(
+            "example.com",
+            &[
+                "v=spf1 include:_spf.example.net -all",
+                "google-site-verification=abc",
+            ],
+        );
+        let stale = MockResolver::default();
+        stale.add_record("example.com", "v=spf1 include:_spf.example.org -all");
+        let failing = MockResolver::default();
+        failing.fail_lookups("example.com");
+
+        let state = shared_state(
+            updated.checker(),
+            Nameservers(Arc::from([])),
+            vec![
+                ("google", updated.checker()),
+                ("cloudflare", stale.checker()),
+            ],
+            History::default(),
+            CacheConfig::default(),
+        );
+        let app = router(state, Features::default(), None);
+
+        let request = Request::get("/api/v1/propagation?domain=example.com")
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = send(&app, request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["consistent"], false);
+        let resolvers = body["resolvers"].as_array().unwrap();
+        let names: Vec<_> = resolvers.iter().map(|answer| &answer["resolver"]).collect();
+        assert_eq!(names, ["system", "google", "cloudflare"]);
+        assert_eq!(
+            resolvers[1]["spf_records"],
+            serde_json::json!(["v=spf1 include:_spf.example.net -all"])
+        );
+        assert_eq!(
+            resolvers[2]["spf_records"],
+            serde_json::json!(["v=spf1 include:_spf.example.org -all"])
+        );
+
+        // Resolvers agreeing on the records are consistent, a failing one is not.
+        for (other, consistent) in [(&updated, true), (&failing, false)] {
+            let state = shared_state(
+                updated.checker(),
+                Nameservers(Arc::from([])),
+                vec![("google", other.checker())],
+                History::default(),
+                CacheConfig::default(),
+            );
+            let app = router(state, Features::default(), None);
+            let request = Request::get("/api/v1/propagation?domain=example.com")
+                .body(Body::empty())
+                .unwrap();
+            let (_, body) = send(&app, request).await;
+            assert_eq!(body["consistent"], consistent);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jobs() {
+        let resolver = MockResolver::default();
+        resolver.add_record("example.com", "v=spf1 include:_spf.example.net -all");
+        resolver.add_record("_spf.example.net", "v=spf1 ip4:192.0.2.0/24 -all");
+        resolver.fail_lookups("broken.example.com");
+        let state = test_state(resolver.checker());
+        let app = router(state.clone(), Features::default(), None);
+
+        let items = serde_json::json!([
+            { "domain": "example.com", "target": ["_spf.example.net", "_spf.example.org"] },
+            { "domain": "broken.example.com", "target": "_spf.example.net" },
+        ]);
+        let (status, body) = send(&app, post_json("/api/v1/jobs", items)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(body["status"], "queued");
+        assert_eq!(body["total"], 3);
+        let id = body["id"].as_str().unwrap().to_string();
+
+        // Waits for the worker instead of polling repeatedly.
+        let job = state.jobs.get(&id).unwrap();
+        loop {
+            let progress = job.progress.notified();
+            if job.response(0, 0).status == JobStatus::Completed {
+                break;
+            }
+            progress.await;
+        }
+
+        let uri = format!("/api/v1/jobs/{id}?limit=2");
+        let (status, body) = send(&app, Request::get(uri).body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "completed");
+        assert_eq!(
+            (&body["completed"], &body["found"], &body["failed"]),
+            (&3.into(), &1.into(), &1.into())
+        );
+        assert_eq!(body["results"].as_array().unwrap().len(), 2);
+        assert_eq!(body["next_offset"], 2);
+
+        let uri = format!("/api/v1/jobs/{id}?offset=2");
+        let (_, body) = send(&app, Request::get(uri).body(Body::empty()).unwrap()).await;
+        assert_eq!(body["results"][0]["domain"], "broken.example.com");
+        assert_eq!(body["results"][0]["error"], "DNS_LOOKUP_FAILED");
+
+        let request = Request::get("/api/v1/jobs/0123456789abcdef")
+            .body(Body::empty())
+            .unwrap();
+        let (status, body) = send(&app, request).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], "JOB_NOT_FOUND");
+    }
+
+    #[tokio::test]
+    async fn test_job_limits() {
+        let app = app(MockResolver::default().checker());
+
+        let targets = vec!["_spf.example.net"; MAX_JOB_SIZE + 1];
+        let items = serde_json::json!([{ "domain": "example.com", "target": targets }]);
+        let (status, body) = send(&app, post_json("/api/v1/jobs", items)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "JOB_TOO_LARGE");
+
+        // The test runtime runs the worker only once the test awaits a pending future, which
+        // the handlers do not, so that all submitted jobs stay queued.
+        let items = serde_json::json!([{ "domain": "example.com", "target": "example.net" }]);
+        for _ in 0..MAX_QUEUED_JOBS {
+            let (status, _) = send(&app, post_json("/api/v1/jobs", items.clone())).await;
+            assert_eq!(status, StatusCode::ACCEPTED);
+        }
+        let (status, body) = send(&app, post_json("/api/v1/jobs", items)).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["error"], "JOB_QUEUE_FULL");
+    }
+
+    #[tokio::test]
+    async fn test_socket() {
+        let resolver = MockResolver::default();
+        resolver.add_record("example.com", "v=spf1 include:_spf.example.net -all");
+        resolver.add_record("_spf.example.net", "v=spf1 ip4:192.0.2.0/24 -all");
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = app(resolver.checker());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let messages = tokio::task::spawn_blocking(move || {
+            let (mut socket, _) = tungstenite::connect(format!("ws://{addr}/ws")).unwrap();
+            let mut messages = Vec::new();
+            for request in [
+                r#"{"id": "1", "domain": "example.com", "target": "_spf.example.net"}"#,
+                "not json",
+            ] {
+                socket.send(request.into()).unwrap();
+                let message = socket.read().unwrap();
+                messages.push(
+                    serde_json::from_str::<serde_json::Value>(message.to_text().unwrap()).unwrap(),
+                );
+            }
+            messages
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(messages[0]["type"], "result");
+        assert_eq!(messages[0]["id"], "1");
+        assert_eq!(messages[0]["result"]["found"], true);
+        assert_eq!(messages[1]["type"], "error");
+        assert_eq!(messages[1]["id"], serde_json::Value::Null);
+        assert_eq!(messages[1]["error"], "INVALID_REQUEST");
+    }
+
+    #[tokio::test]
+    async fn test_admin_token() {
+        let resolver = MockResolver::default();
+        resolver.add_record("example.com", "v=spf1 -all");
+        let checker = resolver.checker();
+
+        // Not served at all without a token configured.
+        let response = app(checker.clone())
+            .oneshot(
+                Request::get("/admin/cache/stats")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let state = test_state(checker);
+        let app = router(
+            state.clone(),
+            Features::default(),
+            Some(AdminToken::new("secret")),
+        );
+        state
+            .checker
+            .check("example.com", "example.net")
             .await
             .unwrap();
+        // The records of the domain and of the target.
+        assert_eq!(state.dns_cache.stats().entries, Some(2));
 
+        for authorization in [None, Some("Bearer wrong"), Some("secret")] {
+            let mut request = Request::post("/admin/cache/purge?domain=example.com");
+            if let Some(authorization) = authorization {
+                request = request.header(header::AUTHORIZATION, authorization);
+            }
+            let response = app
+                .clone()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["error"], "UNAUTHORIZED");
+        }
+        assert_eq!(state.dns_cache.stats().entries, Some(2));
+
+        let response = app
+            .oneshot(
+                Request::post("/admin/cache/purge?domain=example.com")
+                    .header(header::AUTHORIZATION, "Bearer secret")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
         assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["domain"], "example.com");
+        assert_eq!(body["purged"], true);
+        assert_eq!(state.dns_cache.stats().entries, Some(1));
     }
 
+    #[test]
+    fn test_traversal_params() {
+        let uri = Uri::from_static(
+            "/?domain=example.com&ip=192.0.2.1&strict=true&max_depth=2&follow_redirects=false",
+        );
+        let Query(params) = Query::<SpfTreeParams>::try_from_uri(&uri).unwrap();
+        let options = params.traversal.check_options();
+
+        assert_eq!(params.domain, "example.com");
+        assert_eq!(options.ip, Some("192.0.2.1".parse().unwrap()));
+        assert!(options.strict);
+        assert_eq!(options.max_depth, Some(2));
+        assert!(!options.follow_redirects);
+
+        let uri = Uri::from_static("/?domain=example.com");
+        let Query(params) = Query::<SpfTreeParams>::try_from_uri(&uri).unwrap();
+        let options = params.traversal.check_options();
+        assert!(!options.strict);
+        assert!(options.follow_redirects);
+
+        let uri = Uri::from_static("/?domain=example.com&max_depth=deep");
+        assert!(Query::<SpfTreeParams>::try_from_uri(&uri).is_err());
+
+        // The flattened parameters are documented along with those of the endpoint.
+        let document = serde_json::to_value(openapi::ApiDoc::openapi()).unwrap();
+        let names: Vec<_> = document["paths"]["/api/v1/spf-tree"]["get"]["parameters"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|param| param["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "domain",
+                "ip",
+                "sender",
+                "helo",
+                "strict",
+                "max_depth",
+                "follow_redirects"
+            ]
+        );
+    }
 }
@@ -0,0 +1,326 @@
+use crate::error::ServiceError;
+use crate::problem::ProblemResponse;
+use axum::body::{to_bytes, Body};
+use axum::extract::{Path, Request};
+use axum::http::{header, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Json, Response};
+use serde_json::{json, Map, Value};
+use utoipa::OpenApi;
+
+/// Version of the response schemas, sent as `schema_version` in each JSON response of the API.
+/// Incremented when a response changes incompatibly.
+pub const SCHEMA_VERSION: u32 = 1;
+
+const SCHEMA_REF_PREFIX: &str = "#/components/schemas/";
+
+/// The OpenAPI document of the HTTP API, generated from the `#[utoipa::path]` annotations of
+/// the handlers. `/ws` and `/graphql` are not part of it.
+#[derive(OpenApi)]
+#[openapi(paths(
+    crate::health,
+    crate::check_spf,
+    crate::check_spf_json,
+    crate::check_spf_batch,
+    crate::check_target_domains,
+    crate::submit_job,
+    crate::get_job,
+    crate::evaluate_record,
+    crate::build_spf,
+    crate::explain,
+    crate::spf_tree,
+    crate::spf_graph,
+    crate::spf_mechanisms,
+    crate::diff_spf,
+    crate::spf_coverage,
+    crate::dangling_includes,
+    crate::optimize_spf,
+    crate::validate_spf,
+    crate::lookup_budget,
+    crate::check_dmarc,
+    crate::check_mx,
+    crate::check_mta_sts,
+    crate::check_bimi,
+    crate::domain_report,
+    crate::txt_records,
+    crate::evaluate,
+    crate::check_ip,
+    crate::check_propagation,
+    crate::check_history,
+    crate::cache_stats,
+    crate::purge_cache,
+    crate::v2::check_spf,
+    schema_names,
+    json_schema,
+))]
+pub struct ApiDoc;
+
+pub async fn openapi_json() -> Json<Value> {
+    Json(document())
+}
+
+/// The OpenAPI document with `schema_version` added to the schemas of the response bodies,
+/// which [`add_schema_version`] adds to the responses rather than the handlers.
+fn document() -> Value {
+    let mut document = serde_json::to_value(ApiDoc::openapi()).expect("serializable document");
+
+    let mut bodies: Vec<String> = document["paths"]
+        .as_object()
+        .into_iter()
+        .flat_map(Map::values)
+        .filter_map(Value::as_object)
+        .flat_map(Map::values)
+        .filter_map(|operation| operation["responses"].as_object())
+        .flat_map(Map::values)
+        .filter_map(|response| response["content"].as_object())
+        .flat_map(Map::values)
+        .filter_map(|content| content["schema"]["$ref"].as_str())
+        .filter_map(|location| location.strip_prefix(SCHEMA_REF_PREFIX))
+        .map(str::to_string)
+        .collect();
+    bodies.sort();
+    bodies.dedup();
+
+    for name in bodies {
+        if let Some(schema) = document["components"]["schemas"].get_mut(&name) {
+            add_schema_version_property(schema);
+        }
+    }
+
+    document
+}
+
+fn add_schema_version_property(schema: &mut Value) {
+    let property = json!({
+        "type": "integer",
+        "format": "int32",
+        "minimum": 0,
+        "description": "Version of the response schema, incremented when it changes incompatibly.",
+    });
+
+    if let Some(properties) = schema["properties"].as_object_mut() {
+        properties.insert("schema_version".to_string(), property);
+
+        if let Some(required) = schema["required"].as_array_mut() {
+            required.insert(0, json!("schema_version"));
+        } else {
+            schema["required"] = json!(["schema_version"]);
+        }
+    } else {
+        // Untagged enums are `oneOf` their variants, which all get the field.
+        let description = schema
+            .as_object_mut()
+            .and_then(|schema| schema.remove("description"));
+        let mut wrapped = json!({
+            "allOf": [
+                schema.take(),
+                {
+                    "type": "object",
+                    "required": ["schema_version"],
+                    "properties": { "schema_version": property },
+                },
+            ],
+        });
+        if let Some(description) = description {
+            wrapped["description"] = description;
+        }
+        *schema = wrapped;
+    }
+}
+
+/// Adds `schema_version` as first field to the JSON object responses of the wrapped routes.
+pub async fn add_schema_version(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+
+    // Includes the problem details of errors, `application/problem+json`.
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| {
+            let content_type = content_type.as_bytes();
+            content_type.starts_with(b"application/json")
+                || content_type.starts_with(b"application/problem+json")
+        });
+    if !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(body) = to_bytes(body, usize::MAX).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(with_schema_version(&body)))
+}
+
+/// Inserts `schema_version` into a serialized JSON object, keeping the order of its fields.
+/// Other values, e.g. arrays, are returned unchanged.
+fn with_schema_version(body: &[u8]) -> Vec<u8> {
+    let Some(fields) = body.strip_prefix(b"{") else {
+        return body.to_vec();
+    };
+
+    let mut versioned = format!("{{\"schema_version\":{SCHEMA_VERSION}").into_bytes();
+    if !fields.trim_ascii_start().starts_with(b"}") {
+        versioned.push(b',');
+    }
+    versioned.extend_from_slice(fields);
+    versioned
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/schema",
+    responses(
+        (status = 200, description = "The names of the schemas served at `/api/v1/schema/{name}`", body = Vec<String>),
+    )
+)]
+pub async fn schema_names() -> Json<Vec<String>> {
+    let document = document();
+    let names = document["components"]["schemas"]
+        .as_object()
+        .map(|schemas| schemas.keys().cloned().collect())
+        .unwrap_or_default();
+
+    Json(names)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/schema/{name}",
+    params(("name" = String, Path, description = "The name of the schema, e.g. `SpfCheckResponse`")),
+    responses(
+        (status = 200, description = "The JSON Schema of the response type", body = Object),
+        (status = 404, description = "Unknown schema", body = ProblemResponse, content_type = "application/problem+json"),
+    )
+)]
+pub async fn json_schema(Path(name): Path<String>) -> Response {
+    match schema_of(&document(), &name) {
+        Some(schema) => (StatusCode::OK, Json(schema)).into_response(),
+        None => ServiceError::SchemaNotFound.into_response(),
+    }
+}
+
+/// The schema `name` of `document` as standalone JSON Schema, with the schemas it references
+/// in `$defs`.
+fn schema_of(document: &Value, name: &str) -> Option<Value> {
+    let schemas = document["components"]["schemas"].as_object()?;
+    let mut schema = schemas.get(name)?.clone();
+
+    let mut defs = Map::new();
+    let mut pending = references(&schema);
+    while let Some(reference) = pending.pop() {
+        if reference == name || defs.contains_key(&reference) {
+            continue;
+        }
+        let Some(def) = schemas.get(&reference) else {
+            continue;
+        };
+        pending.extend(references(def));
+        defs.insert(reference, def.clone());
+    }
+
+    let mut defs = Value::Object(defs);
+    relocate_references(&mut schema, name);
+    relocate_references(&mut defs, name);
+
+    let mut standalone = json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": format!("/api/v1/schema/{name}"),
+        "title": name,
+    });
+    if let (Some(standalone), Value::Object(schema)) = (standalone.as_object_mut(), schema) {
+        standalone.extend(schema);
+    }
+    if defs.as_object().is_some_and(|defs| !defs.is_empty()) {
+        standalone["$defs"] = defs;
+    }
+
+    Some(standalone)
+}
+
+/// The names of the component schemas referenced by `schema`.
+fn references(schema: &Value) -> Vec<String> {
+    match schema {
+        Value::Object(fields) => fields
+            .iter()
+            .flat_map(|(key, value)| match (key.as_str(), value) {
+                ("$ref", Value::String(location)) => location
+                    .strip_prefix(SCHEMA_REF_PREFIX)
+                    .map(|name| vec![name.to_string()])
+                    .unwrap_or_default(),
+                _ => references(value),
+            })
+            .collect(),
+        Value::Array(values) => values.iter().flat_map(references).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Points the references to component schemas to `$defs`, or to the root for `root`.
+fn relocate_references(schema: &mut Value, root: &str) {
+    match schema {
+        Value::Object(fields) => {
+            for (key, value) in fields.iter_mut() {
+                match value {
+                    Value::String(location) if key == "$ref" => {
+                        if let Some(name) = location.strip_prefix(SCHEMA_REF_PREFIX) {
+                            *location = if name == root {
+                                "#".to_string()
+                            } else {
+                                format!("#/$defs/{name}")
+                            };
+                        }
+                    }
+                    _ => relocate_references(value, root),
+                }
+            }
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| relocate_references(value, root)),
+        _ => {}
+    }
+}
+
+/// Swagger UI at `/swagger-ui`, showing the document served at `/api/openapi.json`.
+#[cfg(feature = "swagger-ui")]
+pub fn swagger_ui() -> utoipa_swagger_ui::SwaggerUi {
+    utoipa_swagger_ui::SwaggerUi::new("/swagger-ui")
+        .config(utoipa_swagger_ui::Config::from("/api/openapi.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_schema_version() {
+        assert_eq!(
+            with_schema_version(br#"{"found":true}"#),
+            br#"{"schema_version":1,"found":true}"#
+        );
+        assert_eq!(with_schema_version(b"{}"), br#"{"schema_version":1}"#);
+        assert_eq!(with_schema_version(b"[1,2]"), b"[1,2]");
+    }
+
+    #[test]
+    fn test_schema_of() {
+        let document = document();
+
+        let schema = schema_of(&document, "SpfTreeResponse").unwrap();
+        assert_eq!(schema["title"], "SpfTreeResponse");
+        assert_eq!(schema["properties"]["schema_version"]["type"], "integer");
+        assert!(schema["$defs"]["SpfTreeNodeResponse"].is_object());
+        assert!(!serde_json::to_string(&schema)
+            .unwrap()
+            .contains(SCHEMA_REF_PREFIX));
+
+        // Only response bodies get the field, not the types nested in them.
+        let node = schema_of(&document, "SpfTreeNodeResponse").unwrap();
+        assert!(node["properties"]["schema_version"].is_null());
+
+        assert!(schema_of(&document, "Unknown").is_none());
+    }
+}
@@ -0,0 +1,110 @@
+use crate::error::ServiceError;
+use crate::ErrorResponse;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Json, Response};
+use serde::Serialize;
+use spf_checker::{CheckError, SpfResult};
+use utoipa::ToSchema;
+
+/// Error response of `/api/v1` as problem details of RFC 9457 (formerly RFC 7807), served as
+/// `application/problem+json`. `error` and the other fields of [`ErrorResponse`] are extension
+/// members, so clients matching on the error code keep working.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ProblemResponse {
+    /// Identifies the kind of problem by its error code, e.g.
+    /// `urn:spf-check:error:DNS_LOOKUP_FAILED`.
+    #[serde(rename = "type")]
+    problem_type: String,
+    /// A short summary of the kind of problem.
+    title: String,
+    /// The HTTP status code of the response.
+    status: u16,
+    /// An explanation of this occurrence of the problem, same as `message`.
+    detail: String,
+    /// The RFC 7208 result receivers give mail checked against the domain when they fail the
+    /// same way: `temperror`, `permerror` or `none`, `null` for errors unrelated to the records,
+    /// e.g. invalid parameters.
+    spf_result: Option<String>,
+    #[serde(flatten)]
+    error: ErrorResponse,
+}
+
+/// Responds with `error` as problem details with `status`.
+pub struct Problem(pub StatusCode, pub ErrorResponse);
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let Problem(status, error) = self;
+        let problem = ProblemResponse {
+            problem_type: format!("urn:spf-check:error:{}", error.error),
+            title: title(&error.error)
+                .or(status.canonical_reason())
+                .unwrap_or_default()
+                .to_string(),
+            status: status.as_u16(),
+            detail: error.message.clone(),
+            spf_result: spf_result(&error.error).map(|result| result.to_string()),
+            error,
+        };
+
+        (
+            status,
+            [(header::CONTENT_TYPE, "application/problem+json")],
+            Json(problem),
+        )
+            .into_response()
+    }
+}
+
+/// The titles of the problems of the error codes, which do not change between occurrences:
+/// those of the [`CheckError`] or [`ServiceError`] of the code.
+fn title(code: &str) -> Option<&'static str> {
+    CheckError::from_code(code)
+        .map(CheckError::title)
+        .or_else(|| ServiceError::title_of(code))
+}
+
+/// The result of a check failing with the error code, `None` for errors unrelated to the
+/// records, e.g. invalid parameters.
+fn spf_result(code: &str) -> Option<SpfResult> {
+    let error = CheckError::from_code(code)?;
+
+    match error {
+        _ if error.is_temporary() => Some(SpfResult::TempError),
+        CheckError::MultipleSpfRecords
+        | CheckError::SpfParseFailed
+        | CheckError::LookupLimitExceeded
+        | CheckError::VoidLookupLimitExceeded => Some(SpfResult::PermError),
+        // https://datatracker.ietf.org/doc/html/rfc7208#section-4.3
+        CheckError::NoSpfRecord | CheckError::DnsNxdomain => Some(SpfResult::None),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+
+    #[tokio::test]
+    async fn test_problem() {
+        let err = anyhow::anyhow!("request timed out").context(CheckError::DnsLookupFailed);
+        let error = ErrorResponse::from(&err);
+
+        let response = Problem(StatusCode::NOT_FOUND, error).into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/problem+json"
+        );
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["type"], "urn:spf-check:error:DNS_LOOKUP_FAILED");
+        assert_eq!(problem["title"], "DNS lookup failed");
+        assert_eq!(problem["status"], 404);
+        assert_eq!(problem["detail"], "A DNS lookup failed: request timed out");
+        assert_eq!(problem["spf_result"], "temperror");
+        assert_eq!(problem["error"], "DNS_LOOKUP_FAILED");
+    }
+}
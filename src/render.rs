@@ -0,0 +1,494 @@
+use crate::{
+    targets_response, BatchItemResponse, CheckTargetsResponse, ErrorResponse, SpfCheckParams,
+    SpfCheckResponse, Targets,
+};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::de::{Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::Serialize;
+use std::fmt::{self, Write};
+use utoipa::{IntoParams, ToSchema};
+
+/// The format `GET /api/v1/check-spf` responds in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseFormat {
+    Json,
+    /// The JSON response as YAML document.
+    Yaml,
+    /// One row per visited domain of each check.
+    Csv,
+    /// A short summary of each check, e.g. to `grep` in shell scripts.
+    Text,
+}
+
+#[derive(Debug, serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FormatParams {
+    /// The format of the response, taking precedence over the `Accept` header.
+    format: Option<ResponseFormat>,
+}
+
+impl ResponseFormat {
+    /// The format of `params`, or else the first one accepted by `headers`, defaulting to JSON.
+    pub fn negotiate(params: &FormatParams, headers: &HeaderMap) -> Self {
+        if let Some(format) = params.format {
+            return format;
+        }
+
+        let accept = headers
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .unwrap_or_default();
+
+        accept
+            .split(',')
+            .filter_map(|media_range| media_range.split(';').next())
+            .find_map(|media_type| match media_type.trim() {
+                "application/json" => Some(Self::Json),
+                "application/yaml" | "application/x-yaml" | "text/yaml" => Some(Self::Yaml),
+                "text/csv" => Some(Self::Csv),
+                "text/plain" => Some(Self::Text),
+                _ => None,
+            })
+            .unwrap_or(Self::Json)
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Yaml => "application/yaml; charset=utf-8",
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Text => "text/plain; charset=utf-8",
+        }
+    }
+}
+
+/// Renders the result of `GET /api/v1/check-spf` in `format`.
+pub fn render(
+    format: ResponseFormat,
+    params: &SpfCheckParams,
+    result: std::result::Result<CheckTargetsResponse, (StatusCode, ErrorResponse)>,
+) -> Response {
+    let status = match &result {
+        Ok(_) => StatusCode::OK,
+        Err((status, _)) => *status,
+    };
+
+    let body = match (format, &result) {
+        (ResponseFormat::Json, _) => return targets_response(result),
+        (ResponseFormat::Yaml, Ok(response)) => to_yaml(response),
+        (ResponseFormat::Yaml, Err((_, error))) => to_yaml(error),
+        (ResponseFormat::Csv, result) => Ok(to_csv(params, result)),
+        (ResponseFormat::Text, result) => Ok(to_text(params, result)),
+    };
+
+    match body {
+        Ok(body) => (
+            status,
+            [(header::CONTENT_TYPE, format.content_type())],
+            body,
+        )
+            .into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// The checks of a response, each either checked or failed with the domain, target and error.
+/// Failed requests count as one failed check.
+fn checks<'a>(
+    params: &'a SpfCheckParams,
+    result: &'a std::result::Result<CheckTargetsResponse, (StatusCode, ErrorResponse)>,
+) -> Vec<std::result::Result<&'a SpfCheckResponse, (&'a str, &'a str, &'a str)>> {
+    match result {
+        Ok(CheckTargetsResponse::One(response)) => vec![Ok(response.as_ref())],
+        Ok(CheckTargetsResponse::Many(response)) => response
+            .results
+            .iter()
+            .map(|result| match result {
+                BatchItemResponse::Checked(response) => Ok(response.as_ref()),
+                BatchItemResponse::Failed {
+                    domain,
+                    target,
+                    error,
+                } => Err((domain.as_str(), target.as_str(), error.error.as_str())),
+            })
+            .collect(),
+        Err((_, error)) => {
+            // Only a single target is known to be the one of the error.
+            let target = match &params.target {
+                Targets::One(target) => target.as_str(),
+                Targets::Many(_) => "",
+            };
+
+            vec![Err((params.domain.as_str(), target, error.error.as_str()))]
+        }
+    }
+}
+
+const CSV_HEADER: [&str; 8] = [
+    "domain",
+    "target",
+    "result",
+    "found",
+    "visited_domain",
+    "spf_record",
+    "ttl",
+    "error",
+];
+
+fn to_csv(
+    params: &SpfCheckParams,
+    result: &std::result::Result<CheckTargetsResponse, (StatusCode, ErrorResponse)>,
+) -> String {
+    let mut csv = String::new();
+    push_csv_row(&mut csv, CSV_HEADER.map(str::to_string));
+
+    for check in checks(params, result) {
+        let response = match check {
+            Ok(response) => response,
+            Err((domain, target, error)) => {
+                push_csv_row(
+                    &mut csv,
+                    [domain, target, "", "", "", "", "", error].map(str::to_string),
+                );
+                continue;
+            }
+        };
+
+        for domain in &response.visited_domains {
+            let error = response
+                .errors
+                .iter()
+                .find(|error| &error.domain == domain)
+                .map(|error| error.error.clone());
+
+            push_csv_row(
+                &mut csv,
+                [
+                    response.domain.clone(),
+                    response.target.clone(),
+                    response.result.clone(),
+                    response.found.to_string(),
+                    domain.clone(),
+                    response
+                        .records
+                        .get(domain)
+                        .cloned()
+                        .flatten()
+                        .unwrap_or_default(),
+                    response
+                        .ttls
+                        .get(domain)
+                        .map(u32::to_string)
+                        .unwrap_or_default(),
+                    error.unwrap_or_default(),
+                ],
+            );
+        }
+    }
+
+    csv
+}
+
+/// Appends a row in the format of RFC 4180, quoting the fields that need it.
+fn push_csv_row<const N: usize>(csv: &mut String, fields: [String; N]) {
+    let fields: Vec<_> = fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\r', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        })
+        .collect();
+
+    csv.push_str(&fields.join(","));
+    csv.push_str("\r\n");
+}
+
+fn to_text(
+    params: &SpfCheckParams,
+    result: &std::result::Result<CheckTargetsResponse, (StatusCode, ErrorResponse)>,
+) -> String {
+    let mut text = String::new();
+
+    for check in checks(params, result) {
+        let response = match check {
+            Ok(response) => response,
+            Err((domain, target, error)) => {
+                let _ = writeln!(text, "{domain} -> {target}: error {error}");
+                continue;
+            }
+        };
+
+        let _ = writeln!(
+            text,
+            "{} -> {}: {}",
+            response.domain, response.target, response.result
+        );
+        if let (Some(mechanism), Some(domain)) = (&response.mechanism, &response.mechanism_domain) {
+            let _ = writeln!(text, "  mechanism: {mechanism} ({domain})");
+        }
+        let _ = writeln!(
+            text,
+            "  dns_lookups: {} of 10, {} domains",
+            response.dns_lookups, response.checked_domains
+        );
+        for warning in &response.warnings {
+            let _ = writeln!(
+                text,
+                "  warning: {} ({}) {}",
+                warning.code, warning.domain, warning.message
+            );
+        }
+        for error in &response.errors {
+            let _ = writeln!(text, "  error: {} ({})", error.error, error.domain);
+        }
+    }
+
+    text
+}
+
+/// A JSON value keeping the order of the fields of objects, unlike `serde_json::Value`, so that
+/// the YAML document lists them in the order of the JSON response.
+enum OrderedValue {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<OrderedValue>),
+    Object(Vec<(String, OrderedValue)>),
+}
+
+impl<'de> Deserialize<'de> for OrderedValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct OrderedVisitor;
+
+        impl<'de> Visitor<'de> for OrderedVisitor {
+            type Value = OrderedValue;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a JSON value")
+            }
+
+            fn visit_unit<E>(self) -> Result<OrderedValue, E> {
+                Ok(OrderedValue::Null)
+            }
+
+            fn visit_bool<E>(self, value: bool) -> Result<OrderedValue, E> {
+                Ok(OrderedValue::Bool(value))
+            }
+
+            fn visit_i64<E>(self, value: i64) -> Result<OrderedValue, E> {
+                Ok(OrderedValue::Number(value.to_string()))
+            }
+
+            fn visit_u64<E>(self, value: u64) -> Result<OrderedValue, E> {
+                Ok(OrderedValue::Number(value.to_string()))
+            }
+
+            fn visit_f64<E>(self, value: f64) -> Result<OrderedValue, E> {
+                Ok(OrderedValue::Number(value.to_string()))
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<OrderedValue, E> {
+                Ok(OrderedValue::String(value.to_string()))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<OrderedValue, A::Error> {
+                let mut values = Vec::new();
+                while let Some(value) = seq.next_element()? {
+                    values.push(value);
+                }
+                Ok(OrderedValue::Array(values))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<OrderedValue, A::Error> {
+                let mut fields = Vec::new();
+                while let Some(field) = map.next_entry()? {
+                    fields.push(field);
+                }
+                Ok(OrderedValue::Object(fields))
+            }
+        }
+
+        deserializer.deserialize_any(OrderedVisitor)
+    }
+}
+
+/// Renders `value` as YAML document, in block style with the fields in the order of its JSON.
+fn to_yaml(value: &impl Serialize) -> serde_json::Result<String> {
+    let value: OrderedValue = serde_json::from_str(&serde_json::to_string(value)?)?;
+
+    let mut yaml = String::new();
+    match &value {
+        OrderedValue::Array(values) if !values.is_empty() => push_yaml_block(&mut yaml, &value, 0),
+        OrderedValue::Object(fields) if !fields.is_empty() => push_yaml_block(&mut yaml, &value, 0),
+        _ => {
+            yaml.push_str(&yaml_scalar(&value));
+            yaml.push('\n');
+        }
+    }
+
+    Ok(yaml)
+}
+
+/// Appends the lines of a non-empty array or object, indented by `indent` spaces.
+fn push_yaml_block(yaml: &mut String, value: &OrderedValue, indent: usize) {
+    let padding = " ".repeat(indent);
+
+    match value {
+        OrderedValue::Object(fields) => {
+            for (key, value) in fields {
+                yaml.push_str(&padding);
+                yaml.push_str(&yaml_string(key));
+                yaml.push(':');
+                push_yaml_value(yaml, value, indent + 2);
+            }
+        }
+        OrderedValue::Array(values) => {
+            for value in values {
+                // Nested blocks start on the line of the dash, in place of their indentation.
+                let mut item = String::new();
+                push_yaml_value(&mut item, value, indent + 2);
+
+                yaml.push_str(&padding);
+                yaml.push('-');
+                match item.strip_prefix('\n') {
+                    Some(block) => {
+                        yaml.push(' ');
+                        yaml.push_str(&block[indent + 2..]);
+                    }
+                    None => yaml.push_str(&item),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Appends the value of a field or array item: a scalar on the same line, or a block on the
+/// following lines.
+fn push_yaml_value(yaml: &mut String, value: &OrderedValue, indent: usize) {
+    match value {
+        OrderedValue::Array(values) if !values.is_empty() => {
+            yaml.push('\n');
+            push_yaml_block(yaml, value, indent);
+        }
+        OrderedValue::Object(fields) if !fields.is_empty() => {
+            yaml.push('\n');
+            push_yaml_block(yaml, value, indent);
+        }
+        _ => {
+            yaml.push(' ');
+            yaml.push_str(&yaml_scalar(value));
+            yaml.push('\n');
+        }
+    }
+}
+
+fn yaml_scalar(value: &OrderedValue) -> String {
+    match value {
+        OrderedValue::Null => "null".to_string(),
+        OrderedValue::Bool(value) => value.to_string(),
+        OrderedValue::Number(value) => value.clone(),
+        OrderedValue::String(value) => yaml_string(value),
+        OrderedValue::Array(_) => "[]".to_string(),
+        OrderedValue::Object(_) => "{}".to_string(),
+    }
+}
+
+/// A plain scalar if it cannot be mistaken for another type or syntax, else a double-quoted
+/// one, whose escapes are those of JSON.
+fn yaml_string(value: &str) -> String {
+    let is_plain = value.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_' | '/' | '@'))
+        && !matches!(
+            value.to_ascii_lowercase().as_str(),
+            "true" | "false" | "null" | "yes" | "no" | "on" | "off" | "y" | "n"
+        );
+
+    if is_plain {
+        value.to_string()
+    } else {
+        serde_json::to_string(value).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_to_yaml() {
+        let value = json!({
+            "result": "pass",
+            "found": true,
+            "visited_domains": ["example.com", "_spf.example.net"],
+            "mechanism": "include:_spf.example.net",
+            "warnings": [{"code": "MISSING_ALL", "domain": "example.com"}],
+            "records": {},
+            "path": null,
+            "dns_lookups": 2,
+        });
+
+        assert_eq!(
+            to_yaml(&value).unwrap(),
+            "dns_lookups: 2\n\
+             found: true\n\
+             mechanism: \"include:_spf.example.net\"\n\
+             path: null\n\
+             records: {}\n\
+             result: pass\n\
+             visited_domains:\n  \
+               - example.com\n  \
+               - _spf.example.net\n\
+             warnings:\n  \
+               - code: MISSING_ALL\n    \
+                 domain: example.com\n"
+        );
+    }
+
+    #[test]
+    fn test_push_csv_row() {
+        let mut csv = String::new();
+        push_csv_row(
+            &mut csv,
+            ["example.com", "v=spf1 include:\"a\",b -all", ""].map(str::to_string),
+        );
+
+        assert_eq!(csv, "example.com,\"v=spf1 include:\"\"a\"\",b -all\",\r\n");
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let headers = |accept: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, accept.parse().unwrap());
+            headers
+        };
+        let params = |format| FormatParams { format };
+
+        assert_eq!(
+            ResponseFormat::negotiate(&params(None), &HeaderMap::new()),
+            ResponseFormat::Json
+        );
+        assert_eq!(
+            ResponseFormat::negotiate(&params(None), &headers("text/csv;q=0.9, */*")),
+            ResponseFormat::Csv
+        );
+        assert_eq!(
+            ResponseFormat::negotiate(&params(None), &headers("text/html, application/yaml")),
+            ResponseFormat::Yaml
+        );
+        assert_eq!(
+            ResponseFormat::negotiate(&params(Some(ResponseFormat::Text)), &headers("text/csv")),
+            ResponseFormat::Text
+        );
+    }
+}
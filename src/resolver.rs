@@ -0,0 +1,534 @@
+use crate::config::Config;
+use crate::Result;
+use anyhow::{bail, Context};
+use spf_checker::SpfChecker;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+use trust_dns_resolver::config::{
+    LookupIpStrategy, NameServerConfig, Protocol, ResolverConfig, ResolverOpts,
+};
+use trust_dns_resolver::TokioAsyncResolver;
+
+/// How long a query waits for an answer if neither `resolver.timeout` nor `/etc/resolv.conf`
+/// set it.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+/// How often a query is sent if neither `resolver.attempts` nor `/etc/resolv.conf` set it.
+const DEFAULT_ATTEMPTS: usize = 2;
+/// The names of the strategies of `resolver.ip_strategy`.
+const IP_STRATEGIES: [(&str, LookupIpStrategy); 5] = [
+    ("ipv4_only", LookupIpStrategy::Ipv4Only),
+    ("ipv6_only", LookupIpStrategy::Ipv6Only),
+    ("ipv4_and_ipv6", LookupIpStrategy::Ipv4AndIpv6),
+    ("ipv6_then_ipv4", LookupIpStrategy::Ipv6thenIpv4),
+    ("ipv4_then_ipv6", LookupIpStrategy::Ipv4thenIpv6),
+];
+/// Port of a name server without one.
+const DNS_PORT: u16 = 53;
+/// Port of a DNS over HTTPS resolver without one.
+const DOH_PORT: u16 = 443;
+/// Port of a DNS over TLS resolver without one (RFC 7858 §3.1), also used by DNS over QUIC
+/// (RFC 9250 §4.1.1).
+const DOT_PORT: u16 = 853;
+/// The path DNS over HTTPS queries are posted to, the only one trust-dns supports.
+const DOH_PATH: &str = "dns-query";
+
+/// How the resolvers of the checks query DNS, see [`Config`].
+#[derive(Debug, Default)]
+pub struct ResolverSettings {
+    timeout: Option<Duration>,
+    attempts: Option<usize>,
+    /// Which address families `lookup_ip` queries, and which addresses of the host of the
+    /// upstream resolver are queried in which order.
+    ip_strategy: Option<LookupIpStrategy>,
+    /// How many dots a name needs to be queried as is before the search domains are tried.
+    ndots: Option<usize>,
+    /// Whether `/etc/hosts` answers the address lookups of the names it lists.
+    use_hosts_file: Option<bool>,
+    /// The name servers queried over UDP and TCP, Google Public DNS if empty.
+    nameservers: Vec<SocketAddr>,
+    /// The name servers, search domains and options of `/etc/resolv.conf`, if they are used
+    /// instead of `nameservers`. Configured options take precedence over its options.
+    system: Option<(ResolverConfig, ResolverOpts)>,
+    /// An encrypted resolver queried by the checks instead of the name servers.
+    upstream: Option<Upstream>,
+    /// The name the certificate of the upstream resolver must be valid for, its host if
+    /// `None`.
+    tls_name: Option<String>,
+}
+
+/// An encrypted resolver, given by a URL like `https://cloudflare-dns.com/dns-query`,
+/// `tls://1.1.1.1` or `quic://dns.adguard-dns.com`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Upstream {
+    /// The URL as configured, reported in `meta.nameservers`.
+    url: String,
+    /// DNS over HTTPS, over TLS or over QUIC.
+    protocol: Protocol,
+    /// The host of the URL, without brackets around IPv6 addresses.
+    host: String,
+    port: u16,
+}
+
+impl ResolverSettings {
+    /// Reads the settings of `[resolver]`, e.g. `resolver.timeout` or `DNS_TIMEOUT`. Options
+    /// that are not set keep those of `/etc/resolv.conf` if enabled, or the defaults.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let timeout = config.parse(
+            "resolver.timeout",
+            "a positive number of seconds",
+            |seconds| {
+                seconds
+                    .parse()
+                    .ok()
+                    .filter(|seconds: &f64| *seconds > 0.0)
+                    .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            },
+        )?;
+
+        let attempts = config.parse("resolver.attempts", "a positive number", |attempts| {
+            attempts.parse().ok().filter(|attempts| *attempts > 0)
+        })?;
+
+        let ip_strategy = config.parse(
+            "resolver.ip_strategy",
+            "one of `ipv4_only`, `ipv6_only`, `ipv4_and_ipv6`, `ipv6_then_ipv4` and `ipv4_then_ipv6`",
+            |strategy| {
+                IP_STRATEGIES
+                    .iter()
+                    .find(|(name, _)| *name == strategy)
+                    .map(|(_, strategy)| *strategy)
+            },
+        )?;
+
+        let ndots = config.parse("resolver.ndots", "a number of dots", |ndots| {
+            ndots.parse().ok()
+        })?;
+
+        let use_hosts_file = config.parse(
+            "resolver.use_hosts_file",
+            "`true` or `false`",
+            |use_hosts_file| use_hosts_file.parse().ok(),
+        )?;
+
+        let nameservers: Vec<SocketAddr> = config
+            .parse(
+                "resolver.nameservers",
+                "a list of addresses like `10.0.0.53` or `10.0.0.53:5353`",
+                |nameservers| {
+                    nameservers
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|nameserver| !nameserver.is_empty())
+                        .map(parse_nameserver)
+                        .collect()
+                },
+            )?
+            .unwrap_or_default();
+
+        let system = match config.flag("resolver.system_conf", false)? {
+            true if !nameservers.is_empty() => {
+                bail!("DNS_NAMESERVERS and DNS_SYSTEM_CONF cannot be used together")
+            }
+            true => Some(
+                trust_dns_resolver::system_conf::read_system_conf()
+                    .context("Failed to read /etc/resolv.conf for DNS_SYSTEM_CONF")?,
+            ),
+            false => None,
+        };
+
+        let upstream = config.parse(
+            "resolver.upstream",
+            "a URL like `https://cloudflare-dns.com/dns-query`, `tls://1.1.1.1` or `quic://dns.adguard-dns.com`",
+            parse_upstream,
+        )?;
+        let tls_name = config.get("resolver.tls_name").map(str::to_string);
+
+        Ok(Self {
+            timeout,
+            attempts,
+            ip_strategy,
+            ndots,
+            use_hosts_file,
+            nameservers,
+            system,
+            upstream,
+            tls_name,
+        })
+    }
+
+    /// The configured name servers, those of `/etc/resolv.conf` with its search domains if
+    /// enabled, and Google Public DNS otherwise.
+    pub fn config(&self) -> ResolverConfig {
+        if let Some((config, _)) = &self.system {
+            return config.clone();
+        }
+        if self.nameservers.is_empty() {
+            return ResolverConfig::default();
+        }
+
+        let nameservers = self
+            .nameservers
+            .iter()
+            .flat_map(|addr| {
+                [Protocol::Udp, Protocol::Tcp]
+                    .map(|protocol| NameServerConfig::new(*addr, protocol))
+            })
+            .collect::<Vec<_>>();
+
+        ResolverConfig::from_parts(None, Vec::new(), nameservers)
+    }
+
+    /// The checker of the checks, querying the upstream resolver if configured and the name
+    /// servers otherwise.
+    pub fn checker(&self) -> Result<SpfChecker> {
+        let config = match &self.upstream {
+            Some(upstream) => upstream.config(self.tls_name.as_deref(), self.opts().ip_strategy)?,
+            None => self.config(),
+        };
+
+        Ok(SpfChecker::new(self.resolver(config)))
+    }
+
+    /// A resolver querying the name servers of `config`.
+    pub fn resolver(&self, config: ResolverConfig) -> TokioAsyncResolver {
+        TokioAsyncResolver::tokio(config, self.opts())
+    }
+
+    /// A resolver querying the configured name servers, even with an upstream resolver, that
+    /// fails lookups whose answers are unsigned or do not pass DNSSEC validation.
+    pub fn validating_resolver(&self) -> TokioAsyncResolver {
+        let mut opts = self.opts();
+        opts.validate = true;
+        TokioAsyncResolver::tokio(self.config(), opts)
+    }
+
+    /// The addresses of the configured name servers, or the URL of the upstream resolver, as
+    /// reported in `meta.nameservers` of the checks.
+    pub fn nameservers(&self) -> Vec<String> {
+        match &self.upstream {
+            Some(upstream) => vec![upstream.url.clone()],
+            None => nameserver_addresses(&self.config()),
+        }
+    }
+
+    /// The options of `/etc/resolv.conf` if enabled, e.g. its `ndots`, or the defaults, with
+    /// the configured options.
+    fn opts(&self) -> ResolverOpts {
+        let mut opts = match &self.system {
+            Some((_, opts)) => *opts,
+            None => {
+                let mut opts = ResolverOpts::default();
+                opts.timeout = DEFAULT_TIMEOUT;
+                opts.attempts = DEFAULT_ATTEMPTS;
+                opts
+            }
+        };
+        opts.timeout = self.timeout.unwrap_or(opts.timeout);
+        opts.attempts = self.attempts.unwrap_or(opts.attempts);
+        opts.ip_strategy = self.ip_strategy.unwrap_or(opts.ip_strategy);
+        opts.ndots = self.ndots.unwrap_or(opts.ndots);
+        opts.use_hosts_file = self.use_hosts_file.unwrap_or(opts.use_hosts_file);
+        opts
+    }
+}
+
+impl Upstream {
+    /// The resolver at the addresses of its host of the families of `ip_strategy`, resolved
+    /// by the system, whose certificate must be valid for `tls_name`, or the host if `None`,
+    /// which is also sent with SNI.
+    fn config(
+        &self,
+        tls_name: Option<&str>,
+        ip_strategy: LookupIpStrategy,
+    ) -> Result<ResolverConfig> {
+        let addrs = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .with_context(|| {
+                format!("Failed to resolve the host of DNS_UPSTREAM `{}`", self.url)
+            })?;
+        let addrs = by_strategy(addrs, ip_strategy);
+        if addrs.is_empty() {
+            bail!(
+                "The host of DNS_UPSTREAM `{}` has no address allowed by DNS_IP_STRATEGY",
+                self.url
+            );
+        }
+        let tls_name = tls_name.unwrap_or(&self.host);
+
+        let nameservers: Vec<_> = addrs
+            .into_iter()
+            .map(|addr| {
+                let mut nameserver = NameServerConfig::new(addr, self.protocol);
+                nameserver.tls_dns_name = Some(tls_name.to_string());
+                nameserver
+            })
+            .collect();
+
+        Ok(ResolverConfig::from_parts(None, Vec::new(), nameservers))
+    }
+}
+
+/// The addresses of `addrs` of the families of `strategy`, in its order.
+fn by_strategy(
+    addrs: impl Iterator<Item = SocketAddr>,
+    strategy: LookupIpStrategy,
+) -> Vec<SocketAddr> {
+    let (ipv4, ipv6): (Vec<_>, Vec<_>) = addrs.partition(SocketAddr::is_ipv4);
+
+    match strategy {
+        LookupIpStrategy::Ipv4Only => ipv4,
+        LookupIpStrategy::Ipv6Only => ipv6,
+        LookupIpStrategy::Ipv6thenIpv4 => [ipv6, ipv4].concat(),
+        LookupIpStrategy::Ipv4AndIpv6 | LookupIpStrategy::Ipv4thenIpv6 => [ipv4, ipv6].concat(),
+    }
+}
+
+/// The distinct addresses of the name servers of `config`, without their ports.
+pub fn nameserver_addresses(config: &ResolverConfig) -> Vec<String> {
+    let mut addresses = Vec::new();
+
+    for name_server in config.name_servers() {
+        let address = name_server.socket_addr.ip().to_string();
+
+        if !addresses.contains(&address) {
+            addresses.push(address);
+        }
+    }
+
+    addresses
+}
+
+/// Parses a name server like `10.0.0.53`, `10.0.0.53:5353`, `2001:db8::53` or
+/// `[2001:db8::53]:5353`, on port 53 if it has none.
+fn parse_nameserver(nameserver: &str) -> Option<SocketAddr> {
+    nameserver.parse().ok().or_else(|| {
+        let ip: IpAddr = nameserver.parse().ok()?;
+        Some(SocketAddr::new(ip, DNS_PORT))
+    })
+}
+
+/// Parses the URL of an upstream resolver: `https://host[:port]/dns-query`, `tls://host[:port]`
+/// or `quic://host[:port]`, the latter two on port 853 if they have none.
+fn parse_upstream(url: &str) -> Option<Upstream> {
+    let (scheme, address) = url.split_once("://")?;
+    let (protocol, address, default_port) = match scheme {
+        "https" => {
+            let (address, path) = address.split_once('/').unwrap_or((address, DOH_PATH));
+            if path != DOH_PATH {
+                return None;
+            }
+            (Protocol::Https, address, DOH_PORT)
+        }
+        "tls" => (Protocol::Tls, address, DOT_PORT),
+        "quic" => (Protocol::Quic, address, DOT_PORT),
+        _ => return None,
+    };
+    let (host, port) = host_and_port(address, default_port)?;
+
+    Some(Upstream {
+        url: url.to_string(),
+        protocol,
+        host,
+        port,
+    })
+}
+
+/// Splits an address like `1.1.1.1`, `dns.example.com:853` or `[2606:4700::1111]:853` into
+/// its host, without brackets, and port, `port` if it has none.
+fn host_and_port(address: &str, port: u16) -> Option<(String, u16)> {
+    if let Ok(addr) = address.parse::<SocketAddr>() {
+        return Some((addr.ip().to_string(), addr.port()));
+    }
+    if let Ok(ip) = address.parse::<IpAddr>() {
+        return Some((ip.to_string(), port));
+    }
+
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (address, port),
+    };
+    let is_host = !host.is_empty() && !host.contains(['/', '[', ']', '?', '#', '@']);
+
+    is_host.then(|| (host.to_string(), port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nameserver() {
+        assert_eq!(
+            parse_nameserver("10.0.0.53"),
+            Some("10.0.0.53:53".parse().unwrap())
+        );
+        assert_eq!(
+            parse_nameserver("10.0.0.53:5353"),
+            Some("10.0.0.53:5353".parse().unwrap())
+        );
+        assert_eq!(
+            parse_nameserver("2001:db8::53"),
+            Some("[2001:db8::53]:53".parse().unwrap())
+        );
+        assert_eq!(
+            parse_nameserver("[2001:db8::53]:5353"),
+            Some("[2001:db8::53]:5353".parse().unwrap())
+        );
+        assert_eq!(parse_nameserver("dns.internal"), None);
+    }
+
+    #[test]
+    fn test_parse_upstream() {
+        let upstream = parse_upstream("https://cloudflare-dns.com/dns-query").unwrap();
+        assert_eq!(upstream.protocol, Protocol::Https);
+        assert_eq!(
+            (upstream.host.as_str(), upstream.port),
+            ("cloudflare-dns.com", 443)
+        );
+        let upstream = parse_upstream("https://[2606:4700::1111]:8443").unwrap();
+        assert_eq!(
+            (upstream.host.as_str(), upstream.port),
+            ("2606:4700::1111", 8443)
+        );
+        let upstream = parse_upstream("tls://1.1.1.1").unwrap();
+        assert_eq!(upstream.protocol, Protocol::Tls);
+        assert_eq!((upstream.host.as_str(), upstream.port), ("1.1.1.1", 853));
+        let upstream = parse_upstream("quic://dns.adguard-dns.com:8853").unwrap();
+        assert_eq!(upstream.protocol, Protocol::Quic);
+        assert_eq!(
+            (upstream.host.as_str(), upstream.port),
+            ("dns.adguard-dns.com", 8853)
+        );
+
+        assert_eq!(parse_upstream("https://dns.google/resolve"), None);
+        assert_eq!(parse_upstream("http://dns.google/dns-query"), None);
+        assert_eq!(parse_upstream("tls://1.1.1.1:dns"), None);
+        assert_eq!(parse_upstream("tls://"), None);
+        assert_eq!(parse_upstream("1.1.1.1"), None);
+    }
+
+    #[test]
+    fn test_config_of_upstream() {
+        let upstream = parse_upstream("https://1.1.1.1/dns-query").unwrap();
+        let config = upstream.config(None, LookupIpStrategy::default()).unwrap();
+        let nameserver = &config.name_servers()[0];
+        assert_eq!(nameserver.socket_addr, "1.1.1.1:443".parse().unwrap());
+        assert_eq!(nameserver.protocol, Protocol::Https);
+        assert_eq!(nameserver.tls_dns_name.as_deref(), Some("1.1.1.1"));
+
+        let config = upstream
+            .config(Some("cloudflare-dns.com"), LookupIpStrategy::default())
+            .unwrap();
+        let nameserver = &config.name_servers()[0];
+        assert_eq!(
+            nameserver.tls_dns_name.as_deref(),
+            Some("cloudflare-dns.com")
+        );
+
+        assert!(upstream.config(None, LookupIpStrategy::Ipv6Only).is_err());
+    }
+
+    #[test]
+    fn test_by_strategy() {
+        let addrs: Vec<SocketAddr> = ["[2001:db8::53]:853", "192.0.2.53:853"]
+            .iter()
+            .map(|addr| addr.parse().unwrap())
+            .collect();
+        let by_strategy = |strategy| by_strategy(addrs.iter().copied(), strategy);
+
+        assert_eq!(by_strategy(LookupIpStrategy::Ipv4Only), [addrs[1]]);
+        assert_eq!(by_strategy(LookupIpStrategy::Ipv6Only), [addrs[0]]);
+        assert_eq!(
+            by_strategy(LookupIpStrategy::Ipv4thenIpv6),
+            [addrs[1], addrs[0]]
+        );
+        assert_eq!(
+            by_strategy(LookupIpStrategy::Ipv6thenIpv4),
+            [addrs[0], addrs[1]]
+        );
+    }
+
+    #[test]
+    fn test_ip_strategy_from_config() {
+        let config = Config::from_env(&[("DNS_IP_STRATEGY", "ipv6_then_ipv4")]);
+        let settings = ResolverSettings::from_config(&config).unwrap();
+        assert_eq!(settings.opts().ip_strategy, LookupIpStrategy::Ipv6thenIpv4);
+
+        let config = Config::from_env(&[("DNS_IP_STRATEGY", "ipv6_first")]);
+        assert_eq!(
+            ResolverSettings::from_config(&config)
+                .unwrap_err()
+                .to_string(),
+            "DNS_IP_STRATEGY (`resolver.ip_strategy`) must be one of `ipv4_only`, `ipv6_only`, \
+             `ipv4_and_ipv6`, `ipv6_then_ipv4` and `ipv4_then_ipv6`"
+        );
+    }
+
+    #[test]
+    fn test_config_of_nameservers() {
+        let settings = ResolverSettings {
+            nameservers: vec!["10.0.0.53:5353".parse().unwrap()],
+            ..ResolverSettings::default()
+        };
+        let config = settings.config();
+        assert_eq!(config.name_servers().len(), 2);
+        assert_eq!(nameserver_addresses(&config), ["10.0.0.53"]);
+
+        let config = ResolverSettings::default().config();
+        assert_eq!(
+            config.name_servers(),
+            ResolverConfig::default().name_servers()
+        );
+    }
+
+    #[test]
+    fn test_system_conf() {
+        let system = trust_dns_resolver::system_conf::parse_resolv_conf(
+            "nameserver 10.0.0.53\nsearch corp.example\noptions ndots:2 timeout:7 attempts:3\n",
+        )
+        .unwrap();
+        let mut settings = ResolverSettings {
+            system: Some(system),
+            ..ResolverSettings::default()
+        };
+
+        let config = settings.config();
+        assert_eq!(nameserver_addresses(&config), ["10.0.0.53"]);
+        assert_eq!(config.search()[0].to_string(), "corp.example");
+        let opts = settings.opts();
+        assert_eq!(
+            (opts.ndots, opts.timeout, opts.attempts),
+            (2, Duration::from_secs(7), 3)
+        );
+
+        settings.timeout = Some(Duration::from_secs(1));
+        settings.ndots = Some(0);
+        let opts = settings.opts();
+        assert_eq!(
+            (opts.ndots, opts.timeout, opts.attempts),
+            (0, Duration::from_secs(1), 3)
+        );
+    }
+
+    #[test]
+    fn test_opts() {
+        let opts = ResolverSettings::default().opts();
+        assert_eq!(
+            (opts.timeout, opts.attempts),
+            (DEFAULT_TIMEOUT, DEFAULT_ATTEMPTS)
+        );
+        assert_eq!(opts.ip_strategy, LookupIpStrategy::Ipv4thenIpv6);
+        assert!(opts.use_hosts_file);
+
+        let settings = ResolverSettings {
+            ip_strategy: Some(LookupIpStrategy::Ipv6Only),
+            ndots: Some(3),
+            use_hosts_file: Some(false),
+            ..ResolverSettings::default()
+        };
+        let opts = settings.opts();
+        assert_eq!(opts.ip_strategy, LookupIpStrategy::Ipv6Only);
+        assert_eq!(opts.ndots, 3);
+        assert!(!opts.use_hosts_file);
+    }
+}
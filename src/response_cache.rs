@@ -0,0 +1,229 @@
+use crate::history::history_key;
+use crate::{CheckTargetsResponse, ErrorResponse, SpfCheckParams, Targets};
+use axum::http::StatusCode;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How many responses are cached at most. While full, further responses are not cached until
+/// cached ones expire.
+const CAPACITY: usize = 1_000;
+
+/// The result of a check of `/api/v1/check-spf`, either its response or its error together
+/// with its status code.
+type CheckResult = std::result::Result<CheckTargetsResponse, (StatusCode, ErrorResponse)>;
+
+/// Identifies the requests answered by the same response: their parameters with the domain
+/// and targets normalized, so that e.g. `Example.com.` and `example.com` share one response.
+/// Keyed by the parsed parameters rather than the query, `GET` and `POST` requests of the same
+/// check share one response, while `format`, which is not one of them, is chosen by each.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ResponseKey(SpfCheckParams);
+
+impl ResponseKey {
+    fn of(params: &SpfCheckParams) -> Self {
+        let target = match &params.target {
+            Targets::One(target) => Targets::One(history_key(target)),
+            Targets::Many(targets) => {
+                Targets::Many(targets.iter().map(|target| history_key(target)).collect())
+            }
+        };
+
+        Self(SpfCheckParams {
+            domain: history_key(&params.domain),
+            target,
+            ..params.clone()
+        })
+    }
+
+    fn domain(&self) -> &str {
+        &self.0.domain
+    }
+}
+
+#[derive(Debug)]
+struct CachedResponse {
+    /// The response with `meta.cached` set.
+    response: CheckTargetsResponse,
+    cached_at: Instant,
+}
+
+/// Remembers complete responses of `GET` and `POST /api/v1/check-spf` for a fixed TTL, so that e.g.
+/// dashboards refreshing the same checks every few seconds do not traverse their trees again.
+/// Disabled with a TTL of zero. Clones share the same responses.
+#[derive(Clone, Debug)]
+pub struct ResponseCache {
+    ttl: Duration,
+    responses: Arc<Mutex<HashMap<ResponseKey, CachedResponse>>>,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            responses: Arc::default(),
+        }
+    }
+
+    /// Removes the cached responses of checks of `domain`, a normalized domain, returning
+    /// whether there were any.
+    pub fn purge(&self, domain: &str) -> bool {
+        let mut responses = self.responses.lock().expect("mutex poisoned");
+        let cached = responses.len();
+        responses.retain(|key, _| key.domain() != domain);
+
+        responses.len() < cached
+    }
+
+    /// Answers the check of `params` with the cached response of an identical check, with
+    /// `meta.cached` set, together with its age. Otherwise runs `check` and caches its result
+    /// if it succeeds. Failed checks are not cached, as they are often caused by transient DNS
+    /// errors.
+    pub async fn get_or_check(
+        &self,
+        params: &SpfCheckParams,
+        check: impl Future<Output = CheckResult>,
+    ) -> (CheckResult, Option<Duration>) {
+        if self.ttl.is_zero() {
+            return (check.await, None);
+        }
+
+        let key = ResponseKey::of(params);
+
+        if let Some((response, age)) = self.get(&key) {
+            return (Ok(response), Some(age));
+        }
+
+        let result = check.await;
+        if let Ok(response) = &result {
+            self.insert(key, response);
+        }
+
+        (result, None)
+    }
+
+    /// Returns the cached response of `key` with its age, `None` if it is not cached or
+    /// expired.
+    fn get(&self, key: &ResponseKey) -> Option<(CheckTargetsResponse, Duration)> {
+        let mut responses = self.responses.lock().expect("mutex poisoned");
+        let cached = responses.get(key)?;
+
+        let age = cached.cached_at.elapsed();
+        if age >= self.ttl {
+            responses.remove(key);
+            return None;
+        }
+
+        Some((cached.response.clone(), age))
+    }
+
+    fn insert(&self, key: ResponseKey, response: &CheckTargetsResponse) {
+        let now = Instant::now();
+        let mut responses = self.responses.lock().expect("mutex poisoned");
+        if responses.len() >= CAPACITY && !responses.contains_key(&key) {
+            responses.retain(|_, cached| now.duration_since(cached.cached_at) < self.ttl);
+            if responses.len() >= CAPACITY {
+                return;
+            }
+        }
+
+        let mut response = response.clone();
+        response.mark_cached();
+        let cached = CachedResponse {
+            response,
+            cached_at: now,
+        };
+        responses.insert(key, cached);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BatchCheckResponse, BatchItemResponse};
+    use axum::extract::Query;
+    use axum::http::Uri;
+    use serde_json::json;
+    use spf_checker::CheckError;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn error() -> ErrorResponse {
+        let err = anyhow::anyhow!("request timed out").context(CheckError::DnsLookupFailed);
+        ErrorResponse::from(&err)
+    }
+
+    fn failed(target: &str) -> BatchItemResponse {
+        BatchItemResponse::Failed {
+            domain: "example.com".to_string(),
+            target: target.to_string(),
+            error: error(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_or_check() {
+        let calls = AtomicUsize::new(0);
+        let cache = ResponseCache::new(Duration::from_secs(30));
+        let check = |uri: &'static str| {
+            let Query(params) = Query::try_from_uri(&Uri::from_static(uri)).unwrap();
+            let calls = &calls;
+            let cache = &cache;
+            async move {
+                let (result, age) = cache
+                    .get_or_check(&params, async {
+                        match calls.fetch_add(1, Ordering::Relaxed) {
+                            2 => Err((StatusCode::NOT_FOUND, error())),
+                            _ => Ok(CheckTargetsResponse::Many(BatchCheckResponse::new(
+                                vec![failed("a.net")],
+                                0,
+                            ))),
+                        }
+                    })
+                    .await;
+
+                (result.map_err(|(status, _)| status).err(), age)
+            }
+        };
+
+        assert_eq!(
+            check("/?domain=example.com&target=a.net").await,
+            (None, None)
+        );
+        let (failure, age) = check("/?target=A.net.&domain=Example.COM&format=csv").await;
+        assert_eq!((failure, age.map(|age| age.as_secs())), (None, Some(0)));
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        // The body of a `POST` request is answered by the same response.
+        let body = json!({ "domain": "example.com.", "target": "a.net" });
+        let params: SpfCheckParams = serde_json::from_value(body).unwrap();
+        let (_, age) = cache.get_or_check(&params, async { unreachable!() }).await;
+        assert!(age.is_some());
+
+        // Other parameters are checked anew, failures are not cached.
+        let uri = "/?domain=example.com&target=a.net&strict=true";
+        assert_eq!(check(uri).await, (None, None));
+        assert!(check(uri).await.1.is_some());
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+
+        assert!(cache.purge("example.com"));
+        assert!(!cache.purge("example.com"));
+        let uri = "/?domain=example.com&target=a.net";
+        assert_eq!(check(uri).await, (Some(StatusCode::NOT_FOUND), None));
+        assert_eq!(check(uri).await, (None, None));
+        assert_eq!(calls.load(Ordering::Relaxed), 4);
+
+        let disabled = ResponseCache::new(Duration::ZERO);
+        let Query(params) = Query::try_from_uri(&Uri::from_static(uri)).unwrap();
+        let (_, age) = disabled
+            .get_or_check(&params, async {
+                Ok(CheckTargetsResponse::Many(BatchCheckResponse::new(
+                    Vec::new(),
+                    0,
+                )))
+            })
+            .await;
+        assert_eq!(age, None);
+        assert!(disabled.responses.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+//! A resolver answering the TXT lookups of the handler tests without DNS.
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use spf_checker::{CheckError, MxRecord, SpfChecker, SpnResolver, TxtRecord};
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone, Default)]
+pub struct MockResolver {
+    records: Arc<Mutex<HashMap<String, Vec<String>>>>,
+    /// Domains whose TXT lookups fail.
+    failing: Arc<Mutex<HashSet<String>>>,
+}
+
+impl MockResolver {
+    pub fn add_records(&self, domain: &str, records: &[&str]) {
+        let mut all = self.records.lock().unwrap();
+        all.insert(
+            domain.to_string(),
+            records.iter().map(|record| record.to_string()).collect(),
+        );
+    }
+
+    pub fn add_record(&self, domain: &str, record: &str) {
+        self.add_records(domain, &[record]);
+    }
+
+    pub fn fail_lookups(&self, domain: &str) {
+        let mut failing = self.failing.lock().unwrap();
+        failing.insert(domain.to_string());
+    }
+
+    pub fn checker(&self) -> SpfChecker {
+        SpfChecker::new(self.clone())
+    }
+}
+
+#[async_trait]
+impl SpnResolver for MockResolver {
+    async fn find_spf_records(&self, domain: &str) -> Result<Vec<String>> {
+        let records = self.lookup_txt(domain).await?;
+        Ok(records
+            .into_iter()
+            .filter(|txt| txt == "v=spf1" || txt.starts_with("v=spf1 "))
+            .collect())
+    }
+
+    async fn lookup_txt(&self, domain: &str) -> Result<Vec<String>> {
+        if self
+            .failing
+            .lock()
+            .expect("mutex poisoned")
+            .contains(domain)
+        {
+            return Err(anyhow!("request timed out")).context(CheckError::DnsLookupFailed);
+        }
+        let records = self.records.lock().expect("mutex poisoned");
+        Ok(records.get(domain).cloned().unwrap_or_default())
+    }
+
+    async fn lookup_txt_records(&self, domain: &str) -> Result<Vec<TxtRecord>> {
+        let records = self.lookup_txt(domain).await?;
+        Ok(records
+            .into_iter()
+            .map(|text| TxtRecord { text, ttl: 300 })
+            .collect())
+    }
+
+    async fn lookup_a(&self, _domain: &str) -> Result<Vec<Ipv4Addr>> {
+        Ok(Vec::new())
+    }
+
+    async fn lookup_aaaa(&self, _domain: &str) -> Result<Vec<Ipv6Addr>> {
+        Ok(Vec::new())
+    }
+
+    async fn lookup_mx(&self, _domain: &str) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    async fn lookup_mx_records(&self, _domain: &str) -> Result<Vec<MxRecord>> {
+        Ok(Vec::new())
+    }
+
+    async fn lookup_ptr(&self, _ip: IpAddr) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    async fn lookup_spf_rr(&self, _domain: &str) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
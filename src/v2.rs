@@ -0,0 +1,461 @@
+use crate::{log_message, AppState, SpfCheckParams, Targets, WarningResponse};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use axum_extra::extract::Query;
+use serde::Serialize;
+use spf_checker::{CheckError, SpfChecker, SpfResult, SpfTreeNode};
+use utoipa::ToSchema;
+
+/// The routes of `/api/v2`. Unlike v1, whose flat responses are kept as they are, a v2 check
+/// answers with the nested tree, the errors of individual domains and an RFC 7208 result.
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/check-spf", get(check_spf))
+}
+
+/// Response of `GET /api/v2/check-spf`.
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = v2::SpfCheckResponse)]
+pub struct SpfCheckResponse {
+    domain: String,
+    target: String,
+    /// The result receivers give mail from the target: the result of the qualifier of the
+    /// matching mechanism, else of the default policy, `none` without SPF record, and
+    /// `temperror` or `permerror` if the check failed.
+    result: String,
+    found: bool,
+    /// The mechanism through which the target was found.
+    #[serde(rename = "match")]
+    matched: Option<MatchResponse>,
+    /// The result of the `all` mechanism terminating the domain's record, following redirects.
+    default_result: Option<String>,
+    dns_lookups: usize,
+    void_lookups: usize,
+    elapsed_ms: u64,
+    /// The include and redirect tree of the domain, `null` if it has too many domains.
+    tree: Option<TreeNodeResponse>,
+    /// The errors of the domains that could not be checked, in traversal order.
+    errors: Vec<DomainErrorResponse>,
+    warnings: Vec<WarningResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MatchResponse {
+    /// The mechanism without qualifier, e.g. `include:_spf.example.com`, `null` if the target
+    /// was found by comparing the mechanisms of its own record.
+    mechanism: Option<String>,
+    /// The domain whose record declares `mechanism`.
+    domain: Option<String>,
+    /// The result of the mechanism's qualifier, e.g. `softfail` for `~include:...`.
+    result: String,
+    /// The domains from `domain` to the one declaring `mechanism`, followed by the target.
+    path: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TreeNodeResponse {
+    domain: String,
+    /// The term of the parent's record referencing the domain, `null` for the root.
+    term: Option<String>,
+    record: Option<String>,
+    #[serde(rename = "loop")]
+    is_loop: bool,
+    /// Why the record of the domain could not be looked up or parsed.
+    error: Option<ErrorCode>,
+    #[schema(no_recursion)]
+    children: Vec<TreeNodeResponse>,
+}
+
+impl From<SpfTreeNode> for TreeNodeResponse {
+    fn from(node: SpfTreeNode) -> Self {
+        Self {
+            domain: node.domain,
+            term: node.term,
+            record: node.record,
+            is_loop: node.is_loop,
+            error: node.error.as_deref().map(ErrorCode::from_code),
+            children: node.children.into_iter().map(Self::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DomainErrorResponse {
+    domain: String,
+    code: ErrorCode,
+    message: String,
+}
+
+impl DomainErrorResponse {
+    fn new(domain: String, code: ErrorCode) -> Self {
+        Self {
+            domain,
+            code,
+            message: code.message().to_string(),
+        }
+    }
+}
+
+/// Error response of the v2 API, e.g. `{"error": {"code": "INVALID_DOMAIN", ...}}`.
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = v2::ErrorResponse)]
+pub struct ErrorResponse {
+    error: ErrorBody,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    code: ErrorCode,
+    message: String,
+}
+
+impl From<ErrorCode> for ErrorResponse {
+    fn from(code: ErrorCode) -> Self {
+        Self {
+            error: ErrorBody {
+                code,
+                message: code.message().to_string(),
+            },
+        }
+    }
+}
+
+/// The error codes of the v2 API. Errors without one of these codes are reported as
+/// `INTERNAL_ERROR`, so that clients can rely on the list being complete.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    InvalidTargetCount,
+    InvalidMechanisms,
+    InvalidDomain,
+    DnsLookupFailed,
+    DnsTimeout,
+    DnsNxdomain,
+    MultipleSpfRecords,
+    SpfParseFailed,
+    TargetSpfParseFailed,
+    LookupLimitExceeded,
+    VoidLookupLimitExceeded,
+    TreeSizeLimitExceeded,
+    InternalError,
+}
+
+impl From<CheckError> for ErrorCode {
+    fn from(error: CheckError) -> Self {
+        match error {
+            CheckError::InvalidDomain => Self::InvalidDomain,
+            CheckError::DnsLookupFailed => Self::DnsLookupFailed,
+            CheckError::DnsTimeout => Self::DnsTimeout,
+            CheckError::DnsNxdomain => Self::DnsNxdomain,
+            CheckError::MultipleSpfRecords => Self::MultipleSpfRecords,
+            CheckError::SpfParseFailed => Self::SpfParseFailed,
+            CheckError::TargetSpfParseFailed => Self::TargetSpfParseFailed,
+            CheckError::LookupLimitExceeded => Self::LookupLimitExceeded,
+            CheckError::VoidLookupLimitExceeded => Self::VoidLookupLimitExceeded,
+            CheckError::TreeSizeLimitExceeded => Self::TreeSizeLimitExceeded,
+            CheckError::DnsLookupsDisabled
+            | CheckError::DnssecUnavailable
+            | CheckError::NoSpfRecord => Self::InternalError,
+        }
+    }
+}
+
+impl ErrorCode {
+    /// The code of an error of the checker, `INTERNAL_ERROR` for errors without code.
+    fn of(err: &anyhow::Error) -> Self {
+        CheckError::of(err).map_or(Self::InternalError, Self::from)
+    }
+
+    /// The code of a failed domain reported with the error code `code`, e.g.
+    /// `DNS_LOOKUP_FAILED`.
+    fn from_code(code: &str) -> Self {
+        CheckError::from_code(code).map_or(Self::InternalError, Self::from)
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            Self::InvalidTargetCount => "Exactly one target must be given",
+            Self::InvalidMechanisms => "`mechanisms` is not a list of mechanism kinds",
+            Self::InvalidDomain => "The domain or target is not a valid domain name",
+            Self::DnsLookupFailed => "A DNS lookup failed",
+            Self::DnsTimeout => "A DNS lookup timed out",
+            Self::DnsNxdomain => "A looked up domain does not exist",
+            Self::MultipleSpfRecords => "The domain publishes more than one SPF record",
+            Self::SpfParseFailed => "The SPF record of the domain does not parse",
+            Self::TargetSpfParseFailed => "The SPF record of the target does not parse",
+            Self::LookupLimitExceeded => "The SPF tree takes more than 10 DNS lookups",
+            Self::VoidLookupLimitExceeded => "Too many DNS lookups returned no records",
+            Self::TreeSizeLimitExceeded => "The SPF tree has too many domains",
+            Self::InternalError => "The check failed unexpectedly",
+        }
+    }
+
+    /// The result of a check failing with the error: `temperror` for DNS failures, which may
+    /// go away when retried, `none` for a domain that does not exist, else `permerror`.
+    fn result(self) -> SpfResult {
+        match self {
+            Self::DnsLookupFailed | Self::DnsTimeout => SpfResult::TempError,
+            Self::DnsNxdomain => SpfResult::None,
+            _ => SpfResult::PermError,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v2/check-spf",
+    params(SpfCheckParams),
+    responses(
+        (status = 200, description = "The check, including failed ones", body = SpfCheckResponse),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse),
+    )
+)]
+pub async fn check_spf(
+    Query(params): Query<SpfCheckParams>,
+    checker: State<SpfChecker>,
+) -> Response {
+    match run_check(&checker, &params).await {
+        Ok(response) => (StatusCode::OK, Json(response)).into_response(),
+        Err(code) => (StatusCode::BAD_REQUEST, Json(ErrorResponse::from(code))).into_response(),
+    }
+}
+
+async fn run_check(
+    checker: &SpfChecker,
+    params: &SpfCheckParams,
+) -> Result<SpfCheckResponse, ErrorCode> {
+    let start = std::time::Instant::now();
+
+    let Targets::One(target) = &params.target else {
+        return Err(ErrorCode::InvalidTargetCount);
+    };
+
+    let options = params
+        .check_options()
+        .map_err(|_| ErrorCode::InvalidMechanisms)?;
+
+    let domain = params.mail_domain();
+    spf_checker::domain_to_ascii(domain).map_err(|_| ErrorCode::InvalidDomain)?;
+    spf_checker::domain_to_ascii(target).map_err(|_| ErrorCode::InvalidDomain)?;
+
+    // Both traverse the same records, which the cache looks up only once.
+    let checker = checker.with_lookup_cache();
+    let (check, tree) = tokio::join!(
+        checker.check_with_options(domain, target, &options),
+        checker.partial_tree(domain, &options),
+    );
+
+    let mut errors = Vec::new();
+
+    let tree = match tree {
+        Ok(tree) => {
+            collect_errors(&tree, &mut errors);
+            Some(tree)
+        }
+        Err(err) => {
+            errors.push(DomainErrorResponse::new(
+                domain.to_string(),
+                ErrorCode::of(&err),
+            ));
+            None
+        }
+    };
+
+    let mut response = SpfCheckResponse {
+        domain: domain.to_string(),
+        target: target.clone(),
+        result: SpfResult::None.to_string(),
+        found: false,
+        matched: None,
+        default_result: None,
+        dns_lookups: 0,
+        void_lookups: 0,
+        elapsed_ms: 0,
+        tree: tree.map(TreeNodeResponse::from),
+        errors,
+        warnings: Vec::new(),
+    };
+
+    match check {
+        Ok(result) => {
+            // The errors of failed domains of the tree are already reported with the tree.
+            response.result = result.result().to_string();
+            response.found = result.found;
+            response.default_result = result
+                .default_policy
+                .map(|qualifier| SpfResult::from(qualifier).to_string());
+            response.dns_lookups = result.dns_mechanism_count;
+            response.void_lookups = result.void_lookups;
+            response.warnings = result
+                .warnings
+                .into_iter()
+                .map(WarningResponse::from)
+                .collect();
+
+            if result.found {
+                response.matched = Some(MatchResponse {
+                    mechanism: result.mechanism,
+                    domain: result.mechanism_domain,
+                    result: result
+                        .qualifier
+                        .map_or(SpfResult::Pass, SpfResult::from)
+                        .to_string(),
+                    path: result.path.unwrap_or_default(),
+                });
+            }
+        }
+        Err(err) => {
+            let code = ErrorCode::of(&err);
+            response.result = code.result().to_string();
+
+            // Errors of the domains of the tree are already reported with their domain.
+            if !response.errors.iter().any(|error| error.code == code) {
+                let failed_domain = match code {
+                    ErrorCode::TargetSpfParseFailed => target,
+                    _ => domain,
+                };
+
+                response
+                    .errors
+                    .push(DomainErrorResponse::new(failed_domain.to_string(), code));
+            }
+        }
+    }
+
+    response.elapsed_ms = start.elapsed().as_millis() as u64;
+
+    log_message(format!(
+        "Checked \"{}\" for \"{}\" (v2): {} ({}ms)",
+        domain, target, response.result, response.elapsed_ms
+    ));
+
+    Ok(response)
+}
+
+fn collect_errors(node: &SpfTreeNode, errors: &mut Vec<DomainErrorResponse>) {
+    if let Some(error) = &node.error {
+        errors.push(DomainErrorResponse::new(
+            node.domain.clone(),
+            ErrorCode::from_code(error),
+        ));
+    }
+
+    for child in &node.children {
+        collect_errors(child, errors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockResolver;
+    use axum::http::Uri;
+
+    fn params(query: &'static str) -> SpfCheckParams {
+        let Query(params) = Query::try_from_uri(&Uri::from_static(query)).unwrap();
+        params
+    }
+
+    #[test]
+    fn test_error_codes() {
+        // Each code of the checker is either a code of the v2 API or an internal error.
+        for error in CheckError::ALL.iter().copied() {
+            let code = ErrorCode::from(error);
+            if code != ErrorCode::InternalError {
+                assert_eq!(serde_json::to_value(code).unwrap(), error.code());
+            }
+            assert_eq!(ErrorCode::from_code(error.code()), code);
+        }
+        assert_eq!(ErrorCode::from_code("BOGUS"), ErrorCode::InternalError);
+
+        let err = anyhow::anyhow!("request timed out").context(CheckError::DnsTimeout);
+        assert_eq!(ErrorCode::of(&err), ErrorCode::DnsTimeout);
+        assert_eq!(
+            ErrorCode::of(&anyhow::anyhow!("bug")),
+            ErrorCode::InternalError
+        );
+
+        assert_eq!(ErrorCode::DnsTimeout.result(), SpfResult::TempError);
+        assert_eq!(ErrorCode::DnsNxdomain.result(), SpfResult::None);
+        assert_eq!(ErrorCode::SpfParseFailed.result(), SpfResult::PermError);
+    }
+
+    #[tokio::test]
+    async fn test_errors_of_the_tree() {
+        let resolver = MockResolver::default();
+        resolver.add_record(
+            "example.com",
+            "v=spf1 include:_spf.a.net include:_spf.b.net include:_spf.example.net -all",
+        );
+        resolver.add_records("_spf.a.net", &["v=spf1 -all", "v=spf1 ~all"]);
+        resolver.fail_lookups("_spf.b.net");
+        resolver.add_record("_spf.example.net", "v=spf1 ip4:192.0.2.0/24 -all");
+
+        let response = run_check(
+            &resolver.checker(),
+            &params("/?domain=example.com&target=_spf.example.net"),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.result, "pass");
+        assert!(response.found);
+        let errors: Vec<_> = response
+            .errors
+            .iter()
+            .map(|error| (error.domain.as_str(), error.code))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                ("_spf.a.net", ErrorCode::MultipleSpfRecords),
+                ("_spf.b.net", ErrorCode::DnsLookupFailed),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_reported_once() {
+        let resolver = MockResolver::default();
+        resolver.fail_lookups("example.com");
+
+        let response = run_check(
+            &resolver.checker(),
+            &params("/?domain=example.com&target=_spf.example.net"),
+        )
+        .await
+        .unwrap();
+
+        // The check fails like the root of the tree, which is reported as one error.
+        assert_eq!(response.result, "temperror");
+        let tree = response.tree.unwrap();
+        assert_eq!(tree.error, Some(ErrorCode::DnsLookupFailed));
+        assert_eq!(response.errors.len(), 1);
+        assert_eq!(response.errors[0].domain, "example.com");
+        assert_eq!(response.errors[0].code, ErrorCode::DnsLookupFailed);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_parameters() {
+        let checker = MockResolver::default().checker();
+
+        for (query, code) in [
+            (
+                "/?domain=example.com&target=a.example.net&target=b.example.net",
+                ErrorCode::InvalidTargetCount,
+            ),
+            (
+                "/?domain=example.com&target=_spf.example.net&mechanisms=bogus",
+                ErrorCode::InvalidMechanisms,
+            ),
+            (
+                "/?domain=b%C3%BC%20cher.example&target=_spf.example.net",
+                ErrorCode::InvalidDomain,
+            ),
+        ] {
+            assert_eq!(run_check(&checker, &params(query)).await.unwrap_err(), code);
+        }
+    }
+}
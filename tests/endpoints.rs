@@ -0,0 +1,251 @@
+//! Runs the `spf-check` binary against a name server answering from a fixed zone and checks
+//! the responses of its endpoints.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::process::Stdio;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::process::{Child, Command};
+use trust_dns_resolver::proto::op::{Message, MessageType, ResponseCode};
+use trust_dns_resolver::proto::rr::rdata::TXT;
+use trust_dns_resolver::proto::rr::{RData, Record, RecordType};
+
+/// The TXT records of the zone served to the service.
+const ZONE: &[(&str, &[&str])] = &[
+    ("example.com", &["v=spf1 include:_spf.example.net -all"]),
+    ("_spf.example.net", &["v=spf1 ip4:192.0.2.0/24 -all"]),
+    ("_dmarc.example.com", &["v=DMARC1; p=reject"]),
+    (
+        "multiple.example.com",
+        &["v=spf1 -all", "v=spf1 include:_spf.example.net -all"],
+    ),
+    (
+        "partial.example.com",
+        &["v=spf1 include:multiple.example.com include:_spf.example.net -all"],
+    ),
+];
+
+/// A running `spf-check`, stopped when dropped.
+struct Service {
+    addr: SocketAddr,
+    _process: Child,
+}
+
+impl Service {
+    /// Starts the service with `nameserver`, if any, on a free port.
+    async fn start(nameserver: Option<SocketAddr>) -> Self {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_spf-check"));
+        command
+            .args(["--listen", "127.0.0.1:0", "--grpc-listen", "127.0.0.1:0"])
+            .env_remove("SPF_CHECK_CONFIG")
+            .stdout(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(nameserver) = nameserver {
+            command.env("DNS_NAMESERVERS", nameserver.to_string());
+        }
+        let mut process = command.spawn().unwrap();
+
+        // The log tells the port picked for port 0.
+        let mut lines = BufReader::new(process.stdout.take().unwrap()).lines();
+        let addr = loop {
+            let line = lines.next_line().await.unwrap().expect("service exited");
+            if let Some((_, listening)) = line.split_once("Listening on ") {
+                break listening.split(' ').next().unwrap().parse().unwrap();
+            }
+        };
+        tokio::spawn(async move { while let Ok(Some(_)) = lines.next_line().await {} });
+
+        Self {
+            addr,
+            _process: process,
+        }
+    }
+
+    /// Starts the service with a name server answering from [`ZONE`].
+    async fn with_zone() -> Self {
+        Self::start(Some(serve_zone().await)).await
+    }
+
+    async fn get(&self, path: &str) -> (u16, Value) {
+        self.request(&format!("GET {path} HTTP/1.1\r\n"), "").await
+    }
+
+    async fn post(&self, path: &str, body: &Value) -> (u16, Value) {
+        let body = body.to_string();
+        let head = format!(
+            "POST {path} HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n",
+            body.len()
+        );
+        self.request(&head, &body).await
+    }
+
+    /// Sends a request of `head` and `body`, returning the status and the JSON body.
+    async fn request(&self, head: &str, body: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(self.addr).await.unwrap();
+        let request = format!(
+            "{head}Host: {}\r\nConnection: close\r\n\r\n{body}",
+            self.addr
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+
+        (status, serde_json::from_str(body).unwrap_or(Value::Null))
+    }
+}
+
+/// Serves the TXT records of [`ZONE`] over UDP, answering other names with `NXDOMAIN`.
+async fn serve_zone() -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    let zone: Arc<HashMap<_, _>> = Arc::new(ZONE.iter().copied().collect());
+
+    tokio::spawn(async move {
+        let mut buf = [0; 512];
+        loop {
+            let (len, peer) = socket.recv_from(&mut buf).await.unwrap();
+            let Ok(query) = Message::from_vec(&buf[..len]) else {
+                continue;
+            };
+
+            let mut response = Message::new();
+            response
+                .set_id(query.id())
+                .set_message_type(MessageType::Response)
+                .set_recursion_desired(query.recursion_desired())
+                .set_recursion_available(true)
+                .add_queries(query.queries().to_vec());
+
+            for question in query.queries() {
+                let name = question.name().to_ascii();
+                match zone.get(name.trim_end_matches('.')) {
+                    Some(records) if question.query_type() == RecordType::TXT => {
+                        for record in *records {
+                            let txt = TXT::new(vec![record.to_string()]);
+                            response.add_answer(Record::from_rdata(
+                                question.name().clone(),
+                                300,
+                                RData::TXT(txt),
+                            ));
+                        }
+                    }
+                    Some(_) => {}
+                    None => {
+                        response.set_response_code(ResponseCode::NXDomain);
+                    }
+                }
+            }
+
+            socket
+                .send_to(&response.to_vec().unwrap(), peer)
+                .await
+                .unwrap();
+        }
+    });
+
+    addr
+}
+
+#[tokio::test]
+async fn test_health() {
+    let service = Service::with_zone().await;
+
+    let (status, _) = service.get("/health").await;
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn test_check_spf() {
+    let service = Service::with_zone().await;
+
+    let (status, body) = service
+        .get("/api/v1/check-spf?domain=example.com&target=_spf.example.net")
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["found"], true);
+    assert_eq!(body["included_domains"], json!(["_spf.example.net"]));
+
+    let (status, body) = service
+        .get("/api/v1/check-spf?domain=example.com&target=_spf.example.org")
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["found"], false);
+
+    let (status, body) = service
+        .get("/api/v1/check-spf?domain=multiple.example.com&target=_spf.example.net")
+        .await;
+    assert_eq!(status, 404);
+    assert_eq!(body["error"], "MULTIPLE_SPF_RECORDS");
+    assert_eq!(
+        body["spf_records"],
+        json!(["v=spf1 -all", "v=spf1 include:_spf.example.net -all"])
+    );
+
+    let (status, body) = service
+        .get("/api/v1/check-spf?domain=example.com&target=_spf.example.net&mechanisms=bogus")
+        .await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"], "INVALID_MECHANISMS");
+}
+
+#[tokio::test]
+async fn test_v2_check_spf() {
+    let service = Service::with_zone().await;
+
+    let (status, body) = service
+        .get("/api/v2/check-spf?domain=partial.example.com&target=_spf.example.net")
+        .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["result"], "pass");
+    assert_eq!(body["match"]["domain"], "partial.example.com");
+    assert_eq!(
+        body["errors"],
+        json!([{
+            "domain": "multiple.example.com",
+            "code": "MULTIPLE_SPF_RECORDS",
+            "message": "The domain publishes more than one SPF record",
+        }])
+    );
+
+    let (status, body) = service
+        .get("/api/v2/check-spf?domain=example.com&target=a.example.net&target=b.example.net")
+        .await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "INVALID_TARGET_COUNT");
+}
+
+#[tokio::test]
+async fn test_graphql() {
+    let service = Service::with_zone().await;
+
+    let query = r#"{
+        checkSpf(domain: "example.com", target: "_spf.example.net") { found }
+        dmarc(domain: "example.com") { record }
+    }"#;
+    let (status, body) = service.post("/graphql", &json!({ "query": query })).await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        body["data"],
+        json!({
+            "checkSpf": { "found": true },
+            "dmarc": { "record": "v=DMARC1; p=reject" },
+        })
+    );
+}
+
+#[tokio::test]
+#[ignore = "Queries real DNS"]
+async fn test_check_spf_with_provided_domains() {
+    let service = Service::start(None).await;
+
+    let (status, _) = service
+        .get("/api/v1/check-spf?domain=auc-online.de&target=spf.easybill-mail.de")
+        .await;
+    assert_eq!(status, 200);
+}